@@ -1,30 +1,182 @@
-use std::{path::PathBuf, process::Stdio, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
 use metered::{ErrorCount, HitCount, ResponseTime, Throughput};
 use serde::{Deserialize, Serialize};
 use tokio::process::Command;
-use tracing::{debug, info, trace};
+use tracing::{debug, info, trace, warn};
+use unifi_protect_client::config::Secret;
 
-use crate::{Error, Result, archive, archive::Archive, task::Prune};
-
-const SECONDS_PER_DAY: u64 = 24 * 60 * 60; // 86400
+use crate::{
+    Error, Result, archive,
+    archive::{Archive, RestoreFilter},
+    clock::Clock,
+    config::deserialize_optional_file_const_or_env,
+    retention::{Candidate, RetentionPolicy},
+    task::Prune,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
 pub struct Config {
     pub ssh_key_path: Option<PathBuf>,
     pub borg_repo: String,
-    pub borg_passphrase: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_file_const_or_env")]
+    pub borg_passphrase: Option<Secret<String>>,
     pub append_only: bool,
-    pub source_path: PathBuf,
+    /// Paths to archive, or `source-path` for just one. Unset archives the path of every local
+    /// backup target, see [`archive_targets`](crate::archive::archive_targets).
+    #[serde(
+        default,
+        alias = "source-path",
+        deserialize_with = "archive::one_or_many"
+    )]
+    pub source_paths: Vec<PathBuf>,
+    /// Passed to `borg create --compression`, e.g. `lz4`, `zstd` or `zstd:10` (`zstd,10` in
+    /// borg's own syntax)
+    #[serde(default = "default_compression")]
+    pub compression: String,
+    /// Create the repository with `borg init` when it doesn't exist yet
+    #[serde(default)]
+    pub init: bool,
+    /// Passed to `borg init --encryption` when creating the repository
+    #[serde(default = "default_encryption")]
+    pub encryption: String,
+    /// With any `keep-*` set, archives older than the retention period are pruned by
+    /// `borg prune` keeping this many daily, weekly, monthly and yearly archives
+    #[serde(default)]
+    pub keep_daily: Option<u32>,
+    #[serde(default)]
+    pub keep_weekly: Option<u32>,
+    #[serde(default)]
+    pub keep_monthly: Option<u32>,
+    #[serde(default)]
+    pub keep_yearly: Option<u32>,
+    /// Name of each archive. `{date}` and `{time}` are its creation time in UTC; `{hostname}` and
+    /// `{nvr}` (the NVR's name) are filled in at startup.
+    #[serde(default = "default_archive_name")]
+    pub archive_name: String,
+    /// Replaces `archive.retention-period` for this repository, e.g. to keep an off-site copy
+    /// for longer than the on-site one
+    #[serde(default, with = "humantime_serde")]
+    pub retention_period: Option<Duration>,
+    /// Run `borg break-lock` once the repository's lock has made operations fail for this long
+    /// and no borg process on this host is using it. Unset never breaks locks.
+    #[serde(default, with = "humantime_serde")]
+    pub break_stale_lock_after: Option<Duration>,
+}
+
+fn default_archive_name() -> String {
+    "{date}_{time}".to_string()
+}
+
+fn default_compression() -> String {
+    "lz4".to_string()
+}
+
+fn default_encryption() -> String {
+    "repokey-blake2".to_string()
 }
 
+impl Config {
+    /// Fill in `{hostname}` and `{nvr}` in `archive-name`, which has to keep `{date}` and
+    /// `{time}` so each archive gets its own name
+    pub fn resolve_archive_name(&mut self, hostname: &str, nvr: &str) -> Result<()> {
+        if !(self.archive_name.contains("{date}") && self.archive_name.contains("{time}")) {
+            return Err(Error::General(format!(
+                "Borg archive-name {:?} for {} needs both {{date}} and {{time}}",
+                self.archive_name, self.borg_repo
+            )));
+        }
+
+        // borg doesn't allow `/` in archive names
+        let clean = |value: &str| value.replace('/', "-");
+        self.archive_name = self
+            .archive_name
+            .replace("{hostname}", &clean(hostname))
+            .replace("{nvr}", &clean(nvr));
+        Ok(())
+    }
+
+    /// The name of an archive created at `time`
+    fn archive_name_at(&self, time: DateTime<Utc>) -> String {
+        self.archive_name
+            .replace("{date}", &time.format("%Y-%m-%d").to_string())
+            .replace("{time}", &time.format("%H-%M-%S").to_string())
+    }
+
+    /// A `--glob-archives` pattern matching the names of this instance's archives, so others
+    /// sharing the repository under another name aren't listed or pruned
+    fn archive_glob(&self) -> String {
+        self.archive_name
+            .replace("{date}", "*")
+            .replace("{time}", "*")
+    }
+
+    /// `compression` as borg takes it
+    fn borg_compression(&self) -> String {
+        self.compression.replace(':', ",")
+    }
+
+    /// `borg prune` arguments for the `keep-*` options, if any are set
+    fn keep_args(&self) -> Vec<String> {
+        [
+            ("--keep-daily", self.keep_daily),
+            ("--keep-weekly", self.keep_weekly),
+            ("--keep-monthly", self.keep_monthly),
+            ("--keep-yearly", self.keep_yearly),
+        ]
+        .into_iter()
+        .filter_map(|(arg, keep)| keep.map(|keep| format!("{arg}={keep}")))
+        .collect()
+    }
+}
+
+/// `borg list --json` output
+#[derive(Debug, Deserialize)]
+struct ArchiveList {
+    archives: Vec<ArchiveEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchiveEntry {
+    name: String,
+    /// Local time, without an offset
+    start: NaiveDateTime,
+}
+
+/// A line of `borg list --json-lines` output for an archive
+#[derive(Debug, Deserialize)]
+struct ArchiveItem {
+    path: String,
+    /// `-` for regular files, `d` for directories
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// How many paths are passed to each `borg extract`, to stay well clear of the argument length
+/// limit
+const EXTRACT_BATCH: usize = 500;
+
+/// What borg says when it gave up waiting for the repository's lock
+const LOCK_TIMEOUT: &str = "Failed to create/acquire the lock";
+
 pub struct BorgBackup {
     pub backup_config: archive::Config,
     pub remote_config: Config,
     pub metrics: Arc<Metrics>,
+    /// Held for each archive, prune, check and compact, so they don't contend for the
+    /// repository's lock with each other, and a lock that's held while nothing here is running
+    /// belongs to something else
+    exclusive: tokio::sync::Mutex<()>,
+    /// When operations first started failing on the repository's lock, until one succeeds
+    locked_since: Mutex<Option<Instant>>,
 }
 
 impl BorgBackup {
@@ -37,7 +189,274 @@ impl BorgBackup {
             backup_config,
             remote_config,
             metrics,
+            exclusive: tokio::sync::Mutex::new(()),
+            locked_since: Mutex::new(None),
+        }
+    }
+
+    /// Run `operation` on its own, and break the repository's lock if it failed on one that's
+    /// gone stale. The operation isn't retried; the next run of it gets the repository.
+    async fn exclusively<T>(&self, operation: impl Future<Output = Result<T>>) -> Result<T> {
+        let _exclusive = self.exclusive.lock().await;
+        let result = operation.await;
+
+        let locked = match &result {
+            Err(err) => err
+                .output()
+                .is_some_and(|output| output.contains(LOCK_TIMEOUT)),
+            Ok(_) => false,
+        };
+        let locked_for = {
+            let mut locked_since = self.locked_since.lock().expect("lock state poisoned");
+            if !locked {
+                *locked_since = None;
+                return result;
+            }
+            locked_since.get_or_insert_with(Instant::now).elapsed()
+        };
+
+        let Some(break_after) = self.remote_config.break_stale_lock_after else {
+            return result;
+        };
+        if locked_for < break_after {
+            debug!(locked_for = ?locked_for, "Borg repository is locked");
+            return result;
+        }
+        if borg_running(&self.remote_config.borg_repo) {
+            warn!(
+                repo = self.remote_config.borg_repo,
+                "Borg repository lock looks stale, but a borg process on this host is using it"
+            );
+            return result;
+        }
+
+        match self.break_lock().await {
+            Ok(()) => {
+                *self.locked_since.lock().expect("lock state poisoned") = None;
+            }
+            Err(err) => warn!(err = ?err, "Failed to break borg repository lock"),
+        }
+        result
+    }
+
+    /// A borg command with the passphrase and SSH key for this repository.
+    fn borg(&self) -> Command {
+        let mut cmd = Command::new("borg");
+
+        if let Some(ref passphrase) = self.remote_config.borg_passphrase {
+            cmd.env("BORG_PASSPHRASE", passphrase.expose());
+        }
+
+        // Set SSH key if provided
+        if let Some(ref ssh_key) = self.remote_config.ssh_key_path {
+            let ssh_cmd = format!("ssh -i {}", ssh_key.display());
+            cmd.env("BORG_RSH", ssh_cmd);
+        }
+
+        cmd
+    }
+
+    async fn list_archives(&self) -> Result<Vec<ArchiveEntry>> {
+        let output = self
+            .borg()
+            .arg("list")
+            .arg("--json")
+            .arg(format!(
+                "--glob-archives={}",
+                self.remote_config.archive_glob()
+            ))
+            .arg(&self.remote_config.borg_repo)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("borg list", &output));
+        }
+
+        Ok(serde_json::from_slice::<ArchiveList>(&output.stdout)?.archives)
+    }
+
+    /// `borg info` of the repository, which fails if it can't be reached or the passphrase is
+    /// wrong, and if it doesn't exist unless `init` is set, in which case it's created
+    async fn check_repo(&self) -> Result<()> {
+        let output = self
+            .borg()
+            .arg("info")
+            .arg(&self.remote_config.borg_repo)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+        let missing = String::from_utf8_lossy(&output.stderr).contains("does not exist");
+        if !(missing && self.remote_config.init) {
+            return Err(Error::subprocess("borg info", &output));
+        }
+
+        self.init_repo().await
+    }
+
+    async fn init_repo(&self) -> Result<()> {
+        let mut cmd = self.borg();
+        cmd.arg("init")
+            .arg(format!("--encryption={}", self.remote_config.encryption));
+        if self.remote_config.append_only {
+            cmd.arg("--append-only");
+        }
+        let output = cmd
+            .arg(&self.remote_config.borg_repo)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("borg init", &output));
+        }
+
+        info!(
+            repo = self.remote_config.borg_repo,
+            encryption = self.remote_config.encryption,
+            "Created borg repository"
+        );
+        Ok(())
+    }
+
+    /// The archive called `name`, or the newest for `latest`. A `repo::` prefix, as the archive
+    /// task logs names with, is ignored.
+    async fn resolve_archive(&self, name: &str) -> Result<String> {
+        if name != "latest" {
+            let name = name.rsplit_once("::").map_or(name, |(_, name)| name);
+            return Ok(name.to_string());
+        }
+
+        self.list_archives()
+            .await?
+            .into_iter()
+            .max_by_key(|archive| archive.start)
+            .map(|archive| archive.name)
+            .ok_or_else(|| {
+                Error::General(format!("{} has no archives", self.remote_config.borg_repo))
+            })
+    }
+
+    /// Every regular file in the archive at `location`, as archived
+    async fn list_files(&self, location: &str) -> Result<Vec<String>> {
+        let output = self
+            .borg()
+            .arg("list")
+            .arg("--json-lines")
+            .arg(location)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("borg list", &output));
+        }
+
+        let mut files = vec![];
+        for line in output.stdout.split(|&byte| byte == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            let item: ArchiveItem = serde_json::from_slice(line)?;
+            if item.kind == "-" {
+                files.push(item.path);
+            }
+        }
+        Ok(files)
+    }
+
+    /// `path` relative to the source path it was archived from. Borg stores paths without their
+    /// leading `/`.
+    fn source_relative<'a>(&self, path: &'a str) -> &'a str {
+        self.remote_config
+            .source_paths
+            .iter()
+            .find_map(|source| {
+                let source = source.to_str()?.trim_matches('/');
+                path.strip_prefix(source)?.strip_prefix('/')
+            })
+            .unwrap_or(path)
+    }
+
+    async fn extract(&self, archive: &str, dest: &Path, select: &RestoreFilter) -> Result<usize> {
+        let archive = self.resolve_archive(archive).await?;
+        let location = format!("{}::{archive}", self.remote_config.borg_repo);
+
+        let files: Vec<_> = self
+            .list_files(&location)
+            .await?
+            .into_iter()
+            .filter(|path| select(self.source_relative(path)))
+            .collect();
+        if files.is_empty() {
+            info!(archive, "Nothing in the archive to restore");
+            return Ok(0);
+        }
+
+        tokio::fs::create_dir_all(dest).await?;
+        for batch in files.chunks(EXTRACT_BATCH) {
+            // borg extracts into the working directory, keeping the archived paths
+            let output = self
+                .borg()
+                .arg("extract")
+                .arg("--show-rc")
+                .arg(&location)
+                .args(batch)
+                .current_dir(dest)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .await?;
+
+            if !output.status.success() {
+                return Err(Error::subprocess("borg extract", &output));
+            }
+        }
+
+        info!(archive, files = files.len(), dest = %dest.display(), "Restored archive");
+        Ok(files.len())
+    }
+
+    /// Prune with `borg prune` and the `keep-*` options, keeping every archive younger than
+    /// the retention period
+    async fn prune_keeping(&self, policy: &RetentionPolicy, keep_args: Vec<String>) -> Result<()> {
+        let keep_within = format!("{}H", (policy.max_age.as_secs() / 3600).max(1));
+        let output = self
+            .borg()
+            .arg("prune")
+            .arg("--list")
+            .arg("--show-rc")
+            .arg(format!("--keep-within={keep_within}"))
+            .args(&keep_args)
+            .arg(format!(
+                "--glob-archives={}",
+                self.remote_config.archive_glob()
+            ))
+            .arg(&self.remote_config.borg_repo)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("borg prune", &output));
         }
+
+        trace!(
+            "Borg prune output: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        info!(keep_within, keep = ?keep_args, "Successfully pruned old archives");
+        Ok(())
     }
 }
 
@@ -46,33 +465,30 @@ impl BorgBackup {
     #[tracing::instrument(skip(self))]
     #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
     async fn archive(&self) -> Result<String> {
+        if self.remote_config.init {
+            self.check_repo().await?;
+        }
+
         let archive_name = format!(
             "{}::{}",
             self.remote_config.borg_repo,
-            Utc::now().format("%Y-%m-%d_%H-%M-%S")
+            self.remote_config.archive_name_at(Utc::now())
         );
 
         // Create archive with borg
-        let mut cmd = Command::new("borg");
+        let mut cmd = self.borg();
         cmd.arg("create")
             .arg("--verbose")
             .arg("--filter=AME")
             .arg("--list")
             .arg("--stats")
             .arg("--show-rc")
-            .arg("--compression=lz4")
+            .arg(format!(
+                "--compression={}",
+                self.remote_config.borg_compression()
+            ))
             .arg(&archive_name)
-            .arg(&self.remote_config.source_path);
-
-        if let Some(ref passphrase) = self.remote_config.borg_passphrase {
-            cmd.env("BORG_PASSPHRASE", passphrase);
-        }
-
-        // Set SSH key if provided
-        if let Some(ref ssh_key) = self.remote_config.ssh_key_path {
-            let ssh_cmd = format!("ssh -i {}", ssh_key.display());
-            cmd.env("BORG_RSH", ssh_cmd);
-        }
+            .args(&self.remote_config.source_paths);
 
         debug!("Creating Archive: {archive_name}");
 
@@ -83,8 +499,7 @@ impl BorgBackup {
             .await?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::Backup(format!("Borg backup failed: {stderr}")));
+            return Err(Error::subprocess("borg create", &output));
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -98,71 +513,351 @@ impl BorgBackup {
         Ok(archive_name)
     }
 
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip(self, policy, clock))]
     #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
-    async fn prune(&self) -> Result<()> {
+    async fn prune(&self, policy: &RetentionPolicy, clock: &dyn Clock) -> Result<()> {
+        let own_policy = self.remote_config.retention_period.map(|retention_period| {
+            RetentionPolicy::new(retention_period, &self.backup_config.retention)
+        });
+        let policy = own_policy.as_ref().unwrap_or(policy);
+
         if self.remote_config.append_only {
             // we don't bother pruning. New archives will have less data and
             // old backups will be cleaned via server-side compaction
             return Ok(());
         }
 
-        info!(
-            "Pruning old backups (retention: {:?} days)",
-            self.backup_config.retention_period
-        );
+        info!("Pruning old archives (retention: {:?})", policy.max_age);
 
-        let mut cmd = Command::new("borg");
-        cmd.arg("prune")
-            .arg("--verbose")
-            .arg("--list")
-            .arg("--show-rc")
-            .arg("--keep-daily")
-            .arg((self.backup_config.retention_period.as_secs() / SECONDS_PER_DAY).to_string())
-            .arg(&self.remote_config.borg_repo);
+        let keep_args = self.remote_config.keep_args();
+        if !keep_args.is_empty() {
+            return self.prune_keeping(policy, keep_args).await;
+        }
 
-        // if self.remote_config.append_only {
-        //     cmd.arg("--append-only");
+        // borg doesn't report archive sizes cheaply, so `max-size` doesn't apply to archives
+        let expired = policy.expired(clock, self.list_archives().await?, |archive| Candidate {
+            names: vec![archive.name.clone()],
+            time: archive
+                .start
+                .and_local_timezone(Local)
+                .earliest()
+                .map_or_else(
+                    || archive.start.and_utc(),
+                    |start| start.with_timezone(&Utc),
+                ),
+            size_bytes: 0,
+            detection_types: vec![],
+            cameras: vec![],
+        });
 
-        if let Some(ref passphrase) = self.remote_config.borg_passphrase {
-            cmd.env("BORG_PASSPHRASE", passphrase);
+        let mut deleted = 0;
+        for archive in &expired {
+            let output = self
+                .borg()
+                .arg("delete")
+                .arg("--show-rc")
+                .arg(format!(
+                    "{}::{}",
+                    self.remote_config.borg_repo, archive.name
+                ))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .await?;
+
+            if output.status.success() {
+                debug!(archive = archive.name, "Deleted archive");
+                deleted += 1;
+            } else {
+                warn!(
+                    archive = archive.name,
+                    stderr = %String::from_utf8_lossy(&output.stderr),
+                    "Failed to delete archive"
+                );
+            }
         }
 
-        // Set SSH key if provided
-        if let Some(ref ssh_key) = self.remote_config.ssh_key_path {
-            let ssh_cmd = format!("ssh -i {}", ssh_key.display());
-            cmd.env("BORG_RSH", ssh_cmd);
+        if deleted < expired.len() {
+            return Err(Error::Backup(format!(
+                "Deleted {deleted} of {} expired archives",
+                expired.len()
+            )));
         }
 
+        info!(deleted, "Successfully pruned old archives");
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn check(&self, verify_data: bool) -> Result<()> {
+        let mut cmd = self.borg();
+        cmd.arg("check").arg("--show-rc");
+        if verify_data {
+            cmd.arg("--verify-data");
+        }
         let output = cmd
+            .arg(&self.remote_config.borg_repo)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
             .await?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::Backup(format!("Borg prune failed: {stderr}")));
+            return Err(Error::subprocess("borg check", &output));
         }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        debug!("Borg prune output: {}", stdout);
+        info!(
+            repo = self.remote_config.borg_repo,
+            verify_data, "Borg repository passed its check"
+        );
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn compact(&self) -> Result<()> {
+        let output = self
+            .borg()
+            .arg("compact")
+            .arg("--show-rc")
+            .arg(&self.remote_config.borg_repo)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("borg compact", &output));
+        }
 
-        info!("Successfully pruned old backups");
+        info!(
+            repo = self.remote_config.borg_repo,
+            "Compacted borg repository"
+        );
         Ok(())
     }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, ErrorCount])]
+    async fn break_lock(&self) -> Result<()> {
+        let output = self
+            .borg()
+            .arg("break-lock")
+            .arg(&self.remote_config.borg_repo)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("borg break-lock", &output));
+        }
+
+        warn!(
+            repo = self.remote_config.borg_repo,
+            "Broke stale borg repository lock"
+        );
+        Ok(())
+    }
+}
+
+/// Whether another borg process on this host has `repo` on its command line. Only Linux is
+/// checked, so elsewhere the answer is always yes and locks are never broken.
+fn borg_running(repo: &str) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(processes) = std::fs::read_dir("/proc") else {
+            return true;
+        };
+        processes.flatten().any(|process| {
+            let Ok(cmdline) = std::fs::read(process.path().join("cmdline")) else {
+                return false;
+            };
+            let args: Vec<_> = cmdline
+                .split(|&byte| byte == 0)
+                .map(String::from_utf8_lossy)
+                .collect();
+            // borg is often run by its interpreter, or through a wrapper script
+            let borg = args.iter().take(3).any(|arg| {
+                arg.rsplit('/')
+                    .next()
+                    .is_some_and(|name| name.starts_with("borg"))
+            });
+            borg && args.iter().any(|arg| arg.contains(repo))
+        })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = repo;
+        true
+    }
 }
 
 #[async_trait]
 impl Archive for BorgBackup {
+    fn name(&self) -> String {
+        format!("borg:{}", self.remote_config.borg_repo)
+    }
+
     async fn archive(&self) -> Result<String> {
-        self.archive().await
+        self.exclusively(self.archive()).await
+    }
+
+    async fn validate(&self) -> Result<()> {
+        self.check_repo().await
+    }
+
+    async fn check(&self, verify_data: bool) -> Result<()> {
+        self.exclusively(self.check(verify_data)).await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.exclusively(self.compact()).await
+    }
+
+    async fn restore(&self, archive: &str, dest: &Path, select: &RestoreFilter) -> Result<usize> {
+        self.extract(archive, dest, select).await
     }
 }
 
 #[async_trait]
 impl Prune for BorgBackup {
-    async fn prune(&self) -> Result<()> {
-        self.prune().await
+    async fn prune(&self, policy: &RetentionPolicy, clock: &dyn Clock) -> Result<()> {
+        self.exclusively(self.prune(policy, clock)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_archive_name() {
+        let mut config: Config = toml::from_str(
+            r#"
+            borg-repo = "user@rsync.net:unifi-protect"
+            append-only = false
+            archive-name = "{hostname}-{nvr}-{date}T{time}"
+            "#,
+        )
+        .unwrap();
+        config.resolve_archive_name("nas", "UNVR/Home").unwrap();
+
+        let time = Utc.with_ymd_and_hms(2025, 8, 4, 3, 0, 0).unwrap();
+        assert_eq!(
+            config.archive_name_at(time),
+            "nas-UNVR-Home-2025-08-04T03-00-00"
+        );
+        assert_eq!(config.archive_glob(), "nas-UNVR-Home-*T*");
+
+        config.archive_name = "{hostname}-{date}".to_string();
+        assert!(config.resolve_archive_name("nas", "UNVR").is_err());
+    }
+
+    #[test]
+    fn test_borg_options() {
+        let config: Config = toml::from_str(
+            r#"
+            borg-repo = "user@rsync.net:unifi-protect"
+            append-only = false
+            compression = "zstd:10"
+            keep-daily = 7
+            keep-monthly = 12
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.borg_compression(), "zstd,10");
+        assert_eq!(
+            config.keep_args(),
+            vec!["--keep-daily=7", "--keep-monthly=12"]
+        );
+        assert!(!config.init);
+
+        // without any keep-* set, pruning goes by the retention period alone
+        let config: Config = toml::from_str(
+            r#"
+            borg-repo = "user@rsync.net:unifi-protect"
+            append-only = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.borg_compression(), default_compression());
+        assert!(config.keep_args().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exclusively() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote_config: Config = toml::from_str(
+            r#"
+            borg-repo = "/nonexistent/unifi-protect"
+            append-only = false
+            break-stale-lock-after = "1h"
+            "#,
+        )
+        .unwrap();
+        let borg = BorgBackup::new(
+            testing::config(&dir, "").archive,
+            remote_config,
+            Arc::new(Metrics::default()),
+        );
+
+        // operations run one at a time
+        let (running, most_running) = (&AtomicUsize::new(0), &AtomicUsize::new(0));
+        let operation = || async move {
+            let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most_running.fetch_max(now_running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok::<_, Error>(())
+        };
+        let (first, second) =
+            tokio::join!(borg.exclusively(operation()), borg.exclusively(operation()));
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(most_running.load(Ordering::SeqCst), 1);
+
+        // failing on the lock starts the clock on it going stale, without breaking it yet, and
+        // anything else stops it
+        let failure = |output: &str| Error::Subprocess {
+            operation: "borg create".to_string(),
+            status: "exit status: 2".to_string(),
+            output: output.to_string(),
+        };
+        let locked = || *borg.locked_since.lock().unwrap();
+        let lock_timeout = format!("{LOCK_TIMEOUT} lock.exclusive (timeout).");
+        assert!(
+            borg.exclusively(async { Err::<(), _>(failure(&lock_timeout)) })
+                .await
+                .is_err()
+        );
+        let since = locked().expect("lock failure tracked");
+        assert!(
+            borg.exclusively(async { Err::<(), _>(failure(&lock_timeout)) })
+                .await
+                .is_err()
+        );
+        assert_eq!(locked(), Some(since));
+        assert!(
+            borg.exclusively(async { Err::<(), _>(failure("Connection closed")) })
+                .await
+                .is_err()
+        );
+        assert_eq!(locked(), None);
+
+        assert!(
+            borg.exclusively(async { Err::<(), _>(failure(&lock_timeout)) })
+                .await
+                .is_err()
+        );
+        assert!(locked().is_some());
+        borg.exclusively(async { Ok(()) }).await.unwrap();
+        assert_eq!(locked(), None);
     }
 }
@@ -0,0 +1,395 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use metered::{ErrorCount, HitCount, ResponseTime, Throughput};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, info};
+
+use crate::{
+    Error, Result, archive,
+    archive::{Archive, RestoreFilter},
+    backup::{Backup, RemoteBackupConfig, RemoteFile, filename::FilenameParser, snapshot},
+    clock::Clock,
+    retention::{Candidate, RetentionPolicy},
+    task::Prune,
+};
+
+/// Extension of each day's bundle
+pub const BUNDLE_EXTENSION: &str = ".tar.zst";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct Config {
+    /// Where bundles are uploaded, configured the same way as a `[[backup.remote]]`
+    pub upload_to: RemoteBackupConfig,
+    /// Paths to bundle backups from, or `source-path` for just one. Unset bundles the path of
+    /// every local backup target, see [`archive_targets`](crate::archive::archive_targets).
+    #[serde(
+        default,
+        alias = "source-path",
+        deserialize_with = "archive::one_or_many"
+    )]
+    pub source_paths: Vec<PathBuf>,
+    /// Directory the bundles go in on the remote
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// zstd level the bundles are compressed with
+    #[serde(default = "default_level")]
+    pub level: i32,
+    /// How long after a day ends it's bundled, leaving time for its last events to be backed up
+    #[serde(default = "default_settle_time", with = "humantime_serde")]
+    pub settle_time: Duration,
+}
+
+fn default_prefix() -> String {
+    "bundles".to_string()
+}
+
+fn default_level() -> i32 {
+    zstd::DEFAULT_COMPRESSION_LEVEL
+}
+
+fn default_settle_time() -> Duration {
+    Duration::from_secs(6 * 60 * 60)
+}
+
+impl Config {
+    /// Where the bundle of `day` goes on the remote
+    fn bundle_path(&self, day: NaiveDate) -> String {
+        format!("{}/{day}{BUNDLE_EXTENSION}", self.prefix)
+    }
+
+    /// The day a file on the remote is the bundle of, if it's one
+    fn bundle_day(&self, path: &str) -> Option<NaiveDate> {
+        let name = path
+            .strip_prefix(&self.prefix)?
+            .strip_prefix('/')?
+            .strip_suffix(BUNDLE_EXTENSION)?;
+        NaiveDate::parse_from_str(name, "%Y-%m-%d").ok()
+    }
+}
+
+/// An archive target which bundles each day's backups into one zstd-compressed tar and uploads it
+/// to a remote, for storage where many small objects cost more than a few large ones.
+pub struct BundleArchive {
+    pub backup_config: archive::Config,
+    pub remote_config: Config,
+    /// `backup.file-structure-format` and those `[[camera]]` blocks set, to tell which day each
+    /// backup belongs to
+    pub file_structure_formats: Vec<String>,
+    /// Built from `upload-to`
+    pub target: Arc<dyn Backup>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl BundleArchive {
+    /// Every backup and sidecar under the source paths by the day its event started, as the file
+    /// and its path in the bundle. A path found under more than one source, as when local targets
+    /// mirror each other, is only taken once.
+    async fn backups_by_day(&self) -> Result<BTreeMap<NaiveDate, Vec<(PathBuf, String)>>> {
+        let formats: Vec<_> = self
+            .file_structure_formats
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let parser = FilenameParser::any_of(&formats);
+        let mut seen = HashSet::new();
+        let mut days: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+        for source in &self.remote_config.source_paths {
+            for (file, path) in walk(source).await? {
+                // a sidecar or snapshot is named after the backup it goes with
+                let backup = path.strip_suffix(".json").unwrap_or(&path);
+                let backup = snapshot::video_path(backup).unwrap_or_else(|| backup.to_string());
+                let Some(start) = parser.parse(&backup).and_then(|parsed| parsed.start_time) else {
+                    continue;
+                };
+                if seen.insert(path.clone()) {
+                    days.entry(start.date_naive())
+                        .or_default()
+                        .push((file, path));
+                }
+            }
+        }
+
+        Ok(days)
+    }
+
+    /// The bundles on the remote, with the day of each
+    async fn list_bundles(&self) -> Result<Vec<(RemoteFile, NaiveDate)>> {
+        Ok(self
+            .target
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|file| {
+                let day = self.remote_config.bundle_day(&file.path)?;
+                Some((file, day))
+            })
+            .collect())
+    }
+
+    async fn extract(&self, archive: &str, dest: &Path, select: &RestoreFilter) -> Result<usize> {
+        let day = match archive {
+            "latest" => self
+                .list_bundles()
+                .await?
+                .into_iter()
+                .map(|(_, day)| day)
+                .max()
+                .ok_or_else(|| Error::General(format!("{} has no bundles", self.target.name())))?,
+            name => {
+                let name = name.rsplit('/').next().unwrap_or(name);
+                let name = name.strip_suffix(BUNDLE_EXTENSION).unwrap_or(name);
+                NaiveDate::parse_from_str(name, "%Y-%m-%d").map_err(|_| {
+                    Error::General(format!(
+                        "Bundles are named by day, as YYYY-MM-DD, not {name}"
+                    ))
+                })?
+            }
+        };
+        let path = self.remote_config.bundle_path(day);
+        // downloaded decompressed, to disk rather than memory, as it's read through twice
+        let bundle = tempfile::NamedTempFile::new()?;
+        self.target.download_to(&path, bundle.path()).await?;
+        let bundle = Arc::new(bundle);
+
+        let entries = {
+            let bundle = bundle.clone();
+            blocking(move || {
+                let mut tar = tar::Archive::new(std::fs::File::open(bundle.path())?);
+                let mut entries = vec![];
+                for entry in tar.entries()? {
+                    entries.push(entry?.path()?.to_string_lossy().into_owned());
+                }
+                Ok(entries)
+            })
+            .await?
+        };
+        let selected: HashSet<_> = entries.into_iter().filter(|path| select(path)).collect();
+        if selected.is_empty() {
+            info!(bundle = path, "Nothing in the bundle to restore");
+            return Ok(0);
+        }
+
+        let restored = selected.len();
+        let dest = dest.to_path_buf();
+        fs::create_dir_all(&dest).await?;
+        blocking(move || {
+            let mut tar = tar::Archive::new(std::fs::File::open(bundle.path())?);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                let path = entry.path()?.to_string_lossy().into_owned();
+                if selected.contains(&path) {
+                    entry.unpack_in(&dest)?;
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+        info!(bundle = path, files = restored, "Restored bundle");
+        Ok(restored)
+    }
+}
+
+#[metered::metered(registry = Metrics, visibility = pub)]
+impl BundleArchive {
+    /// Bundle and upload every day which has settled and isn't on the remote yet. Days already
+    /// past the archive retention period are left alone, or they'd be bundled again after each
+    /// prune.
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn archive(&self) -> Result<String> {
+        let now = Utc::now();
+        let age = |duration| chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::MAX);
+        let settled = now.checked_sub_signed(age(self.remote_config.settle_time));
+        let expired = now.checked_sub_signed(age(self.backup_config.retention_period));
+
+        let existing: HashSet<_> = self
+            .list_bundles()
+            .await?
+            .into_iter()
+            .map(|(_, day)| day)
+            .collect();
+
+        let mut bundled = vec![];
+        for (day, files) in self.backups_by_day().await? {
+            if settled.is_none_or(|settled| day >= settled.date_naive()) {
+                break;
+            }
+            if expired.is_some_and(|expired| day < expired.date_naive()) || existing.contains(&day)
+            {
+                continue;
+            }
+
+            let path = self.remote_config.bundle_path(day);
+            let bundle = tempfile::NamedTempFile::new()?;
+            let count = files.len();
+            let level = self.remote_config.level;
+            let dest = bundle.path().to_path_buf();
+            blocking(move || write_bundle(&dest, &files, level)).await?;
+
+            self.target.upload_from(&path, bundle.path()).await?;
+            info!(bundle = path, files = count, "Uploaded bundle");
+            bundled.push(path);
+        }
+
+        if bundled.is_empty() {
+            debug!("No days left to bundle");
+        }
+        Ok(bundled.join(", "))
+    }
+
+    #[tracing::instrument(skip(self, policy, clock))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn prune(&self, policy: &RetentionPolicy, clock: &dyn Clock) -> Result<()> {
+        let expired = policy.expired(clock, self.list_bundles().await?, |(file, day)| Candidate {
+            names: vec![file.path.clone()],
+            time: day.and_time(Default::default()).and_utc(),
+            size_bytes: file.size_bytes,
+            detection_types: vec![],
+            cameras: vec![],
+        });
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        let paths: Vec<_> = expired.into_iter().map(|(file, _)| file.path).collect();
+        self.target.delete_many(&paths).await?;
+        info!(deleted = paths.len(), "Pruned old bundles");
+        Ok(())
+    }
+}
+
+/// Every file under `root`, with its path relative to `root`
+async fn walk(root: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() {
+                let Ok(relative) = path.strip_prefix(root) else {
+                    continue;
+                };
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                files.push((path, relative));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Write `files`, each under its path, to a tar at `dest` compressed with zstd at `level`
+fn write_bundle(dest: &Path, files: &[(PathBuf, String)], level: i32) -> Result<()> {
+    let encoder = zstd::stream::write::Encoder::new(std::fs::File::create(dest)?, level)?;
+    let mut tar = tar::Builder::new(encoder);
+    for (file, path) in files {
+        tar.append_path_with_name(file, path)?;
+    }
+    tar.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Run `f` off the async runtime
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Error::Backup(format!("Bundle task failed: {e}")))?
+}
+
+#[async_trait]
+impl Archive for BundleArchive {
+    fn name(&self) -> String {
+        format!("bundle:{}", self.target.name())
+    }
+
+    async fn archive(&self) -> Result<String> {
+        self.archive().await
+    }
+
+    async fn validate(&self) -> Result<()> {
+        self.target.validate().await
+    }
+
+    async fn restore(&self, archive: &str, dest: &Path, select: &RestoreFilter) -> Result<usize> {
+        self.extract(archive, dest, select).await
+    }
+}
+
+#[async_trait]
+impl Prune for BundleArchive {
+    async fn prune(&self, policy: &RetentionPolicy, clock: &dyn Clock) -> Result<()> {
+        self.prune(policy, clock).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TestContext};
+
+    #[test]
+    fn test_bundle_path() {
+        let config: Config = toml::from_str(
+            r#"
+            upload-to = { local = { path-buf = "/srv/cold" } }
+            "#,
+        )
+        .unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2025, 8, 4).unwrap();
+        let path = config.bundle_path(day);
+        assert_eq!(path, "bundles/2025-08-04.tar.zst");
+        assert_eq!(config.bundle_day(&path), Some(day));
+        assert_eq!(config.bundle_day("bundles/2025-08-04.mp4"), None);
+        assert_eq!(config.bundle_day("Driveway/2025-08-04.tar.zst"), None);
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let test = TestContext::new("").await;
+        let target = test.context.backup_targets.load()[0].clone();
+        let source = test.dir.path().join("source");
+        let video = "Front Door/2025-08-04/12-00-00_motion.mp4";
+        std::fs::create_dir_all(source.join("Front Door/2025-08-04")).unwrap();
+        std::fs::write(source.join(video), b"video").unwrap();
+        std::fs::create_dir_all(test.backup_dir().join("bundles")).unwrap();
+        write_bundle(
+            &test.backup_dir().join("bundles/2025-08-04.tar.zst"),
+            &[(source.join(video), video.to_string())],
+            zstd::DEFAULT_COMPRESSION_LEVEL,
+        )
+        .unwrap();
+
+        let bundles = BundleArchive {
+            backup_config: testing::config(&test.dir, "").archive,
+            remote_config: toml::from_str(&format!(
+                "upload-to = {{ local = {{ path-buf = {:?} }} }}",
+                test.backup_dir()
+            ))
+            .unwrap(),
+            file_structure_formats: vec![],
+            target,
+            metrics: Arc::default(),
+        };
+        let dest = test.dir.path().join("restored");
+        let restored = bundles.extract("latest", &dest, &|_| true).await.unwrap();
+        assert_eq!(restored, 1);
+        assert_eq!(std::fs::read(dest.join(video)).unwrap(), b"video");
+    }
+}
@@ -1,15 +1,58 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
-use crate::{Result, metrics::Metrics, task::Prune};
+use crate::{
+    Error, Result,
+    backup::{RemoteBackupConfig, TargetValidation, backup_target},
+    metrics::Metrics,
+    retention::{RetentionConfig, RetentionPolicy},
+    task::Prune,
+};
 
 pub mod borg;
+pub mod bundle;
+
+/// Chooses which files to restore, given each one's path as it was on the backup target
+pub type RestoreFilter = dyn Fn(&str) -> bool + Send + Sync;
 
 #[async_trait]
 pub trait Archive: Prune + Send + Sync {
+    /// Stable identifier for this target, recorded alongside failures in the database
+    fn name(&self) -> String;
     async fn archive(&self) -> Result<String>;
+    /// A cheap check, run at startup, that the target is configured correctly and reachable
+    async fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Check the consistency of the stored archives, also reading back every chunk of data with
+    /// `verify_data`
+    async fn check(&self, _verify_data: bool) -> Result<()> {
+        Ok(())
+    }
+    /// Free the space left behind by pruned archives
+    async fn compact(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Restore the files of `archive`, or the newest for `latest`, that `select` picks into
+    /// `dest`. Returns how many were restored.
+    async fn restore(&self, archive: &str, dest: &Path, select: &RestoreFilter) -> Result<usize>;
+}
+
+/// When an archive run counts as successful, with several archive targets mirroring each other
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SuccessPolicy {
+    /// Every target has to archive
+    #[default]
+    All,
+    /// One target archiving is enough; the others' failures are only recorded
+    Any,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,34 +60,209 @@ pub trait Archive: Prune + Send + Sync {
 pub struct Config {
     #[serde(with = "humantime_serde")]
     pub archive_interval: Duration,
+    /// Runs are skipped when nothing was backed up since the last. With this set, an archive is
+    /// still made once this long has passed since the last, new backups or not.
+    #[serde(default, with = "humantime_serde")]
+    pub max_archive_interval: Option<Duration>,
     #[serde(with = "humantime_serde")]
     pub retention_period: Duration,
+    /// Count and hold limits on top of `retention_period`
+    #[serde(default)]
+    pub retention: RetentionConfig,
     #[serde(with = "humantime_serde")]
     pub purge_interval: Duration,
+    /// Snapshot the database on every archive run and upload it to each backup target
+    #[serde(default)]
+    pub backup_database: bool,
+    /// How many database snapshots each backup target keeps. Older ones are deleted once a new
+    /// snapshot is uploaded.
+    #[serde(default = "default_database_snapshots")]
+    pub database_snapshots: usize,
+    /// Log when archives would be created without creating them or uploading snapshots
+    #[serde(default)]
+    pub dry_run: bool,
+    /// What to do about targets that fail their startup check
+    #[serde(default)]
+    pub validate_targets: TargetValidation,
+    /// Whether a run fails when any archive target does, or only when all of them do
+    #[serde(default)]
+    pub success_policy: SuccessPolicy,
+    /// How often to check the consistency of each archive target. Unset disables checks.
+    #[serde(default, with = "humantime_serde")]
+    pub check_interval: Option<Duration>,
+    /// How often a check also reads back every chunk of data, which is far slower. Unset never
+    /// verifies data.
+    #[serde(default, with = "humantime_serde")]
+    pub verify_data_interval: Option<Duration>,
+    /// How often to compact each archive target. Unset disables compaction.
+    #[serde(default, with = "humantime_serde")]
+    pub compact_interval: Option<Duration>,
     pub remote: Vec<RemoteArchiveConfig>,
 }
 
+fn default_database_snapshots() -> usize {
+    7
+}
+
+impl Config {
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy::new(self.retention_period, &self.retention)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
 pub enum RemoteArchiveConfig {
     Borg(borg::Config),
+    Bundle(bundle::Config),
 }
 
+/// The configured archive targets. Targets without a `source-path` archive every local backup
+/// target's path, so there has to be at least one. `nvr_name` fills in `{nvr}` in archive
+/// names.
 pub fn archive_targets(
     config: &crate::config::Config,
     metrics: &Arc<Metrics>,
-) -> Vec<Arc<dyn Archive>> {
+    nvr_name: &str,
+) -> Result<Vec<Arc<dyn Archive>>> {
+    let local_paths: Vec<_> = config
+        .backup
+        .remote
+        .iter()
+        .filter_map(|remote| match remote {
+            RemoteBackupConfig::Local(local) => Some(local.path_buf.clone()),
+            _ => None,
+        })
+        .collect();
+    let hostname = hostname();
     let mut targets = vec![];
 
     for remote in &config.archive.remote {
         targets.push(match remote {
-            RemoteArchiveConfig::Borg(remote) => Arc::new(borg::BorgBackup {
-                backup_config: config.archive.clone(),
-                remote_config: remote.clone(),
-                metrics: metrics.borg_archive.clone(),
-            }) as Arc<dyn Archive>,
+            RemoteArchiveConfig::Borg(remote) => {
+                let mut remote = remote.clone();
+                if remote.source_paths.is_empty() {
+                    if local_paths.is_empty() {
+                        return Err(Error::General(format!(
+                            "Borg archive {} has no source-path, and there's no local backup \
+                             target to archive instead",
+                            remote.borg_repo
+                        )));
+                    }
+                    remote.source_paths = local_paths.clone();
+                }
+                remote.resolve_archive_name(&hostname, nvr_name)?;
+
+                Arc::new(borg::BorgBackup::new(
+                    config.archive.clone(),
+                    remote,
+                    metrics.borg_archive.clone(),
+                )) as Arc<dyn Archive>
+            }
+            RemoteArchiveConfig::Bundle(remote) => {
+                let mut remote = remote.clone();
+                if remote.source_paths.is_empty() {
+                    if local_paths.is_empty() {
+                        return Err(Error::General(
+                            "Bundle archive has no source-path, and there's no local backup \
+                             target to bundle instead"
+                                .to_string(),
+                        ));
+                    }
+                    remote.source_paths = local_paths.clone();
+                }
+
+                Arc::new(bundle::BundleArchive {
+                    backup_config: config.archive.clone(),
+                    file_structure_formats: config
+                        .backup
+                        .file_structure_formats()
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                    target: backup_target(&config.backup, &remote.upload_to, metrics),
+                    remote_config: remote,
+                    metrics: metrics.bundle_archive.clone(),
+                }) as Arc<dyn Archive>
+            }
         });
     }
 
-    targets
+    Ok(targets)
+}
+
+/// This machine's hostname, for `{hostname}` in archive names
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        rustix::system::uname()
+            .nodename()
+            .to_string_lossy()
+            .into_owned()
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_string())
+    }
+}
+
+/// A path, or a list of them
+pub(crate) fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<PathBuf>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(path) => vec![path],
+        OneOrMany::Many(paths) => paths,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        convert::protect_event_from_database_event,
+        testing::{self, TestContext},
+    };
+
+    #[tokio::test]
+    async fn test_archive_targets_default_to_local_targets() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let cold = test.dir.path().join("cold");
+        let mut config = testing::config(&test.dir, "");
+        config.archive.remote = vec![
+            toml::from_str(&format!(
+                "bundle = {{ upload-to = {{ local = {{ path-buf = {cold:?} }} }} }}"
+            ))
+            .unwrap(),
+        ];
+
+        // a backup on the local target from a day that's settled
+        let start = (Utc::now() - chrono::Duration::days(2)).timestamp_millis();
+        let event = protect_event_from_database_event(
+            testing::event("event", start, start + 10_000),
+            &context.protect_bootstrap.load(),
+        );
+        let target = context.backup_targets.load()[0].clone();
+        target.backup(&event, b"video").await.unwrap();
+
+        let archives = archive_targets(&config, &context.metrics, "UNVR").unwrap();
+        let bundled = archives[0].archive().await.unwrap();
+        assert!(bundled.ends_with(bundle::BUNDLE_EXTENSION));
+        assert!(cold.join(&bundled).exists());
+
+        // nothing to archive without a local target or a source path
+        config.backup.remote.clear();
+        assert!(archive_targets(&config, &context.metrics, "UNVR").is_err());
+    }
 }
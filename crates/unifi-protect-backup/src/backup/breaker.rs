@@ -0,0 +1,186 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock, atomic::AtomicU64},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+
+use crate::clock::{Clock, system_clock};
+
+#[derive(Debug, Default, Serialize)]
+pub struct CircuitBreakerMetrics {
+    /// Targets whose circuit is open or being probed
+    pub open: AtomicU64,
+    /// Times a circuit opened
+    pub trips: AtomicU64,
+    /// Uploads let through an open circuit to check whether its target has recovered
+    pub probes: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Uploads go to the target as usual
+    Closed,
+    /// Uploads skip the target until `probe_at`
+    Open { probe_at: DateTime<Utc> },
+    /// One upload is checking whether the target has recovered, the rest still skip it
+    Probing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    #[serde(flatten)]
+    pub state: BreakerState,
+    /// Uploads to the target which failed since the last that succeeded
+    pub consecutive_failures: u32,
+    pub last_transition: DateTime<Utc>,
+}
+
+/// Whether an upload should go to a target, see [`CircuitBreakers::allow`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Allowed,
+    /// The circuit is open but due a probe, which this upload is
+    Probe,
+    Rejected,
+}
+
+/// A circuit breaker per backup target, keeping uploads away from a target that keeps failing
+/// (e.g. a cloud provider's outage) while the others carry on.
+pub struct CircuitBreakers {
+    inner: RwLock<BTreeMap<String, BreakerStatus>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(system_clock())
+    }
+}
+
+impl CircuitBreakers {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: RwLock::new(BTreeMap::new()),
+            clock,
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, BreakerStatus> {
+        self.inner.read().expect("breaker lock poisoned").clone()
+    }
+
+    /// Whether an upload may go to `target` now. Once an open circuit's probe is due, the first
+    /// caller gets to probe and everyone else is rejected until the probe's outcome is known.
+    pub fn allow(&self, target: &str) -> Admission {
+        let mut inner = self.inner.write().expect("breaker lock poisoned");
+        let Some(status) = inner.get_mut(target) else {
+            return Admission::Allowed;
+        };
+
+        match status.state {
+            BreakerState::Closed => Admission::Allowed,
+            BreakerState::Open { probe_at } if probe_at <= self.clock.now() => {
+                status.state = BreakerState::Probing;
+                status.last_transition = self.clock.now();
+                Admission::Probe
+            }
+            BreakerState::Open { .. } | BreakerState::Probing => Admission::Rejected,
+        }
+    }
+
+    /// Record a successful upload, closing the circuit. Returns whether it was open.
+    pub fn succeeded(&self, target: &str) -> bool {
+        let mut inner = self.inner.write().expect("breaker lock poisoned");
+        let Some(status) = inner.remove(target) else {
+            return false;
+        };
+        status.state != BreakerState::Closed
+    }
+
+    /// Record a failed upload, opening the circuit for `probe_interval` once `threshold` have
+    /// failed in a row; a failed probe opens it again. Returns the failures in a row if this
+    /// opened a closed circuit.
+    pub fn failed(&self, target: &str, threshold: u32, probe_interval: Duration) -> Option<u32> {
+        if threshold == 0 {
+            return None;
+        }
+
+        let now = self.clock.now();
+        let mut inner = self.inner.write().expect("breaker lock poisoned");
+        let status = inner.entry(target.to_string()).or_insert(BreakerStatus {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            last_transition: now,
+        });
+        status.consecutive_failures += 1;
+        if status.consecutive_failures < threshold {
+            return None;
+        }
+
+        let was_closed = status.state == BreakerState::Closed;
+        status.state = BreakerState::Open {
+            probe_at: self.clock.after(probe_interval),
+        };
+        status.last_transition = now;
+        was_closed.then_some(status.consecutive_failures)
+    }
+
+    /// Let the next upload probe again wherever a probe ended without an outcome, e.g. because
+    /// the export failed to download.
+    pub fn reopen_unfinished_probes(&self) {
+        let now = self.clock.now();
+        let mut inner = self.inner.write().expect("breaker lock poisoned");
+        for status in inner.values_mut() {
+            if status.state == BreakerState::Probing {
+                status.state = BreakerState::Open { probe_at: now };
+                status.last_transition = now;
+            }
+        }
+    }
+}
+
+impl Serialize for CircuitBreakers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let start = Utc.with_ymd_and_hms(2025, 8, 4, 12, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let breakers = CircuitBreakers::new(clock.clone());
+        let interval = Duration::from_secs(600);
+
+        assert_eq!(breakers.failed("s3", 3, interval), None);
+        assert_eq!(breakers.failed("s3", 3, interval), None);
+        assert_eq!(breakers.failed("s3", 3, interval), Some(3));
+        assert_eq!(breakers.allow("s3"), Admission::Rejected);
+        assert_eq!(breakers.allow("local"), Admission::Allowed);
+
+        clock.advance(interval);
+        assert_eq!(breakers.allow("s3"), Admission::Probe);
+        assert_eq!(breakers.allow("s3"), Admission::Rejected);
+
+        // a failed probe opens the circuit again without counting as a new trip
+        assert_eq!(breakers.failed("s3", 3, interval), None);
+        assert_eq!(breakers.allow("s3"), Admission::Rejected);
+
+        clock.advance(interval);
+        assert_eq!(breakers.allow("s3"), Admission::Probe);
+        assert!(breakers.succeeded("s3"));
+        assert_eq!(breakers.allow("s3"), Admission::Allowed);
+        assert!(!breakers.succeeded("s3"));
+    }
+}
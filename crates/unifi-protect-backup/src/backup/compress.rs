@@ -0,0 +1,176 @@
+use std::{fmt::Display, io::Write, path::PathBuf};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{Error, Result};
+
+/// Extension given to files stored zstd-compressed, which is also how they're recognised when
+/// read back, so files stored before compression was turned on still read as they are.
+pub const ZSTD_EXTENSION: &str = ".zst";
+
+/// A target's `compress` option: `zstd`, or `zstd:LEVEL` for a zstd level up to 22
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Compression {
+    Zstd { level: i32 },
+}
+
+impl Compression {
+    /// Where a backup with `filename` is stored when compressed
+    pub fn filename(&self, filename: &str) -> String {
+        match self {
+            Self::Zstd { .. } => format!("{filename}{ZSTD_EXTENSION}"),
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Zstd { level } => Ok(zstd::bulk::compress(data, *level)?),
+        }
+    }
+
+    /// Compress `video` as it arrives, off the async runtime. The returned handle fails if
+    /// compression did, in which case the stream ended early; check it before keeping the
+    /// upload.
+    pub fn compress_stream(
+        &self,
+        mut video: mpsc::Receiver<Bytes>,
+    ) -> (mpsc::Receiver<Bytes>, JoinHandle<Result<()>>) {
+        let Self::Zstd { level } = *self;
+        let (sender, receiver) = mpsc::channel(1);
+
+        let handle = tokio::task::spawn_blocking(move || {
+            let mut encoder = zstd::stream::write::Encoder::new(vec![], level)?;
+            while let Some(chunk) = video.blocking_recv() {
+                encoder.write_all(&chunk)?;
+                let compressed = std::mem::take(encoder.get_mut());
+                // the target hanging up is its own error to report
+                if !compressed.is_empty() && sender.blocking_send(compressed.into()).is_err() {
+                    return Ok(());
+                }
+            }
+            sender.blocking_send(encoder.finish()?.into()).ok();
+            Ok(())
+        });
+
+        (receiver, handle)
+    }
+}
+
+/// Whether the stored file at `path` is compressed, going by its extension
+pub fn is_compressed(path: &str) -> bool {
+    path.ends_with(ZSTD_EXTENSION)
+}
+
+/// The original contents of a stored file, decompressing it if [`is_compressed`]
+pub fn decompress(path: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_compressed(path) {
+        return Ok(data);
+    }
+    Ok(zstd::stream::decode_all(data.as_slice())?)
+}
+
+/// [`decompress`] the stored file at `path`, copied locally to `source`, into `dest` a chunk at a
+/// time, off the async runtime
+pub async fn decompress_file(path: &str, source: PathBuf, dest: PathBuf) -> Result<()> {
+    let compressed = is_compressed(path);
+    tokio::task::spawn_blocking(move || {
+        let mut reader = std::fs::File::open(source)?;
+        let mut writer = std::fs::File::create(dest)?;
+        if compressed {
+            zstd::stream::copy_decode(reader, writer)?;
+        } else {
+            std::io::copy(&mut reader, &mut writer)?;
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| Error::Backup(format!("Decompression task failed: {e}")))?
+}
+
+/// Await [`Compression::compress_stream`]'s handle, if there is one
+pub async fn finished(handle: Option<JoinHandle<Result<()>>>) -> Result<()> {
+    match handle {
+        Some(handle) => handle
+            .await
+            .map_err(|e| Error::Backup(format!("Compression task failed: {e}")))?,
+        None => Ok(()),
+    }
+}
+
+impl TryFrom<String> for Compression {
+    type Error = Error;
+
+    fn try_from(spec: String) -> Result<Self> {
+        let invalid = || {
+            Error::General(format!(
+                "Invalid compression {spec:?}: expected zstd[:LEVEL]"
+            ))
+        };
+
+        let (algorithm, level) = match spec.split_once(':') {
+            Some((algorithm, level)) => (algorithm, Some(level)),
+            None => (spec.as_str(), None),
+        };
+        if !algorithm.eq_ignore_ascii_case("zstd") {
+            return Err(invalid());
+        }
+
+        let level = match level {
+            Some(level) => level.parse().map_err(|_| invalid())?,
+            None => zstd::DEFAULT_COMPRESSION_LEVEL,
+        };
+        if !zstd::compression_level_range().contains(&level) {
+            return Err(invalid());
+        }
+
+        Ok(Self::Zstd { level })
+    }
+}
+
+impl From<Compression> for String {
+    fn from(compression: Compression) -> Self {
+        compression.to_string()
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Zstd { level } => write!(f, "zstd:{level}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(
+            Compression::try_from("zstd:19".to_string()).unwrap(),
+            Compression::Zstd { level: 19 }
+        );
+        assert_eq!(
+            Compression::try_from("zstd".to_string()).unwrap(),
+            Compression::Zstd {
+                level: zstd::DEFAULT_COMPRESSION_LEVEL
+            }
+        );
+        assert!(Compression::try_from("zstd:99".to_string()).is_err());
+        assert!(Compression::try_from("gzip".to_string()).is_err());
+
+        let compression = Compression::Zstd { level: 3 };
+        let path = compression.filename("2025/08/04/driveway.mp4");
+        assert_eq!(path, "2025/08/04/driveway.mp4.zst");
+
+        let data = b"ftypisom".repeat(1024);
+        let compressed = compression.compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&path, compressed).unwrap(), data);
+        assert_eq!(decompress("driveway.mp4", data.clone()).unwrap(), data);
+    }
+}
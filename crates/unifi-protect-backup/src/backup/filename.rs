@@ -0,0 +1,419 @@
+use chrono::{
+    DateTime, NaiveDate, NaiveTime, Utc,
+    format::{Item, StrftimeItems},
+};
+use regex::Regex;
+use unifi_protect_client::events::{EventType, SmartDetectType};
+
+use crate::{Error, Result, backup::compress::ZSTD_EXTENSION};
+
+/// What can be recovered about an event from a path written with a `file-structure-format` by
+/// `ProtectEvent::format_filename`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedFilename {
+    pub camera_id: Option<String>,
+    pub camera_name: Option<String>,
+    /// Only known when the format has both `{date}` and `{time}`, or the strftime specifiers
+    /// `%Y`, `%m`, `%d`, `%H` and `%M`
+    pub start_time: Option<DateTime<Utc>>,
+    /// Only known when the format has `{date}` and `{end_time}` and the event had finished
+    pub end_time: Option<DateTime<Utc>>,
+    pub detection_type: Option<String>,
+    pub event_id: Option<String>,
+    pub part: Option<u32>,
+}
+
+impl ParsedFilename {
+    /// The event type and smart detection types behind `{detection_type}`
+    pub fn detection(&self) -> Option<(EventType, Vec<SmartDetectType>)> {
+        parse_detection_type(self.detection_type.as_deref()?)
+    }
+}
+
+const PLACEHOLDERS: [(&str, &str); 14] = [
+    ("camera_name", r"[^/]+?"),
+    ("camera_id", r"[^/]+?"),
+    ("camera_mac", r"[^/]+?"),
+    ("nvr_name", r"[^/]+?"),
+    ("date", r"\d{4}-\d{2}-\d{2}"),
+    ("time", r"\d{2}-\d{2}-\d{2}"),
+    ("end_time", r"\d{2}-\d{2}-\d{2}|ongoing"),
+    ("duration", r"\d+|ongoing"),
+    ("event_type", r"[^/]+?"),
+    ("detection_type", r"[^/]+?"),
+    ("event_id", r"[^/]+?"),
+    ("part", r"\d*"),
+    ("collision", r"\d*"),
+    ("ext", r"[^/]+?"),
+];
+
+/// strftime specifiers the start time is read back from, by the letter after the `%`. Any other
+/// specifier matches without being read.
+const STRFTIME_FIELDS: [(char, &str); 6] = [
+    ('Y', "%Y"),
+    ('m', "%m"),
+    ('d', "%d"),
+    ('H', "%H"),
+    ('M', "%M"),
+    ('S', "%S"),
+];
+
+/// Check a `file-structure-format` only has known `{placeholders}` and valid strftime
+/// specifiers, so a typo fails at startup rather than naming directories after itself
+pub fn validate_format(format: &str) -> Result<()> {
+    let placeholder = Regex::new(r"\{([^{}/]*)\}").expect("placeholder regex is valid");
+    for captures in placeholder.captures_iter(format) {
+        let name = &captures[1];
+        if !PLACEHOLDERS.iter().any(|(known, _)| *known == name) {
+            let known: Vec<_> = PLACEHOLDERS
+                .iter()
+                .map(|(known, _)| format!("{{{known}}}"))
+                .collect();
+            return Err(Error::General(format!(
+                "Unknown placeholder {{{name}}} in file-structure-format {format:?}, expected one \
+                 of {}",
+                known.join(", ")
+            )));
+        }
+    }
+
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(Error::General(format!(
+            "Invalid strftime specifier in file-structure-format {format:?}"
+        )));
+    }
+    Ok(())
+}
+
+/// Matches paths written with one `file-structure-format`, or any of several.
+pub struct FilenameParser {
+    patterns: Vec<Pattern>,
+}
+
+struct Pattern {
+    regex: Regex,
+    /// Placeholder behind each capture group, in group order
+    groups: Vec<&'static str>,
+}
+
+impl FilenameParser {
+    pub fn new(format: &str) -> Self {
+        Self::any_of(&[format])
+    }
+
+    /// Matches paths written with any of `formats`, trying them in order, e.g. a target's own
+    /// and those `[[camera]]` blocks set
+    pub fn any_of(formats: &[&str]) -> Self {
+        let mut patterns = vec![];
+        for format in formats {
+            // parts of a long event get a suffix when the format doesn't place `{part}` itself.
+            // Try that first, or the suffix would be taken as part of the placeholder before it.
+            let mut variants = vec![];
+            if !format.contains("{part}") {
+                variants.push(with_suffix(format, "_part{part}"));
+            }
+            variants.push(format.to_string());
+            // as do events whose filename another event's backup already had, last as they're
+            // rare and the suffix could as well be part of a name
+            if !format.contains("{collision}") {
+                for variant in variants.clone() {
+                    variants.push(with_suffix(&variant, "_{collision}"));
+                }
+            }
+            patterns.extend(variants.iter().map(|variant| Pattern::new(variant)));
+        }
+
+        Self { patterns }
+    }
+
+    pub fn parse(&self, path: &str) -> Option<ParsedFilename> {
+        // a compressed backup is the path it would have had, plus the compression's extension
+        let path = path.strip_suffix(ZSTD_EXTENSION).unwrap_or(path);
+        self.patterns.iter().find_map(|pattern| pattern.parse(path))
+    }
+}
+
+impl Pattern {
+    fn new(format: &str) -> Self {
+        let mut regex = String::from("^");
+        let mut groups = vec![];
+        let mut rest = format;
+
+        while !rest.is_empty() {
+            let placeholder = PLACEHOLDERS.iter().find_map(|(name, pattern)| {
+                rest.strip_prefix(&format!("{{{name}}}"))
+                    .map(|after| (*name, *pattern, after))
+            });
+
+            match placeholder {
+                Some((name, pattern, after)) => {
+                    regex.push_str(&format!("(?P<g{}>{pattern})", groups.len()));
+                    groups.push(name);
+                    rest = after;
+                }
+                None if rest.starts_with("%%") => {
+                    regex.push('%');
+                    rest = &rest[2..];
+                }
+                None if rest.starts_with('%') => {
+                    // padding modifiers, e.g. `%-d`, change the width but not the value
+                    let padded = !rest[1..].starts_with(['-', '_', '0']);
+                    let spec = rest[1..].trim_start_matches(['-', '_', '0']);
+                    let letter = spec.chars().next().unwrap_or_default();
+                    let field = STRFTIME_FIELDS.iter().find(|(field, _)| *field == letter);
+                    match field {
+                        Some((_, name)) => {
+                            let pattern = match (letter, padded) {
+                                ('Y', _) => r"\d{4}",
+                                (_, true) => r"\d{2}",
+                                (_, false) => r" ?\d{1,2}",
+                            };
+                            regex.push_str(&format!("(?P<g{}>{pattern})", groups.len()));
+                            groups.push(name);
+                        }
+                        None => regex.push_str(r"[^/]*?"),
+                    }
+                    rest = spec.get(letter.len_utf8()..).unwrap_or_default();
+                }
+                None => {
+                    let literal = rest.chars().next().unwrap_or_default();
+                    regex.push_str(&regex::escape(&literal.to_string()));
+                    rest = &rest[literal.len_utf8()..];
+                }
+            }
+        }
+        regex.push('$');
+
+        Self {
+            regex: Regex::new(&regex).expect("escaped format is a valid regex"),
+            groups,
+        }
+    }
+
+    fn parse(&self, path: &str) -> Option<ParsedFilename> {
+        let captures = self.regex.captures(path)?;
+
+        // a placeholder used more than once must have the same value everywhere
+        let mut values: Vec<(&str, &str)> = vec![];
+        for (index, name) in self.groups.iter().copied().enumerate() {
+            let value = captures.name(&format!("g{index}"))?.as_str();
+            match values.iter().find(|(seen, _)| *seen == name) {
+                Some((_, seen_value)) if *seen_value != value => return None,
+                Some(_) => {}
+                None => values.push((name, value)),
+            }
+        }
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(seen, _)| *seen == name)
+                .map(|(_, value)| *value)
+        };
+
+        let field = |name| value(name).and_then(|value| value.trim().parse::<u32>().ok());
+        let date = value("date")
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+            .or_else(|| NaiveDate::from_ymd_opt(field("%Y")? as i32, field("%m")?, field("%d")?));
+        let time =
+            |name| value(name).and_then(|time| NaiveTime::parse_from_str(time, "%H-%M-%S").ok());
+        let start = time("time").or_else(|| {
+            NaiveTime::from_hms_opt(field("%H")?, field("%M")?, field("%S").unwrap_or(0))
+        });
+        let start_time = date
+            .zip(start)
+            .map(|(date, time)| date.and_time(time).and_utc());
+        let end_time = date.zip(time("end_time")).map(|(date, end)| {
+            let end_time = date.and_time(end).and_utc();
+            // events spanning midnight end on the day after the one in the path
+            match start_time {
+                Some(start_time) if end_time < start_time => end_time + chrono::Duration::days(1),
+                _ => end_time,
+            }
+        });
+
+        Some(ParsedFilename {
+            camera_id: value("camera_id").map(str::to_string),
+            camera_name: value("camera_name").map(str::to_string),
+            start_time,
+            end_time,
+            detection_type: value("detection_type").map(str::to_string),
+            event_id: value("event_id").map(str::to_string),
+            part: value("part").and_then(|part| part.parse().ok()),
+        })
+    }
+}
+
+/// `format` with `suffix` before its extension, as `ProtectEvent::format_filename` adds it
+fn with_suffix(format: &str, suffix: &str) -> String {
+    match format.rsplit_once('.') {
+        Some((stem, extension)) => format!("{stem}{suffix}.{extension}"),
+        None => format!("{format}{suffix}"),
+    }
+}
+
+/// The inverse of `ProtectEvent::format_detection_type`
+fn parse_detection_type(detection_type: &str) -> Option<(EventType, Vec<SmartDetectType>)> {
+    match detection_type {
+        "motion" => return Some((EventType::Motion, vec![])),
+        "ring" => return Some((EventType::Ring, vec![])),
+        "line" => return Some((EventType::Line, vec![])),
+        "smart_detect" => return Some((EventType::SmartDetect, vec![])),
+        _ => {}
+    }
+    if let Ok(kind) = detection_type.parse() {
+        return Some((EventType::Audio(kind), vec![]));
+    }
+    if let Ok(sensor @ EventType::Sensor(_)) = detection_type.parse() {
+        return Some((sensor, vec![]));
+    }
+
+    // smart detection types are joined with `_`, which `license_plate` contains itself
+    let mut smart_detect_types = vec![];
+    let mut words = detection_type.split('_').peekable();
+    while let Some(word) = words.next() {
+        let smart_detect_type = match (word, words.peek()) {
+            ("license", Some(&"plate")) => {
+                words.next();
+                SmartDetectType::LicensePlate
+            }
+            _ => word.parse().ok()?,
+        };
+        smart_detect_types.push(smart_detect_type);
+    }
+
+    Some((EventType::SmartDetect, smart_detect_types))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use unifi_protect_client::events::ProtectEvent;
+
+    use super::*;
+
+    fn event(part: Option<u32>) -> ProtectEvent {
+        ProtectEvent {
+            id: "66b0c0ffee".to_string(),
+            camera_id: "cam1".to_string(),
+            camera_name: Some("Front Door".to_string()),
+            camera_mac: Some("AABBCCDDEEFF".to_string()),
+            nvr_name: Some("Home".to_string()),
+            start_time: Some(
+                Utc.with_ymd_and_hms(2025, 8, 4, 23, 59, 30)
+                    .unwrap()
+                    .timestamp_millis(),
+            ),
+            end_time: Some(
+                Utc.with_ymd_and_hms(2025, 8, 5, 0, 1, 0)
+                    .unwrap()
+                    .timestamp_millis(),
+            ),
+            event_type: EventType::SmartDetect,
+            smart_detect_types: vec![SmartDetectType::Person, SmartDetectType::LicensePlate],
+            thumbnail_id: None,
+            heatmap_id: None,
+            is_finished: true,
+            part,
+            collision: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_formatted_filename() {
+        let format = "{camera_name}/{date}/{time}-{end_time}_{detection_type}.mp4";
+        let parser = FilenameParser::new(format);
+
+        for part in [None, Some(2)] {
+            let path = event(part).format_filename(format, '_');
+            let parsed = parser.parse(&path).expect("formatted path parses");
+
+            assert_eq!(parsed.camera_name.as_deref(), Some("Front Door"));
+            assert_eq!(
+                parsed.start_time,
+                Some(Utc.with_ymd_and_hms(2025, 8, 4, 23, 59, 30).unwrap())
+            );
+            assert_eq!(
+                parsed.end_time,
+                Some(Utc.with_ymd_and_hms(2025, 8, 5, 0, 1, 0).unwrap())
+            );
+            assert_eq!(parsed.part, part);
+            assert_eq!(
+                parsed.detection(),
+                Some((
+                    EventType::SmartDetect,
+                    vec![SmartDetectType::Person, SmartDetectType::LicensePlate]
+                ))
+            );
+        }
+
+        let path = event(None).format_filename(format, '_');
+        assert_eq!(
+            parser.parse(&format!("{path}{ZSTD_EXTENSION}")),
+            parser.parse(&path)
+        );
+
+        assert_eq!(parser.parse("database/events-20250804-120000.db"), None);
+    }
+
+    #[test]
+    fn test_repeated_placeholders_must_agree() {
+        let parser = FilenameParser::new("{camera_id}/{event_id}/{camera_id}.mp4");
+
+        let parsed = parser.parse("cam1/abc/cam1.mp4").unwrap();
+        assert_eq!(parsed.camera_id.as_deref(), Some("cam1"));
+        assert_eq!(parsed.event_id.as_deref(), Some("abc"));
+        assert_eq!(parser.parse("cam1/abc/cam2.mp4"), None);
+    }
+
+    #[test]
+    fn test_parse_any_of() {
+        let parser = FilenameParser::any_of(&["{camera_id}/{event_id}.mp4", "{camera_name}.mp4"]);
+
+        let parsed = parser.parse("cam1/abc.mp4").unwrap();
+        assert_eq!(parsed.event_id.as_deref(), Some("abc"));
+        let parsed = parser.parse("Front Door_part2.mp4").unwrap();
+        assert_eq!(parsed.camera_name.as_deref(), Some("Front Door"));
+        assert_eq!(parsed.part, Some(2));
+    }
+
+    #[test]
+    fn test_strftime_and_extended_placeholders() {
+        let format = "{nvr_name}/{camera_mac}/%Y/%m/%d/%H-%M-%S_{event_type}_{duration}s.{ext}";
+        let parser = FilenameParser::new(format);
+
+        let path = event(Some(2)).format_filename(format, '_');
+        assert_eq!(
+            path,
+            "Home/AABBCCDDEEFF/2025/08/04/23-59-30_smartdetect_90s_part2.mp4"
+        );
+        let parsed = parser.parse(&path).expect("formatted path parses");
+        assert_eq!(
+            parsed.start_time,
+            Some(Utc.with_ymd_and_hms(2025, 8, 4, 23, 59, 30).unwrap())
+        );
+        assert_eq!(parsed.part, Some(2));
+
+        assert!(validate_format(format).is_ok());
+        assert!(validate_format("{camera_name}/{tyop}.mp4").is_err());
+        assert!(validate_format("{camera_name}/%Q.mp4").is_err());
+    }
+
+    #[test]
+    fn test_sanitized_and_colliding_filenames() {
+        let format = "{camera_name}/{date}/{time}.mp4";
+        let parser = FilenameParser::new(format);
+
+        let mut event = event(None);
+        event.camera_name = Some("Gate: Back/Side\u{7}".to_string());
+        event.collision = Some(2);
+        let path = event.format_filename(format, '-');
+        assert_eq!(path, "Gate- Back-Side-/2025-08-04/23-59-30_2.mp4");
+
+        let parsed = parser.parse(&path).expect("colliding path parses");
+        assert_eq!(parsed.camera_name.as_deref(), Some("Gate- Back-Side-"));
+        assert_eq!(
+            parsed.start_time,
+            Some(Utc.with_ymd_and_hms(2025, 8, 4, 23, 59, 30).unwrap())
+        );
+    }
+}
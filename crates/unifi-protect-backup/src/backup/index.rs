@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+/// Where the index goes on each backup target, relative to its base
+pub const INDEX_PATH: &str = "index.html";
+
+/// A backup as listed in the index
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    pub camera: String,
+    pub start_time: DateTime<Utc>,
+    pub event_type: String,
+    /// 1-based part number, or 0 for a whole event
+    pub part: u32,
+    /// Relative to the target's base, as is the index
+    pub path: String,
+    /// Stored zstd-compressed, so it downloads rather than plays in a browser
+    pub compressed: bool,
+    /// A snapshot of the event, relative to the target's base
+    pub thumbnail: Option<String>,
+}
+
+/// A self-contained page listing `entries` by camera and then by day (UTC, as in backup paths),
+/// newest first, linking to each backup relative to the target's base
+pub fn render(entries: &[IndexEntry], generated_at: DateTime<Utc>) -> String {
+    let mut cameras: BTreeMap<&str, BTreeMap<String, Vec<&IndexEntry>>> = BTreeMap::new();
+    for entry in entries {
+        cameras
+            .entry(&entry.camera)
+            .or_default()
+            .entry(entry.start_time.format("%Y-%m-%d").to_string())
+            .or_default()
+            .push(entry);
+    }
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>UniFi Protect Backups</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         li { margin: 0.25em 0; }\n\
+         img { height: 90px; vertical-align: middle; margin-right: 0.5em; }\n\
+         </style>\n</head>\n<body>\n<h1>UniFi Protect Backups</h1>\n",
+    );
+    html.push_str(&format!(
+        "<p>{} backups, listed {}</p>\n",
+        entries.len(),
+        generated_at.format("%Y-%m-%d %H:%M:%S UTC")
+    ));
+
+    for (camera, days) in &cameras {
+        html.push_str(&format!("<h2>{}</h2>\n", escape(camera)));
+        for (day, entries) in days.iter().rev() {
+            html.push_str(&format!(
+                "<details>\n<summary>{day} ({} backups)</summary>\n<ul>\n",
+                entries.len()
+            ));
+            let mut entries = entries.clone();
+            entries.sort_by_key(|entry| std::cmp::Reverse((entry.start_time, entry.part)));
+            for entry in entries {
+                html.push_str("<li>");
+                if let Some(thumbnail) = &entry.thumbnail {
+                    html.push_str(&format!(
+                        "<img src=\"{}\" alt=\"\" loading=\"lazy\">",
+                        escape(&url_path(thumbnail))
+                    ));
+                }
+                let mut label = format!(
+                    "{} {}",
+                    entry.start_time.format("%H:%M:%S"),
+                    entry.event_type
+                );
+                if entry.part > 0 {
+                    label.push_str(&format!(" (part {})", entry.part));
+                }
+                let download = if entry.compressed { " download" } else { "" };
+                html.push_str(&format!(
+                    "<a href=\"{}\"{download}>{}</a>",
+                    escape(&url_path(&entry.path)),
+                    escape(&label)
+                ));
+                if entry.compressed {
+                    html.push_str(" (zstd compressed)");
+                }
+                html.push_str("</li>\n");
+            }
+            html.push_str("</ul>\n</details>\n");
+        }
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// `path` as a relative URL, with each segment percent-encoded
+fn url_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            segment
+                .bytes()
+                .map(|byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                        (byte as char).to_string()
+                    }
+                    _ => format!("%{byte:02X}"),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let entry = |camera: &str, start: i64, path: &str| IndexEntry {
+            camera: camera.to_string(),
+            start_time: DateTime::from_timestamp(start, 0).unwrap(),
+            event_type: "motion".to_string(),
+            part: 0,
+            path: path.to_string(),
+            compressed: path.ends_with(".zst"),
+            thumbnail: Some(path.replace(".mp4", ".jpg")),
+        };
+        let html = render(
+            &[
+                entry(
+                    "Front <Door>",
+                    1_722_772_800,
+                    "Front Door/2024-08-04/12-00-00.mp4",
+                ),
+                entry(
+                    "Front <Door>",
+                    1_722_859_200,
+                    "Front Door/2024-08-05/12-00-00.mp4",
+                ),
+                entry(
+                    "Driveway",
+                    1_722_686_400,
+                    "Driveway/2024-08-03/12-00-00 #1.mp4",
+                ),
+                entry(
+                    "Driveway",
+                    1_722_690_000,
+                    "Driveway/2024-08-03/13-00-00.mp4.zst",
+                ),
+            ],
+            DateTime::from_timestamp(1_722_900_000, 0).unwrap(),
+        );
+
+        assert!(html.contains("<h2>Front &lt;Door&gt;</h2>"));
+        assert!(html.contains("href=\"Front%20Door/2024-08-04/12-00-00.mp4\""));
+        assert!(html.contains("src=\"Driveway/2024-08-03/12-00-00%20%231.jpg\""));
+        assert!(html.contains(
+            "href=\"Driveway/2024-08-03/13-00-00.mp4.zst\" download>13:00:00 motion</a> (zstd compressed)"
+        ));
+        assert!(html.contains("12-00-00.mp4\">12:00:00 motion</a></li>"));
+        // cameras by name, days newest first
+        let position = |text: &str| html.find(text).unwrap();
+        assert!(position("Driveway</h2>") < position("Front &lt;Door&gt;</h2>"));
+        assert!(position("2024-08-05 (1 backups)") < position("2024-08-04 (1 backups)"));
+    }
+}
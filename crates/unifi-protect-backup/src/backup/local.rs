@@ -1,186 +1,694 @@
-use std::{path::PathBuf, sync::Arc, time::SystemTime};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use metered::{ErrorCount, HitCount, ResponseTime, Throughput};
 use serde::{Deserialize, Serialize};
-use tokio::{fs, io::AsyncWriteExt};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+};
 use tracing::{debug, info, warn};
 
 use unifi_protect_client::events::ProtectEvent;
 
-use crate::{Result, backup, backup::Backup, task::Prune};
+use crate::{
+    Error, Result, backup,
+    backup::{
+        Backup, FailureDomain, RemoteFile, TargetOverrides,
+        compress::{self, Compression},
+        filename::FilenameParser,
+    },
+    bandwidth::{BandwidthSchedule, Limiter},
+    clock::system_clock,
+    retention::RetentionPolicy,
+    size::ByteSize,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
 pub struct Config {
     pub path_buf: PathBuf,
+    /// Limit on writes to this target, e.g. a network share
+    #[serde(default)]
+    pub bwlimit: Option<BandwidthSchedule>,
+    /// Compress event backups, e.g. `zstd` or `zstd:19`, adding the compression's extension
+    #[serde(default)]
+    pub compress: Option<Compression>,
+    /// Cap on everything stored under `path-buf`, e.g. `500GiB`; the oldest files are pruned to
+    /// stay under it regardless of retention
+    #[serde(default)]
+    pub max_size: Option<ByteSize>,
+    /// Refuse to write while the filesystem has less than this free, e.g. `10GiB`, rather than
+    /// filling it
+    #[serde(default)]
+    pub min_free_space: Option<ByteSize>,
+    #[serde(flatten)]
+    pub overrides: TargetOverrides,
 }
 
 pub struct LocalBackup {
     pub backup_config: backup::Config,
     pub remote_config: Config,
     pub metrics: Arc<Metrics>,
+    /// Paces writes to `bwlimit`, if set
+    pub limiter: Option<Arc<Limiter>>,
+}
+
+/// Appended to a file's name while it's being written
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// How much of a streamed upload is written between free space checks
+const FREE_SPACE_CHECK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Bytes available to unprivileged users on the filesystem holding `path`, or `None` where that
+/// can't be found out
+#[cfg(unix)]
+fn available_space(path: &Path) -> std::io::Result<Option<u64>> {
+    let stat = rustix::fs::statvfs(path)?;
+    Ok(Some(stat.f_bavail.saturating_mul(stat.f_frsize)))
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Where `file_path` is written until it's complete, next to it so renaming it into place is
+/// atomic
+fn partial_path(file_path: &Path) -> PathBuf {
+    let mut partial_path = file_path.to_path_buf().into_os_string();
+    partial_path.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial_path)
 }
 
-#[metered::metered(registry = Metrics, visibility = pub)]
 impl LocalBackup {
-    pub fn new(
-        backup_config: backup::Config,
-        remote_config: Config,
-        metrics: Arc<Metrics>,
-    ) -> Self {
-        Self {
-            backup_config,
-            remote_config,
-            metrics,
+    async fn write_file(&self, filename: &str, data: &[u8]) -> Result<()> {
+        // Use configured base path
+        let file_path = self.remote_config.path_buf.join(filename);
+
+        // Create parent directories
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+            self.check_free_space(parent, data.len() as u64).await?;
         }
+
+        // a crash part way leaves a partial file rather than a truncated backup
+        let partial_path = partial_path(&file_path);
+        let mut file = tokio::fs::File::create(&partial_path).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+        file.sync_all().await?;
+        fs::rename(&partial_path, &file_path).await?;
+
+        Ok(())
     }
 
-    #[tracing::instrument(skip(self))]
-    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
-    async fn prune_directory(
-        &self,
-        dir_path: &PathBuf,
-        cutoff_time: SystemTime,
-        total_deleted: &mut i32,
-        total_size_freed: &mut u64,
-    ) -> Result<()> {
+    async fn copy_file(&self, filename: &str, source: &Path) -> Result<()> {
+        let file_path = self.remote_config.path_buf.join(filename);
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+            let size = fs::metadata(source).await?.len();
+            self.check_free_space(parent, size).await?;
+        }
+
+        let partial_path = partial_path(&file_path);
+        fs::copy(source, &partial_path).await?;
+        fs::File::open(&partial_path).await?.sync_all().await?;
+        fs::rename(&partial_path, &file_path).await?;
+
+        Ok(())
+    }
+
+    async fn find_stray_partials(&self, older_than: DateTime<Utc>) -> Result<Vec<String>> {
+        Ok(self
+            .list()
+            .await?
+            .into_iter()
+            .filter(|file| file.path.ends_with(PARTIAL_SUFFIX))
+            .filter(|file| file.modified.is_some_and(|modified| modified < older_than))
+            .map(|file| file.path)
+            .collect())
+    }
+
+    /// Create the base path if it's missing, failing if it can't be or can't be written to
+    async fn check_path(&self) -> Result<()> {
+        let path = &self.remote_config.path_buf;
+        tokio::fs::create_dir_all(path).await?;
+        if tokio::fs::metadata(path).await?.permissions().readonly() {
+            return Err(Error::Backup(format!("{} is read-only", path.display())));
+        }
+        Ok(())
+    }
+
+    /// Remove the directories under `dir_path` which pruning left empty.
+    async fn remove_empty_directories(&self, dir_path: &PathBuf) -> Result<()> {
         let mut dir_entries = fs::read_dir(dir_path).await?;
 
         while let Some(entry) = dir_entries.next_entry().await? {
             let path = entry.path();
-            let metadata = entry.metadata().await?;
-
-            if metadata.is_dir() {
-                // Recursively prune subdirectories
-                if let Err(e) = Box::pin(self.prune_directory(
-                    &path,
-                    cutoff_time,
-                    total_deleted,
-                    total_size_freed,
-                ))
-                .await
-                {
-                    warn!("Failed to prune directory {}: {}", path.display(), e);
-                    continue;
-                }
+            if !entry.metadata().await?.is_dir() {
+                continue;
+            }
 
-                // Try to remove empty directories
-                if let Ok(mut empty_check) = fs::read_dir(&path).await {
-                    if empty_check.next_entry().await?.is_none() {
-                        if let Err(e) = fs::remove_dir(&path).await {
-                            debug!("Failed to remove empty directory {}: {}", path.display(), e);
-                        } else {
-                            debug!("Removed empty directory: {}", path.display());
-                        }
-                    }
-                }
-            } else if metadata.is_file() {
-                // Check if file is older than retention period
-                if let Ok(modified_time) = metadata.modified() {
-                    if modified_time < cutoff_time {
-                        let file_size = metadata.len();
-
-                        match fs::remove_file(&path).await {
-                            Ok(()) => {
-                                debug!("Pruned old file: {}", path.display());
-                                *total_deleted += 1;
-                                *total_size_freed += file_size;
-                            }
-                            Err(e) => {
-                                warn!("Failed to remove file {}: {}", path.display(), e);
-                            }
-                        }
+            if let Err(e) = Box::pin(self.remove_empty_directories(&path)).await {
+                warn!("Failed to prune directory {}: {}", path.display(), e);
+                continue;
+            }
+
+            if let Ok(mut empty_check) = fs::read_dir(&path).await {
+                if empty_check.next_entry().await?.is_none() {
+                    if let Err(e) = fs::remove_dir(&path).await {
+                        debug!("Failed to remove empty directory {}: {}", path.display(), e);
+                    } else {
+                        debug!("Removed empty directory: {}", path.display());
                     }
-                } else {
-                    warn!(
-                        "Could not get modification time for file: {}",
-                        path.display()
-                    );
                 }
             }
         }
 
         Ok(())
     }
+}
+
+#[metered::metered(registry = Metrics, visibility = pub)]
+impl LocalBackup {
+    pub fn new(
+        backup_config: backup::Config,
+        remote_config: Config,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let limiter = remote_config
+            .bwlimit
+            .clone()
+            .map(|schedule| Arc::new(Limiter::new(schedule, system_clock())));
+
+        Self {
+            backup_config,
+            remote_config,
+            metrics,
+            limiter,
+        }
+    }
 
     #[tracing::instrument(skip(self, video_data))]
     #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
     async fn backup(&self, event: &ProtectEvent, video_data: &[u8]) -> Result<String> {
-        let filename = event.format_filename(&self.backup_config.file_structure_format);
+        let mut filename = self.backup_config.filename(event);
+        let compressed = match &self.remote_config.compress {
+            Some(compression) => {
+                filename = compression.filename(&filename);
+                Some(compression.compress(video_data)?)
+            }
+            None => None,
+        };
+        let video_data = compressed.as_deref().unwrap_or(video_data);
         info!("Backing up event {} as {}", event.id, filename);
 
-        // Use configured base path
-        let file_path = self.remote_config.path_buf.join(&filename);
+        if let Some(limiter) = &self.limiter {
+            limiter.consume(video_data.len()).await;
+        }
+        self.write_file(&filename, video_data).await?;
 
-        // Create parent directories
-        if let Some(parent) = file_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
+        info!(
+            filename = filename,
+            "Backed up motion event to local storage"
+        );
+        Ok(filename)
+    }
+
+    #[tracing::instrument(skip(self, video))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn backup_stream(
+        &self,
+        event: &ProtectEvent,
+        mut video: mpsc::Receiver<Bytes>,
+        resume_from: u64,
+    ) -> Result<String> {
+        let mut filename = self.backup_config.filename(event);
+        let mut resume_from = resume_from;
+        let mut compressing = None;
+        if let Some(compression) = &self.remote_config.compress {
+            filename = compression.filename(&filename);
+            // how far the earlier attempt got says nothing about where its compressed output
+            // stopped
+            resume_from = 0;
+            let (compressed, handle) = compression.compress_stream(video);
+            video = compressed;
+            compressing = Some(handle);
         }
+        info!("Streaming event {} to {}", event.id, filename);
+
+        let file_path = self.remote_config.path_buf.join(&filename);
+        let dir = file_path.parent().unwrap_or(&self.remote_config.path_buf);
+        fs::create_dir_all(dir).await?;
+        self.check_free_space(dir, 0).await?;
 
-        let mut file = tokio::fs::File::create(&file_path).await?;
-        file.write_all(video_data).await?;
+        // written under a temporary name until complete, so an interrupted upload can be resumed
+        // and is never mistaken for a backup
+        let partial_path = partial_path(&file_path);
+
+        // keep what both this target and the earlier attempt agree was written
+        let mut skip = match fs::metadata(&partial_path).await {
+            Ok(metadata) if resume_from > 0 => metadata.len().min(resume_from),
+            _ => 0,
+        };
+        let mut file = if skip > 0 {
+            info!(
+                filename,
+                skip, "Resuming interrupted upload to local storage"
+            );
+            let file = fs::OpenOptions::new()
+                .append(true)
+                .open(&partial_path)
+                .await?;
+            file.set_len(skip).await?;
+            file
+        } else {
+            fs::File::create(&partial_path).await?
+        };
+
+        // the size isn't known up front, so free space is checked again as the upload grows
+        let mut unchecked = 0;
+        while let Some(mut chunk) = video.recv().await {
+            if skip > 0 {
+                let skipped = skip.min(chunk.len() as u64);
+                chunk = chunk.slice(skipped as usize..);
+                skip -= skipped;
+            }
+            if let Some(limiter) = &self.limiter {
+                limiter.consume(chunk.len()).await;
+            }
+            unchecked += chunk.len() as u64;
+            if unchecked >= FREE_SPACE_CHECK_BYTES {
+                self.check_free_space(dir, 0).await?;
+                unchecked = 0;
+            }
+            file.write_all(&chunk).await?;
+        }
         file.flush().await?;
+        file.sync_all().await?;
+        compress::finished(compressing).await?;
+        fs::rename(&partial_path, &file_path).await?;
 
         info!(
             filename = filename,
-            "Backed up motion event to local storage"
+            "Streamed motion event to local storage"
         );
         Ok(filename)
     }
 
     #[tracing::instrument(skip(self))]
     #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
-    async fn prune(&self) -> Result<()> {
-        info!(
-            "Pruning old backups from local storage (retention: {:?})",
-            self.backup_config.retention_period
-        );
+    async fn relocate(&self, from: &str, to: &str) -> Result<()> {
+        let from_path = self.remote_config.path_buf.join(from);
+        let to_path = self.remote_config.path_buf.join(to);
+
+        if let Some(parent) = to_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        tokio::fs::rename(&from_path, &to_path).await?;
+
+        debug!(from = from, to = to, "Relocated backup in local storage");
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn exists(&self, path: &str) -> Result<bool> {
+        Ok(fs::try_exists(self.remote_config.path_buf.join(path)).await?)
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn delete(&self, path: &str) -> Result<()> {
+        match fs::remove_file(self.remote_config.path_buf.join(path)).await {
+            Ok(()) => debug!(path, "Deleted backup from local storage"),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                debug!(path, "Backup already gone from local storage");
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn download(&self, path: &str) -> Result<Vec<u8>> {
+        let data = fs::read(self.remote_config.path_buf.join(path)).await?;
+        compress::decompress(path, data)
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        let base = &self.remote_config.path_buf;
+        let mut files = vec![];
+        let mut dirs = vec![base.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            let mut dir_entries = fs::read_dir(&dir).await?;
+            while let Some(entry) = dir_entries.next_entry().await? {
+                let path = entry.path();
+                let metadata = entry.metadata().await?;
+
+                if metadata.is_dir() {
+                    dirs.push(path);
+                } else if metadata.is_file() {
+                    let Ok(relative) = path.strip_prefix(base) else {
+                        continue;
+                    };
+                    files.push(RemoteFile {
+                        path: relative.to_string_lossy().replace('\\', "/"),
+                        size_bytes: metadata.len(),
+                        modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+                    });
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        // recorded hashes are of the export, so a compressed backup is hashed as it decompresses
+        if compress::is_compressed(path) {
+            return match fs::read(self.remote_config.path_buf.join(path)).await {
+                Ok(data) => Ok(Some(backup::sha256(&compress::decompress(path, data)?))),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            };
+        }
+
+        let mut file = match fs::File::open(self.remote_config.path_buf.join(path)).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        // videos can be large, so hash them a buffer at a time rather than reading them whole
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 1024 * 1024];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(Some(format!("{:x}", hasher.finalize())))
+    }
+
+    #[tracing::instrument(skip(self, paths), fields(paths = paths.len()))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn delete_many(&self, paths: &[String]) -> Result<()> {
+        let mut last_error = None;
+        for path in paths {
+            if let Err(e) = self.delete(path).await {
+                warn!("Failed to remove file {}: {}", path, e);
+                last_error = Some(e);
+            }
+        }
 
-        let retention_period = self.backup_config.retention_period;
-        let cutoff_time = SystemTime::now()
-            .checked_sub(retention_period)
-            .ok_or_else(|| {
-                std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid retention period")
-            })?;
-
-        let mut total_deleted = 0;
-        let mut total_size_freed = 0u64;
-
-        match self
-            .prune_directory(
-                &self.remote_config.path_buf,
-                cutoff_time,
-                &mut total_deleted,
-                &mut total_size_freed,
-            )
+        self.remove_empty_directories(&self.remote_config.path_buf)
             .await
-        {
-            Ok(()) => {
-                info!(
-                    "Successfully pruned {} files, freed {} bytes from local storage",
-                    total_deleted, total_size_freed
-                );
+            .inspect_err(|e| warn!("Error during pruning: {}", e))?;
+
+        match last_error {
+            Some(e) => Err(e),
+            None => {
+                info!("Deleted {} files from local storage", paths.len());
                 Ok(())
             }
-            Err(e) => {
-                warn!("Error during pruning: {}", e);
-                Err(e)
+        }
+    }
+
+    #[tracing::instrument(skip(self, policy))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn enforce_max_size(&self, policy: &RetentionPolicy) -> Result<Vec<String>> {
+        let Some(ByteSize(max_size)) = self.remote_config.max_size else {
+            return Ok(vec![]);
+        };
+
+        let mut files = self.list().await?;
+        let mut total: u64 = files.iter().map(|file| file.size_bytes).sum();
+        if total <= max_size {
+            return Ok(vec![]);
+        }
+
+        let parser = FilenameParser::any_of(&self.backup_config.file_structure_formats());
+        files.sort_by_key(|file| file.modified);
+
+        let mut deleted = vec![];
+        for file in files {
+            if total <= max_size {
+                break;
+            }
+
+            let mut names = vec![file.path.clone()];
+            names.extend(parser.parse(&file.path).and_then(|parsed| parsed.event_id));
+            if policy.is_held(&names) {
+                continue;
+            }
+
+            match fs::remove_file(self.remote_config.path_buf.join(&file.path)).await {
+                Ok(()) => {
+                    total -= file.size_bytes;
+                    deleted.push(file.path);
+                }
+                Err(e) => warn!("Failed to remove file {}: {}", file.path, e),
             }
         }
+
+        self.remove_empty_directories(&self.remote_config.path_buf)
+            .await
+            .inspect_err(|e| warn!("Error during pruning: {}", e))?;
+
+        if total > max_size {
+            warn!(
+                total,
+                max_size, "Local storage is still over max-size, only held files remain"
+            );
+        }
+        info!(
+            "Pruned {} files to keep local storage under {} bytes",
+            deleted.len(),
+            max_size
+        );
+        Ok(deleted)
+    }
+
+    /// Fail if writing `incoming` more bytes to `dir` would leave less than `min-free-space` on
+    /// its filesystem
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, ErrorCount])]
+    async fn check_free_space(&self, dir: &Path, incoming: u64) -> Result<()> {
+        let Some(ByteSize(min_free_space)) = self.remote_config.min_free_space else {
+            return Ok(());
+        };
+        let Some(available) = available_space(dir)? else {
+            return Ok(());
+        };
+
+        if available < min_free_space.saturating_add(incoming) {
+            warn!(
+                available,
+                min_free_space,
+                incoming,
+                "Refusing to write to local storage, it's low on free space"
+            );
+            return Err(Error::Backup(format!(
+                "Not enough free space on {}: {available} bytes free, {min_free_space} kept free",
+                dir.display()
+            )));
+        }
+        Ok(())
     }
 }
 
 #[async_trait]
 impl Backup for LocalBackup {
+    fn name(&self) -> String {
+        format!("local:{}", self.remote_config.path_buf.display())
+    }
+
+    fn backup_config(&self) -> &backup::Config {
+        &self.backup_config
+    }
+
     async fn backup(&self, event: &ProtectEvent, video_data: &[u8]) -> Result<String> {
         self.backup(event, video_data).await
     }
+
+    async fn backup_stream(
+        &self,
+        event: &ProtectEvent,
+        video: mpsc::Receiver<Bytes>,
+        resume_from: u64,
+    ) -> Result<String> {
+        self.backup_stream(event, video, resume_from).await
+    }
+
+    async fn upload(&self, filename: &str, data: &[u8]) -> Result<()> {
+        self.write_file(filename, data).await
+    }
+
+    async fn upload_from(&self, filename: &str, source: &Path) -> Result<()> {
+        self.copy_file(filename, source).await
+    }
+
+    async fn relocate(&self, from: &str, to: &str) -> Result<()> {
+        self.relocate(from, to).await
+    }
+
+    fn failure_domain(&self) -> FailureDomain {
+        FailureDomain::Local
+    }
+
+    async fn validate(&self) -> Result<()> {
+        self.check_path().await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        self.exists(path).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.delete(path).await
+    }
+
+    async fn download(&self, path: &str) -> Result<Vec<u8>> {
+        self.download(path).await
+    }
+
+    async fn download_to(&self, path: &str, dest: &Path) -> Result<()> {
+        let source = self.remote_config.path_buf.join(path);
+        compress::decompress_file(path, source, dest.to_path_buf()).await
+    }
+
+    fn destination(&self, event: &ProtectEvent) -> String {
+        let filename = self.backup_config.filename(event);
+        match &self.remote_config.compress {
+            Some(compression) => compression.filename(&filename),
+            None => filename,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        self.list().await
+    }
+
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        self.sha256(path).await
+    }
+
+    async fn delete_many(&self, paths: &[String]) -> Result<()> {
+        self.delete_many(paths).await
+    }
+
+    async fn enforce_max_size(&self, policy: &RetentionPolicy) -> Result<Vec<String>> {
+        self.enforce_max_size(policy).await
+    }
+
+    async fn stray_partials(&self, older_than: DateTime<Utc>) -> Result<Vec<String>> {
+        self.find_stray_partials(older_than).await
+    }
 }
 
-#[async_trait]
-impl Prune for LocalBackup {
-    async fn prune(&self) -> Result<()> {
-        self.prune().await
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{convert::protect_event_from_database_event, testing};
+
+    /// A local target under `dir`, with `remote` added to its config as TOML
+    fn target(dir: &TempDir, remote: &str) -> LocalBackup {
+        let remote_config = toml::from_str(&format!(
+            "path-buf = \"{}/backups\"\n{remote}",
+            dir.path().display()
+        ))
+        .unwrap();
+        LocalBackup::new(
+            testing::config(dir, "").backup,
+            remote_config,
+            Arc::new(Metrics::default()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_backup_stream_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = target(&dir, "");
+        let event = protect_event_from_database_event(
+            testing::event("event", 0, 10_000),
+            &testing::bootstrap(),
+        );
+
+        // an earlier attempt got as far as the first chunk
+        let file_path = dir.path().join("backups").join(target.destination(&event));
+        std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        std::fs::write(partial_path(&file_path), b"hello").unwrap();
+
+        let (sender, receiver) = mpsc::channel(2);
+        sender.send(Bytes::from_static(b"hello")).await.unwrap();
+        sender.send(Bytes::from_static(b" world")).await.unwrap();
+        drop(sender);
+        let filename = target.backup_stream(&event, receiver, 5).await.unwrap();
+
+        let written = std::fs::read(dir.path().join("backups").join(filename));
+        assert_eq!(written.unwrap(), b"hello world");
+        assert!(!partial_path(&file_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_find_stray_partials() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = target(&dir, "");
+        let base = dir.path().join("backups");
+        std::fs::create_dir_all(&base).unwrap();
+        let now = Utc::now();
+        for (name, modified) in [
+            ("old.mp4.partial", now - chrono::Duration::days(2)),
+            ("new.mp4.partial", now),
+            ("old.mp4", now - chrono::Duration::days(2)),
+        ] {
+            let path = base.join(name);
+            std::fs::write(&path, b"video").unwrap();
+            let file = std::fs::File::open(&path).unwrap();
+            file.set_modified(modified.into()).unwrap();
+        }
+
+        let older_than = now - chrono::Duration::days(1);
+        let partials = target.find_stray_partials(older_than).await.unwrap();
+        assert_eq!(partials, vec!["old.mp4.partial"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_min_free_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("backups");
+
+        // more than any filesystem here has free
+        let full = target(&dir, "min-free-space = \"100000TiB\"");
+        assert!(full.upload("full.json", b"{}").await.is_err());
+        assert!(!base.join("full.json").exists());
+        assert!(!base.join("full.json.partial").exists());
+
+        let roomy = target(&dir, "min-free-space = \"1KiB\"");
+        roomy.upload("roomy.json", b"{}").await.unwrap();
+        assert!(base.join("roomy.json").exists());
     }
 }
@@ -1,18 +1,270 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
 
-use unifi_protect_client::events::ProtectEvent;
+use unifi_protect_client::{events::ProtectEvent, models::ExportQuality};
+use unifi_protect_data::Database;
 
-use crate::{Result, metrics::Metrics, task::Prune};
+use crate::{
+    Result,
+    bandwidth::BandwidthSchedule,
+    metrics::Metrics,
+    privacy::PrivacySchedule,
+    retention::{RetentionConfig, RetentionPolicy},
+    sensor::SensorClips,
+    size::ByteSize,
+    validate::ExportVerification,
+};
 
+pub mod breaker;
+pub mod compress;
+pub mod filename;
+pub mod index;
 pub mod local;
+pub mod pipeline;
+pub mod rc;
 pub mod rclone;
+pub mod sidecar;
+pub mod snapshot;
+pub mod spool;
+pub mod sts;
 
 #[async_trait]
-pub trait Backup: Prune + Send + Sync {
+pub trait Backup: Send + Sync {
+    /// Stable identifier for this target, recorded alongside each backup in the database
+    fn name(&self) -> String;
+    /// `[backup]` as it applies to this target, with the target's [`TargetOverrides`] applied
+    fn backup_config(&self) -> &Config;
+    /// Whether `event` is backed up to this target, see [`Config::accepts`] and
+    /// [`Config::camera_targets`]
+    fn accepts(&self, event: &ProtectEvent) -> bool {
+        let config = self.backup_config();
+        config.accepts(event) && config.camera_targets(event, &self.name())
+    }
     async fn backup(&self, event: &ProtectEvent, video_data: &[u8]) -> Result<String>;
+    /// [`backup`](Self::backup) an export as it downloads, writing chunks until the channel
+    /// closes. A closed channel doesn't mean the download succeeded, see [`pipeline`].
+    ///
+    /// `resume_from` is how many bytes of this same export an interrupted earlier attempt passed
+    /// to the target. Targets that can pick up from there skip that much of the stream; others
+    /// start over.
+    async fn backup_stream(
+        &self,
+        event: &ProtectEvent,
+        video: mpsc::Receiver<Bytes>,
+        resume_from: u64,
+    ) -> Result<String>;
+    /// Store a file which isn't an event, e.g. a database snapshot, at `filename`
+    async fn upload(&self, filename: &str, data: &[u8]) -> Result<()>;
+    /// [`upload`](Self::upload) the local file at `source`. Targets that can copy it as it is
+    /// do, rather than reading it all into memory first.
+    async fn upload_from(&self, filename: &str, source: &Path) -> Result<()> {
+        let data = tokio::fs::read(source).await?;
+        self.upload(filename, &data).await
+    }
+    /// Move a previously backed up file to a new path within this target
+    async fn relocate(&self, from: &str, to: &str) -> Result<()>;
+    fn failure_domain(&self) -> FailureDomain;
+    /// A cheap check, run at startup, that the target is configured correctly and reachable
+    async fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Whether a previously backed up file is still present
+    async fn exists(&self, path: &str) -> Result<bool>;
+    /// Remove a previously backed up file; one that's already gone isn't an error
+    async fn delete(&self, path: &str) -> Result<()>;
+    /// [`delete`](Self::delete) many files at once, failing if any of them couldn't be deleted
+    async fn delete_many(&self, paths: &[String]) -> Result<()> {
+        for path in paths {
+            self.delete(path).await?;
+        }
+        Ok(())
+    }
+    /// Read back a previously backed up file
+    async fn download(&self, path: &str) -> Result<Vec<u8>>;
+    /// [`download`](Self::download) a previously backed up file to the local file at `dest`.
+    /// Targets that can copy it there directly do, rather than reading it all into memory first.
+    async fn download_to(&self, path: &str, dest: &Path) -> Result<()> {
+        let data = self.download(path).await?;
+        Ok(tokio::fs::write(dest, data).await?)
+    }
+    /// Where [`backup`](Self::backup) would store `event`, relative to the target's base
+    fn destination(&self, event: &ProtectEvent) -> String;
+    /// Every file stored on this target, with paths relative to its base
+    async fn list(&self) -> Result<Vec<RemoteFile>>;
+    /// Hex SHA-256 of a previously backed up file as it's stored now, or `None` if it's gone
+    async fn sha256(&self, path: &str) -> Result<Option<String>>;
+    /// Uploads which were interrupted and last written before `older_than`, left under a
+    /// temporary name rather than a backup's. Paths are relative to the target's base.
+    async fn stray_partials(&self, _older_than: DateTime<Utc>) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+    /// Delete the oldest files until the target fits its size cap, if it has one, whatever else
+    /// `policy` would keep. Held files are never deleted. Returns the paths deleted.
+    async fn enforce_max_size(&self, _policy: &RetentionPolicy) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+/// How long an interrupted upload's partial file is kept for the upload to be resumed. Older
+/// ones are no longer being written or resumed, and pruning deletes them.
+pub const STRAY_PARTIAL_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Paths events are being backed up to now, by target and path. An upload isn't recorded in the
+/// database until its batch finishes, so without these, events in the same batch which resolve to
+/// the same filename would each see it free and overwrite one another.
+#[derive(Debug, Default)]
+pub struct Reservations {
+    paths: Mutex<HashMap<(String, String), (String, usize)>>,
+}
+
+impl Reservations {
+    /// Reserve `paths` for `event_id`, unless another event has any of them
+    fn try_reserve(
+        self: &Arc<Self>,
+        event_id: &str,
+        paths: Vec<(String, String)>,
+    ) -> Option<Reservation> {
+        let mut reserved = self.paths.lock().expect("reservations lock poisoned");
+        let taken = paths.iter().any(|path| {
+            reserved
+                .get(path)
+                .is_some_and(|(holder, _)| holder != event_id)
+        });
+        if taken {
+            return None;
+        }
+        for path in &paths {
+            reserved
+                .entry(path.clone())
+                .or_insert_with(|| (event_id.to_string(), 0))
+                .1 += 1;
+        }
+        Some(Reservation {
+            reservations: self.clone(),
+            paths,
+        })
+    }
+}
+
+/// Paths held by [`Reservations`] for an event, released when dropped. Held until the event's
+/// backups are recorded, when the database takes over keeping others off them.
+#[derive(Debug)]
+pub struct Reservation {
+    reservations: Arc<Reservations>,
+    paths: Vec<(String, String)>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut reserved = self
+            .reservations
+            .paths
+            .lock()
+            .expect("reservations lock poisoned");
+        for path in &self.paths {
+            if let Some((_, count)) = reserved.get_mut(path) {
+                *count -= 1;
+                if *count == 0 {
+                    reserved.remove(path);
+                }
+            }
+        }
+    }
+}
+
+/// The collision number, if `event` needs one, at which none of `targets` already has, or is
+/// uploading, another event's backup where it would be stored, with those paths reserved for it.
+/// Events can resolve to the same filename when the format doesn't tell them apart, e.g. without
+/// `{event_id}`.
+pub async fn collision(
+    database: &Database,
+    reservations: &Arc<Reservations>,
+    targets: &[&Arc<dyn Backup>],
+    event: &ProtectEvent,
+) -> Result<(Option<u32>, Reservation)> {
+    collision_at(database, reservations, targets, event, |target, event| {
+        target.destination(event)
+    })
+    .await
+}
+
+/// [`collision`] for backups stored at `path` rather than [`Backup::destination`], e.g. a
+/// snapshot in place of the video
+pub async fn collision_at(
+    database: &Database,
+    reservations: &Arc<Reservations>,
+    targets: &[&Arc<dyn Backup>],
+    event: &ProtectEvent,
+    path: impl Fn(&dyn Backup, &ProtectEvent) -> String,
+) -> Result<(Option<u32>, Reservation)> {
+    let mut event = event.clone();
+    event.collision = None;
+    loop {
+        let paths: Vec<_> = targets
+            .iter()
+            .map(|target| (target.name(), path(target.as_ref(), &event)))
+            .collect();
+        // reserved before checking the database, so an upload recorded in between is seen there
+        if let Some(reservation) = reservations.try_reserve(&event.id, paths.clone()) {
+            let mut taken = false;
+            for (target, path) in &paths {
+                let backup = database.get_backup_by_path(target, path).await?;
+                if backup.is_some_and(|backup| backup.event_id != event.id) {
+                    taken = true;
+                    break;
+                }
+            }
+            if !taken {
+                return Ok((event.collision, reservation));
+            }
+        }
+        event.collision = Some(event.collision.map_or(2, |n| n + 1));
+    }
+}
+
+/// Hex SHA-256 of `data`, as recorded with each backup
+pub fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFile {
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Where a target keeps its copies. Ordered so that sorting targets puts remote ones first:
+/// pruning removes those before the local copy it can still check cheaply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureDomain {
+    Remote,
+    Local,
+}
+
+/// What to do at startup about a target that fails [`Backup::validate`] (or its archive
+/// equivalent)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetValidation {
+    /// Refuse to start
+    #[default]
+    Fail,
+    /// Log a warning and run without the target
+    Disable,
+    /// Don't check targets
+    Off,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +272,9 @@ pub trait Backup: Prune + Send + Sync {
 pub struct Config {
     #[serde(with = "humantime_serde")]
     pub retention_period: Duration,
+    /// Count, size, per-type and hold limits on top of `retention_period`
+    #[serde(default)]
+    pub retention: RetentionConfig,
     #[serde(with = "humantime_serde")]
     pub poll_interval: Duration,
     #[serde(with = "humantime_serde")]
@@ -27,13 +282,390 @@ pub struct Config {
     #[serde(with = "humantime_serde")]
     pub purge_interval: Duration,
     pub file_structure_format: String,
+    /// Put in place of characters in camera names and other details which can't be in a path
+    #[serde(default = "default_path_replacement")]
+    pub path_replacement: char,
     pub detection_types: Vec<String>,
     pub ignore_cameras: Vec<String>,
     pub cameras: Vec<String>,
-    pub download_buffer_size: u64,
+    /// How much of an export to read before checking and passing it on, e.g. `8KiB`
+    pub download_buffer_size: ByteSize,
     pub parallel_uploads: u32,
+    /// Mark events skipped when the NVR still has no footage to export `missing-after` they
+    /// ended, e.g. because it was already purged, rather than retrying them forever
     pub skip_missing: bool,
+    #[serde(default = "default_missing_after", with = "humantime_serde")]
+    pub missing_after: Duration,
+    /// Export events on the same camera no more than this far apart as one. Unset exports every
+    /// event on its own.
+    #[serde(default, with = "humantime_serde")]
+    pub merge_gap: Option<Duration>,
+    /// Exports smaller than this per second of event duration are treated as corrupt, e.g. `16KiB`
+    #[serde(default = "default_min_export_bytes_per_second")]
+    pub min_export_bytes_per_second: ByteSize,
+    /// Also check each export plays for as long as its event before it's marked backed up
+    #[serde(default)]
+    pub verify_exports: ExportVerification,
+    /// How long to wait after an event ends before exporting it, giving the NVR time to flush
+    #[serde(default, with = "humantime_serde")]
+    pub download_delay: Duration,
+    /// How long to wait before retrying an export the NVR reported as not ready yet
+    #[serde(default = "default_export_retry_delay", with = "humantime_serde")]
+    pub export_retry_delay: Duration,
+    /// Give up on an event, as for `max_event_attempts`, once its export has been reported as
+    /// not ready this many times in a row. Zero retries it until it's ready.
+    #[serde(default = "default_max_export_retries")]
+    pub max_export_retries: u32,
+    /// Stream to export events from
+    #[serde(default)]
+    pub export_quality: ExportQuality,
+    /// Per-camera overrides of `export_quality`, keyed by camera id or name
+    #[serde(default)]
+    pub camera_export_quality: HashMap<String, ExportQuality>,
+    /// Exports longer than this go through an NVR export job instead of the direct export
+    /// endpoint (newer Protect versions only). Unset disables export jobs.
+    #[serde(default, with = "humantime_serde")]
+    pub export_job_threshold: Option<Duration>,
+    #[serde(default = "default_export_job_poll_interval", with = "humantime_serde")]
+    pub export_job_poll_interval: Duration,
+    /// Give up on an export job that hasn't completed after this long
+    #[serde(default = "default_export_job_timeout", with = "humantime_serde")]
+    pub export_job_timeout: Duration,
+    /// Stop scheduling exports for a camera once this many in a row have failed, only probing it
+    /// every `degraded-probe-interval`. Zero never gives up on a camera.
+    #[serde(default = "default_degraded_after")]
+    pub degraded_after: u32,
+    #[serde(default = "default_degraded_probe_interval", with = "humantime_serde")]
+    pub degraded_probe_interval: Duration,
+    /// Stop uploading to a target once this many uploads to it in a row have failed, only
+    /// probing it every `breaker-probe-interval`. Zero never gives up on a target.
+    #[serde(default = "default_breaker_after")]
+    pub breaker_after: u32,
+    #[serde(default = "default_breaker_probe_interval", with = "humantime_serde")]
+    pub breaker_probe_interval: Duration,
+    /// Stop retrying an event after this many failed attempts at backing it up, marking it
+    /// skipped and sending an alert. Zero retries every event until it's backed up.
+    #[serde(default)]
+    pub max_event_attempts: u32,
+    /// Alert when at least this fraction of the last `failure-rate-window` uploads to a target
+    /// failed. Unset never alerts on a target's failure rate.
+    #[serde(default)]
+    pub failure_rate_alert: Option<f64>,
+    #[serde(default = "default_failure_rate_window")]
+    pub failure_rate_window: usize,
+    /// Alert when a backed up camera has been disconnected from the NVR for this long. Unset
+    /// never alerts on disconnected cameras.
+    #[serde(default, with = "humantime_serde")]
+    pub camera_offline_after: Option<Duration>,
+    /// How long the NVR keeps footage. With it set, an alert is sent once the oldest event
+    /// waiting to be backed up is `backlog-alert-at` of the way to being deleted by the NVR.
+    #[serde(default, with = "humantime_serde")]
+    pub nvr_retention: Option<Duration>,
+    #[serde(default = "default_backlog_alert_at")]
+    pub backlog_alert_at: f64,
+    /// Windows during which events are kept in the database but never backed up
+    #[serde(default)]
+    pub privacy_hours: Vec<PrivacySchedule>,
+    /// Record a clip from a camera when a UP Sense sensor triggers. Unset records none.
+    #[serde(default)]
+    pub sensor_clips: Option<SensorClips>,
+    /// Rhai script deciding per event whether it's backed up (`scripting` feature)
+    #[serde(default)]
+    pub filter_script: Option<PathBuf>,
+    /// Never prune an event that's still within retention on one target down to fewer than
+    /// this many copies, and restore copies when there are fewer. Unset deletes every expired
+    /// copy and never restores any.
+    #[serde(default)]
+    pub min_copies: Option<u32>,
+    /// How often to check that events have `min_copies` copies
+    #[serde(default = "default_reconcile_interval", with = "humantime_serde")]
+    pub reconcile_interval: Duration,
+    /// How often to re-hash every backed up copy and compare it with the checksum recorded at
+    /// upload. Unset disables verification.
+    #[serde(default, with = "humantime_serde")]
+    pub verify_interval: Option<Duration>,
+    /// How often to compare the stored duration of a sample of the backups made since the last
+    /// comparison with their events' durations. Unset disables sampling.
+    #[serde(default, with = "humantime_serde")]
+    pub integrity_sample_interval: Option<Duration>,
+    /// Backups sampled from each target every `integrity-sample-interval`
+    #[serde(default = "default_integrity_sample_size")]
+    pub integrity_sample_size: usize,
+    /// Exports (with `verify-exports`) and sampled backups shorter than their event by more than
+    /// this count as truncated
+    #[serde(
+        default = "default_integrity_drift_tolerance",
+        with = "humantime_serde"
+    )]
+    pub integrity_drift_tolerance: Duration,
+    /// How often to write and delete a canary file on each backup target and check each archive
+    /// target is reachable. Unset disables health checks.
+    #[serde(default, with = "humantime_serde")]
+    pub health_check_interval: Option<Duration>,
+    /// How often to upload an `index.html` to each backup target listing its backups by camera
+    /// and day, for browsing them without a server. Unset uploads no index.
+    #[serde(default, with = "humantime_serde")]
+    pub html_index_interval: Option<Duration>,
+    /// Stage exports on disk and upload them to every target from there. Unset streams each
+    /// export straight to the targets.
+    #[serde(default)]
+    pub spool: Option<spool::Config>,
+    /// Limit on exports from the NVR, shared by every event being backed up. Streamed uploads
+    /// run at the pace of the download, so this limits them too.
+    #[serde(default)]
+    pub bwlimit: Option<BandwidthSchedule>,
+    /// Write a JSON description of each backup next to it, see [`sidecar::Sidecar`]
+    #[serde(default)]
+    pub sidecars: bool,
+    /// Back up a JPEG snapshot of each event with its video (`with-video`) or in place of it
+    /// (`only`), see [`snapshot::SnapshotMode`]
+    #[serde(default)]
+    pub snapshots: snapshot::SnapshotMode,
+    /// Delete an event's backups from every target when the event is deleted on the NVR
+    #[serde(default)]
+    pub mirror_deletions: bool,
+    /// Log what would be exported, uploaded and restored, and where to, without exporting from
+    /// the NVR or writing to any target
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Log what pruning would delete without deleting anything
+    #[serde(default)]
+    pub prune_dry_run: bool,
+    /// What to do about targets that fail their startup check
+    #[serde(default)]
+    pub validate_targets: TargetValidation,
     pub remote: Vec<RemoteBackupConfig>,
+    /// The top-level `[[camera]]` blocks, copied in by
+    /// [`Config::with_camera_configs`](crate::config::Config::with_camera_configs)
+    #[serde(skip)]
+    pub per_camera: Vec<CameraConfig>,
+}
+
+impl Config {
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        let mut policy = RetentionPolicy::new(self.retention_period, &self.retention);
+        for camera in &self.per_camera {
+            let Some(retention_period) = camera.retention_period else {
+                continue;
+            };
+            for key in camera.id.iter().chain(&camera.name) {
+                policy
+                    .camera_overrides
+                    .insert(key.clone(), retention_period);
+            }
+        }
+        policy
+    }
+
+    /// The `[[camera]]` block for a camera, matched by id or name
+    pub fn camera_config(
+        &self,
+        camera_id: &str,
+        camera_name: Option<&str>,
+    ) -> Option<&CameraConfig> {
+        self.per_camera
+            .iter()
+            .find(|camera| camera.matches(camera_id, camera_name))
+    }
+
+    /// This config with a target's overrides in place of the settings they override
+    pub fn for_target(&self, overrides: &TargetOverrides) -> Self {
+        let mut config = self.clone();
+        if let Some(format) = &overrides.file_structure_format {
+            config.file_structure_format = format.clone();
+        }
+        if let Some(detection_types) = &overrides.detection_types {
+            config.detection_types = detection_types.clone();
+        }
+        if let Some(cameras) = &overrides.cameras {
+            config.cameras = cameras.clone();
+        }
+        if let Some(ignore_cameras) = &overrides.ignore_cameras {
+            config.ignore_cameras = ignore_cameras.clone();
+        }
+        config
+    }
+
+    /// Whether the event passes the camera and `detection-types` filters, with the camera's own
+    /// `detection-types` in place of the target's if it sets them
+    pub fn accepts(&self, event: &ProtectEvent) -> bool {
+        let camera_name = event.camera_name.as_deref();
+        let detection_types = self
+            .camera_config(&event.camera_id, camera_name)
+            .and_then(|camera| camera.detection_types.as_ref())
+            .unwrap_or(&self.detection_types);
+        self.camera_enabled(&event.camera_id, camera_name) && event.should_backup(detection_types)
+    }
+
+    /// Whether the camera's `targets`, if it sets any, include the target named `target`
+    pub fn camera_targets(&self, event: &ProtectEvent, target: &str) -> bool {
+        self.camera_config(&event.camera_id, event.camera_name.as_deref())
+            .and_then(|camera| camera.targets.as_ref())
+            .is_none_or(|targets| targets.iter().any(|name| name == target))
+    }
+
+    /// `max-event-length` for a camera, with its own in place of `[backup]`'s if it sets one
+    pub fn max_event_length(&self, camera_id: &str, camera_name: Option<&str>) -> Duration {
+        self.camera_config(camera_id, camera_name)
+            .and_then(|camera| camera.max_event_length)
+            .unwrap_or(self.max_event_length)
+    }
+
+    /// `file-structure-format` for the event's camera, with its own in place of the target's if
+    /// it sets one
+    pub fn file_structure_format(&self, event: &ProtectEvent) -> &str {
+        self.camera_config(&event.camera_id, event.camera_name.as_deref())
+            .and_then(|camera| camera.file_structure_format.as_deref())
+            .unwrap_or(&self.file_structure_format)
+    }
+
+    /// Where `event` is stored, relative to the target's base and before any compression
+    pub fn filename(&self, event: &ProtectEvent) -> String {
+        event.format_filename(self.file_structure_format(event), self.path_replacement)
+    }
+
+    /// Formats of every backup this config could have named, for parsing them back: the
+    /// target's and each camera's own
+    pub fn file_structure_formats(&self) -> Vec<&str> {
+        let mut formats = vec![self.file_structure_format.as_str()];
+        for camera in &self.per_camera {
+            if let Some(format) = camera.file_structure_format.as_deref() {
+                if !formats.contains(&format) {
+                    formats.push(format);
+                }
+            }
+        }
+        formats
+    }
+
+    /// Whether the camera passes the `cameras` and `ignore-cameras` filters, matched by id or name
+    pub fn camera_enabled(&self, camera_id: &str, camera_name: Option<&str>) -> bool {
+        let matches = |camera: &String| camera == camera_id || Some(camera.as_str()) == camera_name;
+        (self.cameras.is_empty() || self.cameras.iter().any(matches))
+            && !self.ignore_cameras.iter().any(matches)
+    }
+
+    pub fn export_quality(&self, camera_id: &str, camera_name: Option<&str>) -> ExportQuality {
+        self.camera_export_quality
+            .get(camera_id)
+            .or_else(|| camera_name.and_then(|name| self.camera_export_quality.get(name)))
+            .copied()
+            .unwrap_or(self.export_quality)
+    }
+}
+
+fn default_path_replacement() -> char {
+    '_'
+}
+
+fn default_min_export_bytes_per_second() -> ByteSize {
+    ByteSize(16 * 1024)
+}
+
+fn default_export_retry_delay() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_max_export_retries() -> u32 {
+    120
+}
+
+fn default_missing_after() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_export_job_poll_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_export_job_timeout() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+fn default_degraded_after() -> u32 {
+    5
+}
+
+fn default_degraded_probe_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_breaker_after() -> u32 {
+    5
+}
+
+fn default_breaker_probe_interval() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+fn default_failure_rate_window() -> usize {
+    20
+}
+
+fn default_backlog_alert_at() -> f64 {
+    0.75
+}
+
+fn default_integrity_sample_size() -> usize {
+    10
+}
+
+fn default_integrity_drift_tolerance() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_reconcile_interval() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+/// Settings from `[backup]` a target can set for itself, e.g. to send only person detections to
+/// an expensive cloud target. Unset ones follow `[backup]`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct TargetOverrides {
+    #[serde(default)]
+    pub file_structure_format: Option<String>,
+    #[serde(default)]
+    pub detection_types: Option<Vec<String>>,
+    #[serde(default)]
+    pub cameras: Option<Vec<String>>,
+    #[serde(default)]
+    pub ignore_cameras: Option<Vec<String>>,
+}
+
+/// A `[[camera]]` block: settings for one camera, matched by `id` or `name`, in place of those
+/// from `[backup]` and the target's overrides. Unset ones follow those.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct CameraConfig {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub detection_types: Option<Vec<String>>,
+    /// Events shorter than this are skipped
+    #[serde(default, with = "humantime_serde")]
+    pub min_event_length: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub max_event_length: Option<Duration>,
+    #[serde(default)]
+    pub file_structure_format: Option<String>,
+    /// Takes the place of `retention-period`, like a `camera-overrides` entry under
+    /// `[backup.retention]`
+    #[serde(default, with = "humantime_serde")]
+    pub retention_period: Option<Duration>,
+    /// Names of the targets this camera's events are backed up to, as recorded with each backup,
+    /// e.g. `local:/srv/protect`. Unset backs up to every target.
+    #[serde(default)]
+    pub targets: Option<Vec<String>>,
+}
+
+impl CameraConfig {
+    pub fn matches(&self, camera_id: &str, camera_name: Option<&str>) -> bool {
+        self.id.as_deref() == Some(camera_id)
+            || self.name.is_some() && self.name.as_deref() == camera_name
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,22 +679,161 @@ pub fn backup_targets(
     config: &crate::config::Config,
     metrics: &Arc<Metrics>,
 ) -> Vec<Arc<dyn Backup>> {
-    let mut targets = vec![];
-
-    for remote in &config.backup.remote {
-        targets.push(match remote {
-            RemoteBackupConfig::Local(remote) => Arc::new(local::LocalBackup {
-                backup_config: config.backup.clone(),
-                remote_config: remote.clone(),
-                metrics: metrics.local_backup.clone(),
-            }) as Arc<dyn Backup>,
-            RemoteBackupConfig::Rclone(remote) => Arc::new(rclone::RcloneBackup {
-                backup_config: config.backup.clone(),
-                remote_config: remote.clone(),
-                metrics: metrics.rclone_backup.clone(),
-            }) as Arc<dyn Backup>,
-        });
+    config
+        .backup
+        .remote
+        .iter()
+        .map(|remote| backup_target(&config.backup, remote, metrics))
+        .collect()
+}
+
+/// The target for one `[[backup.remote]]`, or a remote configured the same way elsewhere
+pub fn backup_target(
+    config: &Config,
+    remote: &RemoteBackupConfig,
+    metrics: &Arc<Metrics>,
+) -> Arc<dyn Backup> {
+    match remote {
+        RemoteBackupConfig::Local(remote) => Arc::new(local::LocalBackup::new(
+            config.for_target(&remote.overrides),
+            remote.clone(),
+            metrics.local_backup.clone(),
+        )),
+        RemoteBackupConfig::Rclone(remote) => Arc::new(rclone::RcloneBackup::new(
+            config.for_target(&remote.overrides),
+            remote.clone(),
+            metrics.rclone_backup.clone(),
+        )),
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        convert::protect_event_from_database_event,
+        testing::{self, CAMERA_ID, CAMERA_NAME, TestContext, event},
+    };
+
+    #[test]
+    fn test_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = testing::config(
+            &dir,
+            r#"
+            min-export-bytes-per-second = "1.5 KiB"
+            retention = { max-size = "500GiB" }
+            "#,
+        )
+        .backup;
+        assert_eq!(config.download_buffer_size, ByteSize(8 << 10));
+        assert_eq!(config.min_export_bytes_per_second, ByteSize(1536));
+        assert_eq!(config.retention_policy().max_size, Some(500 << 30));
+
+        // plain numbers are still bytes
+        let config = testing::config(&dir, "retention = { max-size = 1024 }").backup;
+        assert_eq!(config.retention_policy().max_size, Some(1024));
+        assert_eq!(
+            config.min_export_bytes_per_second,
+            default_min_export_bytes_per_second()
+        );
+    }
+
+    #[test]
+    fn test_camera_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = testing::config(
+            &dir,
+            r#"
+            [[camera]]
+            name = "Front Door"
+            detection-types = ["ring"]
+            retention-period = "7d"
+
+            [[camera]]
+            id = "porch"
+            retention-period = "90d"
+            "#,
+        )
+        .backup;
+        let bootstrap = testing::bootstrap();
 
-    targets
+        // matched by name, with its own detection types
+        let motion = protect_event_from_database_event(event("motion", 0, 10_000), &bootstrap);
+        assert!(!config.accepts(&motion));
+        let mut ring = event("ring", 0, 10_000);
+        ring.event_type = "ring".to_string();
+        assert!(config.accepts(&protect_event_from_database_event(ring, &bootstrap)));
+        // matched by id, and not on the NVR, so without a name
+        let mut porch = event("porch", 0, 10_000);
+        porch.camera_id = "porch".to_string();
+        assert!(config.accepts(&protect_event_from_database_event(porch, &bootstrap)));
+
+        let day = Duration::from_secs(24 * 60 * 60);
+        let policy = config.retention_policy();
+        let cameras =
+            |cameras: &[&str]| -> Vec<String> { cameras.iter().map(ToString::to_string).collect() };
+        assert_eq!(
+            policy.max_age_for(&[], &cameras(&[CAMERA_ID, CAMERA_NAME])),
+            7 * day
+        );
+        assert_eq!(policy.max_age_for(&[], &cameras(&["porch"])), 90 * day);
+        assert_eq!(policy.max_age_for(&[], &cameras(&["driveway"])), 30 * day);
+    }
+
+    #[tokio::test]
+    async fn test_collision() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let backup_targets = context.backup_targets.load_full();
+        let targets: Vec<_> = backup_targets.iter().collect();
+        let bootstrap = context.protect_bootstrap.load();
+        // the format has no `{event_id}`, so events starting together resolve to the same path
+        let first = protect_event_from_database_event(event("first", 0, 10_000), &bootstrap);
+        let second = protect_event_from_database_event(event("second", 0, 10_000), &bootstrap);
+        let third = protect_event_from_database_event(event("third", 0, 10_000), &bootstrap);
+        let database = &context.database;
+        let reservations = &context.reservations;
+
+        // uploading, but not recorded yet
+        let (collision_first, first_reserved) = collision(database, reservations, &targets, &first)
+            .await
+            .unwrap();
+        assert_eq!(collision_first, None);
+        let (collision_second, second_reserved) =
+            collision(database, reservations, &targets, &second)
+                .await
+                .unwrap();
+        assert_eq!(collision_second, Some(2));
+
+        // recorded, and no longer reserved
+        database
+            .insert_event(&event("first", 0, 10_000))
+            .await
+            .unwrap();
+        database
+            .insert_backup(&unifi_protect_data::Backup {
+                event_id: "first".to_string(),
+                target: targets[0].name(),
+                part: 0,
+                remote_path: targets[0].destination(&first),
+                backup_time: Utc::now(),
+                size_bytes: 5,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+        drop(first_reserved);
+        let (collision_third, _) = collision(database, reservations, &targets, &third)
+            .await
+            .unwrap();
+        assert_eq!(collision_third, Some(3));
+
+        // released without being recorded, e.g. the upload failed
+        drop(second_reserved);
+        let (collision_third, _) = collision(database, reservations, &targets, &third)
+            .await
+            .unwrap();
+        assert_eq!(collision_third, Some(2));
+    }
 }
@@ -0,0 +1,144 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{StreamExt, future::join_all};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::warn;
+use unifi_protect_client::{VideoStream, events::ProtectEvent};
+
+use crate::{
+    Error, Result,
+    backup::Backup,
+    validate::{ExportVerifier, validate_header, validate_length},
+};
+
+/// An export streamed to its targets
+pub struct Streamed {
+    pub size_bytes: u64,
+    /// Hex SHA-256 of the whole export, as [`super::sha256`] would give
+    pub sha256: String,
+    /// Each target's remote path or upload error, in the order the targets were given
+    pub uploads: Vec<Result<String>>,
+}
+
+/// Upload an export to every target while it downloads. The download is re-chunked into buffers
+/// of `download-buffer-size` bytes and each target holds at most one at a time, so memory use
+/// doesn't grow with the export and the slowest target sets the pace.
+///
+/// Nothing is uploaded until the start of the export looks like a video. If the download then
+/// fails part way, or the export turns out too short for `duration_ms` (in size, or with
+/// `verify-exports` in how long it plays), the uploads are deleted again and the error returned,
+/// as a rejected buffered export would be.
+///
+/// `resume_from` holds, by target name, how much of this same export an interrupted earlier
+/// attempt passed to each target, see [`Backup::backup_stream`]. `progress` is called with the
+/// bytes passed to every target so far each time a buffer has been.
+pub async fn stream_to_targets(
+    mut video: VideoStream,
+    event: &ProtectEvent,
+    targets: &[&Arc<dyn Backup>],
+    resume_from: &HashMap<String, u64>,
+    config: &crate::backup::Config,
+    duration_ms: i64,
+    progress: impl Fn(u64),
+) -> Result<Streamed> {
+    // enough for the header check
+    let buffer_size = (config.download_buffer_size.0 as usize).max(8);
+
+    let mut first = BytesMut::new();
+    while first.len() < buffer_size {
+        match video.next().await {
+            Some(chunk) => first.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    validate_header(&first)
+        .inspect_err(|err| warn!(event_id = event.id, err = ?err, "Rejecting export"))?;
+
+    let mut verifier = ExportVerifier::new(config, duration_ms)?;
+    let (senders, receivers): (Vec<_>, Vec<_>) =
+        targets.iter().map(|_| mpsc::channel::<Bytes>(1)).unzip();
+
+    let download = async move {
+        let mut hasher = Sha256::new();
+        let mut size_bytes = 0;
+        let mut buffer = first;
+        let mut finished = false;
+
+        while !finished {
+            while buffer.len() < buffer_size {
+                match video.next().await {
+                    Some(chunk) => buffer.extend_from_slice(&chunk?),
+                    None => {
+                        finished = true;
+                        break;
+                    }
+                }
+            }
+
+            let chunk = buffer.split().freeze();
+            if chunk.is_empty() {
+                continue;
+            }
+            hasher.update(&chunk);
+            verifier.feed(&chunk).await?;
+            size_bytes += chunk.len() as u64;
+            for sender in &senders {
+                // a target whose upload failed has hung up, the others carry on
+                sender.send(chunk.clone()).await.ok();
+            }
+            progress(size_bytes);
+        }
+
+        // the uploads can finish while the export is checked
+        drop(senders);
+        verifier
+            .finish()
+            .await
+            .inspect_err(|err| warn!(event_id = event.id, err = ?err, "Rejecting export"))?;
+
+        Ok::<_, Error>((size_bytes, format!("{:x}", hasher.finalize())))
+    };
+    let uploads = join_all(targets.iter().zip(receivers).map(|(target, receiver)| {
+        let resume_from = resume_from.get(&target.name()).copied().unwrap_or_default();
+        target.backup_stream(event, receiver, resume_from)
+    }));
+
+    // an error ends the download early, dropping the senders, so every upload still finishes
+    let (download, uploads) = tokio::join!(download, uploads);
+
+    let download = download.and_then(|(size_bytes, sha256)| {
+        validate_length(
+            size_bytes,
+            duration_ms,
+            config.min_export_bytes_per_second.0,
+        )
+        .inspect_err(|err| warn!(event_id = event.id, err = ?err, "Rejecting export"))?;
+        Ok((size_bytes, sha256))
+    });
+
+    match download {
+        Ok((size_bytes, sha256)) => Ok(Streamed {
+            size_bytes,
+            sha256,
+            uploads,
+        }),
+        Err(err) => {
+            for (target, upload) in targets.iter().zip(&uploads) {
+                let Ok(path) = upload else {
+                    continue;
+                };
+                if let Err(err) = target.delete(path).await {
+                    warn!(
+                        err = ?err,
+                        target = target.name(),
+                        path,
+                        "Failed to delete the upload of an incomplete export"
+                    );
+                }
+            }
+            Err(err)
+        }
+    }
+}
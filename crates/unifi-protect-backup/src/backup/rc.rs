@@ -0,0 +1,272 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, stream};
+use reqwest::{StatusCode, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use tracing::debug;
+use unifi_protect_client::config::Secret;
+
+use crate::{Error, Result, backup::RemoteFile, config::deserialize_optional_file_const_or_env};
+
+/// A long-running `rclone rcd` for an rclone target to send its uploads, deletes and listings to
+/// over the remote control API, rather than starting rclone for each of them. The daemon's own
+/// flags and config apply to those operations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct Config {
+    /// Where the daemon listens, e.g. `http://localhost:5572`
+    pub url: String,
+    /// Matching the daemon's `--rc-user`
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Matching the daemon's `--rc-pass`
+    #[serde(default, deserialize_with = "deserialize_optional_file_const_or_env")]
+    pub pass: Option<Secret<String>>,
+    /// How often the progress of an upload is logged, from the daemon's stats for it
+    #[serde(default = "default_progress_interval", with = "humantime_serde")]
+    pub progress_interval: Duration,
+}
+
+fn default_progress_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Transfer stats of a group of operations, from `core/stats`
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Stats {
+    pub bytes: u64,
+    /// Bytes per second
+    pub speed: f64,
+    pub transfers: u64,
+    pub errors: u64,
+    /// Seconds
+    pub elapsed_time: f64,
+}
+
+/// An entry of `operations/list`, the same as `rclone lsjson` gives
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ListEntry {
+    pub path: String,
+    /// -1 for objects whose size the backend doesn't know
+    pub size: i64,
+    pub mod_time: Option<DateTime<Utc>>,
+}
+
+impl From<ListEntry> for RemoteFile {
+    fn from(entry: ListEntry) -> Self {
+        Self {
+            path: entry.path,
+            size_bytes: entry.size.max(0) as u64,
+            modified: entry.mod_time,
+        }
+    }
+}
+
+/// A client for the rclone remote control API, reusing its connections to the daemon.
+pub struct RcClient {
+    config: Config,
+    client: reqwest::Client,
+}
+
+impl RcClient {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn request(&self, method: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{method}", self.config.url.trim_end_matches('/'));
+        let request = self.client.post(url);
+        match &self.config.user {
+            Some(user) => request.basic_auth(user, self.config.pass.as_ref().map(Secret::expose)),
+            None => request,
+        }
+    }
+
+    /// The response of an RC call, or `None` if it failed because something wasn't found
+    async fn response<T: DeserializeOwned>(
+        method: &str,
+        response: reqwest::Response,
+    ) -> Result<Option<T>> {
+        let status = response.status();
+        let body = response.bytes().await?;
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            // errors come back as `{"error": "...", "status": 500, ...}`
+            let message = serde_json::from_slice::<Value>(&body)
+                .ok()
+                .and_then(|error| error["error"].as_str().map(str::to_string))
+                .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+            return Err(Error::Backup(format!(
+                "rclone rc {method} failed ({status}): {message}"
+            )));
+        }
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<Option<T>> {
+        let response = self.request(method).json(&params).send().await?;
+        Self::response(method, response).await
+    }
+
+    /// Upload `body` to `path` under `fs`, logging the daemon's progress on it as it goes.
+    /// Returns the stats of the finished transfer.
+    pub async fn upload<S>(&self, fs: &str, path: &str, body: S) -> Result<Stats>
+    where
+        S: Stream<Item = Bytes> + Send + 'static,
+    {
+        let (dir, filename) = path.rsplit_once('/').unwrap_or(("", path));
+        // the transfer's own group, so its stats aren't mixed up with concurrent uploads
+        let group = format!("upload/{path}");
+        let boundary = format!(
+            "unifi-protect-backup-{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        let (head, tail) = multipart_frame(&boundary, filename);
+        let body = stream::once(async move { head })
+            .chain(body)
+            .chain(stream::once(async move { tail }))
+            .map(Ok::<_, std::io::Error>);
+
+        let request = self
+            .request("operations/uploadfile")
+            .query(&[("fs", fs), ("remote", dir), ("_group", group.as_str())])
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(reqwest::Body::wrap_stream(body))
+            .send();
+        tokio::pin!(request);
+
+        let mut progress = tokio::time::interval(self.config.progress_interval);
+        progress.tick().await;
+        let response = loop {
+            tokio::select! {
+                response = &mut request => break response?,
+                _ = progress.tick() => {
+                    if let Ok(stats) = self.stats(&group).await {
+                        debug!(
+                            path,
+                            bytes = stats.bytes,
+                            speed = stats.speed as u64,
+                            "Uploading to rclone daemon"
+                        );
+                    }
+                }
+            }
+        };
+        Self::response::<Value>("operations/uploadfile", response)
+            .await?
+            .ok_or_else(|| Error::Backup(format!("rclone rc couldn't find {fs} to upload to")))?;
+
+        let stats = self.stats(&group).await;
+        self.call::<Value>("core/stats-delete", json!({ "group": group }))
+            .await
+            .ok();
+        stats
+    }
+
+    pub async fn stats(&self, group: &str) -> Result<Stats> {
+        Ok(self
+            .call("core/stats", json!({ "group": group }))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Whether `path` under `fs` is a file
+    pub async fn exists(&self, fs: &str, path: &str) -> Result<bool> {
+        let stat: Option<Value> = self
+            .call("operations/stat", json!({ "fs": fs, "remote": path }))
+            .await?;
+        Ok(stat.is_some_and(|stat| stat["item"].is_object()))
+    }
+
+    /// Delete the file at `path` under `fs`. Returns whether it was there to delete.
+    pub async fn delete_file(&self, fs: &str, path: &str) -> Result<bool> {
+        let deleted: Option<Value> = self
+            .call("operations/deletefile", json!({ "fs": fs, "remote": path }))
+            .await?;
+        Ok(deleted.is_some())
+    }
+
+    /// Remove old versions of files under `fs`, on backends that keep them
+    pub async fn cleanup(&self, fs: &str) -> Result<()> {
+        self.call::<Value>("operations/cleanup", json!({ "fs": fs }))
+            .await?;
+        Ok(())
+    }
+
+    /// List the top of `fs`, which fails if the daemon can't be reached or doesn't know the
+    /// remote. A path that doesn't exist yet is fine.
+    pub async fn check(&self, fs: &str) -> Result<()> {
+        self.call::<Value>(
+            "operations/list",
+            json!({ "fs": fs, "remote": "", "opt": { "dirsOnly": true } }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Every file under `fs`, with paths relative to it
+    pub async fn list(&self, fs: &str) -> Result<Vec<ListEntry>> {
+        #[derive(Deserialize)]
+        struct List {
+            list: Vec<ListEntry>,
+        }
+
+        let list: Option<List> = self
+            .call(
+                "operations/list",
+                json!({
+                    "fs": fs,
+                    "remote": "",
+                    "opt": { "recurse": true, "filesOnly": true },
+                }),
+            )
+            .await?;
+        // nothing has been backed up yet
+        Ok(list.map(|list| list.list).unwrap_or_default())
+    }
+}
+
+/// What goes before and after the file's contents in a `multipart/form-data` upload of one file
+fn multipart_frame(boundary: &str, filename: &str) -> (Bytes, Bytes) {
+    let filename = filename.replace('"', "%22");
+    let head = format!(
+        "--{boundary}\r\n\
+         Content-Disposition: form-data; name=\"file0\"; filename=\"{filename}\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n"
+    );
+    let tail = format!("\r\n--{boundary}--\r\n");
+    (head.into(), tail.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_frame() {
+        let (head, tail) = multipart_frame("xyz", "12-00-00 \"front\".mp4");
+        assert_eq!(
+            head,
+            "--xyz\r\nContent-Disposition: form-data; name=\"file0\"; \
+             filename=\"12-00-00 %22front%22.mp4\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n"
+        );
+        assert_eq!(tail, "\r\n--xyz--\r\n");
+    }
+}
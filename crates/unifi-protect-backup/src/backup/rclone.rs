@@ -1,13 +1,31 @@
 use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, stream};
 use metered::{ErrorCount, HitCount, ResponseTime, Throughput};
 use serde::{Deserialize, Serialize};
-use std::{process::Stdio, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
 use tempfile::NamedTempFile;
-use tokio::{io::AsyncWriteExt, process::Command};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::Command,
+    sync::mpsc,
+};
 use tracing::{debug, info, trace};
-use unifi_protect_client::events::ProtectEvent;
+use unifi_protect_client::{config::Secret, events::ProtectEvent};
 
-use crate::{Error, Result, backup, backup::Backup, task::Prune};
+use crate::{
+    Error, Result, backup,
+    backup::{
+        Backup, FailureDomain, RemoteFile, TargetOverrides,
+        compress::{self, Compression},
+        rc, sts,
+    },
+    config::deserialize_optional_file_const_or_env,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
@@ -18,12 +36,212 @@ pub struct Config {
     pub stream_upload: bool,
     #[serde(default)]
     pub chunk_stream_uploads: bool,
+    /// Use temporary credentials from STS instead of the keys in the rclone config (S3 remotes)
+    #[serde(default)]
+    pub sts: Option<sts::Config>,
+    /// Passed to every rclone command as `--bwlimit`, so in rclone's syntax, e.g. `10M` or
+    /// `08:00,512K 23:00,off`
+    #[serde(default)]
+    pub bwlimit: Option<String>,
+    /// The rclone binary to run, `rclone` from the `PATH` if unset
+    #[serde(default)]
+    pub binary: Option<PathBuf>,
+    /// Passed as `--config`, in place of rclone's default config file
+    #[serde(default)]
+    pub config_file: Option<PathBuf>,
+    /// Passed to every rclone command as `--transfers`
+    #[serde(default)]
+    pub transfers: Option<u32>,
+    /// Passed to every rclone command as `--s3-storage-class`, e.g. `GLACIER_IR`
+    #[serde(default)]
+    pub s3_storage_class: Option<String>,
+    /// Passed to every rclone command after the options above, e.g. `["--fast-list"]`
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    /// Password of an encrypted rclone config, set as `RCLONE_CONFIG_PASS`
+    #[serde(default, deserialize_with = "deserialize_optional_file_const_or_env")]
+    pub config_pass: Option<Secret<String>>,
+    /// Upload, delete and list through a running `rclone rcd` instead of starting rclone each time
+    #[serde(default)]
+    pub rc: Option<rc::Config>,
+    /// Compress event backups, e.g. `zstd` or `zstd:19`, adding the compression's extension
+    #[serde(default)]
+    pub compress: Option<Compression>,
+    #[serde(flatten)]
+    pub overrides: TargetOverrides,
+}
+
+impl Config {
+    /// The name backups to this remote are recorded under, see [`Backup::name`]
+    pub fn target_name(&self) -> String {
+        format!("rclone:{}:{}", self.remote, self.base_path)
+    }
 }
 
 pub struct RcloneBackup {
     pub backup_config: backup::Config,
     pub remote_config: Config,
     pub metrics: Arc<Metrics>,
+    pub credentials: Option<Arc<sts::CredentialProvider>>,
+    pub rc: Option<rc::RcClient>,
+}
+
+impl RcloneBackup {
+    /// An rclone command for this remote, with the configured flags and temporary credentials
+    /// injected, for the subcommand and its arguments to follow.
+    async fn rclone(&self) -> Result<Command> {
+        let config = &self.remote_config;
+        let mut command = Command::new(config.binary.as_deref().unwrap_or(Path::new("rclone")));
+        if let Some(bwlimit) = &config.bwlimit {
+            command.env("RCLONE_BWLIMIT", bwlimit);
+        }
+        // rclone takes its global flags ahead of the subcommand as well as after it
+        if let Some(config_file) = &config.config_file {
+            command.arg("--config").arg(config_file);
+        }
+        if let Some(transfers) = config.transfers {
+            command.arg("--transfers").arg(transfers.to_string());
+        }
+        if let Some(storage_class) = &config.s3_storage_class {
+            command.arg("--s3-storage-class").arg(storage_class);
+        }
+        command.args(&config.extra_args);
+        if let Some(pass) = &config.config_pass {
+            command.env("RCLONE_CONFIG_PASS", pass.expose());
+        }
+
+        if let Some(provider) = &self.credentials {
+            let credentials = provider.credentials().await?;
+            // rclone reads per-remote overrides from RCLONE_CONFIG_<REMOTE>_<OPTION>
+            let prefix = format!(
+                "RCLONE_CONFIG_{}",
+                self.remote_config
+                    .remote
+                    .split(':')
+                    .next()
+                    .unwrap_or_default()
+                    .to_uppercase()
+                    .replace('-', "_")
+            );
+            command
+                .env(format!("{prefix}_ENV_AUTH"), "false")
+                .env(format!("{prefix}_ACCESS_KEY_ID"), credentials.access_key_id)
+                .env(
+                    format!("{prefix}_SECRET_ACCESS_KEY"),
+                    credentials.secret_access_key,
+                )
+                .env(format!("{prefix}_SESSION_TOKEN"), credentials.session_token);
+        }
+
+        Ok(command)
+    }
+
+    fn remote_path(&self, filename: &str) -> String {
+        format!(
+            "{}:/{}/{}",
+            self.remote_config.remote,
+            self.remote_config
+                .base_path
+                .trim_start_matches('/')
+                .trim_end_matches('/'),
+            filename
+        )
+    }
+
+    /// The remote path backups are stored under, as the rclone daemon takes it
+    fn rc_fs(&self) -> String {
+        self.remote_path("").trim_end_matches('/').to_string()
+    }
+
+    /// Upload `video` to `filename` through the rclone daemon
+    async fn rc_upload<S>(&self, rc: &rc::RcClient, video: S, filename: &str) -> Result<String>
+    where
+        S: futures_util::Stream<Item = Bytes> + Send + 'static,
+    {
+        let stats = rc.upload(&self.rc_fs(), filename, video).await?;
+        info!(
+            filename,
+            remote = self.remote_config.remote,
+            size_bytes = stats.bytes,
+            speed = stats.speed as u64,
+            elapsed_secs = stats.elapsed_time,
+            "Successfully uploaded event through rclone daemon"
+        );
+        Ok(filename.to_string())
+    }
+
+    /// List the top of the base path, which fails if the remote isn't in rclone's config or
+    /// can't be reached
+    async fn check_remote(&self) -> Result<()> {
+        if let Some(rc) = &self.rc {
+            return rc.check(&self.rc_fs()).await;
+        }
+
+        let output = self
+            .rclone()
+            .await?
+            .arg("lsf")
+            .arg("--max-depth")
+            .arg("1")
+            .arg(self.remote_path(""))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone lsf: {e}")))?;
+
+        match output.status.code() {
+            // directory not found, as it is until the first backup
+            Some(0) | Some(3) => Ok(()),
+            _ => Err(Error::subprocess("rclone lsf", &output)),
+        }
+    }
+
+    /// The stored contents of `path`, as `rclone cat` gives them
+    async fn cat(&self, path: &str) -> Result<std::process::Output> {
+        self.rclone()
+            .await?
+            .arg("cat")
+            .arg(self.remote_path(path))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone cat: {e}")))
+    }
+
+    /// Upload a local file with `rclone copyto`, which knows its size up front.
+    async fn copy_file(&self, source: &Path, dest_path: &str, filename: &str) -> Result<String> {
+        debug!("Uploading {} to {}", source.display(), dest_path);
+
+        // Execute rclone copyto command (copies file to specific destination name)
+        let output = self
+            .rclone()
+            .await?
+            .arg("copyto")
+            .arg(source)
+            .arg(dest_path)
+            .arg("--progress")
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone: {e}")))?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("rclone copyto", &output));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        trace!("Rclone output: {}", stdout);
+
+        info!(
+            filename = filename,
+            remote = self.remote_config.remote,
+            dest_path = dest_path,
+            "Successfully backed up event to rclone remote"
+        );
+
+        Ok(filename.to_string())
+    }
 }
 
 #[metered::metered(registry = Metrics, visibility = pub)]
@@ -33,128 +251,424 @@ impl RcloneBackup {
         remote_config: Config,
         metrics: Arc<Metrics>,
     ) -> Self {
+        let credentials = remote_config
+            .sts
+            .clone()
+            .map(|config| Arc::new(sts::CredentialProvider::new(config)));
+        let rc = remote_config.rc.clone().map(rc::RcClient::new);
+
         Self {
             backup_config,
             remote_config,
             metrics,
+            credentials,
+            rc,
         }
     }
 
     #[tracing::instrument(skip(self, video_data))]
     #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
     async fn backup(&self, event: &ProtectEvent, video_data: &[u8]) -> Result<String> {
-        let filename = event.format_filename(&self.backup_config.file_structure_format);
+        let filename = self.backup_config.filename(event);
+        match &self.remote_config.compress {
+            Some(compression) => {
+                let compressed = compression.compress(video_data)?;
+                self.upload_file(&compressed, &compression.filename(&filename))
+                    .await
+            }
+            None => self.upload_file(video_data, &filename).await,
+        }
+    }
 
-        let dest_path = format!(
-            "{}:/{}/{}",
-            self.remote_config.remote,
-            self.remote_config
-                .base_path
-                .trim_start_matches('/')
-                .trim_end_matches('/'),
-            filename
+    #[tracing::instrument(skip(self, video))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn backup_stream(
+        &self,
+        event: &ProtectEvent,
+        mut video: mpsc::Receiver<Bytes>,
+        resume_from: u64,
+    ) -> Result<String> {
+        let mut filename = self.backup_config.filename(event);
+        let mut compressing = None;
+        if let Some(compression) = &self.remote_config.compress {
+            filename = compression.filename(&filename);
+            let (compressed, handle) = compression.compress_stream(video);
+            video = compressed;
+            compressing = Some(handle);
+        }
+        if let Some(rc) = &self.rc {
+            // the daemon takes the upload as it arrives, without needing its size up front
+            let video = stream::unfold(video, |mut video| async move {
+                video.recv().await.map(|chunk| (chunk, video))
+            });
+            let filename = self.rc_upload(rc, video, &filename).await?;
+            compress::finished(compressing).await?;
+            return Ok(filename);
+        }
+
+        let dest_path = self.remote_path(&filename);
+        if resume_from > 0 {
+            // neither rcat nor copyto can append to what an earlier attempt left on the remote
+            debug!(
+                filename,
+                resume_from, "Restarting interrupted upload from the beginning"
+            );
+        }
+
+        if !self.remote_config.stream_upload {
+            // copyto needs the whole file, so spool it to disk rather than memory
+            let temp_file = NamedTempFile::new()
+                .map_err(|e| Error::Backup(format!("Failed to create temp file: {e}")))?;
+            let mut file = tokio::fs::File::create(temp_file.path())
+                .await
+                .map_err(|e| Error::Backup(format!("Failed to open temp file: {e}")))?;
+            while let Some(chunk) = video.recv().await {
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| Error::Backup(format!("Failed to write video data: {e}")))?;
+            }
+            file.flush()
+                .await
+                .map_err(|e| Error::Backup(format!("Failed to flush temp file: {e}")))?;
+            compress::finished(compressing).await?;
+
+            return self
+                .copy_file(temp_file.path(), &dest_path, &filename)
+                .await;
+        }
+
+        // the size isn't known until the download ends, so rcat can't be told it
+        let mut child = self
+            .rclone()
+            .await?
+            .arg("rcat")
+            .arg(&dest_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Backup(format!("Failed to spawn rclone rcat: {e}")))?;
+
+        let mut size_bytes = 0;
+        {
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| Error::Backup("Failed to get stdin handle".to_string()))?;
+
+            while let Some(chunk) = video.recv().await {
+                stdin.write_all(&chunk).await.map_err(|e| {
+                    Error::Backup(format!("Failed to write chunk to rclone stdin: {e}"))
+                })?;
+                size_bytes += chunk.len();
+            }
+
+            stdin
+                .flush()
+                .await
+                .map_err(|e| Error::Backup(format!("Failed to flush stdin: {e}")))?;
+
+            // Close stdin to signal end of data (stdin is dropped automatically here)
+        }
+
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to wait for rclone rcat: {e}")))?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("rclone rcat", &output));
+        }
+        compress::finished(compressing).await?;
+
+        info!(
+            filename = filename,
+            remote = self.remote_config.remote,
+            dest_path = dest_path,
+            size_bytes,
+            "Successfully streamed event to rclone remote"
         );
 
+        Ok(filename)
+    }
+
+    async fn upload_file(&self, data: &[u8], filename: &str) -> Result<String> {
+        if let Some(rc) = &self.rc {
+            let video = stream::once(std::future::ready(Bytes::copy_from_slice(data)));
+            return self.rc_upload(rc, video, filename).await;
+        }
+
+        let dest_path = self.remote_path(filename);
+
         if self.remote_config.stream_upload {
             if self.remote_config.chunk_stream_uploads {
                 // Use chunked streaming upload
-                self.chunked_stream_upload(video_data, &dest_path, &filename)
-                    .await
+                self.chunked_stream_upload(data, &dest_path, filename).await
             } else {
                 // Use single write streaming upload
-                self.single_stream_upload(video_data, &dest_path, &filename)
-                    .await
+                self.single_stream_upload(data, &dest_path, filename).await
             }
         } else {
             // Use traditional temp file upload
-            self.temp_file_upload(video_data, &dest_path, &filename)
-                .await
+            self.temp_file_upload(data, &dest_path, filename).await
         }
     }
 
     #[tracing::instrument(skip(self))]
     #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
-    async fn prune(&self) -> Result<()> {
-        info!(
-            "Pruning old backups from rclone remote (retention: {:?})",
-            self.backup_config.retention_period
+    async fn relocate(&self, from: &str, to: &str) -> Result<()> {
+        let from_path = self.remote_path(from);
+        let to_path = self.remote_path(to);
+
+        // moveto uses a server-side move/copy where the backend supports it
+        let output = self
+            .rclone()
+            .await?
+            .arg("moveto")
+            .arg(&from_path)
+            .arg(&to_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone moveto: {e}")))?;
+
+        if !output.status.success() {
+            return Err(Error::subprocess("rclone moveto", &output));
+        }
+
+        debug!(
+            from = from_path,
+            to = to_path,
+            "Relocated backup on rclone remote"
         );
+        Ok(())
+    }
 
-        // Convert Duration to a format rclone understands (e.g., "30d", "720h", "43200m")
-        let retention_days = self.backup_config.retention_period.as_secs() / (24 * 60 * 60);
-        let min_age = if retention_days > 0 {
-            format!("{retention_days}d")
-        } else {
-            // Fallback to hours if less than a day
-            let retention_hours = self.backup_config.retention_period.as_secs() / (60 * 60);
-            if retention_hours > 0 {
-                format!("{retention_hours}h")
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn exists(&self, path: &str) -> Result<bool> {
+        if let Some(rc) = &self.rc {
+            return rc.exists(&self.rc_fs(), path).await;
+        }
+
+        let output = self
+            .rclone()
+            .await?
+            .arg("lsf")
+            .arg("--files-only")
+            .arg(self.remote_path(path))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone lsf: {e}")))?;
+
+        match output.status.code() {
+            Some(0) => Ok(!output.stdout.trim_ascii().is_empty()),
+            // directory not found
+            Some(3) => Ok(false),
+            _ => Err(Error::subprocess("rclone lsf", &output)),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn delete(&self, path: &str) -> Result<()> {
+        let remote_path = self.remote_path(path);
+        if let Some(rc) = &self.rc {
+            if rc.delete_file(&self.rc_fs(), path).await? {
+                debug!(remote_path, "Deleted backup from rclone remote");
             } else {
-                // Fallback to minutes
-                let retention_minutes = self.backup_config.retention_period.as_secs() / 60;
-                format!("{}m", retention_minutes.max(1)) // Ensure at least 1 minute
+                debug!(remote_path, "Backup already gone from rclone remote");
             }
+            return Ok(());
+        }
+
+        let output = self
+            .rclone()
+            .await?
+            .arg("deletefile")
+            .arg(&remote_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone deletefile: {e}")))?;
+
+        match output.status.code() {
+            Some(0) => debug!(remote_path, "Deleted backup from rclone remote"),
+            // directory or file not found
+            Some(3) | Some(4) => debug!(remote_path, "Backup already gone from rclone remote"),
+            _ => return Err(Error::subprocess("rclone deletefile", &output)),
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn download(&self, path: &str) -> Result<Vec<u8>> {
+        let output = self.cat(path).await?;
+        if !output.status.success() {
+            return Err(Error::subprocess("rclone cat", &output));
+        }
+
+        compress::decompress(path, output.stdout)
+    }
+
+    /// Copy a stored file to `dest` with `rclone copyto`, rather than through memory, then
+    /// decompress it if it's stored compressed
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn download_to(&self, path: &str, dest: &Path) -> Result<()> {
+        let copied = if compress::is_compressed(path) {
+            Some(NamedTempFile::new()?)
+        } else {
+            None
         };
+        let output = self
+            .rclone()
+            .await?
+            .arg("copyto")
+            .arg(self.remote_path(path))
+            .arg(copied.as_ref().map_or(dest, |copied| copied.path()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone copyto: {e}")))?;
+        if !output.status.success() {
+            return Err(Error::subprocess("rclone copyto", &output));
+        }
 
-        let remote_path = format!(
-            "{}:/{}",
-            self.remote_config.remote,
-            self.remote_config
-                .base_path
-                .trim_start_matches('/')
-                .trim_end_matches('/')
-        );
+        match copied {
+            Some(copied) => {
+                compress::decompress_file(path, copied.path().to_path_buf(), dest.to_path_buf())
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
 
-        debug!("Pruning files older than {} from {}", min_age, remote_path);
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        if let Some(rc) = &self.rc {
+            let entries = rc.list(&self.rc_fs()).await?;
+            return Ok(entries.into_iter().map(RemoteFile::from).collect());
+        }
 
-        // First, do a dry run to see what would be deleted
-        let dry_run_output = Command::new("rclone")
-            .arg("delete")
-            .arg(&remote_path)
-            .arg("--min-age")
-            .arg(&min_age)
-            .arg("--dry-run")
-            .arg("--verbose")
+        let output = self
+            .rclone()
+            .await?
+            .arg("lsjson")
+            .arg("--recursive")
+            .arg("--files-only")
+            .arg(self.remote_path(""))
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
             .await
-            .map_err(|e| Error::Backup(format!("Failed to execute rclone dry-run: {e}")))?;
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone lsjson: {e}")))?;
 
-        if !dry_run_output.status.success() {
-            let stderr = String::from_utf8_lossy(&dry_run_output.stderr);
-            return Err(Error::Backup(format!("Rclone dry-run failed: {stderr}")));
+        match output.status.code() {
+            Some(0) => {}
+            // nothing has been backed up yet
+            Some(3) => return Ok(vec![]),
+            _ => return Err(Error::subprocess("rclone lsjson", &output)),
         }
 
-        // rclone prints dry run info to stderr
-        let dry_run_stderr = String::from_utf8_lossy(&dry_run_output.stderr);
-        let files_to_delete: Vec<&str> = dry_run_stderr
-            .lines()
-            .filter(|line| line.contains("Skipped delete as --dry-run is set"))
-            .collect();
+        let entries: Vec<rc::ListEntry> = serde_json::from_slice(&output.stdout)?;
+        Ok(entries.into_iter().map(RemoteFile::from).collect())
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        // recorded hashes are of the export, so a compressed backup is hashed once decompressed
+        if compress::is_compressed(path) {
+            let output = self.cat(path).await?;
+            return match output.status.code() {
+                Some(0) => {
+                    let data = compress::decompress(path, output.stdout)?;
+                    Ok(Some(backup::sha256(&data)))
+                }
+                // directory or file not found
+                Some(3) | Some(4) => Ok(None),
+                _ => Err(Error::subprocess("rclone cat", &output)),
+            };
+        }
+
+        // `--download` hashes what's actually stored rather than a hash the backend may have
+        // recorded at upload, which is what catches bit-rot
+        let output = self
+            .rclone()
+            .await?
+            .arg("hashsum")
+            .arg("sha256")
+            .arg("--download")
+            .arg(self.remote_path(path))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to execute rclone hashsum: {e}")))?;
+
+        match output.status.code() {
+            Some(0) => {}
+            // directory or file not found
+            Some(3) | Some(4) => return Ok(None),
+            _ => return Err(Error::subprocess("rclone hashsum", &output)),
+        }
+
+        // `sha256sum` format: "<hex digest>  <filename>"
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .map(str::to_lowercase))
+    }
+
+    #[tracing::instrument(skip(self, paths), fields(paths = paths.len()))]
+    #[measure([HitCount, Throughput, ErrorCount, ResponseTime])]
+    async fn delete_many(&self, paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
 
-        if files_to_delete.is_empty() {
+        if let Some(rc) = &self.rc {
+            // one request per file, over the same connection to the daemon
+            let fs = self.rc_fs();
+            for path in paths {
+                rc.delete_file(&fs, path).await?;
+            }
+            if let Err(e) = rc.cleanup(&fs).await {
+                debug!("Rclone cleanup warning (may be normal): {e}");
+            }
             info!(
                 remote = self.remote_config.remote,
-                min_age = min_age,
-                "No files older than {} found to prune",
-                min_age
+                files_deleted = paths.len(),
+                "Deleted backups through rclone daemon and cleaned up hidden versions"
             );
             return Ok(());
         }
 
-        info!(
-            "Found {} files to delete that are older than {}",
-            files_to_delete.len(),
-            min_age
-        );
+        // one rclone run for the whole list rather than one per file
+        let files_from = NamedTempFile::new()
+            .map_err(|e| Error::Backup(format!("Failed to create temp file: {e}")))?;
+        let file_list: String = paths.iter().map(|path| format!("{path}\n")).collect();
+        tokio::fs::write(files_from.path(), file_list)
+            .await
+            .map_err(|e| Error::Backup(format!("Failed to write temp file: {e}")))?;
 
-        // Execute actual rclone delete command with --min-age filter
-        let output = Command::new("rclone")
+        let remote_path = self.remote_path("");
+        let output = self
+            .rclone()
+            .await?
             .arg("delete")
             .arg(&remote_path)
-            .arg("--min-age")
-            .arg(&min_age)
+            .arg("--files-from-raw")
+            .arg(files_from.path())
             .arg("--verbose")
             .arg("--b2-hard-delete")
             .arg("--stats")
@@ -166,8 +680,7 @@ impl RcloneBackup {
             .map_err(|e| Error::Backup(format!("Failed to execute rclone delete: {e}")))?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::Backup(format!("Rclone prune failed: {stderr}")));
+            return Err(Error::subprocess("rclone delete", &output));
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -175,7 +688,9 @@ impl RcloneBackup {
 
         // Run cleanup to remove hidden versions on B2
         info!("Running cleanup to remove hidden file versions from B2");
-        let cleanup_output = Command::new("rclone")
+        let cleanup_output = self
+            .rclone()
+            .await?
             .arg("cleanup")
             .arg(&remote_path)
             .stdout(Stdio::piped())
@@ -191,9 +706,8 @@ impl RcloneBackup {
 
         info!(
             remote = self.remote_config.remote,
-            min_age = min_age,
-            files_deleted = files_to_delete.len(),
-            "Successfully pruned old backups from rclone remote and cleaned up hidden versions"
+            files_deleted = paths.len(),
+            "Deleted backups from rclone remote and cleaned up hidden versions"
         );
 
         Ok(())
@@ -214,7 +728,9 @@ impl RcloneBackup {
         );
 
         // Execute rclone rcat command with size parameter
-        let mut child = Command::new("rclone")
+        let mut child = self
+            .rclone()
+            .await?
             .arg("rcat")
             .arg(dest_path)
             .arg("--size")
@@ -255,10 +771,7 @@ impl RcloneBackup {
             .map_err(|e| Error::Backup(format!("Failed to wait for rclone rcat: {e}")))?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::Backup(format!(
-                "Rclone single stream upload failed: {stderr}"
-            )));
+            return Err(Error::subprocess("rclone rcat", &output));
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -290,7 +803,9 @@ impl RcloneBackup {
         );
 
         // Execute rclone rcat command with size parameter
-        let mut child = Command::new("rclone")
+        let mut child = self
+            .rclone()
+            .await?
             .arg("rcat")
             .arg(dest_path)
             .arg("--size")
@@ -333,10 +848,7 @@ impl RcloneBackup {
             .map_err(|e| Error::Backup(format!("Failed to wait for rclone rcat: {e}")))?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::Backup(format!(
-                "Rclone chunked stream upload failed: {stderr}"
-            )));
+            return Err(Error::subprocess("rclone rcat", &output));
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -377,48 +889,182 @@ impl RcloneBackup {
             .await
             .map_err(|e| Error::Backup(format!("Failed to flush temp file: {e}")))?;
 
-        debug!("Uploading {} to {}", temp_path.display(), dest_path);
+        self.copy_file(temp_path, dest_path, filename).await
+    }
+}
 
-        // Execute rclone copyto command (copies file to specific destination name)
-        let output = Command::new("rclone")
-            .arg("copyto")
-            .arg(temp_path)
-            .arg(dest_path)
-            .arg("--progress")
-            .output()
-            .await
-            .map_err(|e| Error::Backup(format!("Failed to execute rclone: {e}")))?;
+/// How much of a local file [`read_chunks`] reads at a time
+const READ_CHUNK_SIZE: usize = 256 * 1024;
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::Backup(format!("Rclone upload failed: {stderr}")));
+/// `file` a chunk at a time. A read error ends the stream early, leaving the error in `failed`.
+fn read_chunks(
+    file: tokio::fs::File,
+    failed: Arc<Mutex<Option<std::io::Error>>>,
+) -> impl Stream<Item = Bytes> + Send + 'static {
+    stream::unfold(Some(file), move |file| {
+        let failed = failed.clone();
+        async move {
+            let mut file = file?;
+            let mut buffer = BytesMut::zeroed(READ_CHUNK_SIZE);
+            match file.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    buffer.truncate(read);
+                    Some((buffer.freeze(), Some(file)))
+                }
+                Err(err) => {
+                    *failed.lock().expect("read error lock poisoned") = Some(err);
+                    None
+                }
+            }
         }
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        trace!("Rclone output: {}", stdout);
-
-        info!(
-            filename = filename,
-            remote = self.remote_config.remote,
-            dest_path = dest_path,
-            "Successfully backed up event to rclone remote"
-        );
-
-        Ok(filename.to_string())
-    }
+    })
 }
 
 #[async_trait]
 impl Backup for RcloneBackup {
+    fn name(&self) -> String {
+        self.remote_config.target_name()
+    }
+
+    fn backup_config(&self) -> &backup::Config {
+        &self.backup_config
+    }
+
     async fn backup(&self, event: &ProtectEvent, video_data: &[u8]) -> Result<String> {
         self.backup(event, video_data).await
     }
+
+    async fn backup_stream(
+        &self,
+        event: &ProtectEvent,
+        video: mpsc::Receiver<Bytes>,
+        resume_from: u64,
+    ) -> Result<String> {
+        self.backup_stream(event, video, resume_from).await
+    }
+
+    async fn upload(&self, filename: &str, data: &[u8]) -> Result<()> {
+        self.upload_file(data, filename).await.map(|_| ())
+    }
+
+    async fn upload_from(&self, filename: &str, source: &Path) -> Result<()> {
+        if let Some(rc) = &self.rc {
+            // a chunk at a time, rather than the whole file in memory
+            let failed = Arc::new(Mutex::new(None));
+            let chunks = read_chunks(tokio::fs::File::open(source).await?, failed.clone());
+            self.rc_upload(rc, chunks, filename).await?;
+            return match failed.lock().expect("read error lock poisoned").take() {
+                Some(err) => Err(err.into()),
+                None => Ok(()),
+            };
+        }
+        let dest_path = self.remote_path(filename);
+        self.copy_file(source, &dest_path, filename)
+            .await
+            .map(|_| ())
+    }
+
+    async fn relocate(&self, from: &str, to: &str) -> Result<()> {
+        self.relocate(from, to).await
+    }
+
+    fn failure_domain(&self) -> FailureDomain {
+        FailureDomain::Remote
+    }
+
+    async fn validate(&self) -> Result<()> {
+        self.check_remote().await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        self.exists(path).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.delete(path).await
+    }
+
+    async fn download(&self, path: &str) -> Result<Vec<u8>> {
+        self.download(path).await
+    }
+
+    async fn download_to(&self, path: &str, dest: &Path) -> Result<()> {
+        self.download_to(path, dest).await
+    }
+
+    fn destination(&self, event: &ProtectEvent) -> String {
+        let filename = self.backup_config.filename(event);
+        match &self.remote_config.compress {
+            Some(compression) => compression.filename(&filename),
+            None => filename,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        self.list().await
+    }
+
+    async fn sha256(&self, path: &str) -> Result<Option<String>> {
+        self.sha256(path).await
+    }
+
+    async fn delete_many(&self, paths: &[String]) -> Result<()> {
+        self.delete_many(paths).await
+    }
 }
 
-#[async_trait]
-impl Prune for RcloneBackup {
-    #[tracing::instrument(skip(self))]
-    async fn prune(&self) -> Result<()> {
-        self.prune().await
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    async fn test_rclone() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote_config: Config = toml::from_str(
+            r#"
+            remote = "s3"
+            base-path = "protect"
+            bwlimit = "10M"
+            binary = "/opt/rclone/rclone"
+            config-file = "/etc/rclone.conf"
+            transfers = 8
+            s3-storage-class = "GLACIER_IR"
+            extra-args = ["--fast-list"]
+            config-pass = "secret"
+            "#,
+        )
+        .unwrap();
+        let target = RcloneBackup::new(
+            testing::config(&dir, "").backup,
+            remote_config,
+            Arc::new(Metrics::default()),
+        );
+
+        let command = target.rclone().await.unwrap();
+        let command = command.as_std();
+        assert_eq!(command.get_program(), "/opt/rclone/rclone");
+        let args: Vec<_> = command.get_args().filter_map(|arg| arg.to_str()).collect();
+        assert_eq!(
+            args,
+            vec![
+                "--config",
+                "/etc/rclone.conf",
+                "--transfers",
+                "8",
+                "--s3-storage-class",
+                "GLACIER_IR",
+                "--fast-list"
+            ]
+        );
+        let envs: HashMap<_, _> = command
+            .get_envs()
+            .filter_map(|(key, value)| Some((key.to_str()?, value?.to_str()?)))
+            .collect();
+        assert_eq!(envs["RCLONE_BWLIMIT"], "10M");
+        assert_eq!(envs["RCLONE_CONFIG_PASS"], "secret");
     }
 }
@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::warn;
+use unifi_protect_client::events::ProtectEvent;
+
+use crate::{
+    Result,
+    backup::{Backup, compress::ZSTD_EXTENSION},
+};
+
+/// Everything needed to make sense of a backup without the database, written next to it as JSON
+/// when `backup.sidecars` is set.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sidecar {
+    pub event_id: String,
+    pub camera_id: String,
+    pub camera_name: Option<String>,
+    pub event_type: String,
+    pub smart_detect_types: Vec<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    /// Set when a long event was backed up in several parts
+    pub part: Option<u32>,
+    /// Version of Protect the event was exported from
+    pub nvr_version: String,
+    /// Size of the export, before any compression by the target
+    pub size_bytes: u64,
+    /// Hex SHA-256 of the export, before any compression by the target
+    pub sha256: String,
+    pub backup_time: DateTime<Utc>,
+}
+
+impl Sidecar {
+    pub fn new(
+        event: &ProtectEvent,
+        nvr_version: &str,
+        size_bytes: u64,
+        sha256: &str,
+        backup_time: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            event_id: event.id.clone(),
+            camera_id: event.camera_id.clone(),
+            camera_name: event.camera_name.clone(),
+            event_type: event.event_type.to_string(),
+            smart_detect_types: event
+                .smart_detect_types
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            start_time: event.start_time.and_then(DateTime::from_timestamp_millis),
+            end_time: event.end_time.and_then(DateTime::from_timestamp_millis),
+            part: event.part,
+            nvr_version: nvr_version.to_string(),
+            size_bytes,
+            sha256: sha256.to_string(),
+            backup_time,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Where the sidecar of the backup stored at `remote_path` goes: the same path, without any
+/// compression extension, plus `.json`
+pub fn sidecar_path(remote_path: &str) -> String {
+    let path = remote_path
+        .strip_suffix(ZSTD_EXTENSION)
+        .unwrap_or(remote_path);
+    format!("{path}.json")
+}
+
+/// Upload the sidecar of the backup stored at `remote_path`. The backup is still good without
+/// one, so failing is only logged.
+pub async fn write(target: &dyn Backup, remote_path: &str, sidecar: &Sidecar) {
+    let path = sidecar_path(remote_path);
+    let result = match sidecar.to_json() {
+        Ok(json) => target.upload(&path, &json).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        warn!(err = ?err, target = target.name(), path, "Failed to write backup sidecar");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_path() {
+        let json = "Driveway/2025-08-04/12-00-00.mp4.json";
+        assert_eq!(sidecar_path("Driveway/2025-08-04/12-00-00.mp4"), json);
+        assert_eq!(sidecar_path("Driveway/2025-08-04/12-00-00.mp4.zst"), json);
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use unifi_protect_client::events::ProtectEvent;
+
+use crate::{Result, backup::compress::ZSTD_EXTENSION, context::Context};
+
+pub const SNAPSHOT_EXTENSION: &str = ".jpg";
+
+/// Whether events get a JPEG snapshot backed up, for quotas too small for video
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotMode {
+    /// Back up the video only
+    #[default]
+    Off,
+    /// Back up the video with a snapshot next to it
+    WithVideo,
+    /// Back up a snapshot instead of the video
+    Only,
+}
+
+/// Where the snapshot of the backup stored at `remote_path` goes: the same path, without any
+/// compression or `.mp4` extension, plus `.jpg`
+pub fn snapshot_path(remote_path: &str) -> String {
+    let path = remote_path
+        .strip_suffix(ZSTD_EXTENSION)
+        .unwrap_or(remote_path);
+    let path = path.strip_suffix(".mp4").unwrap_or(path);
+    format!("{path}{SNAPSHOT_EXTENSION}")
+}
+
+/// The path of the video a snapshot at `path` is named after, if it's a snapshot, for parsing
+/// the event's details back out of it
+pub fn video_path(path: &str) -> Option<String> {
+    path.strip_suffix(SNAPSHOT_EXTENSION)
+        .map(|path| format!("{path}.mp4"))
+}
+
+/// The event's thumbnail, or if the NVR has none, a snapshot from its camera at the event's start
+pub async fn fetch(context: &Context, event: &ProtectEvent) -> Result<Vec<u8>> {
+    match context.protect_client.get_event_thumbnail(&event.id).await {
+        Ok(jpeg) => Ok(jpeg),
+        Err(err) => {
+            debug!(err = ?err, event_id = event.id, "No event thumbnail, taking a snapshot");
+            let start = event.start_time.unwrap_or_default();
+            let camera_id = &event.camera_id;
+            Ok(context
+                .protect_client
+                .get_camera_snapshot(camera_id, start)
+                .await?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        convert::protect_event_from_database_event,
+        testing::{self, CAMERA_ID, TestContext},
+    };
+
+    #[test]
+    fn test_snapshot_path() {
+        let jpeg = "Driveway/2025-08-04/12-00-00.jpg";
+        assert_eq!(snapshot_path("Driveway/2025-08-04/12-00-00.mp4"), jpeg);
+        assert_eq!(snapshot_path("Driveway/2025-08-04/12-00-00.mp4.zst"), jpeg);
+        assert_eq!(
+            video_path(jpeg).unwrap(),
+            "Driveway/2025-08-04/12-00-00.mp4"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let event = protect_event_from_database_event(
+            testing::event("event", 0, 10_000),
+            &context.protect_bootstrap.load(),
+        );
+        test.protect.set_snapshot(CAMERA_ID, b"snapshot".to_vec());
+
+        // without a thumbnail, a snapshot from the camera
+        assert_eq!(fetch(context, &event).await.unwrap(), b"snapshot");
+
+        test.protect.set_thumbnail("event", b"thumbnail".to_vec());
+        assert_eq!(fetch(context, &event).await.unwrap(), b"thumbnail");
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Mutex,
+};
+use tracing::{debug, info, warn};
+use unifi_protect_client::{VideoStream, error::Error as ClientError};
+
+use crate::{Error, Result, size::ByteSize};
+
+const PARTIAL_EXTENSION: &str = "partial";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct Config {
+    pub path: PathBuf,
+    /// Evict spooled exports once together they're larger than this, e.g. `20GiB`
+    pub max_size: ByteSize,
+    #[serde(default)]
+    pub eviction: Eviction,
+}
+
+/// Which spooled exports make room first when the spool is over `max-size`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Eviction {
+    /// Those staged longest ago
+    #[default]
+    Oldest,
+    /// The largest, keeping as many exports as possible
+    Largest,
+}
+
+/// Exports staged on disk once and uploaded to every target from there, so a target that's slow
+/// or failing doesn't mean exporting from the NVR again for each attempt.
+pub struct Spool {
+    config: Config,
+    /// Held while evicting, so concurrent stages don't each size up the spool and then evict
+    /// what the other just staged, or evict more than either needed to
+    evicting: Mutex<()>,
+}
+
+impl Spool {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            evicting: Mutex::new(()),
+        }
+    }
+
+    fn path(&self, event_id: &str, part: u32) -> PathBuf {
+        self.config.path.join(format!("{event_id}_{part}.mp4"))
+    }
+
+    /// The staged export of an event part, if it's still spooled
+    pub async fn get(&self, event_id: &str, part: u32) -> Option<PathBuf> {
+        let path = self.path(event_id, part);
+        fs::try_exists(&path).await.ok()?.then_some(path)
+    }
+
+    /// Write an export to the spool as it downloads, then evict other exports if the spool is now
+    /// over its size. Checking the export is left to the upload.
+    pub async fn stage(
+        &self,
+        event_id: &str,
+        part: u32,
+        mut video: VideoStream,
+    ) -> Result<PathBuf> {
+        fs::create_dir_all(&self.config.path).await?;
+
+        // a download that fails part way never looks staged
+        let path = self.path(event_id, part);
+        let partial = path.with_extension(PARTIAL_EXTENSION);
+        let written = async {
+            let mut file = fs::File::create(&partial).await?;
+            while let Some(chunk) = video.next().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.flush().await?;
+            Ok::<_, Error>(())
+        }
+        .await;
+        if let Err(err) = written {
+            fs::remove_file(&partial).await.ok();
+            return Err(err);
+        }
+
+        fs::rename(&partial, &path).await?;
+        debug!(event_id, part, "Staged export in the spool");
+
+        self.evict(&path).await?;
+        Ok(path)
+    }
+
+    /// Read a staged export back in chunks of `buffer_size` bytes.
+    pub async fn open(&self, path: &Path, buffer_size: usize) -> Result<VideoStream> {
+        let file = fs::File::open(path).await?;
+        let buffer_size = buffer_size.max(1);
+
+        Ok(Box::pin(stream::unfold(
+            Some(file),
+            move |file| async move {
+                let mut file = file?;
+                let mut buffer = BytesMut::zeroed(buffer_size);
+                match file.read(&mut buffer).await {
+                    Ok(0) => None,
+                    Ok(read) => {
+                        buffer.truncate(read);
+                        Some((Ok(Bytes::from(buffer)), Some(file)))
+                    }
+                    Err(err) => Some((Err(ClientError::from(err)), None)),
+                }
+            },
+        )))
+    }
+
+    /// Drop an export from the spool once every target has it, or it turned out to be bad.
+    pub async fn remove(&self, event_id: &str, part: u32) {
+        let path = self.path(event_id, part);
+        if let Err(err) = fs::remove_file(&path).await
+            && err.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!(err = ?err, path = %path.display(), "Failed to remove export from the spool");
+        }
+    }
+
+    /// Remove staged exports other than `keep`, in `eviction` order, until the spool is within
+    /// `max-size`. Evicted exports are downloaded from the NVR again if still needed.
+    async fn evict(&self, keep: &Path) -> Result<()> {
+        let _evicting = self.evicting.lock().await;
+        let mut staged = vec![];
+        let mut total = 0;
+        let mut entries = fs::read_dir(&self.config.path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            total += metadata.len();
+            let partial = path.extension().is_some_and(|ext| ext == PARTIAL_EXTENSION);
+            if path != keep && !partial {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                staged.push((path, metadata.len(), modified));
+            }
+        }
+
+        match self.config.eviction {
+            Eviction::Oldest => staged.sort_by_key(|(_, _, modified)| *modified),
+            Eviction::Largest => staged.sort_by_key(|(_, size, _)| Reverse(*size)),
+        }
+
+        let mut evicted = 0;
+        for (path, size, _) in staged {
+            if total <= self.config.max_size.0 {
+                break;
+            }
+            match fs::remove_file(&path).await {
+                Ok(()) => {
+                    total -= size;
+                    evicted += 1;
+                }
+                Err(err) => warn!(err = ?err, path = %path.display(), "Failed to evict export"),
+            }
+        }
+
+        if evicted > 0 {
+            info!(
+                evicted,
+                spooled_bytes = total,
+                "Evicted exports from the spool"
+            );
+        }
+        if total > self.config.max_size.0 {
+            warn!(
+                spooled_bytes = total,
+                max_size = %self.config.max_size,
+                "Spool is over its size even after eviction"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::join;
+
+    use super::*;
+
+    fn video(data: &'static [u8]) -> VideoStream {
+        Box::pin(stream::iter([Ok(Bytes::from_static(data))]))
+    }
+
+    #[tokio::test]
+    async fn test_stage_evicts_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::new(Config {
+            path: dir.path().to_path_buf(),
+            max_size: ByteSize(15),
+            eviction: Eviction::Oldest,
+        });
+
+        // only one of the two fits, and evicting one of them makes room for the other
+        let (a, b) = join(
+            spool.stage("a", 0, video(b"0123456789")),
+            spool.stage("b", 0, video(b"0123456789")),
+        )
+        .await;
+        let (a, b) = (a.unwrap(), b.unwrap());
+        let kept = [&a, &b].into_iter().filter(|path| path.exists()).count();
+        assert_eq!(kept, 1);
+    }
+}
@@ -0,0 +1,208 @@
+use std::{path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::{Error, Result};
+
+/// Short-lived credentials obtained from STS via `AssumeRoleWithWebIdentity`. Unset fields fall
+/// back to the `AWS_ROLE_ARN` and `AWS_WEB_IDENTITY_TOKEN_FILE` environment variables, which are
+/// injected automatically on EKS (IRSA) and similar platforms.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct Config {
+    pub role_arn: Option<String>,
+    pub web_identity_token_file: Option<PathBuf>,
+    #[serde(default = "default_session_name")]
+    pub session_name: String,
+    /// Requested lifetime of each set of credentials
+    #[serde(default = "default_duration", with = "humantime_serde")]
+    pub duration: Duration,
+    /// Credentials are refreshed this long before they expire
+    #[serde(default = "default_refresh_before", with = "humantime_serde")]
+    pub refresh_before: Duration,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+}
+
+fn default_session_name() -> String {
+    "unifi-protect-backup".to_string()
+}
+
+fn default_duration() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_refresh_before() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_endpoint() -> String {
+    "https://sts.amazonaws.com".to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: String,
+    pub expiration: DateTime<Utc>,
+}
+
+pub struct CredentialProvider {
+    config: Config,
+    client: reqwest::Client,
+    cached: Mutex<Option<Credentials>>,
+}
+
+impl CredentialProvider {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Current credentials, assuming the role again if the cached ones are close to expiry.
+    pub async fn credentials(&self) -> Result<Credentials> {
+        let mut cached = self.cached.lock().await;
+
+        let refresh_at = |credentials: &Credentials| {
+            credentials.expiration
+                - chrono::Duration::from_std(self.config.refresh_before)
+                    .unwrap_or(chrono::Duration::zero())
+        };
+
+        match cached.as_ref() {
+            Some(credentials) if refresh_at(credentials) > Utc::now() => Ok(credentials.clone()),
+            _ => {
+                let credentials = self.assume_role().await?;
+                info!(
+                    expiration = %credentials.expiration,
+                    "Obtained temporary storage credentials"
+                );
+                *cached = Some(credentials.clone());
+                Ok(credentials)
+            }
+        }
+    }
+
+    async fn assume_role(&self) -> Result<Credentials> {
+        let role_arn = match &self.config.role_arn {
+            Some(role_arn) => role_arn.clone(),
+            None => std::env::var("AWS_ROLE_ARN").map_err(|_| {
+                Error::Credentials("No role-arn configured and AWS_ROLE_ARN is not set".to_string())
+            })?,
+        };
+        let token_file = match &self.config.web_identity_token_file {
+            Some(path) => path.clone(),
+            None => std::env::var("AWS_WEB_IDENTITY_TOKEN_FILE")
+                .map(PathBuf::from)
+                .map_err(|_| {
+                    Error::Credentials(
+                        "No web-identity-token-file configured and AWS_WEB_IDENTITY_TOKEN_FILE is not set"
+                            .to_string(),
+                    )
+                })?,
+        };
+
+        // the token is rotated on disk by the platform, so read it again on every refresh
+        let token = tokio::fs::read_to_string(&token_file).await.map_err(|e| {
+            Error::Credentials(format!(
+                "Failed to read web identity token {}: {e}",
+                token_file.display()
+            ))
+        })?;
+
+        debug!(role_arn, "Assuming role with web identity");
+
+        // AssumeRoleWithWebIdentity is authenticated by the token itself, no request signing needed
+        let response = self
+            .client
+            .post(&self.config.endpoint)
+            .form(&[
+                ("Action", "AssumeRoleWithWebIdentity"),
+                ("Version", "2011-06-15"),
+                ("RoleArn", role_arn.as_str()),
+                ("RoleSessionName", self.config.session_name.as_str()),
+                ("WebIdentityToken", token.trim()),
+                (
+                    "DurationSeconds",
+                    self.config.duration.as_secs().to_string().as_str(),
+                ),
+            ])
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(Error::Credentials(format!(
+                "AssumeRoleWithWebIdentity failed ({status}): {}",
+                xml_value(&body, "Message").unwrap_or(&body)
+            )));
+        }
+
+        parse_credentials(&body)
+    }
+}
+
+fn parse_credentials(body: &str) -> Result<Credentials> {
+    let field = |tag: &str| {
+        xml_value(body, tag)
+            .map(str::to_string)
+            .ok_or_else(|| Error::Credentials(format!("STS response is missing {tag}")))
+    };
+
+    let expiration = DateTime::parse_from_rfc3339(&field("Expiration")?)
+        .map_err(|e| Error::Credentials(format!("Invalid credential expiration: {e}")))?
+        .with_timezone(&Utc);
+
+    Ok(Credentials {
+        access_key_id: field("AccessKeyId")?,
+        secret_access_key: field("SecretAccessKey")?,
+        session_token: field("SessionToken")?,
+        expiration,
+    })
+}
+
+/// The text content of the first `<tag>` element. STS responses are flat enough that this avoids
+/// pulling in an XML parser.
+fn xml_value<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = body[start..].find(&format!("</{tag}>"))? + start;
+    Some(body[start..end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_credentials() {
+        let body = r#"<AssumeRoleWithWebIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleWithWebIdentityResult>
+    <Credentials>
+      <SessionToken>session-token</SessionToken>
+      <SecretAccessKey>secret</SecretAccessKey>
+      <Expiration>2025-08-14T13:34:41Z</Expiration>
+      <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+    </Credentials>
+  </AssumeRoleWithWebIdentityResult>
+</AssumeRoleWithWebIdentityResponse>"#;
+
+        let credentials = parse_credentials(body).unwrap();
+        assert_eq!(credentials.access_key_id, "ASIAEXAMPLE");
+        assert_eq!(credentials.secret_access_key, "secret");
+        assert_eq!(credentials.session_token, "session-token");
+        assert_eq!(
+            credentials.expiration.to_rfc3339(),
+            "2025-08-14T13:34:41+00:00"
+        );
+
+        assert!(parse_credentials("<Error><Message>denied</Message></Error>").is_err());
+    }
+}
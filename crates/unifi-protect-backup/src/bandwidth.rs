@@ -0,0 +1,178 @@
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveTime};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::time::{Instant, sleep_until};
+use unifi_protect_client::VideoStream;
+
+use crate::{Error, clock::Clock};
+
+/// A bandwidth limit in rclone's `--bwlimit` syntax: a single rate such as `10MiB`, or a timetable
+/// of `HH:MM,RATE` entries such as `08:00,512K 19:00,10M 23:00,off`, in the host's local time.
+/// Rates without a unit are KiB/s and `off` is unlimited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BandwidthSchedule {
+    spec: String,
+    /// Start of each entry with its rate in bytes per second, sorted by start
+    entries: Vec<(NaiveTime, Option<u64>)>,
+}
+
+impl BandwidthSchedule {
+    /// The limit in bytes per second at `time`, or `None` if unlimited. Before the first entry of
+    /// a timetable, the last entry from the previous day still applies.
+    pub fn rate_at(&self, time: NaiveTime) -> Option<u64> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= time)
+            .or(self.entries.last())
+            .and_then(|(_, rate)| *rate)
+    }
+}
+
+impl TryFrom<String> for BandwidthSchedule {
+    type Error = Error;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        let invalid =
+            |reason: &str| Error::General(format!("Invalid bandwidth limit {spec:?}: {reason}"));
+
+        let words: Vec<_> = spec.split_whitespace().collect();
+        let mut entries = vec![];
+        for word in &words {
+            let (start, rate) = match word.split_once(',') {
+                Some((start, rate)) => (
+                    NaiveTime::parse_from_str(start, "%H:%M")
+                        .map_err(|_| invalid("times must be HH:MM"))?,
+                    rate,
+                ),
+                // a plain rate applies all day, so only makes sense on its own
+                None if words.len() == 1 => (NaiveTime::MIN, *word),
+                None => return Err(invalid("timetable entries must be HH:MM,RATE")),
+            };
+            entries.push((
+                start,
+                parse_rate(rate).ok_or_else(|| invalid("unknown rate"))?,
+            ));
+        }
+        if entries.is_empty() {
+            return Err(invalid("no rate given"));
+        }
+        entries.sort_by_key(|(start, _)| *start);
+
+        Ok(Self { spec, entries })
+    }
+}
+
+impl From<BandwidthSchedule> for String {
+    fn from(schedule: BandwidthSchedule) -> Self {
+        schedule.spec
+    }
+}
+
+impl Display for BandwidthSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.spec)
+    }
+}
+
+/// Bytes per second from a rate such as `512K`, `10MiB` or `off`; `None` inside means unlimited
+fn parse_rate(rate: &str) -> Option<Option<u64>> {
+    if rate.eq_ignore_ascii_case("off") {
+        return Some(None);
+    }
+
+    let split = rate
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(rate.len());
+    let (number, unit) = rate.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.to_ascii_lowercase().trim_end_matches("/s") {
+        "b" => 1,
+        "" | "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        _ => return None,
+    };
+
+    Some(Some((number * multiplier as f64) as u64))
+}
+
+/// Paces data to a [`BandwidthSchedule`], shared by everything the limit applies to
+pub struct Limiter {
+    schedule: BandwidthSchedule,
+    clock: Arc<dyn Clock>,
+    /// When the data let through so far will have been sent at the current rate
+    next: Mutex<Instant>,
+}
+
+impl Limiter {
+    pub fn new(schedule: BandwidthSchedule, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            schedule,
+            clock,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until `bytes` more can be sent within the limit.
+    pub async fn consume(&self, bytes: usize) {
+        let time = self.clock.now().with_timezone(&Local).time();
+        let Some(rate) = self.schedule.rate_at(time).filter(|rate| *rate > 0) else {
+            return;
+        };
+
+        let start = {
+            let mut next = self.next.lock().expect("limiter lock poisoned");
+            // unused allowance doesn't carry over, so an idle limiter can't burst
+            let start = (*next).max(Instant::now());
+            *next = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            start
+        };
+        sleep_until(start).await;
+    }
+
+    /// Pass `video` through at no more than the limit.
+    pub fn throttle(self: &Arc<Self>, video: VideoStream) -> VideoStream {
+        let limiter = Arc::clone(self);
+        Box::pin(video.then(move |chunk| {
+            let limiter = Arc::clone(&limiter);
+            async move {
+                if let Ok(chunk) = &chunk {
+                    limiter.consume(chunk.len()).await;
+                }
+                chunk
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let flat = BandwidthSchedule::try_from("10MiB".to_string()).unwrap();
+        assert_eq!(flat.rate_at(at(12, 0)), Some(10 * 1024 * 1024));
+
+        let timetable =
+            BandwidthSchedule::try_from("08:00,512K 19:00,10M 23:00,off".to_string()).unwrap();
+        assert_eq!(timetable.rate_at(at(7, 59)), None);
+        assert_eq!(timetable.rate_at(at(8, 0)), Some(512 * 1024));
+        assert_eq!(timetable.rate_at(at(20, 0)), Some(10 * 1024 * 1024));
+
+        assert!(BandwidthSchedule::try_from("fast".to_string()).is_err());
+        assert!(BandwidthSchedule::try_from("10M 20M".to_string()).is_err());
+    }
+}
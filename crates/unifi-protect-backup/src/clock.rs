@@ -0,0 +1,96 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+/// The source of "now" for scheduling decisions (retention cutoffs, export delays, retry and
+/// next-run times), so they can be tested against a [`ManualClock`] rather than the wall clock.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// The time `duration` before now, saturating for periods too long to represent.
+    fn ago(&self, duration: Duration) -> DateTime<Utc> {
+        chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| self.now().checked_sub_signed(duration))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+    }
+
+    /// The time `duration` from now, saturating for periods too long to represent.
+    fn after(&self, duration: Duration) -> DateTime<Utc> {
+        chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| self.now().checked_add_signed(duration))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+/// The wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock which only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: RwLock<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: RwLock::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.write().expect("clock lock poisoned") = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.write().expect("clock lock poisoned");
+        *now += chrono::Duration::from_std(duration).expect("duration out of range");
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.read().expect("clock lock poisoned")
+    }
+}
+
+pub fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let start = Utc.with_ymd_and_hms(2025, 8, 4, 12, 0, 0).unwrap();
+        let clock = ManualClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_secs(90 * 60));
+        assert_eq!(
+            clock.now(),
+            Utc.with_ymd_and_hms(2025, 8, 4, 13, 30, 0).unwrap()
+        );
+        assert_eq!(clock.ago(Duration::from_secs(90 * 60)), start);
+
+        // retention periods too long for chrono don't wrap around
+        assert_eq!(clock.ago(Duration::MAX), DateTime::<Utc>::MIN_UTC);
+        assert_eq!(clock.after(Duration::MAX), DateTime::<Utc>::MAX_UTC);
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+use tracing::{info, warn};
+use unifi_protect_client::{ProtectClient, models::ExportQuality};
+
+use crate::{
+    Error, Result, backup,
+    config::{Config, default_config_path},
+    retention::RetentionPolicy,
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CamerasCommand {
+    /// Print the effective backup settings of every camera on the NVR
+    Export {
+        #[arg(long, value_enum, default_value = "toml")]
+        format: Format,
+    },
+    /// Apply camera settings from a file produced by `cameras export` to the config file
+    Import {
+        /// TOML or JSON file, detected by extension
+        file: PathBuf,
+        /// Config file to update
+        #[arg(long, env = "CONFIG")]
+        config_file: Option<PathBuf>,
+        /// Print the updated config instead of writing it
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+/// The resolved settings for one camera. Settings with a `[backup]` default are left as they are
+/// on import when they're missing, so files exported before they were added still import.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CameraSettings {
+    pub id: String,
+    /// Informational only; cameras are matched by id on import, along with the name of any
+    /// `[[camera]]` block
+    #[serde(default)]
+    pub name: String,
+    /// Whether the camera passes the `cameras` and `ignore-cameras` filters
+    pub backup: bool,
+    pub export_quality: ExportQuality,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detection_types: Option<Vec<String>>,
+    /// Unset has no minimum
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min_event_length: Option<Duration>,
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_event_length: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_structure_format: Option<String>,
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub retention_period: Option<Duration>,
+    /// Names of the targets the camera's events are backed up to. Unset backs up to every target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<Vec<String>>,
+}
+
+impl CameraSettings {
+    /// What `config` does with the camera, after its `[[camera]]` block if it has one
+    fn resolve(config: &backup::Config, camera_id: &str, camera_name: &str) -> Self {
+        let camera = config.camera_config(camera_id, Some(camera_name));
+        let names = [camera_id.to_string(), camera_name.to_string()];
+        Self {
+            id: camera_id.to_string(),
+            name: camera_name.to_string(),
+            backup: config.camera_enabled(camera_id, Some(camera_name)),
+            export_quality: config.export_quality(camera_id, Some(camera_name)),
+            detection_types: Some(
+                camera
+                    .and_then(|camera| camera.detection_types.clone())
+                    .unwrap_or_else(|| config.detection_types.clone()),
+            ),
+            min_event_length: camera.and_then(|camera| camera.min_event_length),
+            max_event_length: Some(config.max_event_length(camera_id, Some(camera_name))),
+            file_structure_format: Some(
+                camera
+                    .and_then(|camera| camera.file_structure_format.clone())
+                    .unwrap_or_else(|| config.file_structure_format.clone()),
+            ),
+            retention_period: Some(config.retention_policy().max_age_for(&[], &names)),
+            targets: camera.and_then(|camera| camera.targets.clone()),
+        }
+    }
+
+    /// The keys of the camera's `[[camera]]` block which produce these settings, with `None` for
+    /// those to remove because `[backup]` already does
+    fn camera_block(&self, config: &backup::Config) -> Result<Vec<(&'static str, Option<Value>)>> {
+        let mut keys = vec![];
+        if let Some(detection_types) = &self.detection_types {
+            let value = (detection_types != &config.detection_types)
+                .then(|| to_value(detection_types))
+                .transpose()?;
+            keys.push(("detection-types", value));
+        }
+        keys.push((
+            "min-event-length",
+            self.min_event_length
+                .map(|length| to_value(humantime_serde::Serde::from(length)))
+                .transpose()?,
+        ));
+        if let Some(max_event_length) = self.max_event_length {
+            let value = (max_event_length != config.max_event_length)
+                .then(|| to_value(humantime_serde::Serde::from(max_event_length)))
+                .transpose()?;
+            keys.push(("max-event-length", value));
+        }
+        if let Some(format) = &self.file_structure_format {
+            let value = (format != &config.file_structure_format)
+                .then(|| to_value(format))
+                .transpose()?;
+            keys.push(("file-structure-format", value));
+        }
+        if let Some(retention_period) = self.retention_period {
+            // what `[backup]` keeps the camera's events for without a `[[camera]]` block
+            let names = [self.id.clone(), self.name.clone()];
+            let default = RetentionPolicy::new(config.retention_period, &config.retention)
+                .max_age_for(&[], &names);
+            let value = (retention_period != default)
+                .then(|| to_value(humantime_serde::Serde::from(retention_period)))
+                .transpose()?;
+            keys.push(("retention-period", value));
+        }
+        keys.push(("targets", self.targets.as_ref().map(to_value).transpose()?));
+        Ok(keys)
+    }
+
+    /// Whether `block`, a `[[camera]]` block, is this camera's, matched as
+    /// [`CameraConfig::matches`](backup::CameraConfig::matches) does
+    fn matches(&self, block: &Table) -> bool {
+        let key = |key: &str| block.get(key).and_then(Item::as_str);
+        key("id") == Some(self.id.as_str())
+            || !self.name.is_empty() && key("name") == Some(self.name.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CameraSettingsFile {
+    camera: Vec<CameraSettings>,
+}
+
+impl CamerasCommand {
+    pub async fn run(&self, config: &Config) -> Result<()> {
+        match self {
+            CamerasCommand::Export { format } => export(config, *format).await,
+            CamerasCommand::Import {
+                file,
+                config_file,
+                dry_run,
+            } => {
+                let config_file = config_file.clone().unwrap_or_else(default_config_path);
+                import(config, file, &config_file, *dry_run)
+            }
+        }
+    }
+}
+
+#[tracing::instrument(skip(config))]
+async fn export(config: &Config, format: Format) -> Result<()> {
+    let protect_client = ProtectClient::new(config.unifi.clone())?;
+    protect_client.login().await?;
+    let bootstrap = protect_client.get_bootstrap().await?;
+
+    let mut cameras: Vec<_> = bootstrap
+        .cameras
+        .values()
+        .map(|camera| CameraSettings::resolve(&config.backup, &camera.id, &camera.name))
+        .collect();
+    cameras.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let file = CameraSettingsFile { camera: cameras };
+    let output = match format {
+        Format::Toml => toml::to_string_pretty(&file)?,
+        Format::Json => serde_json::to_string_pretty(&file)?,
+    };
+    println!("{output}");
+
+    Ok(())
+}
+
+/// Edit the camera filters and quality overrides in `[backup]` and the `[[camera]]` blocks in
+/// place so they produce `file`'s settings, keeping the config file's comments and formatting.
+/// Cameras missing from the file are backed up with the default quality.
+#[tracing::instrument(skip(config))]
+fn import(config: &Config, file: &Path, config_file: &Path, dry_run: bool) -> Result<()> {
+    let contents = fs::read_to_string(file)?;
+    let settings: CameraSettingsFile = if file
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        serde_json::from_str(&contents)?
+    } else {
+        toml::from_str(&contents)?
+    };
+
+    let mut document: DocumentMut = fs::read_to_string(config_file)?.parse()?;
+    apply(config, &settings.camera, config_file, &mut document)?;
+
+    let output = document.to_string();
+    if dry_run {
+        println!("{output}");
+        return Ok(());
+    }
+
+    fs::write(config_file, output)?;
+    info!(
+        cameras = settings.camera.len(),
+        config_file = %config_file.display(),
+        "Imported camera settings"
+    );
+
+    Ok(())
+}
+
+fn apply(
+    config: &Config,
+    cameras: &[CameraSettings],
+    config_file: &Path,
+    document: &mut DocumentMut,
+) -> Result<()> {
+    let Some(backup) = document.get_mut("backup").and_then(Item::as_table_like_mut) else {
+        return Err(Error::General(format!(
+            "{} has no [backup] section",
+            config_file.display()
+        )));
+    };
+
+    let ignored: Array = cameras
+        .iter()
+        .filter(|camera| !camera.backup)
+        .map(|camera| camera.id.as_str())
+        .collect();
+    let export_quality = cameras
+        .iter()
+        .filter(|camera| camera.export_quality != config.backup.export_quality)
+        .map(|camera| Ok((camera.id.as_str(), to_value(camera.export_quality)?)))
+        .collect::<Result<InlineTable>>()?;
+
+    if !config.backup.cameras.is_empty() {
+        warn!("Clearing the `cameras` allow-list; `ignore-cameras` now selects cameras");
+    }
+    set(backup, "cameras", Array::new());
+    set(backup, "ignore-cameras", ignored);
+    set(backup, "camera-export-quality", export_quality);
+
+    let Some(blocks) = document
+        .entry("camera")
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+    else {
+        return Err(Error::General(format!(
+            "`camera` in {} isn't a list of [[camera]] blocks",
+            config_file.display()
+        )));
+    };
+    for camera in cameras {
+        let keys = camera.camera_block(&config.backup)?;
+        let index = match blocks.iter().position(|block| camera.matches(block)) {
+            Some(index) => index,
+            // nothing to override, so the camera doesn't need a block
+            None if keys.iter().all(|(_, value)| value.is_none()) => continue,
+            None => {
+                let mut block = Table::new();
+                block.insert("id", toml_edit::value(camera.id.as_str()));
+                if !camera.name.is_empty() {
+                    block.insert("name", toml_edit::value(camera.name.as_str()));
+                }
+                blocks.push(block);
+                blocks.len() - 1
+            }
+        };
+        let block = blocks
+            .get_mut(index)
+            .expect("block at the index just found");
+        for (key, value) in keys {
+            match value {
+                Some(value) => set(block, key, value),
+                None => {
+                    block.remove(key);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Set `key` in `table`, keeping the comments and formatting around the value it replaces
+fn set(table: &mut dyn TableLike, key: &str, value: impl Into<Value>) {
+    let mut value = value.into();
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(old) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        None => {
+            table.insert(key, Item::Value(value));
+        }
+    }
+}
+
+/// A setting as it's written in the config file, e.g. `"7days"` for a duration
+fn to_value(setting: impl Serialize) -> Result<Value> {
+    Ok(toml::Value::try_from(setting)?.to_string().parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, CAMERA_ID, CAMERA_NAME};
+
+    #[test]
+    fn resolve_applies_the_camera_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = testing::config(
+            &dir,
+            r#"
+            [[camera]]
+            name = "Front Door"
+            detection-types = ["person"]
+            min-event-length = "5s"
+            retention-period = "7d"
+            targets = ["local:/srv/protect"]
+            "#,
+        );
+
+        let settings = CameraSettings::resolve(&config.backup, CAMERA_ID, CAMERA_NAME);
+
+        assert!(settings.backup);
+        assert_eq!(settings.detection_types, Some(vec!["person".to_string()]));
+        assert_eq!(settings.min_event_length, Some(Duration::from_secs(5)));
+        assert_eq!(
+            settings.max_event_length,
+            Some(Duration::from_secs(60 * 60))
+        );
+        assert_eq!(
+            settings.file_structure_format.as_deref(),
+            Some(config.backup.file_structure_format.as_str())
+        );
+        assert_eq!(
+            settings.retention_period,
+            Some(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            settings.targets,
+            Some(vec!["local:/srv/protect".to_string()])
+        );
+    }
+
+    #[test]
+    fn apply_edits_the_config_file_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = testing::config(&dir, "");
+        let mut settings = CameraSettings::resolve(&config.backup, CAMERA_ID, CAMERA_NAME);
+        settings.backup = false;
+        settings.export_quality = ExportQuality::Medium;
+        settings.retention_period = Some(Duration::from_secs(7 * 24 * 60 * 60));
+        let unchanged = CameraSettings::resolve(&config.backup, "other", "Yard");
+        let mut document: DocumentMut = r#"
+# How to reach the NVR
+[unifi]
+address = "127.0.0.1"
+
+[backup]
+retention-period = "30d" # a month
+# everything but the yard
+ignore-cameras = ["Yard"]
+"#
+        .parse()
+        .unwrap();
+
+        apply(
+            &config,
+            &[settings, unchanged],
+            Path::new("config.toml"),
+            &mut document,
+        )
+        .unwrap();
+
+        let output = document.to_string();
+        assert!(output.contains("# How to reach the NVR"));
+        assert!(output.contains(r#"retention-period = "30d" # a month"#));
+        assert!(output.contains("# everything but the yard\nignore-cameras = [\"camera\"]"));
+        assert_eq!(
+            document["backup"]["camera-export-quality"][CAMERA_ID].as_str(),
+            Some("medium")
+        );
+        let blocks = document["camera"].as_array_of_tables().unwrap();
+        assert_eq!(blocks.len(), 1);
+        let block = blocks.get(0).unwrap();
+        assert_eq!(block["id"].as_str(), Some(CAMERA_ID));
+        assert_eq!(block["retention-period"].as_str(), Some("7days"));
+        assert!(!block.contains_key("detection-types"));
+        assert!(!block.contains_key("max-event-length"));
+    }
+}
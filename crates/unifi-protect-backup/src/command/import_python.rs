@@ -0,0 +1,125 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use chrono::DateTime;
+use clap::Args;
+use tracing::{info, warn};
+use unifi_protect_client::events::EventType;
+use unifi_protect_data::{Backup, legacy::read_legacy_database};
+
+use crate::{Result, backup::RemoteBackupConfig, config::Config};
+
+#[derive(Args, Debug, Clone)]
+pub struct ImportPythonDbArgs {
+    /// The Python tool's database, `events.sqlite` in its data directory
+    database: PathBuf,
+    /// Record every backup against this target name instead of matching rclone destinations
+    #[arg(long)]
+    target: Option<String>,
+    /// Only report what would be imported
+    #[arg(long, default_value = "false")]
+    dry_run: bool,
+}
+
+/// Import events and backups from the database of the Python unifi-protect-backup, so events it
+/// already uploaded aren't uploaded again. Existing events are left untouched.
+#[tracing::instrument(skip(config))]
+pub async fn import_python_db(config: &Config, args: &ImportPythonDbArgs) -> Result<()> {
+    let legacy = read_legacy_database(&args.database).await?;
+
+    // the Python tool records the rclone destination, e.g. `gdrive:/unifi_protect`
+    let targets: HashMap<String, String> = config
+        .backup
+        .remote
+        .iter()
+        .filter_map(|remote| match remote {
+            RemoteBackupConfig::Rclone(remote) => Some((
+                normalize_destination(&format!("{}:{}", remote.remote, remote.base_path)),
+                remote.target_name(),
+            )),
+            RemoteBackupConfig::Local(_) => None,
+        })
+        .collect();
+
+    let mut backups: HashMap<&str, Vec<Backup>> = HashMap::new();
+    let mut unmatched = 0;
+    for backup in &legacy.backups {
+        let target = match &args.target {
+            Some(target) => target.clone(),
+            None => match targets.get(&normalize_destination(&backup.remote)) {
+                Some(target) => target.clone(),
+                None => {
+                    warn!(
+                        remote = backup.remote,
+                        "No configured rclone target matches this destination, pass --target"
+                    );
+                    unmatched += 1;
+                    continue;
+                }
+            },
+        };
+
+        backups.entry(&backup.event_id).or_default().push(Backup {
+            event_id: backup.event_id.clone(),
+            target,
+            part: 0,
+            remote_path: backup.path.clone(),
+            backup_time: DateTime::UNIX_EPOCH,
+            size_bytes: 0,
+            sha256: None,
+        });
+    }
+
+    let database = config.database.open().await?;
+    let mut imported_events = 0;
+    let mut imported_backups = 0;
+    let mut existing = 0;
+
+    for mut event in legacy.events {
+        if database.get_event_by_id(&event.id).await?.is_some() {
+            existing += 1;
+            continue;
+        }
+
+        event.event_type = event
+            .event_type
+            .parse::<EventType>()
+            .map(|event_type| event_type.to_string())
+            .unwrap_or(event.event_type);
+        let event_backups = backups.remove(event.id.as_str()).unwrap_or_default();
+        event.backed_up = !event_backups.is_empty();
+        // the Python tool doesn't record when it uploaded, the event's end is close enough
+        let backup_time =
+            DateTime::from_timestamp_millis(event.end_time.unwrap_or(event.start_time))
+                .unwrap_or_default();
+
+        imported_events += 1;
+        imported_backups += event_backups.len();
+        if args.dry_run {
+            continue;
+        }
+
+        database.insert_event(&event).await?;
+        for mut backup in event_backups {
+            backup.backup_time = backup_time;
+            database.insert_backup(&backup).await?;
+        }
+    }
+
+    info!(
+        imported_events,
+        imported_backups,
+        existing,
+        unmatched,
+        dry_run = args.dry_run,
+        "Imported Python unifi-protect-backup database"
+    );
+    Ok(())
+}
+
+/// `remote:/path/` and `remote:path` name the same destination
+fn normalize_destination(destination: &str) -> String {
+    match destination.split_once(':') {
+        Some((remote, path)) => format!("{remote}:{}", path.trim_matches('/')),
+        None => destination.trim_matches('/').to_string(),
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use tracing::info;
+use unifi_protect_data::ledger::Ledger;
+
+use crate::{Error, Result, config::Config};
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Format {
+    /// One document holding both tables
+    Json,
+    /// `events.csv` and `backups.csv` in a directory
+    Csv,
+}
+
+#[tracing::instrument(skip(config))]
+pub async fn export_db(config: &Config, format: Format, output: Option<&Path>) -> Result<()> {
+    let database = config.database.open().await?;
+    let ledger = database.export_ledger().await?;
+
+    match (format, output) {
+        (Format::Json, Some(path)) => ledger.write_json(BufWriter::new(File::create(path)?))?,
+        (Format::Json, None) => ledger.write_json(io::stdout().lock())?,
+        (Format::Csv, Some(dir)) => ledger.write_csv(dir)?,
+        (Format::Csv, None) => {
+            return Err(Error::General(
+                "CSV exports need --output <directory>".to_string(),
+            ));
+        }
+    }
+
+    info!(
+        events = ledger.events.len(),
+        backups = ledger.backups.len(),
+        "Exported database"
+    );
+    Ok(())
+}
+
+/// Load a ledger from a JSON file or a directory of CSV files written by `export-db`.
+#[tracing::instrument(skip(config))]
+pub async fn import_db(config: &Config, input: &Path, dry_run: bool) -> Result<()> {
+    let ledger = if input.is_dir() {
+        Ledger::read_csv(input)?
+    } else {
+        Ledger::read_json(BufReader::new(File::open(input)?))?
+    };
+
+    info!(
+        events = ledger.events.len(),
+        backups = ledger.backups.len(),
+        dry_run,
+        "Importing database"
+    );
+    if dry_run {
+        return Ok(());
+    }
+
+    let database = config.database.open().await?;
+    database.import_ledger(&ledger).await?;
+
+    info!("Imported database");
+    Ok(())
+}
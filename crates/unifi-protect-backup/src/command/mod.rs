@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use clap::Subcommand;
+
+use crate::{Result, config::Config, context::Context};
+
+mod cameras;
+mod import_python;
+mod ledger;
+mod reconstruct;
+mod redownload;
+mod relayout;
+mod restore;
+mod search;
+mod self_update;
+mod show_failure;
+mod stats;
+mod verify;
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Move existing backups to match the current `file-structure-format`
+    Relayout {
+        /// Only report which backups would be moved
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// Export recent events again with the current settings, replacing the backed up copies
+    Redownload {
+        /// Only re-export events which started within this many days
+        #[arg(long)]
+        days: u32,
+        /// Only report which events would be re-exported
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// Rebuild events and backups in the database from the files on the backup targets
+    Reconstruct(reconstruct::ReconstructArgs),
+    /// Print the recorded errors and subprocess output for an event's failed backups
+    ShowFailure {
+        /// Event id, `archive` for archive runs or `database` for database snapshots
+        event: String,
+        /// Target name as recorded in the database, e.g. `rclone:s3:bucket`; prefixes match
+        target: String,
+    },
+    /// Extract backups from an archive target, optionally just one camera's or day's
+    Restore(restore::RestoreArgs),
+    /// Check backed up copies against the checksums recorded when they were uploaded
+    Verify(verify::VerifyArgs),
+    /// Print event, storage and backlog statistics from the database
+    Stats {
+        /// How many days of events to count per camera
+        #[arg(long, default_value = "30")]
+        days: u32,
+        #[arg(long, value_enum, default_value = "table")]
+        format: stats::Format,
+    },
+    /// Find recorded events by camera, detection type, time range or free text
+    Search(search::SearchArgs),
+    /// Dump the events and backups tables, e.g. to move the database to another host
+    ExportDb {
+        #[arg(long, value_enum, default_value = "json")]
+        format: ledger::Format,
+        /// File for JSON (stdout if omitted), directory for CSV
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Load events and backups written by `export-db`, replacing rows with the same key
+    ImportDb {
+        /// JSON file or directory of CSV files
+        input: PathBuf,
+        /// Only report what would be imported
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// Import events and backups from the Python unifi-protect-backup's database
+    ImportPythonDb(import_python::ImportPythonDbArgs),
+    /// Export or import the effective per-camera settings
+    Cameras {
+        #[command(subcommand)]
+        command: cameras::CamerasCommand,
+    },
+    /// Download and install the latest release, replacing the running binary
+    SelfUpdate(self_update::SelfUpdateArgs),
+}
+
+impl Command {
+    pub async fn run(&self, config: &Config) -> Result<()> {
+        match self {
+            Command::Relayout { dry_run } => {
+                let context = Context::new(config.clone()).await?;
+                relayout::relayout(&context, &config.backup, *dry_run).await
+            }
+            Command::Redownload { days, dry_run } => {
+                let context = Context::new(config.clone()).await?;
+                redownload::redownload(&context, &config.backup, *days, *dry_run).await
+            }
+            Command::Reconstruct(args) => {
+                let context = Context::new(config.clone()).await?;
+                reconstruct::reconstruct(&context, args).await
+            }
+            Command::Restore(args) => {
+                let context = Context::new(config.clone()).await?;
+                restore::restore(&context, &config.backup, args).await
+            }
+            Command::ShowFailure { event, target } => {
+                show_failure::show_failure(config, event, target).await
+            }
+            Command::Verify(args) => {
+                let context = Context::new(config.clone()).await?;
+                verify::verify(&context, args).await
+            }
+            Command::Stats { days, format } => stats::stats(config, *days, *format).await,
+            Command::Search(args) => search::search(config, args).await,
+            Command::ExportDb { format, output } => {
+                ledger::export_db(config, *format, output.as_deref()).await
+            }
+            Command::ImportDb { input, dry_run } => {
+                ledger::import_db(config, input, *dry_run).await
+            }
+            Command::ImportPythonDb(args) => import_python::import_python_db(config, args).await,
+            Command::Cameras { command } => command.run(config).await,
+            Command::SelfUpdate(args) => self_update::self_update(args).await,
+        }
+    }
+}
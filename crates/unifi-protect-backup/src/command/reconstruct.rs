@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+
+use clap::Args;
+use tracing::{info, warn};
+use unifi_protect_client::events::{EventType, sanitize};
+use unifi_protect_data::{Backup, Event};
+
+use crate::{
+    Result,
+    backup::{
+        filename::FilenameParser, index::INDEX_PATH, local::PARTIAL_SUFFIX,
+        snapshot::SNAPSHOT_EXTENSION,
+    },
+    context::Context,
+};
+
+#[derive(Args, Debug, Clone)]
+pub struct ReconstructArgs {
+    /// Only list this target, by its recorded name, e.g. `rclone:s3:bucket`
+    #[arg(long)]
+    target: Option<String>,
+    /// Only report what would be recorded
+    #[arg(long, default_value = "false")]
+    dry_run: bool,
+}
+
+/// Rebuild events and backups from the files on the backup targets, by parsing their paths with
+/// each target's current `file-structure-format`. A way back after losing the database while the
+/// backups survived; events and backups already recorded are left untouched.
+#[tracing::instrument(skip(context))]
+pub async fn reconstruct(context: &Context, args: &ReconstructArgs) -> Result<()> {
+    let cameras_by_name: BTreeMap<String, String> = context
+        .protect_bootstrap
+        .load()
+        .cameras
+        .values()
+        .map(|camera| (camera.name.clone(), camera.id.clone()))
+        .collect();
+
+    let mut events: BTreeMap<String, Event> = BTreeMap::new();
+    let mut backups: Vec<Backup> = vec![];
+    let mut unrecognized = 0;
+
+    for target in context.backup_targets.load_full().iter() {
+        if args
+            .target
+            .as_ref()
+            .is_some_and(|name| *name != target.name())
+        {
+            continue;
+        }
+
+        let parser = FilenameParser::any_of(&target.backup_config().file_structure_formats());
+        let replacement = target.backup_config().path_replacement;
+        let files = target.list().await?;
+        info!(
+            target = target.name(),
+            files = files.len(),
+            "Listed backup target"
+        );
+
+        for file in files {
+            // sidecars describe the backups next to them, they aren't backups themselves
+            if file.path.ends_with(".json") {
+                continue;
+            }
+            // nor are snapshots; those taken in place of videos can't be told apart from them
+            if file.path.ends_with(SNAPSHOT_EXTENSION) {
+                continue;
+            }
+            // nor are uploads which never completed
+            if file.path.ends_with(PARTIAL_SUFFIX) {
+                continue;
+            }
+            // nor is the index of them
+            if file.path == INDEX_PATH {
+                continue;
+            }
+            let Some(parsed) = parser.parse(&file.path) else {
+                unrecognized += 1;
+                continue;
+            };
+            let Some(start_time) = parsed.start_time else {
+                warn!(
+                    path = file.path,
+                    "No start time in path, `{{date}}` and `{{time}}` needed"
+                );
+                unrecognized += 1;
+                continue;
+            };
+            // names in paths are sanitized
+            let camera_id = parsed.camera_id.clone().or_else(|| {
+                let name = parsed.camera_name.as_ref()?;
+                cameras_by_name
+                    .iter()
+                    .find(|(camera, _)| sanitize(camera, replacement) == *name)
+                    .map(|(_, camera_id)| camera_id.clone())
+            });
+            let Some(camera_id) = camera_id else {
+                warn!(path = file.path, "No camera on the NVR matches this path");
+                unrecognized += 1;
+                continue;
+            };
+
+            // without `{event_id}` in the format, every part and copy of an event still agrees
+            // on the camera and start time
+            let event_id = parsed.event_id.clone().unwrap_or_else(|| {
+                format!(
+                    "reconstructed-{camera_id}-{}",
+                    start_time.timestamp_millis()
+                )
+            });
+            let (event_type, smart_detect_types) =
+                parsed.detection().unwrap_or((EventType::Motion, vec![]));
+
+            events.entry(event_id.clone()).or_insert_with(|| Event {
+                id: event_id.clone(),
+                event_type: event_type.to_string(),
+                camera_id,
+                start_time: start_time.timestamp_millis(),
+                end_time: parsed.end_time.map(|end_time| end_time.timestamp_millis()),
+                backed_up: true,
+                skip_reason: None,
+                smart_detect_types: smart_detect_types
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                thumbnail_id: None,
+                heatmap_id: None,
+            });
+            backups.push(Backup {
+                event_id,
+                target: target.name(),
+                part: parsed.part.unwrap_or(0),
+                remote_path: file.path,
+                backup_time: file.modified.unwrap_or(start_time),
+                size_bytes: file.size_bytes,
+                // what's there now might already be damaged, so it can't vouch for itself
+                sha256: None,
+            });
+        }
+    }
+
+    let mut new_events = vec![];
+    let mut existing_events = 0;
+    for event in events.into_values() {
+        if context.database.get_event_by_id(&event.id).await?.is_some() {
+            existing_events += 1;
+            continue;
+        }
+
+        if args.dry_run {
+            info!(
+                event_id = event.id,
+                camera_id = event.camera_id,
+                "Would record event"
+            );
+        }
+        new_events.push(event);
+    }
+
+    let mut new_backups = vec![];
+    let mut existing_backups = 0;
+    for backup in backups {
+        let recorded = context
+            .database
+            .get_backups_by_event(&backup.event_id)
+            .await?
+            .into_iter()
+            .any(|existing| existing.target == backup.target && existing.part == backup.part);
+        if recorded {
+            existing_backups += 1;
+            continue;
+        }
+
+        if args.dry_run {
+            info!(
+                event_id = backup.event_id,
+                target = backup.target,
+                remote_path = backup.remote_path,
+                "Would record backup"
+            );
+        }
+        new_backups.push(backup);
+    }
+
+    if !args.dry_run {
+        context.database.insert_events(&new_events).await?;
+        context.database.record_backups(&new_backups).await?;
+    }
+
+    info!(
+        recorded_events = new_events.len(),
+        existing_events,
+        recorded_backups = new_backups.len(),
+        existing_backups,
+        unrecognized,
+        dry_run = args.dry_run,
+        "Reconstruction complete"
+    );
+    Ok(())
+}
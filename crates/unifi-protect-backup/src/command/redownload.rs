@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use tracing::{info, warn};
+use unifi_protect_client::error::Error as ClientError;
+use unifi_protect_data::{Backup, Event};
+
+use crate::{
+    Error, Result,
+    backup::{
+        self, sha256,
+        sidecar::{self, Sidecar},
+    },
+    context::Context,
+    convert::protect_event_from_database_event,
+    task::{download_segment, segments},
+};
+
+/// Export events from the last `days` again with the current settings (e.g. a new
+/// `export-quality`) and replace the copies on every target they were backed up to.
+#[tracing::instrument(skip(context, config))]
+pub async fn redownload(
+    context: &Context,
+    config: &backup::Config,
+    days: u32,
+    dry_run: bool,
+) -> Result<()> {
+    let cutoff = (context.clock.now() - chrono::Duration::days(days as i64)).timestamp_millis();
+
+    let mut targets_by_event: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for backup in context.database.get_backups().await? {
+        let targets = targets_by_event.entry(backup.event_id).or_default();
+        if !targets.contains(&backup.target) {
+            targets.push(backup.target);
+        }
+    }
+
+    let mut redownloaded = 0;
+    let mut missing = 0;
+    let mut failed = 0;
+
+    for (event_id, target_names) in targets_by_event {
+        let Some(event) = context.database.get_event_by_id(&event_id).await? else {
+            continue;
+        };
+        if event.start_time < cutoff || event.end_time.is_none() {
+            continue;
+        }
+
+        if dry_run {
+            info!(event_id, targets = ?target_names, "Would redownload event");
+            redownloaded += 1;
+            continue;
+        }
+
+        match redownload_event(context, config, event, &target_names).await {
+            Ok(()) => redownloaded += 1,
+            Err(Error::ProtectClient(ClientError::ExportNotReady(reason))) => {
+                info!(
+                    event_id,
+                    reason, "Event no longer available on the NVR, skipping"
+                );
+                missing += 1;
+            }
+            Err(err) => {
+                warn!(event_id, err = ?err, "Failed to redownload event");
+                failed += 1;
+            }
+        }
+    }
+
+    info!(
+        redownloaded,
+        missing, failed, dry_run, "Redownload complete"
+    );
+    Ok(())
+}
+
+async fn redownload_event(
+    context: &Context,
+    config: &backup::Config,
+    event: Event,
+    target_names: &[String],
+) -> Result<()> {
+    let event_id = event.id.clone();
+    let camera_id = event.camera_id.clone();
+    let (start_time, end_time) = (event.start_time, event.end_time.unwrap_or(event.start_time));
+
+    let mut protect_event =
+        protect_event_from_database_event(event, &context.protect_bootstrap.load());
+    if protect_event.camera_name.is_none() {
+        protect_event.camera_name = context.camera_name(&camera_id).await?;
+    }
+
+    let backup_targets = context.backup_targets.load_full();
+    let targets: Vec<_> = backup_targets
+        .iter()
+        .filter(|target| target_names.contains(&target.name()))
+        .collect();
+    if targets.is_empty() {
+        warn!(
+            event_id,
+            "None of the event's backup targets are configured any more"
+        );
+        return Ok(());
+    }
+
+    // replaced below, and deleted afterwards wherever the new copy landed somewhere else
+    let previous: Vec<_> = context
+        .database
+        .get_backups_by_event(&event_id)
+        .await?
+        .into_iter()
+        .filter(|backup| target_names.contains(&backup.target))
+        .collect();
+    let mut uploaded = vec![];
+
+    let quality = config.export_quality(&camera_id, protect_event.camera_name.as_deref());
+    let max_event_length =
+        config.max_event_length(&camera_id, protect_event.camera_name.as_deref());
+    let segments = segments(start_time, end_time, max_event_length);
+    let chunked = segments.len() > 1;
+
+    for (index, (segment_start, segment_end)) in segments.into_iter().enumerate() {
+        let part = if chunked { index as u32 + 1 } else { 0 };
+        let video_data = download_segment(
+            context,
+            config,
+            &camera_id,
+            segment_start,
+            segment_end,
+            quality,
+        )
+        .await?;
+        let checksum = sha256(&video_data);
+
+        protect_event.part = chunked.then_some(part);
+        let (collision, _reservation) = backup::collision(
+            &context.database,
+            &context.reservations,
+            &targets,
+            &protect_event,
+        )
+        .await?;
+        protect_event.collision = collision;
+        for target in &targets {
+            let remote_path = target.backup(&protect_event, video_data.as_slice()).await?;
+            let backup_time = context.clock.now();
+            if config.sidecars {
+                let sidecar = Sidecar::new(
+                    &protect_event,
+                    &context.protect_bootstrap.load().nvr.version,
+                    video_data.len() as u64,
+                    &checksum,
+                    backup_time,
+                );
+                sidecar::write(target.as_ref(), &remote_path, &sidecar).await;
+            }
+            uploaded.push((target.name(), part, remote_path.clone()));
+            context
+                .database
+                .insert_backup(&Backup {
+                    event_id: event_id.clone(),
+                    target: target.name(),
+                    part,
+                    remote_path,
+                    backup_time,
+                    size_bytes: video_data.len() as u64,
+                    sha256: Some(checksum.clone()),
+                })
+                .await?;
+        }
+    }
+
+    for backup in previous {
+        let replaced = uploaded
+            .iter()
+            .any(|(target, part, _)| *target == backup.target && *part == backup.part);
+        let reused = uploaded
+            .iter()
+            .any(|(target, _, path)| *target == backup.target && *path == backup.remote_path);
+        if reused {
+            continue;
+        }
+        let Some(target) = targets.iter().find(|target| target.name() == backup.target) else {
+            continue;
+        };
+        // e.g. after a change to the file structure, compression or how the event is split
+        if let Err(err) = target.delete(&backup.remote_path).await {
+            warn!(
+                event_id,
+                target = backup.target,
+                path = backup.remote_path,
+                err = ?err,
+                "Failed to delete the copy a redownload replaced"
+            );
+            continue;
+        }
+        if !replaced {
+            context
+                .database
+                .delete_backup(&backup.event_id, &backup.target, backup.part)
+                .await?;
+        }
+    }
+
+    info!(event_id, ?quality, "Redownloaded event");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::testing::{self, CAMERA_ID, TestContext};
+
+    #[tokio::test]
+    async fn test_redownload() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let mut event = testing::event("event", start, start + 10_000);
+        event.backed_up = true;
+        context.database.insert_event(&event).await.unwrap();
+        // backed up under a file structure that has since changed
+        std::fs::write(test.backup_dir().join("event.mp4"), b"old").unwrap();
+        context
+            .database
+            .insert_backup(&Backup {
+                event_id: "event".to_string(),
+                target: context.backup_targets.load()[0].name(),
+                part: 0,
+                remote_path: "event.mp4".to_string(),
+                backup_time: context.clock.now(),
+                size_bytes: 3,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+        test.protect.set_export(CAMERA_ID, testing::video());
+
+        redownload(context, &config, 1, false).await.unwrap();
+        let backups = context.database.get_backups().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_ne!(backups[0].remote_path, "event.mp4");
+        assert_eq!(backups[0].sha256, Some(sha256(&testing::video())));
+        let written = std::fs::read(test.backup_dir().join(&backups[0].remote_path));
+        assert_eq!(written.unwrap(), testing::video());
+        assert!(!test.backup_dir().join("event.mp4").exists());
+    }
+
+    #[tokio::test]
+    async fn test_redownload_sidecar() {
+        let test = TestContext::new("sidecars = true").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let mut event = testing::event("event", start, start + 10_000);
+        event.backed_up = true;
+        context.database.insert_event(&event).await.unwrap();
+        context
+            .database
+            .insert_backup(&Backup {
+                event_id: "event".to_string(),
+                target: context.backup_targets.load()[0].name(),
+                part: 0,
+                remote_path: "event.mp4".to_string(),
+                backup_time: context.clock.now(),
+                size_bytes: 3,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+        test.protect.set_export(CAMERA_ID, testing::video());
+
+        redownload(context, &config, 1, false).await.unwrap();
+        let backups = context.database.get_backups().await.unwrap();
+        let path = test
+            .backup_dir()
+            .join(sidecar::sidecar_path(&backups[0].remote_path));
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(json["event_id"], "event");
+        assert_eq!(json["sha256"], sha256(&testing::video()));
+    }
+}
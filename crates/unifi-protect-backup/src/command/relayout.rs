@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use tracing::{info, warn};
+
+use crate::{
+    Result,
+    backup::{
+        self,
+        compress::{self, ZSTD_EXTENSION},
+        sidecar::sidecar_path,
+        snapshot::{SNAPSHOT_EXTENSION, SnapshotMode, snapshot_path},
+    },
+    context::Context,
+    convert::protect_event_from_database_event,
+};
+
+#[tracing::instrument(skip(context, config))]
+pub async fn relayout(context: &Context, config: &backup::Config, dry_run: bool) -> Result<()> {
+    let backup_targets = context.backup_targets.load_full();
+    let targets: HashMap<_, _> = backup_targets
+        .iter()
+        .map(|target| (target.name(), target))
+        .collect();
+
+    let mut relocated = 0;
+    let mut failed = 0;
+
+    for backup in context.database.get_backups().await? {
+        let Some(target) = targets.get(&backup.target) else {
+            warn!(
+                target = backup.target,
+                event_id = backup.event_id,
+                "Backup target is no longer configured, skipping"
+            );
+            continue;
+        };
+
+        let Some(event) = context.database.get_event_by_id(&backup.event_id).await? else {
+            continue;
+        };
+
+        let mut protect_event =
+            protect_event_from_database_event(event, &context.protect_bootstrap.load());
+        if protect_event.camera_name.is_none() {
+            protect_event.camera_name = context.camera_name(&protect_event.camera_id).await?;
+        }
+        protect_event.part = (backup.part > 0).then_some(backup.part);
+
+        // numbered past any other event's backup already at the new path
+        let new_path = loop {
+            let mut new_path = target.backup_config().filename(&protect_event);
+            if backup.remote_path.ends_with(SNAPSHOT_EXTENSION) {
+                new_path = snapshot_path(&new_path);
+            } else if compress::is_compressed(&backup.remote_path) {
+                new_path.push_str(ZSTD_EXTENSION);
+            }
+            match context
+                .database
+                .get_backup_by_path(&backup.target, &new_path)
+                .await?
+            {
+                Some(other) if other.event_id != backup.event_id || other.part != backup.part => {
+                    protect_event.collision = Some(protect_event.collision.map_or(2, |n| n + 1));
+                }
+                _ => break new_path,
+            }
+        };
+        if new_path == backup.remote_path {
+            continue;
+        }
+
+        info!(
+            target = backup.target,
+            from = backup.remote_path,
+            to = new_path,
+            dry_run,
+            "Relocating backup"
+        );
+
+        if dry_run {
+            relocated += 1;
+            continue;
+        }
+
+        match target.relocate(&backup.remote_path, &new_path).await {
+            Ok(()) => {
+                if config.sidecars {
+                    let (from, to) = (sidecar_path(&backup.remote_path), sidecar_path(&new_path));
+                    if let Err(err) = target.relocate(&from, &to).await {
+                        warn!(err = ?err, event_id = backup.event_id, "Failed to relocate sidecar");
+                    }
+                }
+                if config.snapshots == SnapshotMode::WithVideo && backup.part <= 1 {
+                    let (from, to) = (snapshot_path(&backup.remote_path), snapshot_path(&new_path));
+                    if let Err(err) = target.relocate(&from, &to).await {
+                        warn!(
+                            err = ?err,
+                            event_id = backup.event_id,
+                            "Failed to relocate snapshot"
+                        );
+                    }
+                }
+                context
+                    .database
+                    .update_backup_remote_path(
+                        &backup.event_id,
+                        &backup.target,
+                        backup.part,
+                        &new_path,
+                    )
+                    .await?;
+                relocated += 1;
+            }
+            Err(err) => {
+                warn!(err = ?err, event_id = backup.event_id, "Failed to relocate backup");
+                failed += 1;
+            }
+        }
+    }
+
+    info!(relocated, failed, dry_run, "Relayout complete");
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::Args;
+use unifi_protect_client::events::sanitize;
+
+use crate::{
+    Error, Result,
+    backup::{self, filename::FilenameParser, snapshot},
+    context::Context,
+};
+
+#[derive(Args, Debug, Clone)]
+pub struct RestoreArgs {
+    /// Archive to restore, as `borg list` names it, or `latest` for the newest
+    #[arg(long)]
+    archive: String,
+    /// Directory to restore into, keeping the archived paths under it
+    #[arg(long)]
+    dest: PathBuf,
+    /// Archive target to restore from, by its recorded name; prefixes match. Only needed with
+    /// more than one.
+    #[arg(long)]
+    target: Option<String>,
+    /// Only restore this camera's backups, by name or id
+    #[arg(long)]
+    camera: Option<String>,
+    /// Only restore backups of events which started on this date (`YYYY-MM-DD`), going by
+    /// the `{date}` in their path
+    #[arg(long)]
+    date: Option<NaiveDate>,
+}
+
+/// Extract backups from an archive target, optionally just those of one camera or day, which
+/// are picked out by parsing their paths with the current `file-structure-format`.
+#[tracing::instrument(skip(context, config))]
+pub async fn restore(context: &Context, config: &backup::Config, args: &RestoreArgs) -> Result<()> {
+    let mut targets = context.archive_targets.iter().filter(|target| {
+        args.target
+            .as_ref()
+            .is_none_or(|name| target.name().starts_with(name.as_str()))
+    });
+    let target = match (targets.next(), targets.next()) {
+        (Some(target), None) => target,
+        (None, _) => return Err(Error::General("No matching archive target".to_string())),
+        (Some(_), Some(_)) => {
+            let names: Vec<_> = context
+                .archive_targets
+                .iter()
+                .map(|target| target.name())
+                .collect();
+            return Err(Error::General(format!(
+                "Several archive targets match, choose one with --target: {}",
+                names.join(", ")
+            )));
+        }
+    };
+
+    let parser = FilenameParser::any_of(&config.file_structure_formats());
+    let filtered = args.camera.is_some() || args.date.is_some();
+    let select = |path: &str| {
+        if !filtered {
+            return true;
+        }
+        // a sidecar or snapshot is named after the backup it goes with
+        let path = path.strip_suffix(".json").unwrap_or(path);
+        let path = snapshot::video_path(path).unwrap_or_else(|| path.to_string());
+        parser.parse(&path).is_some_and(|parsed| {
+            // names in paths are sanitized
+            let camera = args.camera.as_ref().is_none_or(|camera| {
+                let camera = sanitize(camera, config.path_replacement);
+                parsed.camera_name.as_ref() == Some(&camera)
+                    || parsed.camera_id.as_ref() == Some(&camera)
+            });
+            let date = args.date.is_none_or(|date| {
+                parsed
+                    .start_time
+                    .is_some_and(|start| start.date_naive() == date)
+            });
+            camera && date
+        })
+    };
+
+    let restored = target.restore(&args.archive, &args.dest, &select).await?;
+    println!(
+        "Restored {restored} file(s) from {} to {}",
+        target.name(),
+        args.dest.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+    use unifi_protect_client::mock::MockProtectClient;
+
+    use super::*;
+    use crate::{
+        archive::{Archive, RestoreFilter},
+        clock::Clock,
+        retention::RetentionPolicy,
+        task::Prune,
+        testing,
+    };
+
+    const FILES: &[&str] = &[
+        "Front Door/2025-08-04/12-00-00_motion.mp4",
+        "Front Door/2025-08-04/12-00-00_motion.mp4.json",
+        "Front Door/2025-08-04/12-00-00_motion.jpg",
+        "Front Door/2025-08-05/12-00-00_motion.mp4",
+        "Garage/2025-08-04/12-00-00_motion.mp4",
+        "database/events-20250804-120000.db",
+    ];
+
+    /// An archive target holding [`FILES`], which records the ones a restore picks
+    struct FakeArchive {
+        name: &'static str,
+        restored: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Archive for FakeArchive {
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn archive(&self) -> Result<String> {
+            Ok("archive".to_string())
+        }
+
+        async fn restore(&self, archive: &str, _: &Path, select: &RestoreFilter) -> Result<usize> {
+            assert_eq!(archive, "latest");
+            let mut restored = self.restored.lock().unwrap();
+            restored.clear();
+            restored.extend(
+                FILES
+                    .iter()
+                    .filter(|path| select(path))
+                    .map(|path| path.to_string()),
+            );
+            Ok(restored.len())
+        }
+    }
+
+    #[async_trait]
+    impl Prune for FakeArchive {
+        async fn prune(&self, _: &RetentionPolicy, _: &dyn Clock) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn args(target: Option<&str>, camera: Option<&str>, date: Option<&str>) -> RestoreArgs {
+        RestoreArgs {
+            archive: "latest".to_string(),
+            dest: PathBuf::from("restored"),
+            target: target.map(str::to_string),
+            camera: camera.map(str::to_string),
+            date: date.map(|date| date.parse().unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("backups")).unwrap();
+        let protect = Arc::new(MockProtectClient::new(testing::bootstrap()));
+        let mut context = Context::with_client(testing::config(&dir, ""), protect)
+            .await
+            .unwrap();
+        let restored = Arc::new(Mutex::new(vec![]));
+        context.archive_targets = ["borg:nas", "borg:offsite"]
+            .into_iter()
+            .map(|name| {
+                Arc::new(FakeArchive {
+                    name,
+                    restored: restored.clone(),
+                }) as Arc<dyn Archive>
+            })
+            .collect();
+        let config = context.backup_config.load().as_ref().clone();
+
+        // the target has to be picked when there's more than one
+        assert!(
+            restore(&context, &config, &args(None, None, None))
+                .await
+                .is_err()
+        );
+        assert!(
+            restore(&context, &config, &args(Some("s3"), None, None))
+                .await
+                .is_err()
+        );
+
+        restore(&context, &config, &args(Some("borg:nas"), None, None))
+            .await
+            .unwrap();
+        assert_eq!(*restored.lock().unwrap(), FILES);
+
+        // a camera and day pick out that backup along with its sidecar and snapshot
+        let camera_and_date = args(Some("borg:off"), Some("Front Door"), Some("2025-08-04"));
+        restore(&context, &config, &camera_and_date).await.unwrap();
+        assert_eq!(*restored.lock().unwrap(), &FILES[..3]);
+
+        restore(
+            &context,
+            &config,
+            &args(Some("borg:off"), None, Some("2025-08-04")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            *restored.lock().unwrap(),
+            [FILES[0], FILES[1], FILES[2], FILES[4]]
+        );
+    }
+}
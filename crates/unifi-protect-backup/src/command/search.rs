@@ -0,0 +1,115 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::Args;
+use unifi_protect_data::EventSearch;
+
+use crate::{Result, command::stats::Format, config::Config};
+
+#[derive(Args, Debug, Clone)]
+pub struct SearchArgs {
+    /// Matched against event ids, camera names, detection types, tags and licence plates
+    query: Option<String>,
+    /// Camera id or name
+    #[arg(long)]
+    camera: Option<String>,
+    /// Event type or smart detection type, e.g. `motion` or `person`
+    #[arg(long)]
+    detection_type: Option<String>,
+    /// Tag read from a detection, e.g. a vehicle's colour or type
+    #[arg(long)]
+    tag: Option<String>,
+    /// Part of a licence plate read from a detection
+    #[arg(long)]
+    plate: Option<String>,
+    /// Events starting at or after this date (`YYYY-MM-DD`, UTC) or RFC 3339 time
+    #[arg(long, value_parser = parse_time)]
+    since: Option<DateTime<Utc>>,
+    /// Events starting before this date or time
+    #[arg(long, value_parser = parse_time)]
+    until: Option<DateTime<Utc>>,
+    #[arg(long, default_value = "50")]
+    limit: u32,
+    /// Skip this many matches, for paging through results
+    #[arg(long, default_value = "0")]
+    offset: u32,
+    #[arg(long, value_enum, default_value = "table")]
+    format: Format,
+}
+
+#[tracing::instrument(skip(config))]
+pub async fn search(config: &Config, args: &SearchArgs) -> Result<()> {
+    let database = config.database.open().await?;
+
+    let events = database
+        .search_events(&EventSearch {
+            query: args.query.clone(),
+            camera: args.camera.clone(),
+            detection_type: args.detection_type.clone(),
+            tag: args.tag.clone(),
+            plate: args.plate.clone(),
+            start: args.since,
+            end: args.until,
+            limit: args.limit,
+            offset: args.offset,
+        })
+        .await?;
+
+    if let Format::Json = args.format {
+        println!("{}", serde_json::to_string_pretty(&events)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<25} {:<24} {:<16} {:>8} {:<10} ID",
+        "START", "CAMERA", "TYPE", "SECONDS", "BACKED UP"
+    );
+    for event in &events {
+        let camera = database
+            .get_camera(&event.camera_id)
+            .await?
+            .map(|camera| camera.name)
+            .unwrap_or_else(|| event.camera_id.clone());
+        let kind = if event.smart_detect_types.is_empty() {
+            event.event_type.clone()
+        } else {
+            event.smart_detect_types.clone()
+        };
+        let seconds = event
+            .end_time
+            .map(|end| ((end - event.start_time) / 1000).to_string())
+            .unwrap_or_else(|| "ongoing".to_string());
+        let backed_up = match (&event.skip_reason, event.backed_up) {
+            (Some(reason), _) => reason.as_str(),
+            (None, true) => "yes",
+            (None, false) => "no",
+        };
+
+        println!(
+            "{:<25} {:<24} {:<16} {:>8} {:<10} {}",
+            DateTime::from_timestamp_millis(event.start_time)
+                .unwrap_or_default()
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            camera,
+            kind,
+            seconds,
+            backed_up,
+            event.id
+        );
+    }
+
+    if events.len() as u32 == args.limit {
+        println!(
+            "\nMore results may follow; use --offset {}",
+            args.offset + args.limit
+        );
+    }
+
+    Ok(())
+}
+
+fn parse_time(s: &str) -> std::result::Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_time(Default::default()).and_utc());
+    }
+    s.parse::<DateTime<Utc>>()
+        .map_err(|e| format!("expected YYYY-MM-DD or an RFC 3339 time: {e}"))
+}
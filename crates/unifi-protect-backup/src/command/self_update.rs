@@ -0,0 +1,188 @@
+use std::{fs, path::Path};
+
+use clap::Args;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{Error, Result};
+
+const RELEASE_FEED: &str = "https://gitlab.stephensampson.dev/api/v4/projects/homelab%2Funifi-protect-backup/releases/permalink/latest";
+
+#[derive(Args, Debug, Clone)]
+pub struct SelfUpdateArgs {
+    /// Only check whether a newer release is available
+    #[arg(long, default_value = "false")]
+    pub check: bool,
+    /// Release feed to query (GitLab releases API)
+    #[arg(long, default_value = RELEASE_FEED)]
+    pub feed_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: ReleaseAssets,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseAssets {
+    links: Vec<ReleaseLink>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseLink {
+    name: String,
+    url: String,
+}
+
+impl Release {
+    fn asset_url(&self, name: &str) -> Result<&str> {
+        self.assets
+            .links
+            .iter()
+            .find(|link| link.name == name)
+            .map(|link| link.url.as_str())
+            .ok_or_else(|| {
+                Error::General(format!(
+                    "Release {} has no asset named {name}",
+                    self.tag_name
+                ))
+            })
+    }
+}
+
+#[tracing::instrument]
+pub async fn self_update(args: &SelfUpdateArgs) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .build()?;
+
+    let release: Release = client
+        .get(&args.feed_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = release.tag_name.trim_start_matches('v');
+    if !is_newer(latest, current) {
+        info!(current, latest, "Already up to date");
+        return Ok(());
+    }
+
+    info!(current, latest, "Update available");
+    if args.check {
+        return Ok(());
+    }
+
+    let asset = asset_name();
+    let binary = client
+        .get(release.asset_url(&asset)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let checksum = client
+        .get(release.asset_url(&format!("{asset}.sha256"))?)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    // checksum files are in `sha256sum` format: "<hex digest>  <filename>"
+    let expected = checksum
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(&binary));
+    if actual != expected {
+        return Err(Error::General(format!(
+            "Checksum mismatch for {asset}: expected {expected}, got {actual}"
+        )));
+    }
+
+    install(&std::env::current_exe()?, &binary)?;
+
+    info!(version = latest, "Updated successfully");
+    Ok(())
+}
+
+/// Swap `binary` in for the executable at `current`, restoring the original if the new binary
+/// doesn't start.
+fn install(current: &Path, binary: &[u8]) -> Result<()> {
+    let staged = current.with_extension("new");
+    let backup = current.with_extension("bak");
+
+    fs::write(&staged, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    fs::rename(current, &backup)?;
+    fs::rename(&staged, current)?;
+
+    let started = std::process::Command::new(current)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success());
+
+    if !started {
+        warn!("New binary failed to start, rolling back");
+        fs::rename(&backup, current)?;
+        return Err(Error::General(
+            "Updated binary failed to start; previous version restored".to_string(),
+        ));
+    }
+
+    let _ = fs::remove_file(&backup);
+    Ok(())
+}
+
+fn asset_name() -> String {
+    format!(
+        "{}-{}-{}",
+        env!("CARGO_PKG_NAME"),
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+/// Compare dotted numeric versions, ignoring any pre-release/build suffix.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    fn parse(version: &str) -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    }
+
+    parse(candidate) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.0", "0.1.0"));
+        assert!(is_newer("0.10.0", "0.9.5"));
+        assert!(!is_newer("0.1.0", "0.1.0"));
+        assert!(!is_newer("0.1.0-rc1", "0.1.0"));
+    }
+}
@@ -0,0 +1,24 @@
+use crate::{Error, Result, config::Config};
+
+#[tracing::instrument(skip(config))]
+pub async fn show_failure(config: &Config, subject: &str, target: &str) -> Result<()> {
+    let database = config.database.open().await?;
+
+    let failures = database.get_failures(subject, target).await?;
+    if failures.is_empty() {
+        return Err(Error::General(format!(
+            "No recorded failures for {subject} on target {target}"
+        )));
+    }
+
+    for failure in failures {
+        println!("{} {}", failure.failure_time.to_rfc3339(), failure.target);
+        println!("error: {}", failure.error);
+        if !failure.output.is_empty() {
+            println!("{}", failure.output.trim_end());
+        }
+        println!();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use clap::ValueEnum;
+use humantime_serde::re::humantime;
+use serde::Serialize;
+use unifi_protect_data::{Backlog, CameraDayCount, TargetUsage};
+
+use crate::{Result, config::Config, size::ByteSize};
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    days: u32,
+    events_per_camera_per_day: Vec<CameraEvents>,
+    targets: Vec<TargetUsage>,
+    #[serde(with = "humantime_serde")]
+    average_event_length: Option<Duration>,
+    backlog: Backlog,
+}
+
+#[derive(Debug, Serialize)]
+struct CameraEvents {
+    #[serde(flatten)]
+    counts: CameraDayCount,
+    /// Last known name, if the camera was ever recorded
+    camera_name: Option<String>,
+}
+
+#[tracing::instrument(skip(config))]
+pub async fn stats(config: &Config, days: u32, format: Format) -> Result<()> {
+    let database = config.database.open().await?;
+
+    let since = Utc::now() - chrono::Duration::days(days as i64);
+    let mut events_per_camera_per_day = vec![];
+    for counts in database.events_per_camera_per_day(since).await? {
+        let camera_name = database
+            .get_camera(&counts.camera_id)
+            .await?
+            .map(|camera| camera.name);
+        events_per_camera_per_day.push(CameraEvents {
+            counts,
+            camera_name,
+        });
+    }
+
+    let stats = Stats {
+        days,
+        events_per_camera_per_day,
+        targets: database.bytes_per_target().await?,
+        average_event_length: database.average_event_length().await?,
+        backlog: database.backlog().await?,
+    };
+
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        Format::Table => print_table(&stats),
+    }
+
+    Ok(())
+}
+
+fn print_table(stats: &Stats) {
+    println!("Events per camera per day (last {} days)", stats.days);
+    println!("{:<12} {:<32} {:>8}", "DAY", "CAMERA", "EVENTS");
+    for entry in &stats.events_per_camera_per_day {
+        println!(
+            "{:<12} {:<32} {:>8}",
+            entry.counts.day,
+            entry
+                .camera_name
+                .as_deref()
+                .unwrap_or(&entry.counts.camera_id),
+            entry.counts.events
+        );
+    }
+    println!();
+
+    println!("Backups per target");
+    println!("{:<48} {:>8} {:>12}", "TARGET", "BACKUPS", "SIZE");
+    for target in &stats.targets {
+        println!(
+            "{:<48} {:>8} {:>12}",
+            target.target,
+            target.backups,
+            ByteSize(target.bytes.max(0) as u64).to_string()
+        );
+    }
+    println!();
+
+    match stats.average_event_length {
+        // whole seconds; milliseconds are noise here
+        Some(length) => println!(
+            "Average event length: {}",
+            humantime::format_duration(Duration::from_secs(length.as_secs()))
+        ),
+        None => println!("Average event length: no finished events"),
+    }
+
+    match stats.backlog.oldest {
+        Some(oldest) => {
+            let age = (Utc::now() - oldest).to_std().unwrap_or_default();
+            println!(
+                "Backlog: {} events, oldest from {} ({} ago)",
+                stats.backlog.events,
+                oldest.to_rfc3339(),
+                humantime::format_duration(Duration::from_secs(age.as_secs()))
+            );
+        }
+        None => println!("Backlog: empty"),
+    }
+}
@@ -0,0 +1,50 @@
+use clap::Args;
+
+use crate::{
+    Error, Result,
+    context::Context,
+    task::{find_stray_partials, verify_backups},
+};
+
+#[derive(Args, Debug, Clone)]
+pub struct VerifyArgs {
+    /// Only verify backups made within this many days
+    #[arg(long)]
+    days: Option<u32>,
+    /// Only verify backups on this target, by its recorded name; prefixes match
+    #[arg(long)]
+    target: Option<String>,
+}
+
+/// Re-hash backed up copies and compare them with the checksums recorded at upload, failing if
+/// any are corrupt or missing.
+#[tracing::instrument(skip(context))]
+pub async fn verify(context: &Context, args: &VerifyArgs) -> Result<()> {
+    let cutoff = args
+        .days
+        .map(|days| context.clock.now() - chrono::Duration::days(days as i64));
+
+    let backups: Vec<_> = context
+        .database
+        .get_backups()
+        .await?
+        .into_iter()
+        .filter(|backup| cutoff.is_none_or(|cutoff| backup.backup_time >= cutoff))
+        .filter(|backup| {
+            args.target
+                .as_ref()
+                .is_none_or(|target| backup.target.starts_with(target.as_str()))
+        })
+        .collect();
+
+    let mut verification = verify_backups(context, backups, |_| {}).await;
+    find_stray_partials(context, args.target.as_deref(), &mut verification).await;
+    print!("{}", verification.report());
+
+    match verification.problems() {
+        0 => Ok(()),
+        problems => Err(Error::Backup(format!(
+            "{problems} backup(s) failed verification"
+        ))),
+    }
+}
@@ -1,43 +1,335 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
-use tracing::debug;
+use arc_swap::{ArcSwap, ArcSwapOption};
+use futures_util::future::join_all;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, warn};
 
-use unifi_protect_client::{ProtectClient, models::Bootstrap};
+use unifi_protect_client::{ProtectClient, api::ProtectApi, models::Bootstrap};
 use unifi_protect_data::Database;
 
 use crate::{
     archive::{Archive, archive_targets},
-    backup::{Backup, backup_targets},
+    backup::{Backup, Reservations, TargetValidation, backup_targets, filename, spool::Spool},
+    bandwidth::Limiter,
+    clock::{Clock, system_clock},
     config::Config,
     metrics::Metrics,
+    notify::Notifier,
+    retention::RetentionPolicy,
+    script::FilterScript,
+    status::Status,
 };
 
 pub struct Context {
-    pub protect_client: ProtectClient,
-    pub protect_bootstrap: Bootstrap,
-    pub backup_targets: Vec<Arc<dyn Backup>>, // dyn b/c we don't know the enabled backup targets until runtime (config-driven)
+    /// The NVR, or a stand-in for it in tests, see [`Context::with_client`]
+    pub protect_client: Arc<dyn ProtectApi>,
+    /// Latest bootstrap from the controller, refreshed periodically and when cameras are adopted
+    pub protect_bootstrap: ArcSwap<Bootstrap>,
+    /// Rebuilt when the config is [reloaded](Self::reload)
+    pub backup_targets: ArcSwap<Vec<Arc<dyn Backup>>>, // dyn b/c we don't know the enabled backup targets until runtime (config-driven)
     pub archive_targets: Vec<Arc<dyn Archive>>, // dyn b/c we don't know the enabled archive targets until runtime (config-driven)
     pub database: Database,
+    /// Per-event filter from `backup.filter-script`, if configured
+    pub filter_script: ArcSwapOption<FilterScript>,
+    /// Email alerts from `[notifications]`, if configured
+    pub notifier: ArcSwapOption<Notifier>,
+    /// Paths being uploaded to now, which aren't in the database yet, see [`collision`]
+    ///
+    /// [`collision`]: crate::backup::collision
+    pub reservations: Arc<Reservations>,
+    /// Staging area for exports from `backup.spool`, if configured
+    pub spool: Option<Spool>,
+    /// Paces exports to `backup.bwlimit`, if configured
+    pub bandwidth: Option<Arc<Limiter>>,
+    /// `[backup]` as last loaded, for tasks to pick up changes from on their next pass
+    pub backup_config: ArcSwap<crate::backup::Config>,
+    /// Resolved from `backup.retention-period` and `[backup.retention]`
+    pub backup_retention: ArcSwap<RetentionPolicy>,
+    /// Resolved from `archive.retention-period` and `[archive.retention]`
+    pub archive_retention: ArcSwap<RetentionPolicy>,
     pub metrics: Arc<Metrics>,
+    pub status: Arc<Status>,
+    /// Source of the current time for scheduling decisions
+    pub clock: Arc<dyn Clock>,
+    /// Woken by the event listener when an event finishes, so the poller backs it up straight
+    /// away rather than on its next sweep
+    pub event_finished: Notify,
+    /// Woken by the event listener when an event is deleted on the NVR, so with
+    /// `mirror-deletions` the pruner deletes its backups straight away
+    pub event_deleted: Notify,
 }
 
 impl Context {
     #[tracing::instrument(skip(config))]
     pub async fn new(config: Config) -> crate::Result<Self> {
         let protect_client = ProtectClient::new(config.unifi.clone())?;
+        Self::with_client(config, Arc::new(protect_client)).await
+    }
+
+    /// Like [`new`](Self::new), but talking to `protect_client` rather than the NVR in `[unifi]`,
+    /// e.g. the client's `MockProtectClient` in tests
+    #[tracing::instrument(skip(config, protect_client))]
+    pub async fn with_client(
+        config: Config,
+        protect_client: Arc<dyn ProtectApi>,
+    ) -> crate::Result<Self> {
         protect_client.login().await?;
         let protect_bootstrap = protect_client.get_bootstrap().await?;
         debug!(bootstrap_data = ?protect_bootstrap, "Received Bootstrap Data from Controller");
 
-        let metrics = Arc::new(Metrics::default());
+        let database = config.database.open().await?;
+        let filter_script = load_filter_script(&config)?;
+        let notifier = config
+            .notifications
+            .as_ref()
+            .map(Notifier::new)
+            .transpose()?;
+        let metrics = Arc::new(Metrics {
+            database: database.metrics(),
+            ..Default::default()
+        });
+        let clock = system_clock();
+
+        let backup_targets = checked_backup_targets(&config, &metrics).await?;
+
+        let mut archive_targets = archive_targets(&config, &metrics, &protect_bootstrap.nvr.name)?;
+        if config.archive.validate_targets != TargetValidation::Off {
+            let checks = join_all(
+                archive_targets
+                    .iter()
+                    .map(|target| async { (target.name(), target.validate().await) }),
+            )
+            .await;
+            let invalid = invalid_targets(config.archive.validate_targets, checks)?;
+            archive_targets.retain(|target| !invalid.contains(&target.name()));
+        }
 
         Ok(Self {
             protect_client,
-            protect_bootstrap,
-            archive_targets: archive_targets(&config, &metrics),
-            backup_targets: backup_targets(&config, &metrics),
-            database: Database::new(config.database.path.as_path()).await?,
+            protect_bootstrap: ArcSwap::from_pointee(protect_bootstrap),
+            archive_targets,
+            backup_targets: ArcSwap::from_pointee(backup_targets),
+            database,
+            filter_script: ArcSwapOption::from_pointee(filter_script),
+            notifier: ArcSwapOption::from_pointee(notifier),
+            reservations: Arc::default(),
+            spool: config.backup.spool.clone().map(Spool::new),
+            bandwidth: config
+                .backup
+                .bwlimit
+                .clone()
+                .map(|schedule| Arc::new(Limiter::new(schedule, clock.clone()))),
+            backup_config: ArcSwap::from_pointee(config.backup.clone()),
+            backup_retention: ArcSwap::from_pointee(config.backup.retention_policy()),
+            archive_retention: ArcSwap::from_pointee(config.archive.retention_policy()),
             metrics,
+            status: Arc::new(Status::new(clock.clone())),
+            clock,
+            event_finished: Notify::new(),
+            event_deleted: Notify::new(),
         })
     }
 }
+
+fn load_filter_script(config: &Config) -> crate::Result<Option<FilterScript>> {
+    config
+        .backup
+        .filter_script
+        .as_deref()
+        .map(FilterScript::load)
+        .transpose()
+}
+
+/// The configured backup targets, less any which failed their startup check, see
+/// [`invalid_targets`]
+async fn checked_backup_targets(
+    config: &Config,
+    metrics: &Arc<Metrics>,
+) -> crate::Result<Vec<Arc<dyn Backup>>> {
+    let mut backup_targets = backup_targets(config, metrics);
+    for target in &backup_targets {
+        for format in target.backup_config().file_structure_formats() {
+            filename::validate_format(format)?;
+        }
+    }
+    if config.backup.validate_targets != TargetValidation::Off {
+        let checks = join_all(
+            backup_targets
+                .iter()
+                .map(|target| async { (target.name(), target.validate().await) }),
+        )
+        .await;
+        let invalid = invalid_targets(config.backup.validate_targets, checks)?;
+        backup_targets.retain(|target| !invalid.contains(&target.name()));
+    }
+    Ok(backup_targets)
+}
+
+/// The targets to run without after their startup checks. Fails, after logging every failed
+/// check, if any failed and `validation` is [`TargetValidation::Fail`].
+fn invalid_targets(
+    validation: TargetValidation,
+    checks: Vec<(String, crate::Result<()>)>,
+) -> crate::Result<HashSet<String>> {
+    let mut invalid = HashSet::new();
+    for (name, check) in checks {
+        if let Err(err) = check {
+            match validation {
+                TargetValidation::Fail => {
+                    error!(target = name, err = ?err, "Target failed its startup check")
+                }
+                _ => warn!(
+                    target = name,
+                    err = ?err,
+                    "Target failed its startup check, running without it"
+                ),
+            }
+            invalid.insert(name);
+        }
+    }
+
+    if validation == TargetValidation::Fail && !invalid.is_empty() {
+        let mut names: Vec<_> = invalid.into_iter().collect();
+        names.sort();
+        return Err(crate::Error::General(format!(
+            "Targets failed their startup check: {}",
+            names.join(", ")
+        )));
+    }
+    Ok(invalid)
+}
+
+impl Context {
+    /// Fetch the bootstrap again so renamed and newly adopted cameras are picked up.
+    #[tracing::instrument(skip(self))]
+    pub async fn refresh_bootstrap(&self) -> crate::Result<()> {
+        let bootstrap = self.protect_client.get_bootstrap().await?;
+
+        let previous = self.protect_bootstrap.load();
+        if previous.cameras.len() != bootstrap.cameras.len() {
+            info!(
+                before = previous.cameras.len(),
+                after = bootstrap.cameras.len(),
+                "Camera list changed"
+            );
+        }
+
+        self.protect_bootstrap.store(Arc::new(bootstrap));
+        Ok(())
+    }
+
+    /// Apply a changed config without restarting: filters, retention, notifications and the
+    /// backup targets, including any added since. Nothing changes if any of it is invalid.
+    ///
+    /// Everything else, e.g. the controller, the database and how often each task runs, only
+    /// changes on restart.
+    #[tracing::instrument(skip_all)]
+    pub async fn reload(&self, config: Config) -> crate::Result<()> {
+        let filter_script = load_filter_script(&config)?;
+        let notifier = config
+            .notifications
+            .as_ref()
+            .map(Notifier::new)
+            .transpose()?;
+        let backup_targets = checked_backup_targets(&config, &self.metrics).await?;
+
+        let previous: HashSet<_> = self
+            .backup_targets
+            .load()
+            .iter()
+            .map(|t| t.name())
+            .collect();
+        let current: HashSet<_> = backup_targets.iter().map(|t| t.name()).collect();
+        for added in current.difference(&previous) {
+            info!(target = added, "Added backup target");
+        }
+        for removed in previous.difference(&current) {
+            info!(target = removed, "Removed backup target");
+        }
+
+        self.filter_script.store(filter_script.map(Arc::new));
+        self.notifier.store(notifier.map(Arc::new));
+        self.backup_targets.store(Arc::new(backup_targets));
+        self.backup_retention
+            .store(Arc::new(config.backup.retention_policy()));
+        self.archive_retention
+            .store(Arc::new(config.archive.retention_policy()));
+        self.backup_config.store(Arc::new(config.backup));
+        info!("Reloaded config");
+        Ok(())
+    }
+
+    /// The camera's current name, falling back to the last name recorded in the database for
+    /// cameras that have since been removed from the NVR.
+    pub async fn camera_name(&self, camera_id: &str) -> crate::Result<Option<String>> {
+        if let Some(camera) = self.protect_bootstrap.load().cameras.get(camera_id) {
+            return Ok(Some(camera.name.clone()));
+        }
+
+        Ok(self
+            .database
+            .get_camera(camera_id)
+            .await?
+            .map(|camera| camera.name))
+    }
+
+    /// Send an alert if notifications are configured. Failing to send is only logged, so
+    /// callers never fail because of an unreachable mail server.
+    pub async fn notify(&self, subject: &str, body: &str) {
+        if let Some(notifier) = self.notifier.load_full() {
+            notifier
+                .notify(subject, body)
+                .await
+                .inspect_err(|err| warn!(err = ?err, subject, "Failed to send notification"))
+                .ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backup::RemoteBackupConfig,
+        testing::{self, TestContext},
+    };
+
+    #[tokio::test]
+    async fn test_checked_backup_targets() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let good = context.backup_targets.load()[0].name();
+
+        // a target that can't be created, under a file
+        std::fs::write(test.dir.path().join("file"), b"").unwrap();
+        let config = |validate_targets: &str| {
+            let mut config = testing::config(
+                &test.dir,
+                &format!("validate-targets = \"{validate_targets}\""),
+            );
+            let RemoteBackupConfig::Local(local) = &config.backup.remote[0] else {
+                panic!("test config backs up to a local target");
+            };
+            let mut broken = local.clone();
+            broken.path_buf = test.dir.path().join("file").join("backups");
+            config.backup.remote.push(RemoteBackupConfig::Local(broken));
+            config
+        };
+
+        assert!(context.reload(config("fail")).await.is_err());
+        assert_eq!(context.backup_targets.load().len(), 1);
+
+        context.reload(config("disable")).await.unwrap();
+        let names: Vec<_> = context
+            .backup_targets
+            .load()
+            .iter()
+            .map(|t| t.name())
+            .collect();
+        assert_eq!(names, vec![good]);
+
+        context.reload(config("off")).await.unwrap();
+        assert_eq!(context.backup_targets.load().len(), 2);
+    }
+}
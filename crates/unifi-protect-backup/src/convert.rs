@@ -15,25 +15,40 @@ pub fn protect_event_to_database_event(protect_event: &ProtectEvent) -> Event {
         start_time: protect_event.start_time.unwrap(),
         end_time: protect_event.end_time,
         backed_up: false,
+        skip_reason: None,
+        smart_detect_types: protect_event
+            .smart_detect_types
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(","),
+        thumbnail_id: protect_event.thumbnail_id.clone(),
+        heatmap_id: protect_event.heatmap_id.clone(),
     }
 }
 
 #[tracing::instrument(skip(event, bootstrap))]
 pub fn protect_event_from_database_event(event: Event, bootstrap: &Bootstrap) -> ProtectEvent {
+    let camera = bootstrap.cameras.get(&event.camera_id);
     ProtectEvent {
         id: event.id,
         camera_id: event.camera_id.clone(),
-        camera_name: bootstrap
-            .cameras
-            .get(&event.camera_id)
-            .map(|c| c.name.clone()),
+        camera_name: camera.map(|c| c.name.clone()),
+        camera_mac: camera.map(|c| c.mac.clone()),
+        nvr_name: Some(bootstrap.nvr.name.clone()),
         start_time: Some(event.start_time),
         end_time: event.end_time,
-        event_type: EventType::Motion, // todo(steve.sampson): extract this
-        smart_detect_types: vec![],    // todo(steve.sampson): extract this
-        thumbnail_id: None,            // todo(steve.sampson): extract this
-        heatmap_id: None,              // todo(steve.sampson): extract this
+        event_type: event.event_type.parse().unwrap_or(EventType::Motion),
+        smart_detect_types: event
+            .smart_detect_types
+            .split(',')
+            .filter_map(|t| t.parse().ok())
+            .collect(),
+        thumbnail_id: event.thumbnail_id,
+        heatmap_id: event.heatmap_id,
         is_finished: event.end_time.is_some(),
+        part: None,
+        collision: None,
     }
 }
 
@@ -50,16 +65,44 @@ pub fn protect_event_from_parts(
         return Err(Error::Api("Missing camera ID".to_string()));
     };
 
+    let smart_detect_types = motion_event_completed_ws_message.smart_detect_types();
+    let event_type = if !smart_detect_types.is_empty() {
+        EventType::SmartDetect
+    } else if let Some(kind) = motion_event_completed_ws_message.audio_detect_type() {
+        EventType::Audio(kind)
+    } else {
+        motion_detected_db_event
+            .event_type
+            .parse()
+            .unwrap_or(EventType::Motion)
+    };
+
     Ok(ProtectEvent {
         id: motion_event_completed_ws_message.action_frame.id.clone(),
         camera_id,
         camera_name: known_camera.map(|c| c.name.clone()),
+        camera_mac: known_camera.map(|c| c.mac.clone()),
+        nvr_name: None,
         start_time: Some(motion_detected_db_event.start_time),
         end_time: motion_event_completed_ws_message.data_frame.end,
-        event_type: EventType::Motion,
-        smart_detect_types: vec![],
-        thumbnail_id: None,
-        heatmap_id: None,
+        event_type,
+        smart_detect_types,
+        thumbnail_id: motion_event_completed_ws_message.thumbnail_id(),
+        heatmap_id: motion_event_completed_ws_message.heatmap_id(),
         is_finished: motion_event_completed_ws_message.data_frame.end.is_some(),
+        part: None,
+        collision: None,
     })
 }
+
+pub fn camera_to_database_camera(camera: &Camera, now: i64) -> unifi_protect_data::Camera {
+    unifi_protect_data::Camera {
+        id: camera.id.clone(),
+        name: camera.name.clone(),
+        mac: camera.mac.clone(),
+        model: camera.model.clone(),
+        first_seen: now,
+        last_updated: now,
+        removed_at: None,
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+use unifi_protect_client::progress::ProgressCallback;
+
+use crate::{
+    clock::{Clock, system_clock},
+    context::Context,
+};
+
+#[derive(Debug, Default, Serialize)]
+pub struct DownloadMetrics {
+    /// Bytes of exports received from the NVR
+    pub bytes: AtomicU64,
+    /// Exports downloading now
+    pub active: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadStatus {
+    pub camera_id: String,
+    /// The footage being exported, in epoch milliseconds
+    pub start: i64,
+    pub end: i64,
+    pub bytes: u64,
+    pub started_at: DateTime<Utc>,
+    /// When the last chunk arrived, or when the download started if none has yet. One that
+    /// falls far behind is stuck rather than slow.
+    pub last_progress_at: DateTime<Utc>,
+    /// Average since the download started
+    pub bytes_per_second: u64,
+}
+
+/// Exports downloading from the NVR now, with how far each has got.
+pub struct Downloads {
+    inner: RwLock<BTreeMap<u64, DownloadStatus>>,
+    next_id: AtomicU64,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Downloads {
+    fn default() -> Self {
+        Self::new(system_clock())
+    }
+}
+
+impl Downloads {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: RwLock::new(BTreeMap::new()),
+            next_id: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<DownloadStatus> {
+        let inner = self.inner.read().expect("downloads lock poisoned");
+        inner.values().cloned().collect()
+    }
+
+    /// Record a download of `[start, end]` from `camera_id` starting, returning its id
+    fn start(&self, camera_id: &str, start: i64, end: i64) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = self.clock.now();
+        self.inner.write().expect("downloads lock poisoned").insert(
+            id,
+            DownloadStatus {
+                camera_id: camera_id.to_string(),
+                start,
+                end,
+                bytes: 0,
+                started_at: now,
+                last_progress_at: now,
+                bytes_per_second: 0,
+            },
+        );
+        id
+    }
+
+    fn progress(&self, id: u64, bytes: usize) {
+        let now = self.clock.now();
+        let mut inner = self.inner.write().expect("downloads lock poisoned");
+        let Some(download) = inner.get_mut(&id) else {
+            return;
+        };
+        download.bytes += bytes as u64;
+        download.last_progress_at = now;
+        let elapsed = (now - download.started_at).num_milliseconds().max(1) as u64;
+        download.bytes_per_second = download.bytes * 1000 / elapsed;
+    }
+
+    fn finish(&self, id: u64) {
+        self.inner
+            .write()
+            .expect("downloads lock poisoned")
+            .remove(&id);
+    }
+}
+
+impl Serialize for Downloads {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+/// Removes its download from the status, however it ends
+struct Download {
+    id: u64,
+    downloads: Arc<Downloads>,
+    metrics: Arc<DownloadMetrics>,
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        self.downloads.finish(self.id);
+        self.metrics.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Track a download of `[start, end]` from `camera_id` in the status and metrics, for as long as
+/// the returned callback is held, i.e. by the export as it arrives.
+pub fn track(context: &Context, camera_id: &str, start: i64, end: i64) -> ProgressCallback {
+    let downloads = context.status.downloads.clone();
+    let metrics = context.metrics.download.clone();
+    metrics.active.fetch_add(1, Ordering::Relaxed);
+    let download = Download {
+        id: downloads.start(camera_id, start, end),
+        downloads,
+        metrics,
+    };
+
+    Arc::new(move |bytes| {
+        download.downloads.progress(download.id, bytes);
+        download
+            .metrics
+            .bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_downloads() {
+        let clock = Arc::new(ManualClock::new(Utc.timestamp_opt(1_000, 0).unwrap()));
+        let downloads = Downloads::new(clock.clone());
+
+        let id = downloads.start("camera", 0, 60_000);
+        clock.advance(std::time::Duration::from_secs(2));
+        downloads.progress(id, 4_000);
+        let snapshot = downloads.snapshot();
+        assert_eq!(snapshot[0].bytes, 4_000);
+        assert_eq!(snapshot[0].bytes_per_second, 2_000);
+        assert_eq!(
+            snapshot[0].last_progress_at,
+            Utc.timestamp_opt(1_002, 0).unwrap()
+        );
+
+        downloads.finish(id);
+        assert!(downloads.snapshot().is_empty());
+    }
+}
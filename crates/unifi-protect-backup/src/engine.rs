@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use tracing::warn;
+
+use unifi_protect_client::api::ProtectApi;
+
+use crate::{Result, config::Config, context::Context, task};
+
+/// The backup service without the binary around it: every background task, sharing one
+/// [`Context`]. Logging, metrics and the command line are left to the caller.
+pub struct BackupEngine {
+    context: Arc<Context>,
+    config: Config,
+}
+
+impl BackupEngine {
+    /// Log in to the NVR, open the database and set up the configured targets.
+    pub async fn new(config: Config) -> Result<Self> {
+        let context = Arc::new(Context::new(config.clone()).await?);
+        Ok(Self { context, config })
+    }
+
+    /// Like [`new`](Self::new), but talking to `protect_client` rather than the NVR in `[unifi]`
+    pub async fn with_client(config: Config, protect_client: Arc<dyn ProtectApi>) -> Result<Self> {
+        let context = Arc::new(Context::with_client(config.clone(), protect_client).await?);
+        Ok(Self { context, config })
+    }
+
+    /// Shared with every task, e.g. for the status and metrics they report
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    /// Run every task until one of them stops.
+    pub async fn run(&self) -> Result<()> {
+        let context = &self.context;
+        let config = &self.config;
+
+        let mut unifi_event_listener = task::UnifiEventListener::new(context.clone());
+        let mut db_poller = task::BackupDbPoller::new(context.clone(), config.backup.clone());
+        let mut archiver = task::Archiver::new(context.clone(), config.archive.clone());
+        let mut pruner = task::Pruner::new(context.clone(), config.backup.clone());
+        let mut bootstrap_refresher =
+            task::BootstrapRefresher::new(context.clone(), config.unifi.clone());
+        let mut reconciler = task::Reconciler::new(context.clone(), config.backup.clone());
+        let mut database_maintenance =
+            task::DatabaseMaintenance::new(context.clone(), config.database.maintenance_interval);
+        let mut verifier = task::Verifier::new(context.clone(), config.backup.verify_interval);
+        let mut integrity_sampler =
+            task::IntegritySampler::new(context.clone(), config.backup.clone());
+        let mut health_checker = task::HealthChecker::new(context.clone(), config.backup.clone());
+        let mut indexer = task::Indexer::new(context.clone(), config.backup.clone());
+        let mut reporter = task::Reporter::new(
+            context.clone(),
+            config.report.clone(),
+            config.backup.dry_run,
+        );
+        let mut camera_monitor = task::CameraMonitor::new(context.clone(), config.backup.clone());
+
+        tokio::select! {
+            res = unifi_event_listener.run() => {
+                warn!("Unifi Event Listener stopped: {:?}", res);
+            }
+            res = db_poller.run() => {
+                warn!("DB Poller stopped: {:?}", res);
+            }
+            res = archiver.run() => {
+                warn!("Archiver stopped: {:?}", res);
+            }
+            res = pruner.run() => {
+                warn!("Pruner stopped: {:?}", res);
+            }
+            res = bootstrap_refresher.run() => {
+                warn!("Bootstrap Refresher stopped: {:?}", res);
+            }
+            res = reconciler.run() => {
+                warn!("Reconciler stopped: {:?}", res);
+            }
+            res = database_maintenance.run() => {
+                warn!("Database Maintenance stopped: {:?}", res);
+            }
+            res = verifier.run() => {
+                warn!("Verifier stopped: {:?}", res);
+            }
+            res = integrity_sampler.run() => {
+                warn!("Integrity Sampler stopped: {:?}", res);
+            }
+            res = health_checker.run() => {
+                warn!("Health Checker stopped: {:?}", res);
+            }
+            res = indexer.run() => {
+                warn!("Indexer stopped: {:?}", res);
+            }
+            res = reporter.run() => {
+                warn!("Reporter stopped: {:?}", res);
+            }
+            res = camera_monitor.run() => {
+                warn!("Camera Monitor stopped: {:?}", res);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -22,12 +22,39 @@ pub enum Error {
     #[error(transparent)]
     Toml(#[from] toml::de::Error),
 
+    #[error(transparent)]
+    TomlSerialization(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    TomlEdit(#[from] toml_edit::TomlError),
+
+    #[cfg(feature = "native-tls")]
     #[error(transparent)]
     NativeTls(#[from] native_tls::Error),
 
     #[error("Backup process failed: {0}")]
     Backup(String),
 
+    #[error("{operation} failed ({status}): {}", last_line(.output))]
+    Subprocess {
+        operation: String,
+        status: String,
+        /// The last [`OUTPUT_TAIL_BYTES`] of stderr (or stdout if stderr was empty)
+        output: String,
+    },
+
+    #[error("Invalid export: {0}")]
+    InvalidExport(String),
+
+    #[error("Notification error: {0}")]
+    Notification(String),
+
+    #[error("Filter script error: {0}")]
+    Script(String),
+
+    #[error("Credential error: {0}")]
+    Credentials(String),
+
     #[error("Authentication failed: {0}")]
     Auth(String),
 
@@ -46,3 +73,41 @@ pub enum Error {
     #[error("Tracing error: {0}")]
     Tracing(String),
 }
+
+/// How much subprocess output is kept with a failure
+pub const OUTPUT_TAIL_BYTES: usize = 16 * 1024;
+
+impl Error {
+    /// A failed subprocess, keeping the tail of its output for later inspection.
+    pub fn subprocess(operation: &str, output: &std::process::Output) -> Self {
+        let stream = if output.stderr.iter().all(u8::is_ascii_whitespace) {
+            &output.stdout
+        } else {
+            &output.stderr
+        };
+        let tail = &stream[stream.len().saturating_sub(OUTPUT_TAIL_BYTES)..];
+
+        Error::Subprocess {
+            operation: operation.to_string(),
+            status: output.status.to_string(),
+            output: String::from_utf8_lossy(tail).into_owned(),
+        }
+    }
+
+    /// Captured subprocess output, if this error came from one.
+    pub fn output(&self) -> Option<&str> {
+        match self {
+            Error::Subprocess { output, .. } => Some(output),
+            _ => None,
+        }
+    }
+}
+
+fn last_line(output: &str) -> &str {
+    output
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("no output")
+}
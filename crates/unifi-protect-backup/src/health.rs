@@ -0,0 +1,133 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock, atomic::AtomicU64},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+
+use crate::clock::{Clock, system_clock};
+
+#[derive(Debug, Default, Serialize)]
+pub struct HealthCheckMetrics {
+    /// Health checks run, over every target
+    pub checks: AtomicU64,
+    /// Health checks which failed
+    pub failures: AtomicU64,
+    /// Targets whose last health check failed
+    pub unavailable: AtomicU64,
+    /// Milliseconds the last successful check of any target took
+    pub last_latency_ms: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetHealth {
+    pub available: bool,
+    /// How long the last successful check took
+    pub latency_ms: Option<u64>,
+    pub last_check: DateTime<Utc>,
+    /// Set while the target is unavailable
+    pub last_error: Option<String>,
+    /// Checks which failed since the last that succeeded
+    pub consecutive_failures: u32,
+}
+
+/// The outcome of the latest health check of each backup and archive target.
+pub struct HealthChecks {
+    inner: RwLock<BTreeMap<String, TargetHealth>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for HealthChecks {
+    fn default() -> Self {
+        Self::new(system_clock())
+    }
+}
+
+impl HealthChecks {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: RwLock::new(BTreeMap::new()),
+            clock,
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, TargetHealth> {
+        self.inner.read().expect("health lock poisoned").clone()
+    }
+
+    /// Record a successful check which took `latency`. Returns whether the target was
+    /// unavailable until now.
+    pub fn succeeded(&self, target: &str, latency: Duration) -> bool {
+        let mut inner = self.inner.write().expect("health lock poisoned");
+        let previous = inner.insert(
+            target.to_string(),
+            TargetHealth {
+                available: true,
+                latency_ms: Some(latency.as_millis() as u64),
+                last_check: self.clock.now(),
+                last_error: None,
+                consecutive_failures: 0,
+            },
+        );
+        previous.is_some_and(|health| !health.available)
+    }
+
+    /// Record a failed check. Returns whether the target was available until now, which it's
+    /// taken to be before its first check.
+    pub fn failed(&self, target: &str, error: impl ToString) -> bool {
+        let now = self.clock.now();
+        let mut inner = self.inner.write().expect("health lock poisoned");
+        let health = inner.entry(target.to_string()).or_insert(TargetHealth {
+            available: true,
+            latency_ms: None,
+            last_check: now,
+            last_error: None,
+            consecutive_failures: 0,
+        });
+
+        let was_available = health.available;
+        health.available = false;
+        health.last_check = now;
+        health.last_error = Some(error.to_string());
+        health.consecutive_failures += 1;
+        was_available
+    }
+}
+
+impl Serialize for HealthChecks {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_health_checks() {
+        let start = Utc.with_ymd_and_hms(2025, 8, 4, 12, 0, 0).unwrap();
+        let checks = HealthChecks::new(Arc::new(ManualClock::new(start)));
+
+        assert!(!checks.succeeded("s3", Duration::from_millis(120)));
+        assert!(checks.failed("s3", "connection refused"));
+        assert!(!checks.failed("s3", "connection refused"));
+        assert!(checks.failed("borg", "repository does not exist"));
+
+        let health = &checks.snapshot()["s3"];
+        assert!(!health.available);
+        assert_eq!(health.consecutive_failures, 2);
+        assert_eq!(health.latency_ms, Some(120));
+
+        assert!(checks.succeeded("s3", Duration::from_millis(80)));
+        let health = &checks.snapshot()["s3"];
+        assert!(health.available);
+        assert_eq!(health.last_error, None);
+        assert_eq!(health.latency_ms, Some(80));
+    }
+}
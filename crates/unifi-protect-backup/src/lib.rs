@@ -1,11 +1,27 @@
 pub mod archive;
 pub mod backup;
+pub mod bandwidth;
+pub mod clock;
+pub mod command;
 pub mod config;
 pub mod context;
 pub mod convert;
+pub mod download;
+pub mod engine;
+pub mod health;
 pub mod metrics;
+pub mod notify;
 pub mod opentelemetry;
+pub mod privacy;
+pub mod retention;
+pub mod script;
+pub mod sensor;
+pub mod size;
+pub mod status;
 pub mod task;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod validate;
 
 pub mod error;
 
@@ -1,30 +1,35 @@
-use std::sync::Arc;
-
 use clap::Parser;
 use tracing::{debug, error, info, warn};
 
 use unifi_protect_backup::{
     Result,
-    config::{Args, Config, check_and_create_config},
+    config::{Args, Config, check_and_create_config, config_in_env},
     context::Context,
+    engine::BackupEngine,
     metrics::start_metrics_server,
-    opentelemetry, task,
+    opentelemetry,
 };
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Args<Config> = Args::parse();
 
-    // Only prompt for config setup if no config file was provided via --config
-    if args.config.is_none() {
+    // Only prompt for config setup if no config file was provided via --config, nor any config
+    // values in the environment
+    if args.config.is_none() && !config_in_env() {
         check_and_create_config()
             .await
             .inspect_err(|err| error!(err = ?err, "Error checking for (or creating) config"))?;
     }
 
-    let config = args
+    let mut config = args
         .get_config()
-        .inspect_err(|err| error!(err = ?err, "Error getting config"))?;
+        .and_then(Config::resolve_secrets)
+        .inspect_err(|err| error!(err = ?err, "Error getting config"))?
+        .with_camera_configs();
+    if args.dry_run {
+        config = config.dry_run();
+    }
     debug!(config = ?config, "Parsed config successfully");
 
     let maybe_loki_task = opentelemetry::init(&config);
@@ -35,24 +40,16 @@ async fn main() -> Result<()> {
         env!("CARGO_PKG_VERSION")
     );
 
-    let context = Arc::new(Context::new(config.clone()).await?);
-    let mut unifi_event_listener = task::UnifiEventListener::new(context.clone());
-    let mut db_poller = task::BackupDbPoller::new(context.clone(), config.backup.clone());
-    let mut archiver = task::Archiver::new(context.clone(), config.archive.clone());
-    let mut pruner = task::Pruner::new(context.clone(), config.backup.clone());
+    if let Some(command) = &args.command {
+        return command.run(&config).await;
+    }
+
+    let engine = BackupEngine::new(config.clone()).await?;
+    let context = engine.context();
 
     tokio::select! {
-        res = unifi_event_listener.run() => {
-            warn!("Unifi Event Listener stopped: {:?}", res);
-        }
-        res = db_poller.run() => {
-            warn!("DB Poller stopped: {:?}", res);
-        }
-        res = archiver.run() => {
-            warn!("Archiver stopped: {:?}", res);
-        }
-        res = pruner.run() => {
-            warn!("Pruner stopped: {:?}", res);
+        res = engine.run() => {
+            warn!("Backup engine stopped: {:?}", res);
         }
         res = async {
           if let Some(loki_task) = maybe_loki_task {
@@ -67,8 +64,11 @@ async fn main() -> Result<()> {
           if let Some(metrics_config) = config.metrics {
             start_metrics_server(
             context.metrics.clone(),
+            context.status.clone(),
+            context.database.clone(),
             metrics_config.address.as_str(),
             metrics_config.port,
+            metrics_config.token,
         ).await
         } else {
             std::future::pending().await // Never resolves
@@ -76,8 +76,42 @@ async fn main() -> Result<()> {
         } => {
             warn!("HTTP server task stopped: {:?}", res);
         }
+        res = reload_on_sighup(&args, context) => {
+            warn!("Config reloader stopped: {:?}", res);
+        }
     }
 
     info!("Exiting...");
     Ok(())
 }
+
+/// Read the config again on every SIGHUP and apply what can change while running, see
+/// [`Context::reload`]. A config that fails to load is logged, and the running one kept.
+#[cfg(unix)]
+async fn reload_on_sighup(args: &Args<Config>, context: &Context) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading config");
+        let mut config = match args.reload_config().and_then(Config::resolve_secrets) {
+            Ok(config) => config.with_camera_configs(),
+            Err(err) => {
+                error!(err = ?err, "Failed to read config, keeping the running one");
+                continue;
+            }
+        };
+        if args.dry_run {
+            config = config.dry_run();
+        }
+        if let Err(err) = context.reload(config).await {
+            error!(err = ?err, "Failed to apply config, keeping the running one");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn reload_on_sighup(_args: &Args<Config>, _context: &Context) -> Result<()> {
+    std::future::pending().await // No SIGHUP to reload on
+}
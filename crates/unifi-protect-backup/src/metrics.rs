@@ -1,24 +1,45 @@
 use crate::{
-    archive::borg::Metrics as BorgArchiveMetrics,
-    backup::{local::Metrics as LocalBackupMetrics, rclone::Metrics as RcloneBackupMetrics},
+    archive::{borg::Metrics as BorgArchiveMetrics, bundle::Metrics as BundleArchiveMetrics},
+    backup::{
+        breaker::CircuitBreakerMetrics, local::Metrics as LocalBackupMetrics,
+        rclone::Metrics as RcloneBackupMetrics,
+    },
+    download::DownloadMetrics,
+    health::HealthCheckMetrics,
+    script::FilterScriptMetrics,
+    status::Status,
+    task::{BacklogMetrics, EventListenerMetrics},
 };
 use hyper::{Request, Response, body::Incoming, server::conn::http1, service::service_fn};
 use hyper_util::rt::TokioIo;
 use serde::Serialize;
 use std::{net::SocketAddr, sync::Arc};
 use tokio::net::TcpListener;
+use unifi_protect_client::config::Secret;
+use unifi_protect_data::{Database, DatabaseMetrics, EventSearch};
 
 #[derive(Default, Serialize)]
 pub struct Metrics {
     pub local_backup: Arc<LocalBackupMetrics>,
     pub rclone_backup: Arc<RcloneBackupMetrics>,
     pub borg_archive: Arc<BorgArchiveMetrics>,
+    pub bundle_archive: Arc<BundleArchiveMetrics>,
+    pub database: Arc<DatabaseMetrics>,
+    pub event_listener: Arc<EventListenerMetrics>,
+    pub filter_script: Arc<FilterScriptMetrics>,
+    pub circuit_breaker: Arc<CircuitBreakerMetrics>,
+    pub health_check: Arc<HealthCheckMetrics>,
+    pub backlog: Arc<BacklogMetrics>,
+    pub download: Arc<DownloadMetrics>,
 }
 
 pub async fn start_metrics_server(
     metrics: Arc<Metrics>,
+    status: Arc<Status>,
+    database: Database,
     address: &str,
     port: u16,
+    token: Option<Secret<String>>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let addr: SocketAddr = format!("{address}:{port}").parse()?;
     let listener = TcpListener::bind(addr).await?;
@@ -29,10 +50,24 @@ pub async fn start_metrics_server(
         let (stream, _) = listener.accept().await?;
         let io = TokioIo::new(stream);
         let metrics = metrics.clone();
+        let status = status.clone();
+        let database = database.clone();
+        let token = token.clone();
 
         tokio::task::spawn(async move {
             if let Err(err) = http1::Builder::new()
-                .serve_connection(io, service_fn(|req| handle_request(req, metrics.clone())))
+                .serve_connection(
+                    io,
+                    service_fn(|req| {
+                        handle_request(
+                            req,
+                            metrics.clone(),
+                            status.clone(),
+                            database.clone(),
+                            token.clone(),
+                        )
+                    }),
+                )
                 .await
             {
                 tracing::error!("Error serving connection: {:?}", err);
@@ -44,6 +79,9 @@ pub async fn start_metrics_server(
 async fn handle_request(
     req: Request<Incoming>,
     metrics: Arc<Metrics>,
+    status: Arc<Status>,
+    database: Database,
+    token: Option<Secret<String>>,
 ) -> Result<Response<String>, hyper::Error> {
     match req.uri().path() {
         "/metrics" => {
@@ -57,6 +95,51 @@ async fn handle_request(
                 .body(prometheus_output)
                 .unwrap())
         }
+        "/status" => {
+            let status_output = serde_json::to_string_pretty(&*status)
+                .unwrap_or_else(|e| format!("Error serializing status: {e}"));
+
+            Ok(Response::builder()
+                .status(200)
+                .header("Content-Type", "application/json")
+                .body(status_output)
+                .unwrap())
+        }
+        "/events/search" => {
+            if !authorized(&req, token.as_ref()) {
+                return Ok(Response::builder()
+                    .status(401)
+                    .header("WWW-Authenticate", "Bearer")
+                    .body("Unauthorized".to_string())
+                    .unwrap());
+            }
+
+            let query = req.uri().query().unwrap_or_default();
+            let search: EventSearch = match serde_urlencoded::from_str(query) {
+                Ok(search) => search,
+                Err(e) => {
+                    return Ok(Response::builder()
+                        .status(400)
+                        .body(format!("Invalid search: {e}"))
+                        .unwrap());
+                }
+            };
+
+            match database.search_events(&search).await {
+                Ok(events) => Ok(Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .body(
+                        serde_json::to_string_pretty(&events)
+                            .unwrap_or_else(|e| format!("Error serializing events: {e}")),
+                    )
+                    .unwrap()),
+                Err(e) => Ok(Response::builder()
+                    .status(500)
+                    .body(format!("Search failed: {e}"))
+                    .unwrap()),
+            }
+        }
         _ => Ok(Response::builder()
             .status(404)
             .body("Not Found".to_string())
@@ -64,6 +147,27 @@ async fn handle_request(
     }
 }
 
+/// Whether `req` carries `token` as a bearer token, or there's no token to check
+fn authorized<B>(req: &Request<B>, token: Option<&Secret<String>>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    req.headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| constant_time_eq(presented, token.expose()))
+}
+
+/// `a == b` in a time that doesn't depend on where they first differ
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,4 +183,22 @@ mod tests {
             .unwrap()
         );
     }
+
+    #[test]
+    fn test_authorized() {
+        let token = Secret::new("s3cret".to_string());
+        let request = |authorization: Option<&str>| {
+            let mut request = Request::builder().uri("/events/search");
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            request.body(()).unwrap()
+        };
+
+        assert!(authorized(&request(None), None));
+        assert!(authorized(&request(Some("Bearer s3cret")), Some(&token)));
+        assert!(!authorized(&request(Some("Bearer s3cre")), Some(&token)));
+        assert!(!authorized(&request(Some("Basic s3cret")), Some(&token)));
+        assert!(!authorized(&request(None), Some(&token)));
+    }
 }
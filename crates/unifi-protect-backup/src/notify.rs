@@ -0,0 +1,80 @@
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::Mailbox,
+    transport::smtp::authentication::Credentials,
+};
+
+use crate::{Error, Result, config::NotificationConfig};
+
+/// Port on which SMTP servers expect TLS from the start rather than via STARTTLS
+const SMTPS_PORT: u16 = 465;
+
+/// Sends alerts by email to the addresses in `[notifications]`.
+pub struct Notifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationConfig) -> Result<Self> {
+        let (Some(host), Some(from), Some(to)) =
+            (&config.smtp_host, &config.email_from, &config.email_to)
+        else {
+            return Err(Error::Notification(
+                "smtp-host, email-from and email-to must all be set".to_string(),
+            ));
+        };
+
+        let port = config.smtp_port.unwrap_or(SMTPS_PORT);
+        let mut transport = if port == SMTPS_PORT {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+        }
+        .map_err(|e| Error::Notification(e.to_string()))?
+        .port(port);
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            transport = transport.credentials(Credentials::new(
+                username.clone(),
+                password.expose().clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: transport.build(),
+            from: parse_mailbox(from)?,
+            // several recipients may be given, comma separated
+            to: to
+                .split(',')
+                .map(str::trim)
+                .map(parse_mailbox)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    #[tracing::instrument(skip(self, body))]
+    pub async fn notify(&self, subject: &str, body: &str) -> Result<()> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("[unifi-protect-backup] {subject}"));
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message
+            .body(body.to_string())
+            .map_err(|e| Error::Notification(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| Error::Notification(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| Error::Notification(format!("{address}: {e}")))
+}
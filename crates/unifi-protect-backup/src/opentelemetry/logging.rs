@@ -22,7 +22,7 @@ pub(crate) fn loki_layer(
     if let (Some(username), Some(password)) = (loki_config.username, loki_config.password) {
         let auth_header = format!(
             "Basic {}",
-            base64::prelude::BASE64_STANDARD.encode(format!("{username}:{password}"))
+            base64::prelude::BASE64_STANDARD.encode(format!("{username}:{}", password.expose()))
         );
         extra_fields.insert("Authorization".to_string(), auth_header);
     }
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// A recurring window (in the host's local time) during which events from the given cameras are
+/// recorded but never exported or uploaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct PrivacySchedule {
+    /// Camera ids or names; empty means every camera
+    #[serde(default)]
+    pub cameras: Vec<String>,
+    /// Days the window starts on; empty means every day
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    /// May be earlier than `start` for windows spanning midnight
+    pub end: NaiveTime,
+}
+
+impl PrivacySchedule {
+    pub fn applies_to(&self, camera_id: &str, camera_name: Option<&str>) -> bool {
+        self.cameras.is_empty()
+            || self
+                .cameras
+                .iter()
+                .any(|camera| camera == camera_id || Some(camera.as_str()) == camera_name)
+    }
+
+    pub fn covers(&self, time: DateTime<Local>) -> bool {
+        let time_of_day = time.time();
+        let (in_window, day) = if self.start <= self.end {
+            (
+                time_of_day >= self.start && time_of_day < self.end,
+                time.weekday(),
+            )
+        } else if time_of_day >= self.start {
+            (true, time.weekday())
+        } else {
+            // the early-morning part of a window which started the day before
+            (time_of_day < self.end, time.weekday().pred())
+        };
+
+        in_window && (self.days.is_empty() || self.days.contains(&day))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        // 2025-08-04 is a Monday
+        Local
+            .with_ymd_and_hms(2025, 8, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_covers() {
+        let office = PrivacySchedule {
+            cameras: vec![],
+            days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        };
+        assert!(office.covers(at(4, 9, 0)));
+        assert!(!office.covers(at(4, 17, 0)));
+        assert!(!office.covers(at(9, 12, 0))); // Saturday
+
+        let overnight = PrivacySchedule {
+            days: vec![Weekday::Fri],
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            ..office
+        };
+        assert!(overnight.covers(at(8, 23, 0))); // Friday night
+        assert!(overnight.covers(at(9, 5, 59))); // Saturday morning
+        assert!(!overnight.covers(at(8, 5, 0))); // Friday morning
+    }
+}
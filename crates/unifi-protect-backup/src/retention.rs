@@ -0,0 +1,350 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use unifi_protect_client::events::{EventType, SmartDetectType};
+
+use crate::{clock::Clock, size::ByteSize};
+
+/// Limits on top of a section's `retention-period`, from its `retention` table
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct RetentionConfig {
+    /// Keep no more than this many of the newest files (or archives) on each target
+    #[serde(default)]
+    pub max_count: Option<usize>,
+    /// Keep no more than this size of the newest files on each target, e.g. `500GiB`
+    #[serde(default)]
+    pub max_size: Option<ByteSize>,
+    /// `retention-period` replacements by detection type, e.g. `person = "90d"`
+    #[serde(default)]
+    pub type_overrides: HashMap<String, humantime_serde::Serde<Duration>>,
+    /// `retention-period` replacements by camera id or name, e.g. `Driveway = "7d"`
+    #[serde(default)]
+    pub camera_overrides: HashMap<String, humantime_serde::Serde<Duration>>,
+    /// Event ids and archive names which are never pruned
+    #[serde(default)]
+    pub holds: Vec<String>,
+    /// Thin out what's kept as it ages, see [`GfsConfig`]
+    #[serde(default)]
+    pub gfs: Option<GfsConfig>,
+}
+
+/// Grandfather-father-son thinning: keep everything for `keep-all`, then one event per camera
+/// per day for `daily` days, then one per camera per week for `weekly` weeks, then nothing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct GfsConfig {
+    #[serde(with = "humantime_serde")]
+    pub keep_all: Duration,
+    #[serde(default)]
+    pub daily: u32,
+    #[serde(default)]
+    pub weekly: u32,
+}
+
+/// Which of [`GfsConfig`]'s periods something falls in
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum GfsSlot {
+    All,
+    Day(NaiveDate),
+    /// ISO year and week
+    Week(i32, u32),
+}
+
+impl GfsConfig {
+    /// The period something from `time` falls in, or `None` once it's past them all
+    fn slot(&self, now: DateTime<Utc>, time: DateTime<Utc>) -> Option<GfsSlot> {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+        let age = (now - time).to_std().unwrap_or_default();
+        let daily_until = self.keep_all + DAY * self.daily;
+        let weekly_until = daily_until + DAY * 7 * self.weekly;
+        let local = time.with_timezone(&Local).date_naive();
+
+        if age < self.keep_all {
+            Some(GfsSlot::All)
+        } else if age < daily_until {
+            Some(GfsSlot::Day(local))
+        } else if age < weekly_until {
+            let week = local.iso_week();
+            Some(GfsSlot::Week(week.year(), week.week()))
+        } else {
+            None
+        }
+    }
+}
+
+/// How long backups, archives and the events behind them are kept. Resolved once per section
+/// from its config, then applied the same way to the database, every backup target and every
+/// archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub max_age: Duration,
+    pub max_count: Option<usize>,
+    pub max_size: Option<u64>,
+    pub type_overrides: HashMap<String, Duration>,
+    pub camera_overrides: HashMap<String, Duration>,
+    pub holds: HashSet<String>,
+    pub gfs: Option<GfsConfig>,
+}
+
+/// What the policy needs to know about a file, backup record or archive to decide whether to
+/// keep it
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// Names a hold can match: event id, path or archive name
+    pub names: Vec<String>,
+    pub time: DateTime<Utc>,
+    pub size_bytes: u64,
+    /// As in `detection-types`, see [`detection_types`]
+    pub detection_types: Vec<String>,
+    /// Id and name of the camera, as far as they're known
+    pub cameras: Vec<String>,
+}
+
+impl RetentionPolicy {
+    pub fn new(max_age: Duration, config: &RetentionConfig) -> Self {
+        Self {
+            max_age,
+            max_count: config.max_count,
+            max_size: config.max_size.map(u64::from),
+            type_overrides: config
+                .type_overrides
+                .iter()
+                .map(|(detection_type, max_age)| (detection_type.clone(), **max_age))
+                .collect(),
+            camera_overrides: config
+                .camera_overrides
+                .iter()
+                .map(|(camera, max_age)| (camera.clone(), **max_age))
+                .collect(),
+            holds: config.holds.iter().cloned().collect(),
+            gfs: config.gfs.clone(),
+        }
+    }
+
+    /// How long something with these detection types from one of these cameras is kept: the
+    /// longest type or camera override among them, or `max_age` if none apply.
+    pub fn max_age_for(&self, detection_types: &[String], cameras: &[String]) -> Duration {
+        let types = detection_types
+            .iter()
+            .filter_map(|detection_type| self.type_overrides.get(detection_type));
+        let cameras = cameras
+            .iter()
+            .filter_map(|camera| self.camera_overrides.get(camera));
+        types.chain(cameras).max().copied().unwrap_or(self.max_age)
+    }
+
+    /// The longest anything from a camera without an override of its own is kept, for records
+    /// that must outlive every copy
+    pub fn longest_age(&self) -> Duration {
+        self.type_overrides
+            .values()
+            .copied()
+            .fold(self.max_age, Duration::max)
+    }
+
+    /// The longest anything from a camera with one of these names is kept, or `None` if the
+    /// camera has no override of its own
+    pub fn longest_age_for_camera(&self, cameras: &[String]) -> Option<Duration> {
+        let camera_age = cameras
+            .iter()
+            .filter_map(|camera| self.camera_overrides.get(camera))
+            .max()?;
+        Some(
+            self.type_overrides
+                .values()
+                .fold(*camera_age, |a, b| a.max(*b)),
+        )
+    }
+
+    pub fn is_held(&self, names: &[String]) -> bool {
+        names.iter().any(|name| self.holds.contains(name))
+    }
+
+    /// The items the policy no longer keeps. Going newest first, an item is kept while it's
+    /// within the age for its detection types and the items kept so far are within `max_count`
+    /// and `max_size`, and, with `gfs`, it's the newest event from its camera in its day or week
+    /// once past `keep-all`. Held items are always kept and don't count towards any limit.
+    pub fn expired<T>(
+        &self,
+        clock: &dyn Clock,
+        items: Vec<T>,
+        candidate: impl Fn(&T) -> Candidate,
+    ) -> Vec<T> {
+        let mut items: Vec<_> = items
+            .into_iter()
+            .map(|item| (candidate(&item), item))
+            .collect();
+        items.sort_by_key(|(candidate, _)| Reverse(candidate.time));
+
+        let mut kept = 0;
+        let mut kept_bytes = 0;
+        let mut gfs_slots = HashMap::new();
+        let mut expired = vec![];
+        for (candidate, item) in items {
+            if self.is_held(&candidate.names) {
+                continue;
+            }
+
+            let max_age = self.max_age_for(&candidate.detection_types, &candidate.cameras);
+            let too_old = candidate.time < clock.ago(max_age);
+            let too_many = self.max_count.is_some_and(|max_count| kept >= max_count);
+            let too_big = self
+                .max_size
+                .is_some_and(|max_size| kept_bytes + candidate.size_bytes > max_size);
+
+            if too_old || too_many || too_big || !self.gfs_keeps(clock, &candidate, &mut gfs_slots)
+            {
+                expired.push(item);
+            } else {
+                kept += 1;
+                kept_bytes += candidate.size_bytes;
+            }
+        }
+
+        expired
+    }
+
+    /// Whether `gfs` keeps `candidate`, claiming its camera's slot for the day or week if it's
+    /// the first (i.e. newest) to get there. All parts of an event share the slot.
+    fn gfs_keeps(
+        &self,
+        clock: &dyn Clock,
+        candidate: &Candidate,
+        slots: &mut HashMap<(Option<String>, GfsSlot), Option<String>>,
+    ) -> bool {
+        let Some(gfs) = &self.gfs else {
+            return true;
+        };
+
+        match gfs.slot(clock.now(), candidate.time) {
+            None => false,
+            Some(GfsSlot::All) => true,
+            Some(slot) => {
+                let event = candidate.names.first().cloned();
+                let camera = candidate.cameras.first().cloned();
+                *slots.entry((camera, slot)).or_insert_with(|| event.clone()) == event
+            }
+        }
+    }
+}
+
+/// The names `type-overrides` are keyed by, matching `detection-types`: each smart detection
+/// type, `audio` and what was heard for audio detections, `sensor` and the event type for sensor
+/// clips, or the event type for other events.
+pub fn detection_types(
+    event_type: &EventType,
+    smart_detect_types: &[SmartDetectType],
+) -> Vec<String> {
+    if let EventType::Audio(kind) = event_type {
+        vec!["audio".to_string(), kind.to_string()]
+    } else if let EventType::Sensor(_) = event_type {
+        vec!["sensor".to_string(), event_type.to_string()]
+    } else if smart_detect_types.is_empty() {
+        vec![event_type.to_string()]
+    } else {
+        smart_detect_types.iter().map(ToString::to_string).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_expired() {
+        let clock = ManualClock::new(DateTime::from_timestamp(100 * 24 * 60 * 60, 0).unwrap());
+        let day = Duration::from_secs(24 * 60 * 60);
+        let policy = RetentionPolicy::new(
+            30 * day,
+            &RetentionConfig {
+                max_count: Some(3),
+                type_overrides: HashMap::from([("person".to_string(), (90 * day).into())]),
+                camera_overrides: HashMap::from([("Driveway".to_string(), (7 * day).into())]),
+                holds: vec!["held".to_string()],
+                ..Default::default()
+            },
+        );
+
+        // (name, days old, detection type, camera)
+        let items = vec![
+            ("new", 1, "motion", "Porch"),
+            ("driveway-motion", 10, "motion", "Driveway"),
+            ("old-motion", 40, "motion", "Porch"),
+            ("old-person", 40, "person", "Driveway"),
+            ("held", 365, "motion", "Porch"),
+            ("older-person", 60, "person", "Porch"),
+            ("oldest-person", 80, "person", "Porch"),
+        ];
+        let expired = policy.expired(&clock, items, |(name, days_old, detection_type, camera)| {
+            Candidate {
+                names: vec![name.to_string()],
+                time: clock.ago(*days_old * day),
+                size_bytes: 0,
+                detection_types: vec![detection_type.to_string()],
+                cameras: vec![camera.to_string()],
+            }
+        });
+        let expired: Vec<_> = expired.into_iter().map(|(name, ..)| name).collect();
+
+        assert_eq!(
+            expired,
+            vec!["driveway-motion", "old-motion", "oldest-person"]
+        );
+        assert_eq!(policy.longest_age(), 90 * day);
+        assert_eq!(
+            policy.longest_age_for_camera(&["Driveway".to_string()]),
+            Some(90 * day)
+        );
+        assert_eq!(policy.longest_age_for_camera(&["Porch".to_string()]), None);
+    }
+
+    #[test]
+    fn test_gfs() {
+        let clock = ManualClock::new(DateTime::from_timestamp(100 * 24 * 60 * 60, 0).unwrap());
+        let hour = Duration::from_secs(60 * 60);
+        let day = 24 * hour;
+        let policy = RetentionPolicy::new(
+            365 * day,
+            &RetentionConfig {
+                gfs: Some(GfsConfig {
+                    keep_all: 2 * day,
+                    daily: 3,
+                    weekly: 2,
+                }),
+                ..Default::default()
+            },
+        );
+
+        // (event, path, hours old, camera)
+        let items = vec![
+            ("new", "new", 1, "Porch"),
+            ("daily", "daily_part0", 72, "Porch"),
+            ("daily", "daily_part1", 72, "Porch"),
+            ("same-day", "same-day", 73, "Porch"),
+            ("other-camera", "other-camera", 73, "Driveway"),
+            ("weekly", "weekly", 9 * 24, "Porch"),
+            ("same-week", "same-week", 9 * 24 + 1, "Porch"),
+            ("too-old", "too-old", 30 * 24, "Porch"),
+        ];
+        let expired = policy.expired(&clock, items, |(event, path, hours_old, camera)| {
+            Candidate {
+                names: vec![event.to_string(), path.to_string()],
+                time: clock.ago(*hours_old * hour),
+                size_bytes: 0,
+                detection_types: vec![],
+                cameras: vec![camera.to_string()],
+            }
+        });
+        let expired: Vec<_> = expired.into_iter().map(|(_, path, ..)| path).collect();
+
+        assert_eq!(expired, vec!["same-day", "same-week", "too-old"]);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{path::Path, sync::atomic::AtomicU64};
+
+use serde::Serialize;
+use unifi_protect_client::events::ProtectEvent;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Default, Serialize)]
+pub struct FilterScriptMetrics {
+    /// Events the script decided not to back up
+    pub rejected: AtomicU64,
+    /// Script evaluations which failed; those events are backed up anyway
+    pub errors: AtomicU64,
+}
+
+/// A user-supplied [rhai](https://rhai.rs) script deciding whether an event is backed up. The
+/// script sees the event as an `event` map and must evaluate to a bool.
+/// Operations a single evaluation may run before it's stopped, so a script that never finishes
+/// can't stall the poller
+#[cfg(feature = "scripting")]
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// How deeply a script's functions may call each other
+#[cfg(feature = "scripting")]
+const MAX_CALL_LEVELS: usize = 32;
+
+#[cfg(feature = "scripting")]
+pub struct FilterScript {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+/// Stand-in for builds without the `scripting` feature; can't be constructed.
+#[cfg(not(feature = "scripting"))]
+pub struct FilterScript {
+    _private: (),
+}
+
+#[cfg(feature = "scripting")]
+impl FilterScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut engine = rhai::Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| Error::Script(format!("{}: {e}", path.display())))?;
+
+        Ok(Self { engine, ast })
+    }
+
+    pub fn matches(&self, event: &ProtectEvent) -> Result<bool> {
+        let mut scope = rhai::Scope::new();
+        scope.push("event", event_map(event));
+
+        self.engine
+            .eval_ast_with_scope::<bool>(&mut scope, &self.ast)
+            .map_err(|e| Error::Script(e.to_string()))
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+impl FilterScript {
+    pub fn load(path: &Path) -> Result<Self> {
+        Err(Error::Script(format!(
+            "{} can't be used: built without the `scripting` feature",
+            path.display()
+        )))
+    }
+
+    pub fn matches(&self, _event: &ProtectEvent) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+/// The event fields exposed to scripts. Times are epoch milliseconds; `hour`, `minute` and
+/// `weekday` (0 = Monday) are the event's start in the host's local time.
+#[cfg(feature = "scripting")]
+fn event_map(event: &ProtectEvent) -> rhai::Map {
+    use chrono::{DateTime, Datelike, Local, Timelike};
+
+    let start = event.start_time.unwrap_or_default();
+    let end = event.end_time.unwrap_or(start);
+    let local_start = DateTime::from_timestamp_millis(start)
+        .unwrap_or_default()
+        .with_timezone(&Local);
+
+    let mut map = rhai::Map::new();
+    map.insert("id".into(), event.id.clone().into());
+    map.insert("camera_id".into(), event.camera_id.clone().into());
+    map.insert(
+        "camera_name".into(),
+        event.camera_name.clone().unwrap_or_default().into(),
+    );
+    map.insert("type".into(), event.event_type.to_string().into());
+    map.insert(
+        "smart_detect_types".into(),
+        event
+            .smart_detect_types
+            .iter()
+            .map(|t| rhai::Dynamic::from(t.to_string()))
+            .collect::<rhai::Array>()
+            .into(),
+    );
+    map.insert("start".into(), start.into());
+    map.insert("end".into(), end.into());
+    map.insert("duration_ms".into(), (end - start).into());
+    map.insert("hour".into(), (local_start.hour() as i64).into());
+    map.insert("minute".into(), (local_start.minute() as i64).into());
+    map.insert(
+        "weekday".into(),
+        (local_start.weekday().num_days_from_monday() as i64).into(),
+    );
+    map
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use std::io::Write;
+
+    use unifi_protect_client::events::EventType;
+
+    use super::*;
+
+    fn script(source: &str) -> FilterScript {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(source.as_bytes()).unwrap();
+        FilterScript::load(file.path()).unwrap()
+    }
+
+    fn event() -> ProtectEvent {
+        ProtectEvent {
+            id: "event".to_string(),
+            camera_id: "camera".to_string(),
+            camera_name: None,
+            camera_mac: None,
+            nvr_name: None,
+            start_time: Some(1_000),
+            end_time: Some(5_000),
+            event_type: EventType::Motion,
+            smart_detect_types: vec![],
+            thumbnail_id: None,
+            heatmap_id: None,
+            is_finished: true,
+            part: None,
+            collision: None,
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(
+            script("event.duration_ms > 1000")
+                .matches(&event())
+                .unwrap()
+        );
+        assert!(
+            !script("event.camera_id == \"porch\"")
+                .matches(&event())
+                .unwrap()
+        );
+
+        // stopped rather than left to spin forever
+        assert!(script("loop {} true").matches(&event()).is_err());
+        assert!(
+            script("fn f(n) { f(n + 1) } f(0)")
+                .matches(&event())
+                .is_err()
+        );
+    }
+}
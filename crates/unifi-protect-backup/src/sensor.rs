@@ -0,0 +1,119 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use unifi_protect_client::{
+    events::{EventType, SensorTrigger},
+    models::Bootstrap,
+};
+use unifi_protect_data::Event;
+
+/// Clips recorded from a camera when a UP Sense sensor triggers, backed up like any other event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct SensorClips {
+    /// How much of the footage before the sensor triggered goes in the clip
+    #[serde(default = "default_before", with = "humantime_serde")]
+    pub before: Duration,
+    /// How much of the footage after the sensor triggered goes in the clip
+    #[serde(default = "default_after", with = "humantime_serde")]
+    pub after: Duration,
+    /// What a sensor has to report to record a clip
+    #[serde(default = "default_triggers")]
+    pub triggers: Vec<SensorTrigger>,
+    /// By sensor id or name, the camera id or name to record from, in place of the camera the
+    /// sensor is paired with in Protect
+    #[serde(default)]
+    pub cameras: HashMap<String, String>,
+}
+
+fn default_before() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_after() -> Duration {
+    Duration::from_secs(25)
+}
+
+fn default_triggers() -> Vec<SensorTrigger> {
+    vec![SensorTrigger::Open, SensorTrigger::Motion]
+}
+
+impl SensorClips {
+    /// The camera `sensor_id` records from: its entry in `cameras` if it has one, otherwise the
+    /// camera it's paired with. `None` if that's neither set nor a camera on the NVR.
+    pub fn camera_for(&self, sensor_id: &str, bootstrap: &Bootstrap) -> Option<String> {
+        let sensor = bootstrap.sensors.get(sensor_id);
+        let configured = self.cameras.get(sensor_id).or_else(|| {
+            let name = &sensor?.name;
+            self.cameras.get(name)
+        });
+        let camera = match configured {
+            Some(camera) => camera,
+            None => sensor?.camera.as_ref()?,
+        };
+
+        bootstrap
+            .cameras
+            .values()
+            .find(|c| c.id == *camera || c.name == *camera)
+            .map(|c| c.id.clone())
+    }
+
+    /// The event recording a clip from `camera_id` around `sensor_id` reporting `trigger` at
+    /// `time` (epoch milliseconds). It's only ready to back up once the clip has ended.
+    pub fn clip_event(
+        &self,
+        sensor_id: &str,
+        trigger: SensorTrigger,
+        time: i64,
+        camera_id: &str,
+    ) -> Event {
+        Event {
+            id: format!("sensor-{sensor_id}-{trigger}-{time}"),
+            event_type: EventType::Sensor(trigger).to_string(),
+            camera_id: camera_id.to_string(),
+            start_time: time - self.before.as_millis() as i64,
+            end_time: Some(time + self.after.as_millis() as i64),
+            backed_up: false,
+            skip_reason: None,
+            smart_detect_types: String::new(),
+            thumbnail_id: None,
+            heatmap_id: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_for() {
+        let bootstrap: Bootstrap = serde_json::from_value(serde_json::json!({
+            "cameras": {
+                "cam1": { "id": "cam1", "name": "Front Door", "mac": "AA", "model": null,
+                          "isConnected": true },
+                "cam2": { "id": "cam2", "name": "Garage", "mac": "BB", "model": null,
+                          "isConnected": true }
+            },
+            "nvr": { "id": "nvr", "name": "NVR", "version": "5.0.0", "timezone": "UTC" },
+            "sensors": {
+                "s1": { "id": "s1", "name": "Front Door Sensor", "mac": "CC", "camera": "cam1" },
+                "s2": { "id": "s2", "name": "Garage Door", "mac": "DD", "camera": null }
+            }
+        }))
+        .unwrap();
+        let mut clips: SensorClips = toml::from_str("").unwrap();
+        assert_eq!(clips.camera_for("s1", &bootstrap).as_deref(), Some("cam1"));
+        assert_eq!(clips.camera_for("s2", &bootstrap), None);
+
+        clips
+            .cameras
+            .insert("Garage Door".to_string(), "Garage".to_string());
+        assert_eq!(clips.camera_for("s2", &bootstrap).as_deref(), Some("cam2"));
+
+        let event = clips.clip_event("s2", SensorTrigger::Open, 60_000, "cam2");
+        assert_eq!((event.start_time, event.end_time), (55_000, Some(85_000)));
+        assert_eq!(event.event_type, "sensor_open");
+    }
+}
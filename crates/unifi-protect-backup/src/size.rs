@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+/// A size in bytes, given either as a plain number of bytes or as a string with a unit such as
+/// `500GiB` or `1GB`. Binary units (`KiB`, `MiB`, ...) are powers of 1024, decimal ones (`KB`,
+/// `MB`, ...) powers of 1000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "SizeSpec", into = "u64")]
+pub struct ByteSize(pub u64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeSpec {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TryFrom<SizeSpec> for ByteSize {
+    type Error = Error;
+
+    fn try_from(spec: SizeSpec) -> Result<Self> {
+        match spec {
+            SizeSpec::Bytes(bytes) => Ok(Self(bytes)),
+            SizeSpec::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl std::str::FromStr for ByteSize {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let invalid = || Error::General(format!("Invalid size {spec:?}: expected e.g. 500GiB"));
+
+        let spec = spec.trim();
+        let split = spec
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(spec.len());
+        let (number, unit) = spec.split_at(split);
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000u64.pow(2),
+            "g" | "gb" => 1000u64.pow(3),
+            "t" | "tb" => 1000u64.pow(4),
+            "ki" | "kib" => 1 << 10,
+            "mi" | "mib" => 1 << 20,
+            "gi" | "gib" => 1 << 30,
+            "ti" | "tib" => 1 << 40,
+            _ => return Err(invalid()),
+        };
+
+        Ok(Self((number * multiplier as f64) as u64))
+    }
+}
+
+impl std::fmt::Display for ByteSize {
+    /// In the largest binary unit the size is at least one of, e.g. `1.5 GiB`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+        let mut size = self.0 as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            write!(f, "{} B", self.0)
+        } else {
+            write!(f, "{size:.1} {}", UNITS[unit])
+        }
+    }
+}
+
+impl From<ByteSize> for u64 {
+    fn from(size: ByteSize) -> Self {
+        size.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_size() {
+        assert_eq!("500GiB".parse::<ByteSize>().unwrap(), ByteSize(500 << 30));
+        assert_eq!("1GB".parse::<ByteSize>().unwrap(), ByteSize(1_000_000_000));
+        assert_eq!("1.5 KiB".parse::<ByteSize>().unwrap(), ByteSize(1536));
+        assert_eq!("4096".parse::<ByteSize>().unwrap(), ByteSize(4096));
+        assert!("500 parsecs".parse::<ByteSize>().is_err());
+        assert!("GiB".parse::<ByteSize>().is_err());
+        assert_eq!(ByteSize(512).to_string(), "512 B");
+        assert_eq!(ByteSize(1536).to_string(), "1.5 KiB");
+
+        #[derive(Deserialize)]
+        struct Config {
+            max_size: ByteSize,
+        }
+        let config: Config = toml::from_str("max_size = 1024").unwrap();
+        assert_eq!(config.max_size, ByteSize(1024));
+        let config: Config = toml::from_str("max_size = \"2MiB\"").unwrap();
+        assert_eq!(config.max_size, ByteSize(2 << 20));
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    backup::breaker::CircuitBreakers,
+    clock::{Clock, system_clock},
+    download::Downloads,
+    health::HealthChecks,
+};
+
+#[derive(Default, Serialize)]
+pub struct Status {
+    pub db_poller: TaskStateMachine,
+    pub archiver: TaskStateMachine,
+    pub pruner: TaskStateMachine,
+    pub bootstrap_refresher: TaskStateMachine,
+    pub database_maintenance: TaskStateMachine,
+    pub reconciler: TaskStateMachine,
+    pub verifier: TaskStateMachine,
+    pub integrity_sampler: TaskStateMachine,
+    pub health_checker: TaskStateMachine,
+    pub indexer: TaskStateMachine,
+    pub reporter: TaskStateMachine,
+    pub camera_monitor: TaskStateMachine,
+    /// By backup target, those which have failed since their last successful upload
+    pub circuit_breakers: CircuitBreakers,
+    /// By backup and archive target, the outcome of its latest health check
+    pub target_health: HealthChecks,
+    /// Exports downloading from the NVR, with how far each has got
+    pub downloads: Arc<Downloads>,
+}
+
+impl Status {
+    /// Status whose transition, next-run and retry times come from `clock`.
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            db_poller: TaskStateMachine::new(clock.clone()),
+            archiver: TaskStateMachine::new(clock.clone()),
+            pruner: TaskStateMachine::new(clock.clone()),
+            bootstrap_refresher: TaskStateMachine::new(clock.clone()),
+            database_maintenance: TaskStateMachine::new(clock.clone()),
+            reconciler: TaskStateMachine::new(clock.clone()),
+            verifier: TaskStateMachine::new(clock.clone()),
+            integrity_sampler: TaskStateMachine::new(clock.clone()),
+            health_checker: TaskStateMachine::new(clock.clone()),
+            indexer: TaskStateMachine::new(clock.clone()),
+            reporter: TaskStateMachine::new(clock.clone()),
+            camera_monitor: TaskStateMachine::new(clock.clone()),
+            circuit_breakers: CircuitBreakers::new(clock.clone()),
+            target_health: HealthChecks::new(clock.clone()),
+            downloads: Arc::new(Downloads::new(clock)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TaskState {
+    /// The task has not started its first iteration yet
+    Idle,
+    /// The task is sleeping until its next scheduled iteration
+    Waiting { next_run: DateTime<Utc> },
+    /// The task is working through `total` items, `completed` of which are done
+    Running { completed: usize, total: usize },
+    /// The last iteration failed; the task will try again at `retry_at`
+    Backoff {
+        error: String,
+        retry_at: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    #[serde(flatten)]
+    pub state: TaskState,
+    pub last_transition: DateTime<Utc>,
+}
+
+pub struct TaskStateMachine {
+    inner: RwLock<TaskStatus>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for TaskStateMachine {
+    fn default() -> Self {
+        Self::new(system_clock())
+    }
+}
+
+impl TaskStateMachine {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: RwLock::new(TaskStatus {
+                state: TaskState::Idle,
+                last_transition: clock.now(),
+            }),
+            clock,
+        }
+    }
+
+    pub fn snapshot(&self) -> TaskStatus {
+        self.inner.read().expect("status lock poisoned").clone()
+    }
+
+    pub fn transition(&self, state: TaskState) {
+        let mut inner = self.inner.write().expect("status lock poisoned");
+        if inner.state != state {
+            *inner = TaskStatus {
+                state,
+                last_transition: self.clock.now(),
+            };
+        }
+    }
+
+    pub fn running(&self, total: usize) {
+        self.transition(TaskState::Running {
+            completed: 0,
+            total,
+        });
+    }
+
+    /// Record progress without counting it as a state transition.
+    pub fn progress(&self, completed: usize) {
+        let mut inner = self.inner.write().expect("status lock poisoned");
+        if let TaskState::Running { total, .. } = inner.state {
+            inner.state = TaskState::Running { completed, total };
+        }
+    }
+
+    pub fn waiting(&self, period: Duration) {
+        self.transition(TaskState::Waiting {
+            next_run: self.clock.after(period),
+        });
+    }
+
+    pub fn backoff(&self, error: impl ToString, period: Duration) {
+        self.transition(TaskState::Backoff {
+            error: error.to_string(),
+            retry_at: self.clock.after(period),
+        });
+    }
+}
+
+impl Serialize for TaskStateMachine {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_backoff_retry_at() {
+        let start = Utc.with_ymd_and_hms(2025, 8, 4, 12, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let status = TaskStateMachine::new(clock.clone());
+
+        clock.advance(Duration::from_secs(60));
+        status.backoff("export failed", Duration::from_secs(300));
+
+        let snapshot = status.snapshot();
+        assert_eq!(
+            snapshot.last_transition,
+            Utc.with_ymd_and_hms(2025, 8, 4, 12, 1, 0).unwrap()
+        );
+        assert_eq!(
+            snapshot.state,
+            TaskState::Backoff {
+                error: "export failed".to_string(),
+                retry_at: Utc.with_ymd_and_hms(2025, 8, 4, 12, 6, 0).unwrap(),
+            }
+        );
+    }
+}
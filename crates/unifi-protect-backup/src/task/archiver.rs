@@ -1,32 +1,594 @@
-use std::sync::Arc;
+use std::{fmt::Display, sync::Arc, time::Duration};
 
-use tokio::time::interval;
+use chrono::{DateTime, Utc};
+
+use tokio::time::{Instant, Interval, interval, interval_at};
 use tracing::{info, warn};
+use unifi_protect_data::Failure;
 
-use crate::{Result, context::Context};
+use crate::{Error, Result, archive::SuccessPolicy, backup::Backup, context::Context};
 
 pub struct Archiver {
     context: Arc<Context>,
     config: crate::archive::Config,
+    /// When the last run that archived to every target started
+    last_archived: Option<DateTime<Utc>>,
+    /// The latest backup as of then, so a run can be skipped when nothing newer has landed
+    high_water_mark: Option<DateTime<Utc>>,
 }
 
 impl Archiver {
     pub fn new(context: Arc<Context>, config: crate::archive::Config) -> Self {
-        Self { context, config }
+        Self {
+            context,
+            config,
+            last_archived: None,
+            high_water_mark: None,
+        }
     }
 
     pub async fn run(&mut self) -> Result<()> {
         info!("Starting Archiver");
 
         let mut interval = interval(self.config.archive_interval);
+        let mut check_interval = self.config.check_interval.map(delayed_interval);
+        let mut compact_interval = self.config.compact_interval.map(delayed_interval);
+        let mut last_verified = self.context.clock.now();
 
         loop {
+            tokio::select! {
+                _ = interval.tick() => self.archive().await,
+                _ = tick(&mut check_interval) => {
+                    // reading back every chunk is slow, so only some checks do it
+                    let now = self.context.clock.now();
+                    let since = (now - last_verified).to_std().unwrap_or_default();
+                    let verify_data = self
+                        .config
+                        .verify_data_interval
+                        .is_some_and(|period| since >= period);
+                    if verify_data {
+                        last_verified = now;
+                    }
+                    self.maintain(Maintenance::Check { verify_data }).await;
+                }
+                _ = tick(&mut compact_interval) => self.maintain(Maintenance::Compact).await,
+            }
+        }
+    }
+
+    async fn archive(&mut self) {
+        if self.config.dry_run {
+            for archiver in &self.context.archive_targets {
+                info!(target = archiver.name(), "Dry run: would create archive");
+            }
+            if self.config.backup_database {
+                info!("Dry run: would upload a database snapshot to every backup target");
+            }
+            return;
+        }
+
+        let started = self.context.clock.now();
+        let latest_backup = self
+            .context
+            .database
+            .latest_backup_time()
+            .await
+            .inspect_err(|err| warn!(err = ?err, "Failed to find the latest backup"))
+            .ok();
+        // when the latest backup can't be found, archive to be safe
+        if latest_backup.is_some_and(|latest| !self.archive_due(latest, started)) {
+            info!("No new backups since the last archive, skipping");
+            self.context
+                .status
+                .archiver
+                .waiting(self.config.archive_interval);
+            return;
+        }
+
+        let status = &self.context.status.archiver;
+        let archive_targets = self.context.archive_targets.as_slice();
+        status.running(archive_targets.len() + self.config.backup_database as usize);
+
+        let mut last_error = None;
+        let mut failed = vec![];
+        for (completed, archiver) in archive_targets.iter().enumerate() {
+            if let Err(err) = archiver.archive().await {
+                warn!(err = ?err, "Failed to create archive");
+                self.context
+                    .database
+                    .insert_failure(&Failure {
+                        subject: "archive".to_string(),
+                        target: archiver.name(),
+                        error: err.to_string(),
+                        output: err.output().unwrap_or_default().to_string(),
+                        failure_time: self.context.clock.now(),
+                    })
+                    .await
+                    .inspect_err(|err| warn!(err = ?err, "Failed to record archive failure"))
+                    .ok();
+                failed.push(format!("{}: {err}", archiver.name()));
+            }
+            status.progress(completed + 1);
+        }
+
+        let succeeded = match self.config.success_policy {
+            SuccessPolicy::All => failed.is_empty(),
+            SuccessPolicy::Any => failed.len() < archive_targets.len() || failed.is_empty(),
+        };
+        if succeeded {
+            if !failed.is_empty() {
+                warn!(
+                    failed = failed.len(),
+                    "Some archive targets failed, but the run succeeded under success-policy = any"
+                );
+            }
+            self.last_archived = Some(started);
+            self.high_water_mark = latest_backup.flatten();
+        } else {
+            // every target gets another go next time, whether or not there's new data
+            last_error = failed.last().cloned();
+            self.context
+                .notify(
+                    "Archive failed",
+                    &format!(
+                        "Failed to archive to {} of {} target(s):\n{}",
+                        failed.len(),
+                        archive_targets.len(),
+                        failed.join("\n")
+                    ),
+                )
+                .await;
+        }
+
+        if self.config.backup_database {
+            if let Err(err) = self.backup_database().await {
+                warn!(err = ?err, "Failed to back up database");
+                last_error = Some(err.to_string());
+            }
+            status.progress(archive_targets.len() + 1);
+        }
+
+        match last_error {
+            Some(err) => status.backoff(err, self.config.archive_interval),
+            None => status.waiting(self.config.archive_interval),
+        }
+    }
+
+    /// Whether to archive, given the latest backup now: always on the first run, and after that
+    /// when something was backed up since the last, or `max-archive-interval` has passed
+    fn archive_due(&self, latest_backup: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        let Some(last_archived) = self.last_archived else {
+            return true;
+        };
+        let overdue = self.config.max_archive_interval.is_some_and(|max| {
+            (now - last_archived)
+                .to_std()
+                .is_ok_and(|since| since >= max)
+        });
+        overdue || latest_backup > self.high_water_mark
+    }
+
+    /// Check or compact every archive target, alerting on those that fail
+    #[tracing::instrument(skip(self))]
+    async fn maintain(&self, maintenance: Maintenance) {
+        if self.config.dry_run {
+            for target in &self.context.archive_targets {
+                info!(
+                    target = target.name(),
+                    "Dry run: would {maintenance} archive"
+                );
+            }
+            return;
+        }
+
+        let mut failed = vec![];
+        for target in &self.context.archive_targets {
+            let result = match maintenance {
+                Maintenance::Check { verify_data } => target.check(verify_data).await,
+                Maintenance::Compact => target.compact().await,
+            };
+            if let Err(err) = result {
+                warn!(err = ?err, target = target.name(), "Failed to {maintenance} archive");
+                self.context
+                    .database
+                    .insert_failure(&Failure {
+                        subject: maintenance.to_string(),
+                        target: target.name(),
+                        error: err.to_string(),
+                        output: err.output().unwrap_or_default().to_string(),
+                        failure_time: self.context.clock.now(),
+                    })
+                    .await
+                    .inspect_err(|err| warn!(err = ?err, "Failed to record {maintenance} failure"))
+                    .ok();
+                failed.push(format!("{}: {err}", target.name()));
+            }
+        }
+
+        if !failed.is_empty() {
+            self.context
+                .notify(
+                    &format!("Archive {maintenance} failed"),
+                    &format!(
+                        "Failed to {maintenance} {} archive target(s):\n{}",
+                        failed.len(),
+                        failed.join("\n")
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Upload a snapshot of the database to every backup target, so losing the local disk
+    /// doesn't lose the record of what was backed up where.
+    #[tracing::instrument(skip(self))]
+    async fn backup_database(&self) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let snapshot = dir.path().join("events.db");
+        self.context.database.snapshot(&snapshot).await?;
+        let data = tokio::fs::read(&snapshot).await?;
+
+        let filename = format!(
+            "database/events-{}.db",
+            self.context.clock.now().format("%Y%m%d-%H%M%S")
+        );
+        let mut failed = 0;
+        for target in self.context.backup_targets.load_full().iter() {
+            if let Err(err) = target.upload(&filename, &data).await {
+                warn!(err = ?err, target = target.name(), "Failed to upload database snapshot");
+                self.context
+                    .database
+                    .insert_failure(&Failure {
+                        subject: "database".to_string(),
+                        target: target.name(),
+                        error: err.to_string(),
+                        output: err.output().unwrap_or_default().to_string(),
+                        failure_time: self.context.clock.now(),
+                    })
+                    .await?;
+                failed += 1;
+            }
+        }
+
+        if failed > 0 {
+            return Err(Error::Backup(format!(
+                "Database snapshot failed to upload to {failed} target(s)"
+            )));
+        }
+
+        info!(filename, size_bytes = data.len(), "Backed up database");
+        for target in self.context.backup_targets.load_full().iter() {
+            if let Err(err) = self.prune_database_snapshots(target.as_ref()).await {
+                warn!(err = ?err, target = target.name(), "Failed to prune database snapshots");
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete all but the newest `database-snapshots` snapshots on `target`. Their names sort by
+    /// when they were taken.
+    async fn prune_database_snapshots(&self, target: &dyn Backup) -> Result<()> {
+        let mut snapshots: Vec<String> = target
+            .list()
+            .await?
+            .into_iter()
+            .map(|file| file.path)
+            .filter(|path| path.starts_with("database/events-") && path.ends_with(".db"))
+            .collect();
+        snapshots.sort_unstable_by(|a, b| b.cmp(a));
+        let expired = snapshots.split_off(self.config.database_snapshots.min(snapshots.len()));
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        target.delete_many(&expired).await?;
+        info!(
+            target = target.name(),
+            deleted = expired.len(),
+            "Pruned database snapshots"
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Maintenance {
+    Check { verify_data: bool },
+    Compact,
+}
+
+impl Display for Maintenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Check { .. } => write!(f, "check"),
+            Self::Compact => write!(f, "compact"),
+        }
+    }
+}
+
+/// An interval whose first tick is a `period` from now, rather than straight away, so
+/// maintenance doesn't hold up the first archive after a restart
+fn delayed_interval(period: Duration) -> Interval {
+    interval_at(Instant::now() + period, period)
+}
+
+/// The next tick of `interval`, or never if there isn't one
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
             interval.tick().await;
-            for archiver in self.context.archive_targets.as_slice() {
-                let _ = archiver.archive().await.inspect_err(|err| {
-                    warn!(err = ?err, "Failed to create archive");
-                });
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Mutex};
+
+    use async_trait::async_trait;
+    use chrono::TimeZone;
+    use unifi_protect_client::mock::MockProtectClient;
+    use unifi_protect_data::Backup as BackupRecord;
+
+    use super::*;
+    use crate::{
+        archive::{Archive, RestoreFilter},
+        clock::{Clock, ManualClock},
+        retention::RetentionPolicy,
+        task::Prune,
+        testing::{self, TestContext},
+    };
+
+    /// An archive target which records what it was asked to do, failing all of it with `fail`
+    struct FakeArchive {
+        name: &'static str,
+        fail: bool,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FakeArchive {
+        fn call(&self, call: String) -> Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}: {call}", self.name));
+            if self.fail {
+                return Err(Error::Backup(format!("{call} failed")));
             }
+            Ok(())
         }
     }
+
+    #[async_trait]
+    impl Archive for FakeArchive {
+        fn name(&self) -> String {
+            format!("fake:{}", self.name)
+        }
+
+        async fn archive(&self) -> Result<String> {
+            self.call("archive".to_string())
+                .map(|()| "archive".to_string())
+        }
+
+        async fn check(&self, verify_data: bool) -> Result<()> {
+            self.call(format!("check verify_data={verify_data}"))
+        }
+
+        async fn compact(&self) -> Result<()> {
+            self.call("compact".to_string())
+        }
+
+        async fn restore(&self, _: &str, _: &Path, _: &RestoreFilter) -> Result<usize> {
+            Ok(0)
+        }
+    }
+
+    #[async_trait]
+    impl Prune for FakeArchive {
+        async fn prune(&self, _: &RetentionPolicy, _: &dyn Clock) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// An archiver whose archive targets are fakes, one per `(name, fail)`, sharing the returned
+    /// call log, and whose clock only moves when the returned one is told to
+    async fn fake_archiver(
+        dir: &tempfile::TempDir,
+        targets: &[(&'static str, bool)],
+        config: impl FnOnce(&mut crate::archive::Config),
+    ) -> (Archiver, Arc<Mutex<Vec<String>>>, Arc<ManualClock>) {
+        std::fs::create_dir_all(dir.path().join("backups")).unwrap();
+        let protect = Arc::new(MockProtectClient::new(testing::bootstrap()));
+        let mut context = Context::with_client(testing::config(dir, ""), protect)
+            .await
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2025, 8, 4, 12, 0, 0).unwrap(),
+        ));
+        context.clock = clock.clone();
+        let calls = Arc::new(Mutex::new(vec![]));
+        context.archive_targets = targets
+            .iter()
+            .map(|&(name, fail)| {
+                Arc::new(FakeArchive {
+                    name,
+                    fail,
+                    calls: calls.clone(),
+                }) as Arc<dyn Archive>
+            })
+            .collect();
+        let mut archive = testing::config(dir, "").archive;
+        config(&mut archive);
+        (Archiver::new(Arc::new(context), archive), calls, clock)
+    }
+
+    #[tokio::test]
+    async fn test_maintain() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archiver, calls, _) =
+            fake_archiver(&dir, &[("good", false), ("bad", true)], |_| {}).await;
+
+        archiver
+            .maintain(Maintenance::Check { verify_data: true })
+            .await;
+        archiver.maintain(Maintenance::Compact).await;
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "good: check verify_data=true",
+                "bad: check verify_data=true",
+                "good: compact",
+                "bad: compact",
+            ]
+        );
+
+        // only the failing target's failures are recorded, under what it was doing
+        let database = &archiver.context.database;
+        for subject in ["check", "compact"] {
+            assert!(
+                database
+                    .get_failures(subject, "fake:good")
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+            let failures = database.get_failures(subject, "fake:bad").await.unwrap();
+            assert_eq!(failures.len(), 1);
+            assert_eq!(
+                failures[0].error,
+                format!("Backup process failed: {subject} failed")
+            );
+        }
+
+        // a dry run leaves the targets alone
+        let dir = tempfile::tempdir().unwrap();
+        let (archiver, calls, _) =
+            fake_archiver(&dir, &[("good", false)], |config| config.dry_run = true).await;
+        archiver.maintain(Maintenance::Compact).await;
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    /// Record a backup of part `part` of an event made now
+    async fn back_up(archiver: &Archiver, part: u32) {
+        let context = &archiver.context;
+        let now = context.clock.now();
+        if part == 0 {
+            let start = now.timestamp_millis();
+            context
+                .database
+                .insert_event(&testing::event("event", start, start + 10_000))
+                .await
+                .unwrap();
+        }
+        context
+            .database
+            .insert_backup(&BackupRecord {
+                event_id: "event".to_string(),
+                target: context.backup_targets.load()[0].name(),
+                part,
+                remote_path: format!("event_part{part}.mp4"),
+                backup_time: now,
+                size_bytes: 5,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_archive_only_new_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut archiver, calls, clock) = fake_archiver(&dir, &[("good", false)], |config| {
+            config.max_archive_interval = Some(Duration::from_secs(7 * 24 * 60 * 60));
+        })
+        .await;
+        let archived = || calls.lock().unwrap().len();
+        back_up(&archiver, 0).await;
+
+        // the first run always archives, and the next only once there's something new
+        archiver.archive().await;
+        assert_eq!(archived(), 1);
+        clock.advance(Duration::from_secs(60 * 60));
+        archiver.archive().await;
+        assert_eq!(archived(), 1);
+        back_up(&archiver, 1).await;
+        archiver.archive().await;
+        assert_eq!(archived(), 2);
+
+        // until max-archive-interval has passed without any
+        clock.advance(Duration::from_secs(6 * 24 * 60 * 60));
+        archiver.archive().await;
+        assert_eq!(archived(), 2);
+        clock.advance(Duration::from_secs(24 * 60 * 60));
+        archiver.archive().await;
+        assert_eq!(archived(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_success_policy() {
+        for (policy, retried) in [(SuccessPolicy::All, true), (SuccessPolicy::Any, false)] {
+            let dir = tempfile::tempdir().unwrap();
+            let targets = [("onsite", false), ("offsite", true)];
+            let (mut archiver, calls, _) =
+                fake_archiver(&dir, &targets, |config| config.success_policy = policy).await;
+            back_up(&archiver, 0).await;
+
+            // every target is tried and the failing one recorded, whatever the policy
+            archiver.archive().await;
+            assert_eq!(
+                *calls.lock().unwrap(),
+                ["onsite: archive", "offsite: archive"]
+            );
+            let failures = archiver
+                .context
+                .database
+                .get_failures("archive", "fake:offsite")
+                .await
+                .unwrap();
+            assert_eq!(failures.len(), 1);
+
+            // a failed run is retried even without new backups, and a successful one isn't
+            calls.lock().unwrap().clear();
+            archiver.archive().await;
+            assert_eq!(calls.lock().unwrap().len(), if retried { 2 } else { 0 });
+        }
+
+        // with every target failing, no policy counts the run as a success
+        let dir = tempfile::tempdir().unwrap();
+        let (mut archiver, calls, _) = fake_archiver(&dir, &[("offsite", true)], |config| {
+            config.success_policy = SuccessPolicy::Any
+        })
+        .await;
+        back_up(&archiver, 0).await;
+        archiver.archive().await;
+        archiver.archive().await;
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_backup_database() {
+        let test = TestContext::new("").await;
+        let snapshots = test.backup_dir().join("database");
+        std::fs::create_dir_all(&snapshots).unwrap();
+        for taken in ["20240101-000000", "20240102-000000", "20240103-000000"] {
+            std::fs::write(snapshots.join(format!("events-{taken}.db")), b"old").unwrap();
+        }
+        std::fs::write(snapshots.join("notes.txt"), b"not a snapshot").unwrap();
+        let mut config = testing::config(&test.dir, "").archive;
+        config.backup_database = true;
+        config.database_snapshots = 2;
+        let archiver = Archiver::new(test.context.clone(), config);
+
+        archiver.backup_database().await.unwrap();
+        let mut kept: Vec<_> = std::fs::read_dir(&snapshots)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        kept.sort();
+        assert_eq!(kept.len(), 3);
+        assert_eq!(kept[0], "events-20240103-000000.db");
+        assert!(kept[1].starts_with("events-") && kept[1] != kept[0]);
+        assert_eq!(kept[2], "notes.txt");
+    }
 }
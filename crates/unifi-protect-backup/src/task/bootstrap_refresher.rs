@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use tokio::time::interval;
+use tracing::{info, warn};
+use unifi_protect_client::config::UnifiConfig;
+
+use crate::{Result, context::Context};
+
+pub struct BootstrapRefresher {
+    context: Arc<Context>,
+    config: UnifiConfig,
+}
+
+impl BootstrapRefresher {
+    pub fn new(context: Arc<Context>, config: UnifiConfig) -> Self {
+        Self { context, config }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        info!("Starting Bootstrap Refresher");
+
+        let mut interval = interval(self.config.bootstrap_refresh_interval);
+        // the context was created with a fresh bootstrap
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let status = &self.context.status.bootstrap_refresher;
+            status.running(1);
+
+            match self.context.refresh_bootstrap().await {
+                Ok(()) => status.waiting(self.config.bootstrap_refresh_interval),
+                Err(err) => {
+                    warn!(err = ?err, "Failed to refresh bootstrap");
+                    status.backoff(err, self.config.bootstrap_refresh_interval);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use humantime_serde::re::humantime;
+use tokio::time::interval;
+use tracing::{info, warn};
+use unifi_protect_client::models::Camera;
+
+use crate::{Result, context::Context};
+
+/// How often the cameras' connection state is looked at. It's kept current by the event listener
+/// and bootstrap refresher, so looking is cheap.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A camera seen disconnected from the NVR
+struct Offline {
+    since: DateTime<Utc>,
+    alerted: bool,
+}
+
+/// Alerts when a camera has been disconnected from the NVR for longer than
+/// `camera-offline-after`, and again when it reconnects. Footage a camera never recorded can't be
+/// backed up.
+pub struct CameraMonitor {
+    context: Arc<Context>,
+    config: crate::backup::Config,
+    offline: HashMap<String, Offline>,
+}
+
+impl CameraMonitor {
+    pub fn new(context: Arc<Context>, config: crate::backup::Config) -> Self {
+        Self {
+            context,
+            config,
+            offline: HashMap::new(),
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let Some(offline_after) = self.config.camera_offline_after else {
+            return std::future::pending().await;
+        };
+
+        info!("Starting Camera Monitor");
+
+        let mut interval = interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            // cameras enabled in the latest config, if it was reloaded
+            self.config = self.context.backup_config.load().as_ref().clone();
+            self.check(offline_after).await;
+            self.context.status.camera_monitor.waiting(CHECK_INTERVAL);
+        }
+    }
+
+    async fn check(&mut self, offline_after: Duration) {
+        let now = self.context.clock.now();
+        let cameras: Vec<Camera> = self
+            .context
+            .protect_bootstrap
+            .load()
+            .cameras
+            .values()
+            .filter(|camera| self.config.camera_enabled(&camera.id, Some(&camera.name)))
+            .cloned()
+            .collect();
+
+        // cameras removed from the NVR or no longer backed up aren't watched
+        self.offline
+            .retain(|camera_id, _| cameras.iter().any(|camera| camera.id == *camera_id));
+
+        for camera in cameras {
+            if camera.is_connected {
+                if let Some(offline) = self.offline.remove(&camera.id) {
+                    self.reconnected(&camera, offline, now).await;
+                }
+                continue;
+            }
+
+            let offline = self.offline.entry(camera.id.clone()).or_insert_with(|| {
+                // the NVR knows when it disconnected, unless it never connected since starting
+                let since = camera
+                    .last_disconnect
+                    .and_then(DateTime::from_timestamp_millis)
+                    .filter(|since| *since <= now)
+                    .unwrap_or(now);
+                Offline {
+                    since,
+                    alerted: false,
+                }
+            });
+            let down = (now - offline.since).to_std().unwrap_or_default();
+            if offline.alerted || down < offline_after {
+                continue;
+            }
+
+            offline.alerted = true;
+            let since = offline.since;
+            warn!(
+                camera_id = camera.id,
+                camera_name = camera.name,
+                ?down,
+                "Camera offline"
+            );
+            self.context
+                .notify(
+                    &format!("Camera {} offline", camera.name),
+                    &format!(
+                        "Camera {} ({}) has been disconnected from the NVR since {}. Nothing it \
+                         would have recorded since then can be backed up.",
+                        camera.name,
+                        camera.id,
+                        since.to_rfc3339()
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Alert that a camera is back, if it was alerted on going offline
+    async fn reconnected(&self, camera: &Camera, offline: Offline, now: DateTime<Utc>) {
+        if !offline.alerted {
+            return;
+        }
+
+        let down = (now - offline.since).to_std().unwrap_or_default();
+        let down = humantime::format_duration(Duration::from_secs(down.as_secs()));
+        info!(camera_id = camera.id, camera_name = camera.name, %down, "Camera back online");
+        self.context
+            .notify(
+                &format!("Camera {} online", camera.name),
+                &format!(
+                    "Camera {} ({}) reconnected to the NVR after being offline for about {down}.",
+                    camera.name, camera.id
+                ),
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use unifi_protect_client::mock::MockProtectClient;
+
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        testing::{self, CAMERA_ID},
+    };
+
+    #[tokio::test]
+    async fn test_check() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("backups")).unwrap();
+        let protect = Arc::new(MockProtectClient::new(testing::bootstrap()));
+        let mut context = Context::with_client(testing::config(&dir, ""), protect)
+            .await
+            .unwrap();
+        let now = Utc.with_ymd_and_hms(2025, 8, 4, 12, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(now));
+        context.clock = clock.clone();
+        let context = Arc::new(context);
+        let config = context.backup_config.load().as_ref().clone();
+        let mut monitor = CameraMonitor::new(context.clone(), config);
+        let offline_after = Duration::from_secs(30 * 60);
+        let set_connected = |connected: bool, last_disconnect: Option<DateTime<Utc>>| {
+            let mut bootstrap = testing::bootstrap();
+            let camera = bootstrap.cameras.get_mut(CAMERA_ID).unwrap();
+            camera.is_connected = connected;
+            camera.last_disconnect = last_disconnect.map(|time| time.timestamp_millis());
+            context.protect_bootstrap.store(Arc::new(bootstrap));
+        };
+
+        // offline since the NVR says it disconnected, and alerted on once it's been long enough
+        set_connected(false, Some(now - chrono::Duration::minutes(10)));
+        monitor.check(offline_after).await;
+        let offline = &monitor.offline[CAMERA_ID];
+        assert_eq!(offline.since, now - chrono::Duration::minutes(10));
+        assert!(!offline.alerted);
+        clock.advance(Duration::from_secs(20 * 60));
+        monitor.check(offline_after).await;
+        assert!(monitor.offline[CAMERA_ID].alerted);
+
+        set_connected(true, None);
+        monitor.check(offline_after).await;
+        assert!(monitor.offline.is_empty());
+
+        // without a disconnect time, or one in the future, it's offline from when it was seen
+        set_connected(false, Some(clock.now() + chrono::Duration::minutes(5)));
+        monitor.check(offline_after).await;
+        assert_eq!(monitor.offline[CAMERA_ID].since, clock.now());
+    }
+}
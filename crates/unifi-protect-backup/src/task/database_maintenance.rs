@@ -0,0 +1,73 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+use crate::{Error, Result, context::Context};
+
+/// Periodically checks the SQLite database for corruption and reclaims free space.
+pub struct DatabaseMaintenance {
+    context: Arc<Context>,
+    maintenance_interval: Option<Duration>,
+}
+
+impl DatabaseMaintenance {
+    pub fn new(context: Arc<Context>, maintenance_interval: Option<Duration>) -> Self {
+        Self {
+            context,
+            maintenance_interval,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let Some(maintenance_interval) = self.maintenance_interval else {
+            return std::future::pending().await;
+        };
+
+        info!("Starting Database Maintenance");
+
+        let mut interval = interval(maintenance_interval);
+        // don't compete with the backlog the other tasks work through at startup
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let status = &self.context.status.database_maintenance;
+            status.running(2);
+
+            match self.maintain().await {
+                Ok(()) => status.waiting(maintenance_interval),
+                Err(err) => {
+                    warn!(err = ?err, "Database maintenance failed");
+                    self.context
+                        .notify("Database maintenance failed", &err.to_string())
+                        .await;
+                    status.backoff(err, maintenance_interval);
+                }
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn maintain(&self) -> Result<()> {
+        let database = &self.context.database;
+
+        let problems = database.integrity_check().await?;
+        if !problems.is_empty() {
+            error!(?problems, "Database integrity check failed");
+            return Err(Error::General(format!(
+                "Integrity check found {} problem(s): {}",
+                problems.len(),
+                problems.join("; ")
+            )));
+        }
+        self.context.status.database_maintenance.progress(1);
+
+        database.optimize().await?;
+        self.context.status.database_maintenance.progress(2);
+
+        info!("Database maintenance complete");
+        Ok(())
+    }
+}
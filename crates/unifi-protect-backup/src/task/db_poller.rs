@@ -1,63 +1,1021 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
-use futures_util::future::join_all;
+use bytes::Bytes;
+use chrono::{DateTime, Local, Utc};
+use futures_util::{future::join_all, stream};
+use humantime_serde::re::humantime;
+use serde::Serialize;
 use tokio::time::interval;
 use tracing::{debug, error, info, warn};
+use unifi_protect_client::{
+    VideoStream, error::Error as ClientError, events::ProtectEvent, models::ExportQuality, progress,
+};
+use unifi_protect_data::{Backup, Failure, InFlightUpload};
 
-use crate::{Error, Result, context::Context, convert::protect_event_from_database_event};
+use crate::{
+    Error, Result,
+    backup::{
+        Reservation,
+        breaker::Admission,
+        collision, collision_at,
+        pipeline::stream_to_targets,
+        sidecar::{self, Sidecar},
+        snapshot::{self, SnapshotMode, snapshot_path},
+    },
+    context::Context,
+    convert::protect_event_from_database_event,
+    download,
+    validate::{ExportVerifier, validate_export},
+};
 
 const BATCH_SIZE: usize = 10;
+/// How much of an upload passes between records of its progress
+const PROGRESS_INTERVAL_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Default, Serialize)]
+pub struct BacklogMetrics {
+    /// Finished events not backed up yet
+    pub events: AtomicU64,
+    /// How long since the oldest of them started
+    pub oldest_age_seconds: AtomicU64,
+}
 
 pub struct BackupDbPoller {
     context: Arc<Context>,
     config: crate::backup::Config,
+    // events whose export wasn't ready yet, and when to try them again
+    deferred: HashMap<String, DateTime<Utc>>,
+    // consecutive failed exports per camera
+    export_failures: HashMap<String, u32>,
+    // cameras whose exports keep failing, and when to next try one of their events
+    degraded: HashMap<String, DateTime<Utc>>,
+    // uploads the last run didn't finish, by event id, until their events are tried again
+    interrupted: HashMap<String, Vec<InFlightUpload>>,
+    // events already reported by a dry run, which stay pending
+    reported: HashSet<String>,
+    // events warned about being older than the NVR keeps footage for, until they're skipped
+    aged_out_warned: HashSet<String>,
+    // failed attempts per event at backing it up, until it's backed up or given up on
+    attempts: HashMap<String, u32>,
+    // exports per event the NVR reported as not ready, until it's backed up or given up on
+    export_retries: HashMap<String, u32>,
+    // whether each of the last `failure-rate-window` uploads per target failed
+    upload_outcomes: HashMap<String, VecDeque<bool>>,
+    // targets alerted on for their failure rate, until it drops back below the threshold
+    failing_targets: HashSet<String>,
+    // whether the backlog was alerted on, until it's no longer at risk
+    backlog_alerted: bool,
+    // uploads the database failed to record, until recording them succeeds
+    unrecorded: Unrecorded,
+}
+
+/// A batch's outcome which is yet to be recorded in the database
+#[derive(Default)]
+struct Unrecorded {
+    backups: Vec<Backup>,
+    backed_up: Vec<String>,
+    // the paths uploaded to stay reserved until they're recorded
+    reserved: Vec<Reservation>,
 }
 
 impl BackupDbPoller {
     pub fn new(context: Arc<Context>, config: crate::backup::Config) -> Self {
-        Self { context, config }
+        Self {
+            context,
+            config,
+            deferred: HashMap::new(),
+            export_failures: HashMap::new(),
+            degraded: HashMap::new(),
+            interrupted: HashMap::new(),
+            reported: HashSet::new(),
+            aged_out_warned: HashSet::new(),
+            attempts: HashMap::new(),
+            export_retries: HashMap::new(),
+            upload_outcomes: HashMap::new(),
+            failing_targets: HashSet::new(),
+            backlog_alerted: false,
+            unrecorded: Unrecorded::default(),
+        }
     }
 
     pub async fn run(&mut self) -> Result<()> {
         info!("Starting DB Poller");
 
+        match self.context.database.take_in_flight_uploads().await {
+            Ok(uploads) => {
+                if !uploads.is_empty() {
+                    info!(
+                        uploads = uploads.len(),
+                        "Found uploads interrupted by the last run"
+                    );
+                }
+                for upload in uploads {
+                    self.interrupted
+                        .entry(upload.event_id.clone())
+                        .or_default()
+                        .push(upload);
+                }
+            }
+            Err(err) => warn!(err = ?err, "Failed to load interrupted uploads"),
+        }
+
         let mut interval = interval(self.config.poll_interval);
 
         loop {
-            interval.tick().await;
+            // the database is the queue; the sweep every `poll-interval` catches whatever a
+            // wake-up didn't, e.g. events deferred or left over from the last run
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.context.event_finished.notified() => {
+                    // the NVR may still be writing the end of the event
+                    tokio::time::sleep(self.config.download_delay).await;
+                    interval.reset();
+                }
+            }
 
-            let pending_backup = self.context.database.get_events_not_backed_up().await?;
+            // filters and the like from the latest config, if it was reloaded
+            self.config = self.context.backup_config.load().as_ref().clone();
+            let result = self.poll().await;
+            let status = &self.context.status.db_poller;
+            match result {
+                Ok(()) => status.waiting(self.config.poll_interval),
+                Err(err) => {
+                    error!(err = ?err, "Failed to poll for events pending backup");
+                    status.backoff(err, self.config.poll_interval);
+                }
+            }
+        }
+    }
+
+    async fn poll(&mut self) -> Result<()> {
+        let now = self.context.clock.now();
+        self.deferred.retain(|_, retry_at| *retry_at > now);
+        if !self.unrecorded.backups.is_empty() || !self.unrecorded.backed_up.is_empty() {
+            self.record(Unrecorded::default()).await;
+        }
 
-            if pending_backup.is_empty() {
+        let ready_before = self
+            .context
+            .clock
+            .ago(self.config.download_delay)
+            .timestamp_millis();
+
+        // oldest first, so a backlog is worked through before the NVR's retention catches it up
+        let pending_backup = self.context.database.get_events_not_backed_up().await?;
+        self.warn_aged_out(&pending_backup);
+        self.check_backlog(&pending_backup).await;
+
+        let pending_backup: Vec<_> = pending_backup
+            .into_iter()
+            .filter(|event| event.end_time.is_some_and(|end| end <= ready_before))
+            .filter(|event| !self.deferred.contains_key(&event.id))
+            // already backed up, only not recorded as such yet
+            .filter(|event| !self.unrecorded.backed_up.contains(&event.id))
+            .collect();
+
+        let pending_backup = self.skip_privacy_hours(pending_backup).await?;
+        let pending_backup = self.skip_too_short(pending_backup).await?;
+        let pending_backup = self.skip_filtered(pending_backup).await?;
+        let pending_backup = self.skip_degraded(pending_backup);
+        let (mut pending_backup, merged) = match self.config.merge_gap {
+            Some(gap) => merge_events(pending_backup, gap),
+            None => (pending_backup, HashMap::new()),
+        };
+        // events about to age out of the NVR go first, then what the last run started
+        let at_risk_before = self.at_risk_before();
+        pending_backup.sort_by_key(|event| {
+            (
+                !at_risk_before.is_some_and(|before| event.start_time <= before),
+                !self.interrupted.contains_key(&event.id),
+            )
+        });
+
+        if pending_backup.is_empty() {
+            return Ok(());
+        }
+        if self.config.dry_run {
+            return self.report_dry_run(&pending_backup).await;
+        }
+
+        info!("Found {} events pending backup", pending_backup.len());
+        self.context.status.db_poller.running(pending_backup.len());
+
+        // Process events in batches of BATCH_SIZE
+        let mut completed = 0;
+        let mut given_up = vec![];
+        let mut lost = vec![];
+        let mut missing = vec![];
+        for batch in pending_backup.chunks(BATCH_SIZE) {
+            let interrupted: Vec<_> = batch
+                .iter()
+                .map(|event| self.interrupted.remove(&event.id).unwrap_or_default())
+                .collect();
+            let batch_futures = batch.iter().zip(interrupted).map(|(event, interrupted)| {
+                let context = Arc::clone(&self.context);
+                let config = &self.config;
+                let event = event.clone();
+
+                async move {
+                    let mut backups = vec![];
+                    let mut failed_uploads = vec![];
+                    let mut reserved = vec![];
+                    let result = process_event(
+                        context,
+                        config,
+                        event,
+                        interrupted,
+                        &mut backups,
+                        &mut failed_uploads,
+                        &mut reserved,
+                    )
+                    .await;
+                    (backups, failed_uploads, reserved, result)
+                }
+            });
+
+            // Wait for all events in this batch to complete
+            let results = join_all(batch_futures).await;
+            self.context
+                .status
+                .circuit_breakers
+                .reopen_unfinished_probes();
+
+            // Record every upload in the batch, including those of events which then failed, so
+            // they aren't uploaded again
+            let mut backups = vec![];
+            let mut backed_up = vec![];
+            // the paths uploaded to stay reserved until they're recorded below
+            let mut reserved = vec![];
+            for (event, (event_backups, failed_uploads, event_reserved, result)) in
+                batch.iter().zip(results)
+            {
+                reserved.extend(event_reserved);
+                for backup in &event_backups {
+                    self.record_upload(&backup.target, false);
+                }
+                for target in &failed_uploads {
+                    self.record_upload(target, true);
+                }
+                backups.extend(event_backups);
+                match &result {
+                    Ok(_) => self.export_succeeded(&event.camera_id),
+                    Err(err) if is_export_failure(err) => {
+                        self.export_failed(&event.camera_id).await
+                    }
+                    Err(_) => {}
+                }
+                // an event only held back by open circuits hasn't failed, it's waiting
+                match &result {
+                    Ok(true) => {
+                        self.attempts.remove(&event.id);
+                        self.export_retries.remove(&event.id);
+                    }
+                    Ok(false) if failed_uploads.is_empty() => {}
+                    Err(Error::ProtectClient(ClientError::ExportNotReady(_))) => {}
+                    Ok(false) => {
+                        let error = format!("Failed to upload to {}", failed_uploads.join(", "));
+                        self.attempt_failed(event, error, &mut given_up);
+                    }
+                    Err(err) => self.attempt_failed(event, err.to_string(), &mut given_up),
+                }
+                match result {
+                    Ok(true) => {
+                        backed_up.push(event.id.clone());
+                        // backed up as part of this event's export
+                        if let Some(followers) = merged.get(&event.id) {
+                            backed_up.extend(followers.iter().cloned());
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(Error::ProtectClient(ClientError::ExportNotReady(_)))
+                        if self.aged_out(event) =>
+                    {
+                        lost.push(event.id.clone());
+                    }
+                    Err(Error::ProtectClient(ClientError::ExportNotReady(reason)))
+                        if self.footage_missing(event) =>
+                    {
+                        warn!(
+                            event_id = event.id,
+                            reason, "NVR has no footage for the event, skipping"
+                        );
+                        missing.push(event.id.clone());
+                    }
+                    Err(Error::ProtectClient(ClientError::ExportNotReady(reason)))
+                        if self.export_retries_exhausted(event) =>
+                    {
+                        given_up.push((
+                            event.clone(),
+                            format!(
+                                "Export still not ready after {} tries: {reason}",
+                                self.config.max_export_retries
+                            ),
+                        ));
+                    }
+                    Err(Error::ProtectClient(ClientError::ExportNotReady(reason))) => {
+                        info!(
+                            event_id = event.id,
+                            reason,
+                            retry_in = ?self.config.export_retry_delay,
+                            "Export not ready yet, deferring"
+                        );
+                        self.deferred.insert(
+                            event.id.clone(),
+                            self.context.clock.after(self.config.export_retry_delay),
+                        );
+                    }
+                    Err(e) => error!("Failed to process event in batch: {}", e),
+                }
+            }
+            self.record(Unrecorded {
+                backups,
+                backed_up,
+                reserved,
+            })
+            .await;
+
+            completed += batch.len();
+            self.context.status.db_poller.progress(completed);
+        }
+
+        for event_id in &missing {
+            self.context
+                .database
+                .mark_event_skipped(event_id, &SkipReason::Missing.to_string())
+                .await?;
+        }
+        self.skip_aged_out(&lost).await?;
+        self.give_up(given_up).await?;
+        self.check_failure_rates().await;
+        Ok(())
+    }
+
+    /// Record a batch's uploads and the events they backed up, along with whatever earlier
+    /// batches couldn't. A database error is logged and everything kept to try again with the next
+    /// batch or poll, rather than the events being uploaded all over again.
+    async fn record(&mut self, batch: Unrecorded) {
+        self.unrecorded.backups.extend(batch.backups);
+        self.unrecorded.backed_up.extend(batch.backed_up);
+        self.unrecorded.reserved.extend(batch.reserved);
+
+        let database = &self.context.database;
+        let recorded = async {
+            database.record_backups(&self.unrecorded.backups).await?;
+            database
+                .mark_events_backed_up(&self.unrecorded.backed_up)
+                .await
+        }
+        .await;
+        match recorded {
+            Ok(()) => self.unrecorded = Unrecorded::default(),
+            Err(err) => warn!(
+                err = ?err,
+                backups = self.unrecorded.backups.len(),
+                events = self.unrecorded.backed_up.len(),
+                "Failed to record backups, retrying with the next batch"
+            ),
+        }
+    }
+
+    /// Whether, with `skip-missing`, an export the NVR says isn't ready means its footage is gone
+    /// for good: it's been `missing-after` since the event ended, long past the NVR flushing it
+    fn footage_missing(&self, event: &unifi_protect_data::Event) -> bool {
+        let missing_before = self
+            .context
+            .clock
+            .ago(self.config.missing_after)
+            .timestamp_millis();
+        self.config.skip_missing && event.end_time.is_some_and(|end| end < missing_before)
+    }
+
+    /// How long the NVR keeps footage: `nvr-retention` if set, otherwise the NVR's own recording
+    /// retention if it's limited by time
+    fn nvr_retention(&self) -> Option<Duration> {
+        self.config.nvr_retention.or_else(|| {
+            self.context
+                .protect_bootstrap
+                .load()
+                .nvr
+                .recording_retention()
+        })
+    }
+
+    /// Events which started before this, in milliseconds since the epoch, are `backlog-alert-at`
+    /// of the way to being deleted by the NVR
+    fn at_risk_before(&self) -> Option<i64> {
+        let nvr_retention = self.nvr_retention()?;
+        let at_risk_after = nvr_retention.mul_f64(self.config.backlog_alert_at.clamp(0.0, 1.0));
+        Some(self.context.clock.ago(at_risk_after).timestamp_millis())
+    }
+
+    /// Whether `event` started longer ago than the NVR keeps footage. It's still tried, as the
+    /// retention is only an estimate, but an export the NVR can't find means it's gone for good.
+    fn aged_out(&self, event: &unifi_protect_data::Event) -> bool {
+        self.nvr_retention().is_some_and(|nvr_retention| {
+            event.start_time < self.context.clock.ago(nvr_retention).timestamp_millis()
+        })
+    }
+
+    /// Warn, once each, about `events` which may have aged out of the NVR. They stay in the queue,
+    /// at the front with the rest at risk.
+    fn warn_aged_out(&mut self, events: &[unifi_protect_data::Event]) {
+        let aged_out: Vec<_> = events.iter().filter(|event| self.aged_out(event)).collect();
+        for event in aged_out {
+            if !self.aged_out_warned.insert(event.id.clone()) {
                 continue;
             }
+            warn!(
+                event_id = event.id,
+                camera_id = event.camera_id,
+                "Event is older than the NVR keeps footage for, trying it before it's lost"
+            );
+        }
+    }
+
+    /// Mark `lost` events, whose footage the NVR no longer has, as skipped and alert on them
+    async fn skip_aged_out(&mut self, lost: &[String]) -> Result<()> {
+        if lost.is_empty() {
+            return Ok(());
+        }
+        for event_id in lost {
+            warn!(
+                event_id,
+                "Event aged out of the NVR before it was backed up, skipping"
+            );
+            self.context
+                .database
+                .mark_event_skipped(event_id, &SkipReason::AgedOut.to_string())
+                .await?;
+            self.aged_out_warned.remove(event_id);
+        }
+
+        let nvr_retention = self.nvr_retention().unwrap_or_default();
+        self.context
+            .notify(
+                &format!("{} events lost", lost.len()),
+                &format!(
+                    "{} events weren't backed up before the NVR deleted their footage, which it \
+                     keeps for {}. They won't be tried again.",
+                    lost.len(),
+                    humantime::format_duration(nvr_retention)
+                ),
+            )
+            .await;
+        Ok(())
+    }
+
+    /// Report the backlog of `pending` events, oldest first, in the metrics. When the NVR's
+    /// retention is known, alert once the oldest has been waiting long enough to be at risk of
+    /// being deleted by the NVR before it's backed up, and again only after the backlog has
+    /// recovered.
+    async fn check_backlog(&mut self, pending: &[unifi_protect_data::Event]) {
+        let now = self.context.clock.now();
+        let oldest = pending
+            .first()
+            .and_then(|event| DateTime::from_timestamp_millis(event.start_time));
+        let age = oldest
+            .and_then(|oldest| (now - oldest).to_std().ok())
+            .unwrap_or_default();
+
+        let metrics = &self.context.metrics.backlog;
+        metrics
+            .events
+            .store(pending.len() as u64, Ordering::Relaxed);
+        metrics
+            .oldest_age_seconds
+            .store(age.as_secs(), Ordering::Relaxed);
+
+        let Some(nvr_retention) = self.nvr_retention() else {
+            return;
+        };
+        let at_risk_before = self.at_risk_before().unwrap_or_default();
+        let at_risk = pending
+            .first()
+            .is_some_and(|event| event.start_time <= at_risk_before);
+        if !at_risk {
+            if self.backlog_alerted {
+                info!(events = pending.len(), ?age, "Backlog no longer at risk");
+                self.backlog_alerted = false;
+            }
+            return;
+        }
+        if self.backlog_alerted {
+            return;
+        }
+
+        self.backlog_alerted = true;
+        let age = humantime::format_duration(Duration::from_secs(age.as_secs()));
+        warn!(events = pending.len(), %age, "Backlog at risk of the NVR's retention");
+        self.context
+            .notify(
+                "Backlog at risk",
+                &format!(
+                    "{} events are waiting to be backed up, the oldest for {age}. The NVR only \
+                     keeps footage for {}, so events not backed up soon may be lost.",
+                    pending.len(),
+                    humantime::format_duration(nvr_retention)
+                ),
+            )
+            .await;
+    }
 
-            info!("Found {} events pending backup", pending_backup.len());
+    /// Count a failed attempt at backing up `event`, adding it to `given_up` once it's used up
+    /// `max-event-attempts`
+    fn attempt_failed(
+        &mut self,
+        event: &unifi_protect_data::Event,
+        error: String,
+        given_up: &mut Vec<(unifi_protect_data::Event, String)>,
+    ) {
+        if self.config.max_event_attempts == 0 {
+            return;
+        }
 
-            // Process events in batches of BATCH_SIZE
-            for batch in pending_backup.chunks(BATCH_SIZE) {
-                let batch_futures = batch.iter().map(|event| {
-                    let context = Arc::clone(&self.context);
-                    let event = event.clone();
+        let attempts = self.attempts.entry(event.id.clone()).or_default();
+        *attempts += 1;
+        if *attempts >= self.config.max_event_attempts {
+            self.attempts.remove(&event.id);
+            given_up.push((event.clone(), error));
+        }
+    }
 
-                    async move { process_event(context, event).await }
-                });
+    /// Count another not-ready export of `event`, and whether that was its last try
+    fn export_retries_exhausted(&mut self, event: &unifi_protect_data::Event) -> bool {
+        if self.config.max_export_retries == 0 {
+            return false;
+        }
 
-                // Wait for all events in this batch to complete
-                let results = join_all(batch_futures).await;
+        let retries = self.export_retries.entry(event.id.clone()).or_default();
+        *retries += 1;
+        if *retries >= self.config.max_export_retries {
+            self.export_retries.remove(&event.id);
+            return true;
+        }
+        false
+    }
 
-                // Log any errors from the batch processing
-                for result in results.into_iter() {
-                    if let Err(e) = result {
-                        error!("Failed to process event in batch: {}", e);
-                    }
+    /// Mark events which failed every attempt as skipped, so they're no longer retried, and send
+    /// a single alert listing them. Targets which did get a copy keep it.
+    async fn give_up(&mut self, events: Vec<(unifi_protect_data::Event, String)>) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = Vec::with_capacity(events.len());
+        for (event, error) in &events {
+            warn!(
+                event_id = event.id,
+                camera_id = event.camera_id,
+                error = %error,
+                "Giving up on backing up event"
+            );
+            self.context
+                .database
+                .mark_event_skipped(&event.id, &SkipReason::Failed.to_string())
+                .await?;
+
+            let camera_name = self
+                .context
+                .camera_name(&event.camera_id)
+                .await
+                .ok()
+                .flatten();
+            let camera = camera_name.as_deref().unwrap_or(&event.camera_id);
+            let start = DateTime::from_timestamp_millis(event.start_time)
+                .unwrap_or_default()
+                .with_timezone(&Local);
+            lines.push(format!("- {} ({camera}, {start}): {error}", event.id));
+        }
+
+        self.context
+            .notify(
+                &format!("{} events not backed up", events.len()),
+                &format!(
+                    "Backing up these events kept failing, so they won't be tried again:\n\n{}",
+                    lines.join("\n")
+                ),
+            )
+            .await;
+        Ok(())
+    }
+
+    /// Remember the outcome of an upload to `target`, keeping the last `failure-rate-window`
+    fn record_upload(&mut self, target: &str, failed: bool) {
+        if self.config.failure_rate_alert.is_none() {
+            return;
+        }
+
+        let outcomes = self.upload_outcomes.entry(target.to_string()).or_default();
+        outcomes.push_back(failed);
+        while outcomes.len() > self.config.failure_rate_window {
+            outcomes.pop_front();
+        }
+    }
+
+    /// Alert once for each target whose last `failure-rate-window` uploads failed at least as
+    /// often as `failure-rate-alert`, and again only after it has dropped back below it
+    async fn check_failure_rates(&mut self) {
+        let Some(threshold) = self.config.failure_rate_alert else {
+            return;
+        };
+
+        let mut alerts = vec![];
+        for (target, outcomes) in &self.upload_outcomes {
+            if outcomes.len() < self.config.failure_rate_window {
+                continue;
+            }
+            let failures = outcomes.iter().filter(|failed| **failed).count();
+            let rate = failures as f64 / outcomes.len() as f64;
+            if rate < threshold {
+                if self.failing_targets.remove(target) {
+                    info!(
+                        target,
+                        rate, "Target failure rate back below the alert threshold"
+                    );
                 }
+            } else if self.failing_targets.insert(target.clone()) {
+                alerts.push((target.clone(), failures, outcomes.len()));
             }
         }
+
+        for (target, failures, uploads) in alerts {
+            warn!(
+                target,
+                failures, uploads, "Target failure rate above the alert threshold"
+            );
+            self.context
+                .notify(
+                    &format!("Backup target {target} failing"),
+                    &format!(
+                        "{failures} of the last {uploads} uploads to {target} failed, at least \
+                         the {:.0}% alert threshold.",
+                        threshold * 100.0
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Log what backing up `events` would export and upload where, once per event, without
+    /// doing any of it. The events stay pending.
+    async fn report_dry_run(&mut self, events: &[unifi_protect_data::Event]) -> Result<()> {
+        for event in events {
+            let Some(end_time) = event.end_time else {
+                continue;
+            };
+            if !self.reported.insert(event.id.clone()) {
+                continue;
+            }
+
+            let mut protect_event = protect_event_from_database_event(
+                event.clone(),
+                &self.context.protect_bootstrap.load(),
+            );
+            if protect_event.camera_name.is_none() {
+                protect_event.camera_name = self.context.camera_name(&event.camera_id).await?;
+            }
+            let existing = self
+                .context
+                .database
+                .get_backups_by_event(&event.id)
+                .await?;
+            let quality = self
+                .config
+                .export_quality(&event.camera_id, protect_event.camera_name.as_deref());
+
+            let max_event_length = self
+                .config
+                .max_event_length(&event.camera_id, protect_event.camera_name.as_deref());
+            let segments = segments(event.start_time, end_time, max_event_length);
+            let chunked = segments.len() > 1;
+            let backup_targets = self.context.backup_targets.load_full();
+            for (index, (segment_start, segment_end)) in segments.into_iter().enumerate() {
+                let part = if chunked { index as u32 + 1 } else { 0 };
+                protect_event.part = chunked.then_some(part);
+
+                let missing: Vec<_> = backup_targets
+                    .iter()
+                    .filter(|target| target.accepts(&protect_event))
+                    .filter(|target| {
+                        !existing
+                            .iter()
+                            .any(|backup| backup.part == part && backup.target == target.name())
+                    })
+                    .collect();
+                if missing.is_empty() {
+                    continue;
+                }
+
+                info!(
+                    event_id = event.id,
+                    part,
+                    segment_start,
+                    segment_end,
+                    ?quality,
+                    "Dry run: would export"
+                );
+                for target in missing {
+                    info!(
+                        event_id = event.id,
+                        part,
+                        target = target.name(),
+                        path = target.destination(&protect_event),
+                        "Dry run: would upload"
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hold back the events of degraded cameras, except one per camera whose probe is due. Held
+    /// back events stay pending for when the camera recovers.
+    fn skip_degraded(
+        &self,
+        events: Vec<unifi_protect_data::Event>,
+    ) -> Vec<unifi_protect_data::Event> {
+        if self.degraded.is_empty() {
+            return events;
+        }
+
+        let now = self.context.clock.now();
+        let mut probing = HashSet::new();
+        events
+            .into_iter()
+            .filter(|event| match self.degraded.get(&event.camera_id) {
+                Some(probe_at) => *probe_at <= now && probing.insert(event.camera_id.clone()),
+                None => true,
+            })
+            .collect()
+    }
+
+    fn export_succeeded(&mut self, camera_id: &str) {
+        self.export_failures.remove(camera_id);
+        if self.degraded.remove(camera_id).is_some() {
+            info!(camera_id, "Camera exports again, no longer degraded");
+        }
+    }
+
+    /// Count a failed export against the camera, degrading it after `degraded-after` in a row. A
+    /// degraded camera's failed probe just pushes its next probe back.
+    async fn export_failed(&mut self, camera_id: &str) {
+        if self.config.degraded_after == 0 {
+            return;
+        }
+
+        let failures = self
+            .export_failures
+            .entry(camera_id.to_string())
+            .or_default();
+        *failures += 1;
+        if *failures < self.config.degraded_after {
+            return;
+        }
+
+        let failures = *failures;
+        let probe_at = self
+            .context
+            .clock
+            .after(self.config.degraded_probe_interval);
+        if self
+            .degraded
+            .insert(camera_id.to_string(), probe_at)
+            .is_some()
+        {
+            debug!(camera_id, failures, "Degraded camera still failing exports");
+            return;
+        }
+
+        let camera_name = self.context.camera_name(camera_id).await.ok().flatten();
+        let camera = camera_name.as_deref().unwrap_or(camera_id);
+        warn!(
+            camera_id,
+            failures,
+            probe_interval = ?self.config.degraded_probe_interval,
+            "Camera exports keep failing, marking it degraded"
+        );
+        self.context
+            .notify(
+                &format!("Camera {camera} degraded"),
+                &format!(
+                    "The last {failures} exports from camera {camera} ({camera_id}) failed. Its \
+                     events are no longer exported, except for one every {} to check whether it \
+                     has recovered.",
+                    humantime::format_duration(self.config.degraded_probe_interval)
+                ),
+            )
+            .await;
     }
+
+    /// Mark events which started inside a privacy window as skipped, returning the rest.
+    async fn skip_privacy_hours(
+        &mut self,
+        events: Vec<unifi_protect_data::Event>,
+    ) -> Result<Vec<unifi_protect_data::Event>> {
+        if self.config.privacy_hours.is_empty() {
+            return Ok(events);
+        }
+
+        let mut pending = Vec::with_capacity(events.len());
+        for event in events {
+            let camera_name = self.context.camera_name(&event.camera_id).await?;
+            let start = DateTime::from_timestamp_millis(event.start_time)
+                .unwrap_or_default()
+                .with_timezone(&Local);
+
+            let private = self.config.privacy_hours.iter().any(|schedule| {
+                schedule.applies_to(&event.camera_id, camera_name.as_deref())
+                    && schedule.covers(start)
+            });
+
+            if private && self.config.dry_run {
+                if self.reported.insert(event.id.clone()) {
+                    info!(
+                        event_id = event.id,
+                        camera_id = event.camera_id,
+                        "Dry run: would skip, within privacy hours"
+                    );
+                }
+            } else if private {
+                info!(
+                    event_id = event.id,
+                    camera_id = event.camera_id,
+                    "Event is within privacy hours, skipping backup"
+                );
+                self.context
+                    .database
+                    .mark_event_skipped(&event.id, &SkipReason::PrivacyHours.to_string())
+                    .await?;
+            } else {
+                pending.push(event);
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Mark events shorter than their camera's `min-event-length` as skipped, returning the rest.
+    async fn skip_too_short(
+        &mut self,
+        events: Vec<unifi_protect_data::Event>,
+    ) -> Result<Vec<unifi_protect_data::Event>> {
+        if self.config.per_camera.is_empty() {
+            return Ok(events);
+        }
+
+        let mut pending = Vec::with_capacity(events.len());
+        for event in events {
+            let camera_name = self.context.camera_name(&event.camera_id).await?;
+            let min_event_length = self
+                .config
+                .camera_config(&event.camera_id, camera_name.as_deref())
+                .and_then(|camera| camera.min_event_length);
+            let length = event.end_time.unwrap_or(event.start_time) - event.start_time;
+            let too_short = min_event_length
+                .is_some_and(|min_event_length| length < min_event_length.as_millis() as i64);
+
+            if !too_short {
+                pending.push(event);
+            } else if self.config.dry_run {
+                if self.reported.insert(event.id.clone()) {
+                    info!(
+                        event_id = event.id,
+                        length, "Dry run: would skip, too short"
+                    );
+                }
+            } else {
+                debug!(
+                    event_id = event.id,
+                    length, "Event is too short, skipping backup"
+                );
+                self.context
+                    .database
+                    .mark_event_skipped(&event.id, &SkipReason::TooShort.to_string())
+                    .await?;
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Mark events the filter script rejects as skipped, returning the rest. An event the script
+    /// fails on is backed up anyway: a skip can't be undone, a spare backup can be pruned.
+    async fn skip_filtered(
+        &mut self,
+        events: Vec<unifi_protect_data::Event>,
+    ) -> Result<Vec<unifi_protect_data::Event>> {
+        let Some(script) = self.context.filter_script.load_full() else {
+            return Ok(events);
+        };
+        let metrics = &self.context.metrics.filter_script;
+
+        let mut pending = Vec::with_capacity(events.len());
+        for event in events {
+            let mut protect_event = protect_event_from_database_event(
+                event.clone(),
+                &self.context.protect_bootstrap.load(),
+            );
+            if protect_event.camera_name.is_none() {
+                protect_event.camera_name = self.context.camera_name(&event.camera_id).await?;
+            }
+
+            let matches = script.matches(&protect_event).unwrap_or_else(|err| {
+                warn!(
+                    event_id = event.id,
+                    err = ?err,
+                    "Filter script failed, backing the event up anyway"
+                );
+                metrics.errors.fetch_add(1, Ordering::Relaxed);
+                true
+            });
+
+            if matches {
+                pending.push(event);
+                continue;
+            }
+
+            debug!(event_id = event.id, "Event rejected by filter script");
+            metrics.rejected.fetch_add(1, Ordering::Relaxed);
+            if self.config.dry_run {
+                if self.reported.insert(event.id.clone()) {
+                    info!(
+                        event_id = event.id,
+                        "Dry run: would skip, rejected by filter script"
+                    );
+                }
+                continue;
+            }
+            self.context
+                .database
+                .mark_event_skipped(&event.id, &SkipReason::FilterScript.to_string())
+                .await?;
+        }
+
+        Ok(pending)
+    }
+}
+
+/// Why an event was deliberately not backed up, recorded as the event's `skip_reason`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    PrivacyHours,
+    FilterScript,
+    /// Every attempt allowed by `max-event-attempts` failed, or `max-export-retries` ran out
+    Failed,
+    /// The NVR deleted the event's footage before it was backed up
+    AgedOut,
+    /// The NVR had no footage to export, with `skip-missing`
+    Missing,
+    /// Shorter than the camera's `min-event-length`
+    TooShort,
 }
 
-async fn process_event(context: Arc<Context>, event: unifi_protect_data::Event) -> Result<()> {
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::PrivacyHours => write!(f, "privacy_hours"),
+            SkipReason::FilterScript => write!(f, "filter_script"),
+            SkipReason::Failed => write!(f, "failed"),
+            SkipReason::AgedOut => write!(f, "aged_out"),
+            SkipReason::Missing => write!(f, "missing"),
+            SkipReason::TooShort => write!(f, "too_short"),
+        }
+    }
+}
+
+/// Whether the error means the NVR couldn't export the camera's footage, rather than the NVR or
+/// a target being unreachable
+fn is_export_failure(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::InvalidExport(_) | Error::ProtectClient(ClientError::Api(_))
+    )
+}
+
+/// Back up every part of the event to every target without a copy yet, pushing a record of each
+/// upload onto `backups` and the target of each failed upload onto `failed_uploads`. Returns
+/// whether the event is now backed up everywhere.
+///
+/// `interrupted` are the event's uploads the last run didn't finish. Where the same spooled
+/// export is still there to upload, targets that can pick up from where they stopped do.
+async fn process_event(
+    context: Arc<Context>,
+    config: &crate::backup::Config,
+    event: unifi_protect_data::Event,
+    interrupted: Vec<InFlightUpload>,
+    backups: &mut Vec<Backup>,
+    failed_uploads: &mut Vec<String>,
+    reserved: &mut Vec<Reservation>,
+) -> Result<bool> {
     info!("Processing event: {}", event.id);
 
     let Some(end_time) = event.end_time else {
@@ -66,35 +1024,1000 @@ async fn process_event(context: Arc<Context>, event: unifi_protect_data::Event)
         ));
     };
 
-    // 1. Download video data from UniFi Protect
-    debug!(event_id = event.id, "Downloading Motion Event");
-    let video_data = context
-        .protect_client
-        .download_event_video(event.camera_id.as_str(), event.start_time, end_time)
-        .await?;
-
     let event_id = event.id.clone();
-    let protect_event = protect_event_from_database_event(event, &context.protect_bootstrap);
+    let camera_id = event.camera_id.clone();
+    let start_time = event.start_time;
+    let mut protect_event =
+        protect_event_from_database_event(event, &context.protect_bootstrap.load());
+    if protect_event.camera_name.is_none() {
+        protect_event.camera_name = context.camera_name(&camera_id).await?;
+    }
+
+    // parts already backed up on a previous attempt don't need to be uploaded again
+    let existing = context.database.get_backups_by_event(&event_id).await?;
+
+    if config.snapshots == SnapshotMode::Only {
+        return process_snapshot(
+            &context,
+            config,
+            &protect_event,
+            &existing,
+            backups,
+            failed_uploads,
+            reserved,
+        )
+        .await;
+    }
+
+    let quality = config.export_quality(&camera_id, protect_event.camera_name.as_deref());
+    let max_event_length =
+        config.max_event_length(&camera_id, protect_event.camera_name.as_deref());
+    let segments = segments(start_time, end_time, max_event_length);
+    let chunked = segments.len() > 1;
+
     // todo(steve.sampson): parallelize backups to different targets
     let mut error = false;
-    for target in context.backup_targets.as_slice() {
-        // 2. Run backup operations using configured backup targets
-        let _ = target
-            .backup(&protect_event, video_data.as_slice())
-            .await
-            .inspect_err(|err| {
-                warn!(err= ?err, "Failed to create backup");
-                error = true;
+    let backup_targets = context.backup_targets.load_full();
+    for (index, (segment_start, segment_end)) in segments.into_iter().enumerate() {
+        let part = if chunked { index as u32 + 1 } else { 0 };
+        let missing = backup_targets
+            .iter()
+            .filter(|target| target.accepts(&protect_event))
+            .filter(|target| {
+                !existing
+                    .iter()
+                    .any(|backup| backup.part == part && backup.target == target.name())
             });
+
+        let (pending_targets, rejected) = admit(&context, &event_id, missing);
+        error |= rejected;
+
+        if pending_targets.is_empty() {
+            continue;
+        }
+
+        // 1. Download video data from UniFi Protect, streaming it to the configured backup
+        // targets as it arrives, or through the spool if there is one
+        debug!(event_id, part, ?quality, "Downloading Motion Event");
+        let mut spool_path = None;
+        let mut resume_from = HashMap::new();
+        let video = match &context.spool {
+            Some(spool) => {
+                let staged = match spool.get(&event_id, part).await {
+                    Some(staged) => {
+                        debug!(event_id, part, "Uploading export already in the spool");
+                        // only the very same export can be picked up part way through
+                        let staged_path = staged.to_string_lossy();
+                        resume_from = interrupted
+                            .iter()
+                            .filter(|upload| {
+                                upload.part == part
+                                    && upload.spool_path.as_deref() == Some(&*staged_path)
+                            })
+                            .map(|upload| (upload.target.clone(), upload.bytes_sent))
+                            .collect();
+                        staged
+                    }
+                    None => {
+                        let video = open_segment(
+                            &context,
+                            config,
+                            &camera_id,
+                            segment_start,
+                            segment_end,
+                            quality,
+                        )
+                        .await?;
+                        spool.stage(&event_id, part, video).await?
+                    }
+                };
+                spool_path = Some(staged.to_string_lossy().into_owned());
+                let video = spool
+                    .open(&staged, config.download_buffer_size.0 as usize)
+                    .await?;
+                // uploads from the spool count towards the limit as much as downloads do
+                match &context.bandwidth {
+                    Some(limiter) => limiter.throttle(video),
+                    None => video,
+                }
+            }
+            None => {
+                open_segment(
+                    &context,
+                    config,
+                    &camera_id,
+                    segment_start,
+                    segment_end,
+                    quality,
+                )
+                .await?
+            }
+        };
+
+        // recorded so a restart can tell which uploads were cut short, and how far they got
+        let in_flight: Vec<_> = pending_targets
+            .iter()
+            .map(|target| InFlightUpload {
+                event_id: event_id.clone(),
+                target: target.name(),
+                part,
+                spool_path: spool_path.clone(),
+                bytes_sent: resume_from.get(&target.name()).copied().unwrap_or_default(),
+                started_at: context.clock.now(),
+            })
+            .collect();
+        context.database.start_uploads(&in_flight).await?;
+
+        let recorded = AtomicU64::new(0);
+        let progress = |bytes_sent: u64| {
+            if bytes_sent - recorded.load(Ordering::Relaxed) < PROGRESS_INTERVAL_BYTES {
+                return;
+            }
+            recorded.store(bytes_sent, Ordering::Relaxed);
+
+            // not worth holding up the upload for
+            let database = context.database.clone();
+            let event_id = event_id.clone();
+            tokio::spawn(async move {
+                if let Err(err) = database
+                    .update_upload_progress(&event_id, part, bytes_sent)
+                    .await
+                {
+                    debug!(err = ?err, event_id, part, "Failed to record upload progress");
+                }
+            });
+        };
+
+        protect_event.part = chunked.then_some(part);
+        let (collision, reservation) = collision(
+            &context.database,
+            &context.reservations,
+            &pending_targets,
+            &protect_event,
+        )
+        .await?;
+        protect_event.collision = collision;
+        reserved.push(reservation);
+        let streamed = stream_to_targets(
+            video,
+            &protect_event,
+            &pending_targets,
+            &resume_from,
+            config,
+            segment_end - segment_start,
+            progress,
+        )
+        .await;
+        context.database.finish_uploads(&event_id, part).await?;
+
+        // the spool keeps an export until every target has it; a bad one is downloaded again
+        if let Some(spool) = &context.spool {
+            let done = match &streamed {
+                Ok(streamed) => streamed.uploads.iter().all(Result::is_ok),
+                Err(_) => true,
+            };
+            if done {
+                spool.remove(&event_id, part).await;
+            }
+        }
+        let streamed = streamed?;
+
+        // the first part's snapshot goes next to it on every target it was uploaded to
+        let snapshot = if config.snapshots == SnapshotMode::WithVideo
+            && part <= 1
+            && streamed.uploads.iter().any(Result::is_ok)
+        {
+            snapshot::fetch(&context, &protect_event)
+                .await
+                .inspect_err(|err| warn!(err = ?err, event_id, "Failed to fetch snapshot"))
+                .ok()
+        } else {
+            None
+        };
+
+        // 2. Record the outcome for each target
+        for (target, upload) in pending_targets.into_iter().zip(streamed.uploads) {
+            match upload {
+                Ok(remote_path) => {
+                    upload_succeeded(&context, &target.name());
+                    let backup_time = context.clock.now();
+                    if config.sidecars {
+                        let sidecar = Sidecar::new(
+                            &protect_event,
+                            &context.protect_bootstrap.load().nvr.version,
+                            streamed.size_bytes,
+                            &streamed.sha256,
+                            backup_time,
+                        );
+                        sidecar::write(target.as_ref(), &remote_path, &sidecar).await;
+                    }
+                    if let Some(jpeg) = &snapshot {
+                        write_snapshot(target, &remote_path, jpeg).await;
+                    }
+                    backups.push(Backup {
+                        event_id: event_id.clone(),
+                        target: target.name(),
+                        part,
+                        remote_path,
+                        backup_time,
+                        size_bytes: streamed.size_bytes,
+                        sha256: Some(streamed.sha256.clone()),
+                    })
+                }
+                Err(err) => {
+                    record_failure(&context, config, &event_id, target, err).await?;
+                    failed_uploads.push(target.name());
+                    error = true;
+                }
+            }
+        }
     }
 
-    if !error {
-        // 3. Update database to mark event as backed up (assuming no error backing up to any targets)
+    // 3. The caller records the backups and marks the event backed up (assuming no error backing
+    // up to any targets) for the whole batch at once
+    Ok(!error)
+}
+
+/// Like [`process_event`], but backing up a snapshot of the event in place of its video
+async fn process_snapshot(
+    context: &Context,
+    config: &crate::backup::Config,
+    event: &ProtectEvent,
+    existing: &[Backup],
+    backups: &mut Vec<Backup>,
+    failed_uploads: &mut Vec<String>,
+    reserved: &mut Vec<Reservation>,
+) -> Result<bool> {
+    let backup_targets = context.backup_targets.load_full();
+    let missing = backup_targets
+        .iter()
+        .filter(|target| target.accepts(event))
+        .filter(|target| !existing.iter().any(|backup| backup.target == target.name()));
+    let (pending_targets, mut error) = admit(context, &event.id, missing);
+    if pending_targets.is_empty() {
+        return Ok(!error);
+    }
+
+    debug!(event_id = event.id, "Fetching snapshot");
+    let jpeg = snapshot::fetch(context, event).await?;
+    let sha256 = crate::backup::sha256(&jpeg);
+
+    let mut event = event.clone();
+    let (collision, reservation) = collision_at(
+        &context.database,
+        &context.reservations,
+        &pending_targets,
+        &event,
+        |target, event| snapshot_path(&target.destination(event)),
+    )
+    .await?;
+    event.collision = collision;
+    reserved.push(reservation);
+
+    for target in pending_targets {
+        let remote_path = snapshot_path(&target.destination(&event));
+        match target.upload(&remote_path, &jpeg).await {
+            Ok(()) => {
+                upload_succeeded(context, &target.name());
+                backups.push(Backup {
+                    event_id: event.id.clone(),
+                    target: target.name(),
+                    part: 0,
+                    remote_path,
+                    backup_time: context.clock.now(),
+                    size_bytes: jpeg.len() as u64,
+                    sha256: Some(sha256.clone()),
+                });
+            }
+            Err(err) => {
+                record_failure(context, config, &event.id, target, err).await?;
+                failed_uploads.push(target.name());
+                error = true;
+            }
+        }
+    }
+
+    Ok(!error)
+}
+
+/// The targets whose circuit lets an upload through, and whether any were held back. Those
+/// whose circuit is open wait for it to close, leaving the event pending.
+fn admit<'a>(
+    context: &Context,
+    event_id: &str,
+    targets: impl Iterator<Item = &'a Arc<dyn crate::backup::Backup>>,
+) -> (Vec<&'a Arc<dyn crate::backup::Backup>>, bool) {
+    let mut admitted = vec![];
+    let mut rejected = false;
+    for target in targets {
+        match context.status.circuit_breakers.allow(&target.name()) {
+            Admission::Allowed => admitted.push(target),
+            Admission::Probe => {
+                info!(
+                    event_id,
+                    target = target.name(),
+                    "Probing target with open circuit"
+                );
+                context
+                    .metrics
+                    .circuit_breaker
+                    .probes
+                    .fetch_add(1, Ordering::Relaxed);
+                admitted.push(target);
+            }
+            Admission::Rejected => {
+                debug!(
+                    event_id,
+                    target = target.name(),
+                    "Skipping target with open circuit"
+                );
+                rejected = true;
+            }
+        }
+    }
+    (admitted, rejected)
+}
+
+/// Count a failed upload of the event against the target and record it in the database
+async fn record_failure(
+    context: &Context,
+    config: &crate::backup::Config,
+    event_id: &str,
+    target: &Arc<dyn crate::backup::Backup>,
+    err: Error,
+) -> Result<()> {
+    warn!(err = ?err, "Failed to create backup");
+    upload_failed(context, config, &target.name()).await;
+    context
+        .database
+        .insert_failure(&Failure {
+            subject: event_id.to_string(),
+            target: target.name(),
+            error: err.to_string(),
+            output: err.output().unwrap_or_default().to_string(),
+            failure_time: context.clock.now(),
+        })
+        .await?;
+    Ok(())
+}
+
+/// Upload a backup's snapshot. Like a sidecar, failing is only logged.
+async fn write_snapshot(target: &Arc<dyn crate::backup::Backup>, remote_path: &str, jpeg: &[u8]) {
+    let path = snapshot_path(remote_path);
+    if let Err(err) = target.upload(&path, jpeg).await {
+        warn!(err = ?err, target = target.name(), path, "Failed to write backup snapshot");
+    }
+}
+
+fn upload_succeeded(context: &Context, target: &str) {
+    if context.status.circuit_breakers.succeeded(target) {
+        info!(target, "Target accepts uploads again, closing its circuit");
         context
-            .database
-            .mark_event_backed_up(event_id.as_str())
+            .metrics
+            .circuit_breaker
+            .open
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Count a failed upload against the target, opening its circuit after `breaker-after` in a row
+async fn upload_failed(context: &Context, config: &crate::backup::Config, target: &str) {
+    let Some(failures) = context.status.circuit_breakers.failed(
+        target,
+        config.breaker_after,
+        config.breaker_probe_interval,
+    ) else {
+        return;
+    };
+
+    warn!(
+        target,
+        failures,
+        probe_interval = ?config.breaker_probe_interval,
+        "Uploads to target keep failing, opening its circuit"
+    );
+    let metrics = &context.metrics.circuit_breaker;
+    metrics.trips.fetch_add(1, Ordering::Relaxed);
+    metrics.open.fetch_add(1, Ordering::Relaxed);
+    context
+        .notify(
+            &format!("Backup target {target} unavailable"),
+            &format!(
+                "The last {failures} uploads to {target} failed. Nothing more is uploaded to it, \
+                 except for one upload every {} to check whether it has recovered; events stay \
+                 pending until then.",
+                humantime::format_duration(config.breaker_probe_interval)
+            ),
+        )
+        .await;
+}
+
+/// Export `[start, end)` from the NVR, through an export job if it's long enough, and check the
+/// result looks like a complete video.
+pub(crate) async fn download_segment(
+    context: &Context,
+    config: &crate::backup::Config,
+    camera_id: &str,
+    start: i64,
+    end: i64,
+    quality: ExportQuality,
+) -> Result<Vec<u8>> {
+    let on_progress = download::track(context, camera_id, start, end);
+    let video_data = if uses_export_job(config, start, end) {
+        let video_data = context
+            .protect_client
+            .export_video_via_job(
+                camera_id,
+                start,
+                end,
+                quality,
+                config.export_job_poll_interval,
+                config.export_job_timeout,
+            )
+            .await?;
+        on_progress(video_data.len());
+        video_data
+    } else {
+        context
+            .protect_client
+            .download_event_video_with_progress(camera_id, start, end, quality, on_progress)
+            .await?
+    };
+
+    validate_export(
+        video_data.as_slice(),
+        end - start,
+        config.min_export_bytes_per_second.0,
+    )
+    .inspect_err(|err| warn!(camera_id, start, err = ?err, "Rejecting export"))?;
+
+    let mut verifier = ExportVerifier::new(config, end - start)?;
+    verifier.feed(&video_data).await?;
+    verifier
+        .finish()
+        .await
+        .inspect_err(|err| warn!(camera_id, start, err = ?err, "Rejecting export"))?;
+
+    Ok(video_data)
+}
+
+/// Like [`download_segment`], but streaming the export rather than holding it in memory, and
+/// leaving the checks to [`stream_to_targets`]. Export jobs can only be downloaded whole, so
+/// those are still held in memory, and aren't paced by `bwlimit`.
+async fn open_segment(
+    context: &Context,
+    config: &crate::backup::Config,
+    camera_id: &str,
+    start: i64,
+    end: i64,
+    quality: ExportQuality,
+) -> Result<VideoStream> {
+    let on_progress = download::track(context, camera_id, start, end);
+    if uses_export_job(config, start, end) {
+        let video_data = context
+            .protect_client
+            .export_video_via_job(
+                camera_id,
+                start,
+                end,
+                quality,
+                config.export_job_poll_interval,
+                config.export_job_timeout,
+            )
             .await?;
+        on_progress(video_data.len());
+        return Ok(Box::pin(stream::once(async {
+            Ok(Bytes::from(video_data))
+        })));
     }
 
-    Ok(())
+    let video = context
+        .protect_client
+        .stream_event_video(camera_id, start, end, quality)
+        .await?;
+    let video = progress::track(video, on_progress);
+    Ok(match &context.bandwidth {
+        Some(limiter) => limiter.throttle(video),
+        None => video,
+    })
+}
+
+fn uses_export_job(config: &crate::backup::Config, start: i64, end: i64) -> bool {
+    config
+        .export_job_threshold
+        .is_some_and(|threshold| end - start > threshold.as_millis() as i64)
+}
+
+/// Split `[start, end)` into consecutive segments no longer than `max_length`. A zero
+/// `max_length` disables splitting.
+pub(crate) fn segments(start: i64, end: i64, max_length: Duration) -> Vec<(i64, i64)> {
+    let max_length = max_length.as_millis() as i64;
+    if max_length <= 0 || end - start <= max_length {
+        return vec![(start, end)];
+    }
+
+    (start..end)
+        .step_by(max_length as usize)
+        .map(|segment_start| (segment_start, (segment_start + max_length).min(end)))
+        .collect()
+}
+
+/// Merge the events on each camera separated by no more than `gap` into the first of them,
+/// extending it to the end of the last and taking on all of their detection types, so they're
+/// exported as one. `events` must be in order of start time. Returns the events left and the
+/// ids of the events merged into each.
+fn merge_events(
+    events: Vec<unifi_protect_data::Event>,
+    gap: Duration,
+) -> (Vec<unifi_protect_data::Event>, HashMap<String, Vec<String>>) {
+    let gap = gap.as_millis() as i64;
+    let mut merged_events: Vec<unifi_protect_data::Event> = Vec::with_capacity(events.len());
+    let mut merged: HashMap<String, Vec<String>> = HashMap::new();
+    // index in `merged_events` of the event each camera's next event may be merged into
+    let mut open: HashMap<String, usize> = HashMap::new();
+
+    for event in events {
+        let (Some(index), Some(end)) = (open.get(&event.camera_id).copied(), event.end_time) else {
+            open.insert(event.camera_id.clone(), merged_events.len());
+            merged_events.push(event);
+            continue;
+        };
+
+        let lead = &mut merged_events[index];
+        let within_gap = lead
+            .end_time
+            .is_some_and(|lead_end| event.start_time - lead_end <= gap);
+        if !within_gap {
+            open.insert(event.camera_id.clone(), merged_events.len());
+            merged_events.push(event);
+            continue;
+        }
+
+        lead.end_time = lead.end_time.max(Some(end));
+        let detections = event
+            .smart_detect_types
+            .split(',')
+            .filter(|d| !d.is_empty());
+        for detection in detections {
+            let known = lead.smart_detect_types.split(',').any(|d| d == detection);
+            if !known {
+                if !lead.smart_detect_types.is_empty() {
+                    lead.smart_detect_types.push(',');
+                }
+                lead.smart_detect_types.push_str(detection);
+            }
+        }
+        merged.entry(lead.id.clone()).or_default().push(event.id);
+    }
+
+    (merged_events, merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use unifi_protect_client::mock_server::MockProtectServer;
+
+    use super::*;
+    use crate::testing::{self, CAMERA_ID, TestContext};
+
+    fn event(
+        id: &str,
+        camera_id: &str,
+        start_time: i64,
+        end_time: i64,
+        smart: &str,
+    ) -> unifi_protect_data::Event {
+        unifi_protect_data::Event {
+            id: id.to_string(),
+            event_type: "Motion".to_string(),
+            camera_id: camera_id.to_string(),
+            start_time,
+            end_time: Some(end_time),
+            backed_up: false,
+            skip_reason: None,
+            smart_detect_types: smart.to_string(),
+            thumbnail_id: None,
+            heatmap_id: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_events() {
+        let events = vec![
+            event("a", "driveway", 0, 10_000, ""),
+            event("b", "porch", 5_000, 8_000, ""),
+            event("c", "driveway", 15_000, 20_000, "person"),
+            event("d", "driveway", 22_000, 30_000, "vehicle,person"),
+            event("e", "driveway", 60_000, 70_000, ""),
+        ];
+
+        let (events, merged) = merge_events(events, Duration::from_secs(5));
+        let ids: Vec<_> = events.iter().map(|event| event.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "e"]);
+        assert_eq!(events[0].end_time, Some(30_000));
+        assert_eq!(events[0].smart_detect_types, "person,vehicle");
+        assert_eq!(merged["a"], vec!["c", "d"]);
+        assert!(!merged.contains_key("b"));
+    }
+
+    #[test]
+    fn test_segments() {
+        let five_minutes = Duration::from_secs(300);
+        assert_eq!(segments(0, 1_000, five_minutes), vec![(0, 1_000)]);
+        assert_eq!(
+            segments(0, 700_000, five_minutes),
+            vec![(0, 300_000), (300_000, 600_000), (600_000, 700_000)]
+        );
+        assert_eq!(segments(0, 700_000, Duration::ZERO), vec![(0, 700_000)]);
+    }
+
+    #[tokio::test]
+    async fn test_record_retries() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let database = &context.database;
+
+        // the event isn't in the database yet, so recording its backup fails
+        let backup = Backup {
+            event_id: "event".to_string(),
+            target: context.backup_targets.load()[0].name(),
+            part: 0,
+            remote_path: "event.mp4".to_string(),
+            backup_time: Utc::now(),
+            size_bytes: 5,
+            sha256: None,
+        };
+        poller
+            .record(Unrecorded {
+                backups: vec![backup],
+                backed_up: vec!["event".to_string()],
+                reserved: vec![],
+            })
+            .await;
+        assert!(database.get_backups().await.unwrap().is_empty());
+        assert_eq!(poller.unrecorded.backups.len(), 1);
+
+        // recorded by the next poll, without the event being uploaded again
+        database
+            .insert_event(&testing::event("event", start, start + 10_000))
+            .await
+            .unwrap();
+        poller.poll().await.unwrap();
+        assert!(test.protect.requested_exports().is_empty());
+        assert_eq!(database.get_backups().await.unwrap().len(), 1);
+        let event = database.get_event_by_id("event").await.unwrap().unwrap();
+        assert!(event.backed_up);
+        assert!(poller.unrecorded.backups.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_collision() {
+        let test = TestContext::new("snapshots = \"only\"").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let database = &context.database;
+        test.protect.set_snapshot(CAMERA_ID, b"snapshot".to_vec());
+
+        // both start at the same time, so the file structure can't tell them apart
+        for id in ["a", "b"] {
+            database
+                .insert_event(&testing::event(id, start, start + 10_000))
+                .await
+                .unwrap();
+        }
+        poller.poll().await.unwrap();
+
+        let backups = database.get_backups().await.unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_ne!(backups[0].remote_path, backups[1].remote_path);
+        for backup in &backups {
+            assert!(backup.remote_path.ends_with(".jpg"));
+            assert!(test.backup_dir().join(&backup.remote_path).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_too_short() {
+        let test = TestContext::new(
+            r#"
+            [[camera]]
+            name = "Front Door"
+            min-event-length = "30s"
+            "#,
+        )
+        .await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let database = &context.database;
+        let short = testing::event("short", 0, 10_000);
+        let long = testing::event("long", 0, 40_000);
+        for event in [&short, &long] {
+            database.insert_event(event).await.unwrap();
+        }
+
+        let pending = poller.skip_too_short(vec![short, long]).await.unwrap();
+        let pending: Vec<_> = pending.iter().map(|event| event.id.as_str()).collect();
+        assert_eq!(pending, vec!["long"]);
+        let short = database.get_event_by_id("short").await.unwrap().unwrap();
+        assert_eq!(short.skip_reason, Some(SkipReason::TooShort.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let test = TestContext::new("dry-run = true").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let database = &context.database;
+        test.protect.set_export(CAMERA_ID, testing::video());
+        database
+            .insert_event(&testing::event("event", start, start + 10_000))
+            .await
+            .unwrap();
+
+        // reported once, and left pending without exporting or uploading anything
+        poller.poll().await.unwrap();
+        poller.poll().await.unwrap();
+        assert!(poller.reported.contains("event"));
+        assert!(test.protect.requested_exports().is_empty());
+        assert!(database.get_backups().await.unwrap().is_empty());
+        let event = database.get_event_by_id("event").await.unwrap().unwrap();
+        assert!(!event.backed_up);
+        assert_eq!(std::fs::read_dir(test.backup_dir()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_poll() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let database = &context.database;
+        test.protect.set_export(CAMERA_ID, testing::video());
+        database
+            .insert_event(&testing::event("event", start, start + 10_000))
+            .await
+            .unwrap();
+
+        poller.poll().await.unwrap();
+        assert_eq!(
+            test.protect.requested_exports(),
+            vec![(CAMERA_ID.to_string(), start, start + 10_000)]
+        );
+        let event = database.get_event_by_id("event").await.unwrap().unwrap();
+        assert!(event.backed_up);
+        let backups = database.get_backups().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].event_id, "event");
+        assert_eq!(backups[0].target, context.backup_targets.load()[0].name());
+        assert_eq!(backups[0].size_bytes, testing::video().len() as u64);
+        let written = std::fs::read(test.backup_dir().join(&backups[0].remote_path)).unwrap();
+        assert_eq!(written, testing::video());
+
+        // backed up once only
+        poller.poll().await.unwrap();
+        assert_eq!(test.protect.requested_exports().len(), 1);
+        assert_eq!(database.get_backups().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_poll_protect_server() {
+        let server = MockProtectServer::start(serde_json::json!({
+            "cameras": [
+                {
+                    "id": CAMERA_ID, "name": testing::CAMERA_NAME, "mac": "AA", "model": null,
+                    "isConnected": true
+                }
+            ],
+            "nvr": { "id": "nvr", "name": "NVR", "version": "5.0.0", "timezone": "UTC" }
+        }))
+        .await
+        .unwrap();
+        server.set_export(CAMERA_ID, testing::video());
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("backups")).unwrap();
+        let mut config = testing::config(&dir, "");
+        config.unifi = server.config();
+        let context = Arc::new(Context::new(config).await.unwrap());
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+
+        // over HTTPS, from login through to the export
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        context
+            .database
+            .insert_event(&testing::event("event", start, start + 10_000))
+            .await
+            .unwrap();
+        poller.poll().await.unwrap();
+        let backups = context.database.get_backups().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        let written = std::fs::read(dir.path().join("backups").join(&backups[0].remote_path));
+        assert_eq!(written.unwrap(), testing::video());
+        assert!(
+            server
+                .requests()
+                .iter()
+                .any(|request| request.contains("export"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_export_retries() {
+        let test = TestContext::new("max-export-retries = 2\nexport-retry-delay = \"0s\"").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let database = &context.database;
+        database
+            .insert_event(&testing::event("event", start, start + 10_000))
+            .await
+            .unwrap();
+
+        // the NVR never has the export ready
+        poller.poll().await.unwrap();
+        let event = database.get_event_by_id("event").await.unwrap().unwrap();
+        assert_eq!(event.skip_reason, None);
+        poller.poll().await.unwrap();
+        let event = database.get_event_by_id("event").await.unwrap().unwrap();
+        assert_eq!(event.skip_reason.as_deref(), Some("failed"));
+        assert_eq!(test.protect.requested_exports().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_give_up() {
+        let test = TestContext::new("max-event-attempts = 2").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let event = testing::event("event", start, start + 10_000);
+        context.database.insert_event(&event).await.unwrap();
+
+        let mut given_up = vec![];
+        poller.attempt_failed(&event, "upload failed".to_string(), &mut given_up);
+        assert!(given_up.is_empty());
+        assert_eq!(poller.attempts["event"], 1);
+        poller.attempt_failed(&event, "upload failed".to_string(), &mut given_up);
+        assert_eq!(given_up.len(), 1);
+        assert!(!poller.attempts.contains_key("event"));
+
+        poller.give_up(given_up).await.unwrap();
+        let event = context.database.get_event_by_id("event").await.unwrap();
+        assert_eq!(
+            event.unwrap().skip_reason,
+            Some(SkipReason::Failed.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failure_rates() {
+        let test = TestContext::new("failure-rate-alert = 0.5\nfailure-rate-window = 4").await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+
+        // nothing's judged until the window is full
+        for failed in [true, true, false] {
+            poller.record_upload("target", failed);
+        }
+        poller.check_failure_rates().await;
+        assert!(poller.failing_targets.is_empty());
+
+        poller.record_upload("target", false);
+        poller.check_failure_rates().await;
+        assert!(poller.failing_targets.contains("target"));
+
+        // only the last uploads in the window count
+        poller.record_upload("target", false);
+        assert_eq!(poller.upload_outcomes["target"].len(), 4);
+        poller.check_failure_rates().await;
+        assert!(poller.failing_targets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backlog() {
+        let test = TestContext::new(r#"nvr-retention = "4d""#).await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let database = &context.database;
+        let hours_ago = |hours| (Utc::now() - chrono::Duration::hours(hours)).timestamp_millis();
+        for (id, start) in [("newer", hours_ago(1)), ("older", hours_ago(2))] {
+            database
+                .insert_event(&testing::event(id, start, start + 10_000))
+                .await
+                .unwrap();
+        }
+
+        // oldest first
+        let pending = database.get_events_not_backed_up().await.unwrap();
+        let ids: Vec<_> = pending.iter().map(|event| event.id.as_str()).collect();
+        assert_eq!(ids, vec!["older", "newer"]);
+
+        poller.check_backlog(&pending).await;
+        let metrics = &context.metrics.backlog;
+        assert_eq!(metrics.events.load(Ordering::Relaxed), 2);
+        let age = metrics.oldest_age_seconds.load(Ordering::Relaxed);
+        assert!((2 * 60 * 60..3 * 60 * 60).contains(&age));
+        assert!(!poller.backlog_alerted);
+
+        // three of the NVR's four days in, until it's caught up on
+        let start = hours_ago(3 * 24);
+        database
+            .insert_event(&testing::event("oldest", start, start + 10_000))
+            .await
+            .unwrap();
+        let pending = database.get_events_not_backed_up().await.unwrap();
+        assert_eq!(pending[0].id, "oldest");
+        poller.check_backlog(&pending).await;
+        assert!(poller.backlog_alerted);
+        poller.check_backlog(&pending[1..]).await;
+        assert!(!poller.backlog_alerted);
+    }
+
+    #[tokio::test]
+    async fn test_skip_missing() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let mut config = context.backup_config.load().as_ref().clone();
+        config.skip_missing = true;
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let database = &context.database;
+
+        // the NVR never has footage for either, but only one ended over `missing-after` ago
+        for (id, hours) in [("missing", 2), ("recent", 0)] {
+            let end = (Utc::now() - chrono::Duration::hours(hours)).timestamp_millis() - 60_000;
+            database
+                .insert_event(&testing::event(id, end - 10_000, end))
+                .await
+                .unwrap();
+        }
+        poller.poll().await.unwrap();
+
+        let missing = database.get_event_by_id("missing").await.unwrap().unwrap();
+        assert_eq!(missing.skip_reason, Some(SkipReason::Missing.to_string()));
+        let recent = database.get_event_by_id("recent").await.unwrap().unwrap();
+        assert_eq!(recent.skip_reason, None);
+        assert!(poller.deferred.contains_key("recent"));
+    }
+
+    #[tokio::test]
+    async fn test_aged_out() {
+        let test = TestContext::new(r#"nvr-retention = "30d""#).await;
+        let context = &test.context;
+        let config = context.backup_config.load().as_ref().clone();
+        let mut poller = BackupDbPoller::new(context.clone(), config);
+        let start = (Utc::now() - chrono::Duration::days(60)).timestamp_millis();
+        let database = &context.database;
+
+        // past the NVR's retention, and the NVR can't find its export
+        database
+            .insert_event(&testing::event("lost", start, start + 10_000))
+            .await
+            .unwrap();
+        poller.poll().await.unwrap();
+        let lost = database.get_event_by_id("lost").await.unwrap().unwrap();
+        assert_eq!(lost.skip_reason.as_deref(), Some("aged_out"));
+
+        // past it too, but the NVR still has the footage
+        test.protect.set_export(CAMERA_ID, testing::video());
+        database
+            .insert_event(&testing::event("kept", start, start + 10_000))
+            .await
+            .unwrap();
+        poller.poll().await.unwrap();
+        let kept = database.get_event_by_id("kept").await.unwrap().unwrap();
+        assert!(kept.backed_up);
+        assert_eq!(kept.skip_reason, None);
+    }
 }
@@ -0,0 +1,128 @@
+use std::{
+    sync::{Arc, atomic::Ordering},
+    time::Instant,
+};
+
+use chrono::Utc;
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use crate::{Result, backup::Backup, context::Context};
+
+/// Where the canary written by each health check goes on a backup target, relative to its base
+pub const CANARY_PATH: &str = ".unifi-protect-backup-health";
+
+/// Periodically writes and deletes a small canary file on each backup target, and checks each
+/// archive target is reachable, so an outage shows in `/status` and the metrics before events
+/// queue up for it.
+pub struct HealthChecker {
+    context: Arc<Context>,
+    config: crate::backup::Config,
+}
+
+impl HealthChecker {
+    pub fn new(context: Arc<Context>, config: crate::backup::Config) -> Self {
+        Self { context, config }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let Some(check_interval) = self.config.health_check_interval else {
+            return std::future::pending().await;
+        };
+
+        info!("Starting Health Checker");
+
+        let mut interval = interval(check_interval);
+
+        loop {
+            interval.tick().await;
+
+            let status = &self.context.status.health_checker;
+            let backup_targets = self.context.backup_targets.load_full();
+            status.running(backup_targets.len() + self.context.archive_targets.len());
+
+            let mut failed = 0;
+            for (done, target) in backup_targets.iter().enumerate() {
+                status.progress(done);
+                let started = Instant::now();
+                // a dry run writes nothing, so only checks the target is reachable
+                let result = match self.config.dry_run {
+                    true => target.validate().await,
+                    false => write_canary(target.as_ref()).await,
+                };
+                if !self.record(&target.name(), started, result).await {
+                    failed += 1;
+                }
+            }
+            for (done, target) in self.context.archive_targets.iter().enumerate() {
+                status.progress(backup_targets.len() + done);
+                let started = Instant::now();
+                let result = target.validate().await;
+                if !self.record(&target.name(), started, result).await {
+                    failed += 1;
+                }
+            }
+
+            if failed > 0 {
+                status.backoff(
+                    format!("{failed} target(s) failed their health check"),
+                    check_interval,
+                );
+            } else {
+                status.waiting(check_interval);
+            }
+        }
+    }
+
+    /// Record the outcome of a target's check, alerting when it becomes unavailable and when it
+    /// recovers. Returns whether the check succeeded.
+    async fn record(&self, target: &str, started: Instant, result: Result<()>) -> bool {
+        let health = &self.context.status.target_health;
+        let metrics = &self.context.metrics.health_check;
+        metrics.checks.fetch_add(1, Ordering::Relaxed);
+
+        match result {
+            Ok(()) => {
+                let latency = started.elapsed();
+                metrics
+                    .last_latency_ms
+                    .store(latency.as_millis() as u64, Ordering::Relaxed);
+                debug!(target, latency = ?latency, "Target passed its health check");
+
+                if health.succeeded(target, latency) {
+                    info!(target, "Target passed its health check again");
+                    metrics.unavailable.fetch_sub(1, Ordering::Relaxed);
+                    self.context
+                        .notify(
+                            &format!("Target {target} available"),
+                            &format!("{target} passed its health check again."),
+                        )
+                        .await;
+                }
+                true
+            }
+            Err(err) => {
+                warn!(err = ?err, target, "Target failed its health check");
+                metrics.failures.fetch_add(1, Ordering::Relaxed);
+
+                if health.failed(target, &err) {
+                    metrics.unavailable.fetch_add(1, Ordering::Relaxed);
+                    self.context
+                        .notify(
+                            &format!("Target {target} unavailable"),
+                            &format!("{target} failed its health check: {err}"),
+                        )
+                        .await;
+                }
+                false
+            }
+        }
+    }
+}
+
+/// Write the canary to `target` and delete it again
+async fn write_canary(target: &dyn Backup) -> Result<()> {
+    let contents = Utc::now().to_rfc3339();
+    target.upload(CANARY_PATH, contents.as_bytes()).await?;
+    target.delete(CANARY_PATH).await
+}
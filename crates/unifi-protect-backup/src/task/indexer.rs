@@ -0,0 +1,192 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::DateTime;
+use tokio::time::interval;
+use tracing::{info, warn};
+use unifi_protect_data::Event;
+
+use crate::{
+    Result,
+    backup::{
+        Backup,
+        compress::is_compressed,
+        index::{self, INDEX_PATH, IndexEntry},
+        snapshot::{SNAPSHOT_EXTENSION, SnapshotMode, snapshot_path},
+    },
+    context::Context,
+};
+
+/// Periodically uploads a static `index.html` to each backup target, listing its backups by
+/// camera and day, so footage can be browsed from a web browser or static site without a server.
+pub struct Indexer {
+    context: Arc<Context>,
+    config: crate::backup::Config,
+}
+
+impl Indexer {
+    pub fn new(context: Arc<Context>, config: crate::backup::Config) -> Self {
+        Self { context, config }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let Some(index_interval) = self.config.html_index_interval else {
+            return std::future::pending().await;
+        };
+
+        info!("Starting Indexer");
+
+        let mut interval = interval(index_interval);
+
+        loop {
+            interval.tick().await;
+            self.config = self.context.backup_config.load().as_ref().clone();
+
+            let status = &self.context.status.indexer;
+            let backup_targets = self.context.backup_targets.load_full();
+            status.running(backup_targets.len());
+
+            match self.index(&backup_targets).await {
+                Ok(0) => status.waiting(index_interval),
+                Ok(failed) => status.backoff(
+                    format!("Failed to upload the index to {failed} target(s)"),
+                    index_interval,
+                ),
+                Err(err) => {
+                    warn!(err = ?err, "Failed to build the index");
+                    status.backoff(err, index_interval);
+                }
+            }
+        }
+    }
+
+    /// Upload each target's index, returning how many uploads failed
+    async fn index(&self, backup_targets: &[Arc<dyn Backup>]) -> Result<usize> {
+        let events: HashMap<String, Event> = self
+            .context
+            .database
+            .get_events()
+            .await?
+            .into_iter()
+            .map(|event| (event.id.clone(), event))
+            .collect();
+        let backups = self.context.database.get_backups().await?;
+        let mut camera_names: HashMap<String, String> = HashMap::new();
+
+        let mut failed = 0;
+        for (done, target) in backup_targets.iter().enumerate() {
+            self.context.status.indexer.progress(done);
+            let name = target.name();
+            let snapshots = target.backup_config().snapshots;
+
+            let mut entries = Vec::new();
+            for backup in backups.iter().filter(|backup| backup.target == name) {
+                let Some(event) = events.get(&backup.event_id) else {
+                    continue;
+                };
+                let camera = match camera_names.get(&event.camera_id) {
+                    Some(camera) => camera.clone(),
+                    None => {
+                        let camera = self
+                            .context
+                            .camera_name(&event.camera_id)
+                            .await?
+                            .unwrap_or_else(|| event.camera_id.clone());
+                        camera_names.insert(event.camera_id.clone(), camera.clone());
+                        camera
+                    }
+                };
+                // a snapshot is its own thumbnail, and with video only the first part has one
+                let thumbnail = if backup.remote_path.ends_with(SNAPSHOT_EXTENSION) {
+                    Some(backup.remote_path.clone())
+                } else if snapshots == SnapshotMode::WithVideo && backup.part <= 1 {
+                    Some(snapshot_path(&backup.remote_path))
+                } else {
+                    None
+                };
+                entries.push(IndexEntry {
+                    camera,
+                    start_time: DateTime::from_timestamp_millis(event.start_time)
+                        .unwrap_or_default(),
+                    event_type: event.event_type.clone(),
+                    part: backup.part,
+                    path: backup.remote_path.clone(),
+                    compressed: is_compressed(&backup.remote_path),
+                    thumbnail,
+                });
+            }
+
+            let html = index::render(&entries, self.context.clock.now());
+            if self.config.dry_run {
+                info!(
+                    target = name,
+                    backups = entries.len(),
+                    "Dry run, not uploading the index"
+                );
+                continue;
+            }
+            match target.upload(INDEX_PATH, html.as_bytes()).await {
+                Ok(()) => info!(target = name, backups = entries.len(), "Uploaded the index"),
+                Err(err) => {
+                    warn!(err = ?err, target = name, "Failed to upload the index");
+                    failed += 1;
+                }
+            }
+        }
+
+        Ok(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use unifi_protect_client::mock::MockProtectClient;
+    use unifi_protect_data::Backup as BackupRecord;
+
+    use super::*;
+    use crate::{clock::ManualClock, testing};
+
+    #[tokio::test]
+    async fn test_index() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("backups")).unwrap();
+        let protect = Arc::new(MockProtectClient::new(testing::bootstrap()));
+        let mut context = Context::with_client(testing::config(&dir, ""), protect)
+            .await
+            .unwrap();
+        let now = Utc.with_ymd_and_hms(2025, 8, 4, 12, 0, 0).unwrap();
+        context.clock = Arc::new(ManualClock::new(now));
+        let context = Arc::new(context);
+
+        let start = now.timestamp_millis();
+        context
+            .database
+            .insert_event(&testing::event("event", start, start + 10_000))
+            .await
+            .unwrap();
+        let target = context.backup_targets.load()[0].clone();
+        context
+            .database
+            .insert_backup(&BackupRecord {
+                event_id: "event".to_string(),
+                target: target.name(),
+                part: 0,
+                remote_path: "event.mp4.zst".to_string(),
+                backup_time: now,
+                size_bytes: 5,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+
+        let indexer = Indexer::new(
+            context.clone(),
+            context.backup_config.load().as_ref().clone(),
+        );
+        assert_eq!(indexer.index(&[target]).await.unwrap(), 0);
+
+        let html = std::fs::read_to_string(dir.path().join("backups").join(INDEX_PATH)).unwrap();
+        assert!(html.contains("listed 2025-08-04 12:00:00 UTC"));
+        assert!(html.contains("href=\"event.mp4.zst\" download>"));
+    }
+}
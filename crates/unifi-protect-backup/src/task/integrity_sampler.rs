@@ -0,0 +1,277 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use tokio::time::interval;
+use tracing::{info, warn};
+use unifi_protect_data::Backup as BackupRecord;
+
+use crate::{
+    Result, backup::snapshot::SNAPSHOT_EXTENSION, context::Context, task::segments,
+    validate::mp4_duration,
+};
+
+/// Periodically compares how long a sample of recent backups actually play for with how long
+/// their events lasted on the NVR, catching exports that firmware updates have started to
+/// truncate.
+pub struct IntegritySampler {
+    context: Arc<Context>,
+    config: crate::backup::Config,
+}
+
+/// How far the stored duration of sampled backups drifted from their events, by target
+#[derive(Debug, Default)]
+pub struct DriftReport {
+    pub targets: BTreeMap<String, TargetDrift>,
+}
+
+#[derive(Debug, Default)]
+pub struct TargetDrift {
+    pub sampled: usize,
+    /// Copies which couldn't be read, have no duration in their header or whose event is gone
+    pub unprobed: usize,
+    /// Stored minus expected duration in milliseconds, summed over the probed copies
+    pub total_drift_ms: i64,
+    /// Copies shorter than their event by more than the tolerance, with the expected and stored
+    /// durations in milliseconds
+    pub truncated: Vec<(BackupRecord, i64, i64)>,
+}
+
+impl TargetDrift {
+    pub fn mean_drift_ms(&self) -> i64 {
+        match self.sampled - self.unprobed {
+            0 => 0,
+            probed => self.total_drift_ms / probed as i64,
+        }
+    }
+}
+
+impl DriftReport {
+    pub fn truncated(&self) -> usize {
+        self.targets
+            .values()
+            .map(|drift| drift.truncated.len())
+            .sum()
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for (target, drift) in &self.targets {
+            report.push_str(&format!(
+                "{target}: {} sampled, mean drift {:+.1}s, {} truncated, {} unprobed\n",
+                drift.sampled,
+                drift.mean_drift_ms() as f64 / 1000.0,
+                drift.truncated.len(),
+                drift.unprobed
+            ));
+        }
+        for drift in self.targets.values() {
+            for (backup, expected_ms, stored_ms) in &drift.truncated {
+                report.push_str(&format!(
+                    "truncated: {} part {} on {} at {} ({:.1}s of {:.1}s)\n",
+                    backup.event_id,
+                    backup.part,
+                    backup.target,
+                    backup.remote_path,
+                    *stored_ms as f64 / 1000.0,
+                    *expected_ms as f64 / 1000.0
+                ));
+            }
+        }
+        report
+    }
+}
+
+impl IntegritySampler {
+    pub fn new(context: Arc<Context>, config: crate::backup::Config) -> Self {
+        Self { context, config }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let Some(sample_interval) = self.config.integrity_sample_interval else {
+            return std::future::pending().await;
+        };
+
+        info!("Starting Integrity Sampler");
+
+        let mut interval = interval(sample_interval);
+        // there's nothing new to sample at startup
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let status = &self.context.status.integrity_sampler;
+            let since = self.context.clock.ago(sample_interval);
+            let report = async {
+                let samples = pick_samples(&self.context, &self.config, since).await?;
+                status.running(samples.len());
+                sample_drift(&self.context, &self.config, samples, |done| {
+                    status.progress(done)
+                })
+                .await
+            };
+            let report = match report.await {
+                Ok(report) => report,
+                Err(err) => {
+                    warn!(err = ?err, "Failed to sample backups");
+                    status.backoff(err, sample_interval);
+                    continue;
+                }
+            };
+
+            for (target, drift) in &report.targets {
+                info!(
+                    target,
+                    sampled = drift.sampled,
+                    mean_drift_ms = drift.mean_drift_ms(),
+                    truncated = drift.truncated.len(),
+                    unprobed = drift.unprobed,
+                    "Sampled backup durations"
+                );
+            }
+
+            if report.truncated() > 0 {
+                self.context
+                    .notify("Sampled backups are truncated", &report.report())
+                    .await;
+                status.backoff(
+                    format!("{} sampled backup(s) truncated", report.truncated()),
+                    sample_interval,
+                );
+            } else {
+                status.waiting(sample_interval);
+            }
+        }
+    }
+}
+
+/// Up to `integrity-sample-size` backups made since `since` on each target, spread evenly over
+/// that time
+pub(crate) async fn pick_samples(
+    context: &Context,
+    config: &crate::backup::Config,
+    since: DateTime<Utc>,
+) -> Result<Vec<BackupRecord>> {
+    let mut by_target: BTreeMap<String, Vec<BackupRecord>> = BTreeMap::new();
+    for backup in context.database.get_backups().await? {
+        // snapshots taken in place of videos have no duration to compare
+        if backup.backup_time >= since && !backup.remote_path.ends_with(SNAPSHOT_EXTENSION) {
+            by_target
+                .entry(backup.target.clone())
+                .or_default()
+                .push(backup);
+        }
+    }
+
+    Ok(by_target
+        .into_values()
+        .flat_map(|mut backups| {
+            backups.sort_by_key(|backup| backup.backup_time);
+            spread(backups, config.integrity_sample_size)
+        })
+        .collect())
+}
+
+/// Download each sampled backup and compare the duration in its header with its event part's,
+/// calling `progress` with the number of backups done so far.
+pub(crate) async fn sample_drift(
+    context: &Context,
+    config: &crate::backup::Config,
+    samples: Vec<BackupRecord>,
+    progress: impl Fn(usize),
+) -> Result<DriftReport> {
+    let tolerance_ms = config.integrity_drift_tolerance.as_millis() as i64;
+    let mut report = DriftReport::default();
+    let backup_targets = context.backup_targets.load_full();
+    for (done, backup) in samples.into_iter().enumerate() {
+        progress(done);
+
+        let Some(target) = backup_targets
+            .iter()
+            .find(|target| target.name() == backup.target)
+        else {
+            continue;
+        };
+        let drift = report.targets.entry(backup.target.clone()).or_default();
+        drift.sampled += 1;
+
+        let Some(expected_ms) = expected_duration_ms(context, config, &backup).await? else {
+            drift.unprobed += 1;
+            continue;
+        };
+        let stored = match target.download(&backup.remote_path).await {
+            Ok(video_data) => mp4_duration(&video_data),
+            Err(err) => {
+                warn!(
+                    err = ?err,
+                    event_id = backup.event_id,
+                    target = backup.target,
+                    "Failed to download sampled backup"
+                );
+                None
+            }
+        };
+        let Some(stored) = stored else {
+            drift.unprobed += 1;
+            continue;
+        };
+
+        let stored_ms = stored.as_millis() as i64;
+        drift.total_drift_ms += stored_ms - expected_ms;
+        if expected_ms - stored_ms > tolerance_ms {
+            warn!(
+                event_id = backup.event_id,
+                target = backup.target,
+                remote_path = backup.remote_path,
+                expected_ms,
+                stored_ms,
+                "Sampled backup is shorter than its event"
+            );
+            drift.truncated.push((backup, expected_ms, stored_ms));
+        }
+    }
+
+    Ok(report)
+}
+
+/// How long the backed up part of the event lasted on the NVR, if the event is still known
+async fn expected_duration_ms(
+    context: &Context,
+    config: &crate::backup::Config,
+    backup: &BackupRecord,
+) -> Result<Option<i64>> {
+    let Some(event) = context.database.get_event_by_id(&backup.event_id).await? else {
+        return Ok(None);
+    };
+    let Some(end_time) = event.end_time else {
+        return Ok(None);
+    };
+
+    if backup.part == 0 {
+        return Ok(Some(end_time - event.start_time));
+    }
+    let camera_name = context.camera_name(&event.camera_id).await?;
+    let max_event_length = config.max_event_length(&event.camera_id, camera_name.as_deref());
+    Ok(segments(event.start_time, end_time, max_event_length)
+        .get(backup.part as usize - 1)
+        .map(|(start, end)| end - start))
+}
+
+/// At most `count` of `items`, spread evenly through them
+fn spread<T>(items: Vec<T>, count: usize) -> Vec<T> {
+    let len = items.len();
+    if len <= count {
+        return items;
+    }
+
+    let picked: HashSet<_> = (0..count).map(|index| index * len / count).collect();
+    items
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| picked.contains(index))
+        .map(|(_, item)| item)
+        .collect()
+}
@@ -1,16 +1,35 @@
-use crate::Result;
+use crate::{Result, clock::Clock, retention::RetentionPolicy};
 
 mod archiver;
+mod bootstrap_refresher;
+mod camera_monitor;
+mod database_maintenance;
 mod db_poller;
+mod health_checker;
+mod indexer;
+mod integrity_sampler;
 mod pruner;
+mod reconciler;
+mod reporter;
 mod unifi_event_listener;
+mod verifier;
 
 pub use archiver::*;
+pub use bootstrap_refresher::*;
+pub use camera_monitor::*;
+pub use database_maintenance::*;
 pub use db_poller::*;
+pub use health_checker::*;
+pub use indexer::*;
+pub use integrity_sampler::*;
 pub use pruner::*;
+pub use reconciler::*;
+pub use reporter::*;
 pub use unifi_event_listener::*;
+pub use verifier::*;
 
 #[async_trait::async_trait]
 pub trait Prune {
-    async fn prune(&self) -> Result<()>;
+    /// Delete whatever `policy` no longer keeps
+    async fn prune(&self, policy: &RetentionPolicy, clock: &dyn Clock) -> Result<()>;
 }
@@ -1,9 +1,24 @@
+use chrono::{DateTime, Utc};
 use futures_util::future::join_all;
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 use tokio::time::interval;
 use tracing::{info, warn};
+use unifi_protect_client::events::EventType;
+use unifi_protect_data::Backup as BackupRecord;
 
-use crate::{Result, context::Context};
+use crate::{
+    Error, Result,
+    backup::{
+        Backup, STRAY_PARTIAL_AGE,
+        sidecar::sidecar_path,
+        snapshot::{SnapshotMode, snapshot_path},
+    },
+    context::Context,
+    retention::{Candidate, RetentionPolicy, detection_types},
+};
 
 pub struct Pruner {
     context: Arc<Context>,
@@ -21,29 +36,675 @@ impl Pruner {
         let mut interval = interval(self.config.purge_interval);
 
         loop {
-            interval.tick().await;
-
-            let futs = self
-                .context
-                .backup_targets
-                .as_slice()
-                .iter()
-                .map(|e| e.prune())
-                .chain(
-                    self.context
-                        .archive_targets
-                        .as_slice()
-                        .iter()
-                        .map(|e| e.prune()),
-                );
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.context.event_deleted.notified() => {
+                    self.config = self.context.backup_config.load().as_ref().clone();
+                    if let Err(err) = self.mirror_deletions().await {
+                        warn!(err = ?err, "Failed to delete backups of events deleted on the NVR");
+                    }
+                    continue;
+                }
+            }
+
+            // as of the latest config, if it was reloaded since the last prune
+            self.config = self.context.backup_config.load().as_ref().clone();
+            let backup_retention = self.context.backup_retention.load_full();
+            let policy = backup_retention.as_ref();
+            let clock = self.context.clock.as_ref();
 
-            let results = join_all(futs).await;
+            let status = &self.context.status.pruner;
+            // the backups, those of deleted events, the size caps, stray partial uploads, then
+            // each archive target
+            status.running(4 + self.context.archive_targets.len());
 
+            let min_copies = self.config.min_copies.unwrap_or(0);
+            let mut results = vec![
+                self.prune_backups(policy, min_copies).await,
+                self.mirror_deletions().await,
+            ];
+            if self.config.prune_dry_run {
+                info!("Dry run: not enforcing size caps, pruning archives or cleaning up events");
+            } else {
+                let archive_retention = self.context.archive_retention.load_full();
+                results.push(self.enforce_max_sizes(policy).await);
+                results.push(self.delete_stray_partials().await);
+                results.extend(
+                    join_all(
+                        self.context
+                            .archive_targets
+                            .as_slice()
+                            .iter()
+                            .map(|e| e.prune(&archive_retention, clock)),
+                    )
+                    .await,
+                );
+                self.cleanup_database(policy).await;
+            }
+            status.progress(results.len());
+
+            let mut last_error = None;
             for result in results {
                 if let Err(err) = result {
                     warn!(err = ?err, "Failed to prune backup");
+                    last_error = Some(err.to_string());
+                }
+            }
+
+            match last_error {
+                Some(err) => status.backoff(err, self.config.purge_interval),
+                None => status.waiting(self.config.purge_interval),
+            }
+        }
+    }
+
+    /// Forget the failures and events `policy` no longer needs
+    async fn cleanup_database(&self, policy: &RetentionPolicy) {
+        let clock = self.context.clock.as_ref();
+        let database = &self.context.database;
+        if let Err(err) = database
+            .cleanup_old_failures(clock.ago(policy.max_age))
+            .await
+        {
+            warn!(err = ?err, "Failed to clean up old failures");
+        }
+
+        // events are kept for as long as any of their copies could be
+        let holds: Vec<_> = policy.holds.iter().cloned().collect();
+        let camera_cutoffs = self.camera_cutoffs(policy);
+        if let Err(err) = database
+            .cleanup_old_events(clock.ago(policy.longest_age()), &camera_cutoffs, &holds)
+            .await
+        {
+            warn!(err = ?err, "Failed to clean up old events");
+        }
+    }
+
+    /// The cutoff for events from each camera with a retention override of its own, by camera
+    /// id. Overrides naming a camera that's no longer on the NVR only apply by id.
+    fn camera_cutoffs(&self, policy: &RetentionPolicy) -> Vec<(String, DateTime<Utc>)> {
+        let clock = self.context.clock.as_ref();
+        let bootstrap = self.context.protect_bootstrap.load();
+
+        let mut cutoffs: HashMap<_, _> = bootstrap
+            .cameras
+            .values()
+            .filter_map(|camera| {
+                let age =
+                    policy.longest_age_for_camera(&[camera.id.clone(), camera.name.clone()])?;
+                Some((camera.id.clone(), clock.ago(age)))
+            })
+            .collect();
+        for camera in policy.camera_overrides.keys() {
+            if let Some(age) = policy.longest_age_for_camera(std::slice::from_ref(camera)) {
+                cutoffs
+                    .entry(camera.clone())
+                    .or_insert_with(|| clock.ago(age));
+            }
+        }
+
+        cutoffs.into_iter().collect()
+    }
+
+    /// Keep each target within its size cap, if it has one, forgetting the backups deleted to do
+    /// so. Runs after retention, so only what it left is pruned further. A target that fails is
+    /// logged and the rest are still kept within theirs.
+    #[tracing::instrument(skip(self, policy))]
+    async fn enforce_max_sizes(&self, policy: &RetentionPolicy) -> Result<()> {
+        let mut failed = 0;
+        for target in self.context.backup_targets.load_full().iter() {
+            if let Err(err) = self.enforce_max_size(target.as_ref(), policy).await {
+                warn!(err = ?err, target = target.name(), "Failed to enforce target's max-size");
+                failed += 1;
+            }
+        }
+
+        match failed {
+            0 => Ok(()),
+            _ => Err(Error::Backup(format!(
+                "Failed to enforce max-size on {failed} targets"
+            ))),
+        }
+    }
+
+    /// Keep one target within its size cap, see [`enforce_max_sizes`](Self::enforce_max_sizes)
+    async fn enforce_max_size(&self, target: &dyn Backup, policy: &RetentionPolicy) -> Result<()> {
+        let database = &self.context.database;
+        let deleted: HashSet<_> = target.enforce_max_size(policy).await?.into_iter().collect();
+        if deleted.is_empty() {
+            return Ok(());
+        }
+
+        let name = target.name();
+        for backup in database.get_backups().await? {
+            if backup.target == name && deleted.contains(&backup.remote_path) {
+                database
+                    .delete_backup(&backup.event_id, &backup.target, backup.part)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete what's left of uploads interrupted more than [`STRAY_PARTIAL_AGE`] ago, which will
+    /// never be resumed
+    async fn delete_stray_partials(&self) -> Result<()> {
+        let older_than = self.context.clock.ago(STRAY_PARTIAL_AGE);
+        for target in self.context.backup_targets.load_full().iter() {
+            let partials = target.stray_partials(older_than).await?;
+            if partials.is_empty() {
+                continue;
+            }
+
+            warn!(
+                target = target.name(),
+                partials = ?partials,
+                "Deleting stray partial uploads"
+            );
+            target.delete_many(&partials).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Delete the backups `policy` no longer keeps, as recorded in the database, applying it to
+    /// each target's copies in turn. A copy is only deleted while at least `min_copies` other
+    /// copies of the same event part are still kept and present on their targets; once every
+    /// copy has expired they're all deleted, remote targets before local ones. With `min_copies`
+    /// of zero, every expired copy is deleted.
+    ///
+    /// Each target's expired copies are deleted in one batch, and their records only once the
+    /// whole batch is gone, in one transaction. A batch that fails keeps its records, so the next
+    /// prune deletes exactly the same paths again, which is harmless for those already gone.
+    /// With `prune-dry-run`, the paths are only logged.
+    #[tracing::instrument(skip(self, policy))]
+    async fn prune_backups(&self, policy: &RetentionPolicy, min_copies: u32) -> Result<()> {
+        let database = &self.context.database;
+        let backup_targets = self.context.backup_targets.load_full();
+        let targets: HashMap<_, _> = backup_targets
+            .iter()
+            .map(|target| (target.name(), target))
+            .collect();
+
+        let mut expired_parts: BTreeMap<(String, u32), Vec<BackupRecord>> = BTreeMap::new();
+        for backup in expired_backups(&self.context, policy).await? {
+            expired_parts
+                .entry((backup.event_id.clone(), backup.part))
+                .or_default()
+                .push(backup);
+        }
+
+        let mut kept = 0;
+        let mut by_target: HashMap<String, Vec<BackupRecord>> = HashMap::new();
+        for ((event_id, part), expired) in expired_parts {
+            let mut retained: Vec<_> = database
+                .get_backups_by_event(&event_id)
+                .await?
+                .into_iter()
+                .filter(|backup| {
+                    backup.part == part && !expired.iter().any(|copy| copy.target == backup.target)
+                })
+                .collect();
+
+            if !retained.is_empty() {
+                // local copies are the cheapest to check
+                retained.sort_by_key(|backup| {
+                    std::cmp::Reverse(targets.get(&backup.target).map(|t| t.failure_domain()))
+                });
+                let verified = verified_copies(&targets, &retained, min_copies).await;
+                if verified < min_copies {
+                    warn!(
+                        event_id,
+                        part,
+                        verified,
+                        min_copies,
+                        "Keeping expired copies, too few copies of the event would remain"
+                    );
+                    kept += expired.len();
+                    continue;
+                }
+            }
+
+            for backup in expired {
+                by_target
+                    .entry(backup.target.clone())
+                    .or_default()
+                    .push(backup);
+            }
+        }
+
+        let (deleted, batch_kept, failed) = self
+            .delete_backups(by_target, self.config.prune_dry_run)
+            .await?;
+        kept += batch_kept;
+
+        info!(deleted, kept, failed, "Pruned expired backups");
+        Ok(())
+    }
+
+    /// With `mirror-deletions`, delete the backups of events deleted on the NVR. One that fails
+    /// to delete keeps its record, so it's tried again on the next pass.
+    #[tracing::instrument(skip(self))]
+    async fn mirror_deletions(&self) -> Result<()> {
+        if !self.config.mirror_deletions {
+            return Ok(());
+        }
+
+        let mut by_target: HashMap<String, Vec<BackupRecord>> = HashMap::new();
+        for backup in self
+            .context
+            .database
+            .get_backups_of_deleted_events()
+            .await?
+        {
+            by_target
+                .entry(backup.target.clone())
+                .or_default()
+                .push(backup);
+        }
+        if by_target.is_empty() {
+            return Ok(());
+        }
+
+        let (deleted, kept, failed) = self.delete_backups(by_target, self.config.dry_run).await?;
+        info!(
+            deleted,
+            kept, failed, "Deleted backups of events deleted on the NVR"
+        );
+        Ok(())
+    }
+
+    /// Delete `by_target`'s backups from each target, and their records in one transaction once
+    /// the target's whole batch is gone. Remote targets go first, so a run that stops part way
+    /// leaves the local copies. With `dry_run`, the paths are only logged. Returns how many
+    /// backups were deleted, kept and failed to delete.
+    async fn delete_backups(
+        &self,
+        by_target: HashMap<String, Vec<BackupRecord>>,
+        dry_run: bool,
+    ) -> Result<(usize, usize, usize)> {
+        let database = &self.context.database;
+        let backup_targets = self.context.backup_targets.load_full();
+        let targets: HashMap<_, _> = backup_targets
+            .iter()
+            .map(|target| (target.name(), target))
+            .collect();
+
+        let mut deleted = 0;
+        let mut kept = 0;
+        let mut failed = 0;
+
+        let mut by_target: Vec<_> = by_target.into_iter().collect();
+        by_target.sort_by_key(|(name, _)| targets.get(name).map(|t| t.failure_domain()));
+        for (name, backups) in by_target {
+            let Some(target) = targets.get(&name) else {
+                warn!(
+                    target = name,
+                    backups = backups.len(),
+                    "Backup target is no longer configured, not deleting"
+                );
+                kept += backups.len();
+                continue;
+            };
+            let mut paths: Vec<_> = backups.iter().map(|b| b.remote_path.clone()).collect();
+            if self.config.sidecars {
+                paths.extend(backups.iter().map(|b| sidecar_path(&b.remote_path)));
+            }
+            if self.config.snapshots == SnapshotMode::WithVideo {
+                paths.extend(backups.iter().map(|b| snapshot_path(&b.remote_path)));
+            }
+
+            if dry_run {
+                for path in &paths {
+                    info!(target = name, path, "Dry run: would delete");
                 }
+                kept += backups.len();
+                continue;
+            }
+
+            match target.delete_many(&paths).await {
+                Ok(()) => {
+                    database.delete_backups(&backups).await?;
+                    deleted += backups.len();
+                }
+                Err(err) => {
+                    warn!(
+                        err = ?err,
+                        target = name,
+                        backups = backups.len(),
+                        "Failed to delete backups"
+                    );
+                    failed += backups.len();
+                }
+            }
+        }
+
+        Ok((deleted, kept, failed))
+    }
+}
+
+/// The recorded backups `policy` no longer keeps, applying it to each target's copies in turn.
+pub(crate) async fn expired_backups(
+    context: &Context,
+    policy: &RetentionPolicy,
+) -> Result<Vec<BackupRecord>> {
+    let database = &context.database;
+
+    // only needed to look up type and camera overrides
+    let mut event_types = HashMap::new();
+    let mut event_cameras = HashMap::new();
+    if !policy.type_overrides.is_empty() || !policy.camera_overrides.is_empty() {
+        let bootstrap = context.protect_bootstrap.load();
+        for event in database.get_events().await? {
+            let event_type = event.event_type.parse().unwrap_or(EventType::Motion);
+            let smart_detect_types: Vec<_> = event
+                .smart_detect_types
+                .split(',')
+                .filter_map(|t| t.parse().ok())
+                .collect();
+            let camera_name = bootstrap
+                .cameras
+                .get(&event.camera_id)
+                .map(|camera| camera.name.clone());
+            event_types.insert(
+                event.id.clone(),
+                detection_types(&event_type, &smart_detect_types),
+            );
+            event_cameras.insert(
+                event.id,
+                [Some(event.camera_id), camera_name]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            );
+        }
+    }
+
+    let mut by_target: HashMap<String, Vec<BackupRecord>> = HashMap::new();
+    for backup in database.get_backups().await? {
+        by_target
+            .entry(backup.target.clone())
+            .or_default()
+            .push(backup);
+    }
+
+    let mut expired = vec![];
+    for backups in by_target.into_values() {
+        expired.extend(policy.expired(context.clock.as_ref(), backups, |backup| {
+            Candidate {
+                names: vec![backup.event_id.clone(), backup.remote_path.clone()],
+                time: backup.backup_time,
+                size_bytes: backup.size_bytes,
+                detection_types: event_types
+                    .get(&backup.event_id)
+                    .cloned()
+                    .unwrap_or_default(),
+                cameras: event_cameras
+                    .get(&backup.event_id)
+                    .cloned()
+                    .unwrap_or_default(),
             }
+        }));
+    }
+
+    Ok(expired)
+}
+
+/// How many of `backups` are still present on their targets, checking no more than needed.
+async fn verified_copies(
+    targets: &HashMap<String, &Arc<dyn Backup>>,
+    backups: &[BackupRecord],
+    min_copies: u32,
+) -> u32 {
+    let mut verified = 0;
+    for backup in backups {
+        if verified >= min_copies {
+            break;
         }
+        let Some(target) = targets.get(&backup.target) else {
+            continue;
+        };
+
+        match target.exists(&backup.remote_path).await {
+            Ok(true) => verified += 1,
+            Ok(false) => warn!(
+                target = backup.target,
+                remote_path = backup.remote_path,
+                "Backup recorded in the database is missing from its target"
+            ),
+            Err(err) => warn!(
+                err = ?err,
+                target = backup.target,
+                "Failed to check whether a backup still exists"
+            ),
+        }
+    }
+
+    verified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backup::{RemoteBackupConfig, backup_targets},
+        size::ByteSize,
+        testing::{self, CAMERA_ID, TestContext, event},
+    };
+
+    #[tokio::test]
+    async fn test_prune_backups() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let target = context.backup_targets.load()[0].name();
+        let now = Utc::now();
+
+        for (id, backup_time) in [("old", now - chrono::Duration::days(60)), ("new", now)] {
+            let start = backup_time.timestamp_millis();
+            context
+                .database
+                .insert_event(&event(id, start, start + 10_000))
+                .await
+                .unwrap();
+            let remote_path = format!("{id}.mp4");
+            std::fs::write(test.backup_dir().join(&remote_path), b"video").unwrap();
+            context
+                .database
+                .insert_backup(&BackupRecord {
+                    event_id: id.to_string(),
+                    target: target.clone(),
+                    part: 0,
+                    remote_path,
+                    backup_time,
+                    size_bytes: 5,
+                    sha256: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let pruner = Pruner::new(
+            context.clone(),
+            context.backup_config.load().as_ref().clone(),
+        );
+        let policy = context.backup_retention.load_full();
+        pruner.prune_backups(&policy, 0).await.unwrap();
+
+        assert!(!test.backup_dir().join("old.mp4").exists());
+        assert!(test.backup_dir().join("new.mp4").exists());
+        let backups = context.database.get_backups().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].event_id, "new");
+    }
+
+    #[tokio::test]
+    async fn test_prune_dry_run() {
+        let test = TestContext::new("prune-dry-run = true").await;
+        let context = &test.context;
+        let backup_time = Utc::now() - chrono::Duration::days(60);
+        let start = backup_time.timestamp_millis();
+        context
+            .database
+            .insert_event(&event("old", start, start + 10_000))
+            .await
+            .unwrap();
+        std::fs::write(test.backup_dir().join("old.mp4"), b"video").unwrap();
+        context
+            .database
+            .insert_backup(&BackupRecord {
+                event_id: "old".to_string(),
+                target: context.backup_targets.load()[0].name(),
+                part: 0,
+                remote_path: "old.mp4".to_string(),
+                backup_time,
+                size_bytes: 5,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+
+        let pruner = Pruner::new(
+            context.clone(),
+            context.backup_config.load().as_ref().clone(),
+        );
+        let policy = context.backup_retention.load_full();
+        pruner.prune_backups(&policy, 0).await.unwrap();
+
+        // expired, but only logged
+        assert!(test.backup_dir().join("old.mp4").exists());
+        assert_eq!(context.database.get_backups().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mirror_deletions() {
+        let test = TestContext::new("mirror-deletions = true").await;
+        let context = &test.context;
+        let target = context.backup_targets.load()[0].name();
+        let database = &context.database;
+        database
+            .insert_event(&event("deleted", 0, 10_000))
+            .await
+            .unwrap();
+        std::fs::write(test.backup_dir().join("deleted.mp4"), b"video").unwrap();
+        database
+            .insert_backup(&BackupRecord {
+                event_id: "deleted".to_string(),
+                target,
+                part: 0,
+                remote_path: "deleted.mp4".to_string(),
+                backup_time: Utc::now(),
+                size_bytes: 5,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+
+        let pruner = Pruner::new(
+            context.clone(),
+            context.backup_config.load().as_ref().clone(),
+        );
+        pruner.mirror_deletions().await.unwrap();
+        assert!(test.backup_dir().join("deleted.mp4").exists());
+
+        database
+            .mark_event_deleted("deleted", 20_000)
+            .await
+            .unwrap();
+        pruner.mirror_deletions().await.unwrap();
+        assert!(!test.backup_dir().join("deleted.mp4").exists());
+        assert!(database.get_backups().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_enforce_max_sizes() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+
+        // a target that can't be listed, ahead of the local one which is over its cap
+        let mut config = testing::config(&test.dir, "");
+        let RemoteBackupConfig::Local(local) = &mut config.backup.remote[0] else {
+            panic!("test config backs up to a local target");
+        };
+        local.max_size = Some(ByteSize(5));
+        let mut missing = local.clone();
+        missing.path_buf = test.dir.path().join("missing");
+        config
+            .backup
+            .remote
+            .insert(0, RemoteBackupConfig::Local(missing));
+        let targets = backup_targets(&config, &context.metrics);
+        let target = targets[1].name();
+        context.backup_targets.store(Arc::new(targets));
+
+        let now = Utc::now();
+        for (id, modified) in [("old", now - chrono::Duration::days(1)), ("new", now)] {
+            let start = modified.timestamp_millis();
+            context
+                .database
+                .insert_event(&event(id, start, start + 10_000))
+                .await
+                .unwrap();
+            let remote_path = format!("{id}.mp4");
+            let path = test.backup_dir().join(&remote_path);
+            std::fs::write(&path, b"video").unwrap();
+            let file = std::fs::File::open(&path).unwrap();
+            file.set_modified(modified.into()).unwrap();
+            context
+                .database
+                .insert_backup(&BackupRecord {
+                    event_id: id.to_string(),
+                    target: target.clone(),
+                    part: 0,
+                    remote_path,
+                    backup_time: modified,
+                    size_bytes: 5,
+                    sha256: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let pruner = Pruner::new(
+            context.clone(),
+            context.backup_config.load().as_ref().clone(),
+        );
+        let policy = context.backup_retention.load_full();
+        assert!(pruner.enforce_max_sizes(&policy).await.is_err());
+
+        assert!(!test.backup_dir().join("old.mp4").exists());
+        assert!(test.backup_dir().join("new.mp4").exists());
+        let backups = context.database.get_backups().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].event_id, "new");
+    }
+
+    #[tokio::test]
+    async fn test_camera_cutoffs() {
+        let test = TestContext::new(
+            r#"
+            [[camera]]
+            name = "Front Door"
+            retention-period = "7d"
+
+            [[camera]]
+            id = "removed"
+            retention-period = "90d"
+            "#,
+        )
+        .await;
+        let context = &test.context;
+        let pruner = Pruner::new(
+            context.clone(),
+            context.backup_config.load().as_ref().clone(),
+        );
+        let policy = context.backup_retention.load_full();
+
+        // by id, whether the override names the camera or its id
+        let cutoffs: HashMap<_, _> = pruner.camera_cutoffs(&policy).into_iter().collect();
+        let days = |camera: &str| (Utc::now() - cutoffs[camera]).num_days();
+        assert_eq!(days(CAMERA_ID), 7);
+        // no longer on the NVR, so only known by its id
+        assert_eq!(days("removed"), 90);
     }
 }
@@ -0,0 +1,392 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use tokio::time::interval;
+use tracing::{info, warn};
+use unifi_protect_data::Backup as BackupRecord;
+
+use crate::{
+    Error, Result,
+    backup::{
+        Backup, collision, sha256,
+        sidecar::{self, Sidecar},
+    },
+    context::Context,
+    convert::protect_event_from_database_event,
+    task::{download_segment, expired_backups, segments},
+};
+
+/// Checks that every event part still within retention has at least `min-copies` copies on
+/// configured targets, uploading it again where it's short.
+pub struct Reconciler {
+    context: Arc<Context>,
+    config: crate::backup::Config,
+}
+
+/// An event part with fewer verified copies than required
+struct Violation {
+    event_id: String,
+    part: u32,
+    verified: u32,
+    repaired: bool,
+}
+
+impl Reconciler {
+    pub fn new(context: Arc<Context>, config: crate::backup::Config) -> Self {
+        Self { context, config }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        info!("Starting Reconciler");
+
+        let mut interval = interval(self.config.reconcile_interval);
+
+        loop {
+            interval.tick().await;
+            self.pass().await;
+        }
+    }
+
+    /// Reconcile once with `min-copies` and filters from the latest config, if it was reloaded.
+    /// Does nothing while `min-copies` isn't set.
+    async fn pass(&mut self) {
+        self.config = self.context.backup_config.load().as_ref().clone();
+        let Some(min_copies) = self.config.min_copies else {
+            return;
+        };
+
+        let status = &self.context.status.reconciler;
+        match self.reconcile(min_copies).await {
+            Ok(violations) => {
+                let unrepaired = violations.iter().filter(|v| !v.repaired).count();
+                if !violations.is_empty() {
+                    self.context
+                        .notify("Backups below min-copies", &report(&violations, min_copies))
+                        .await;
+                }
+
+                if unrepaired > 0 {
+                    status.backoff(
+                        format!("{unrepaired} event part(s) below min-copies"),
+                        self.config.reconcile_interval,
+                    );
+                } else {
+                    status.waiting(self.config.reconcile_interval);
+                }
+            }
+            Err(err) => {
+                warn!(err = ?err, "Failed to reconcile backups");
+                status.backoff(err, self.config.reconcile_interval);
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn reconcile(&self, min_copies: u32) -> Result<Vec<Violation>> {
+        let policy = self.context.backup_retention.load_full();
+        let expired: HashSet<_> = expired_backups(&self.context, &policy)
+            .await?
+            .into_iter()
+            .map(|backup| (backup.event_id, backup.target, backup.part))
+            .collect();
+
+        let mut parts: BTreeMap<(String, u32), Vec<BackupRecord>> = BTreeMap::new();
+        for backup in self.context.database.get_backups().await? {
+            let key = (backup.event_id.clone(), backup.target.clone(), backup.part);
+            if !expired.contains(&key) {
+                parts
+                    .entry((backup.event_id.clone(), backup.part))
+                    .or_default()
+                    .push(backup);
+            }
+        }
+
+        let status = &self.context.status.reconciler;
+        status.running(parts.len());
+
+        let mut violations = vec![];
+        for (completed, ((event_id, part), backups)) in parts.into_iter().enumerate() {
+            let mut present = vec![];
+            for backup in &backups {
+                let Some(target) = self.target(&backup.target) else {
+                    continue;
+                };
+                match target.exists(&backup.remote_path).await {
+                    Ok(true) => present.push(backup),
+                    Ok(false) => warn!(
+                        event_id,
+                        target = backup.target,
+                        remote_path = backup.remote_path,
+                        "Backup is missing from its target"
+                    ),
+                    Err(err) => warn!(
+                        err = ?err,
+                        event_id,
+                        target = backup.target,
+                        "Failed to check whether a backup still exists"
+                    ),
+                }
+            }
+            status.progress(completed + 1);
+
+            let verified = present.len() as u32;
+            if verified >= min_copies {
+                continue;
+            }
+
+            warn!(
+                event_id,
+                part, verified, min_copies, "Event has too few copies"
+            );
+            let repaired = match self.repair(&event_id, part, &present, min_copies).await {
+                Ok(copies) => copies >= min_copies,
+                Err(err) => {
+                    warn!(err = ?err, event_id, part, "Failed to restore copies");
+                    false
+                }
+            };
+            violations.push(Violation {
+                event_id,
+                part,
+                verified,
+                repaired,
+            });
+        }
+
+        Ok(violations)
+    }
+
+    /// Upload the part to targets without a copy until `min_copies` exist, reading it from a
+    /// target that still has it or exporting it from the NVR again. Returns the copies there
+    /// are afterwards.
+    async fn repair(
+        &self,
+        event_id: &str,
+        part: u32,
+        present: &[&BackupRecord],
+        min_copies: u32,
+    ) -> Result<u32> {
+        let Some(event) = self.context.database.get_event_by_id(event_id).await? else {
+            return Err(Error::Backup(format!(
+                "Event {event_id} is not in the database"
+            )));
+        };
+        let camera_id = event.camera_id.clone();
+        let (start_time, end_time) = (event.start_time, event.end_time.unwrap_or(event.start_time));
+
+        let mut protect_event =
+            protect_event_from_database_event(event, &self.context.protect_bootstrap.load());
+        if protect_event.camera_name.is_none() {
+            protect_event.camera_name = self.context.camera_name(&camera_id).await?;
+        }
+        protect_event.part = (part > 0).then_some(part);
+
+        // a target filtering the event out isn't somewhere to keep a copy of it
+        let needed = min_copies.saturating_sub(present.len() as u32) as usize;
+        let backup_targets = self.context.backup_targets.load_full();
+        let targets: Vec<_> = backup_targets
+            .iter()
+            .filter(|target| {
+                !present.iter().any(|backup| backup.target == target.name())
+                    && target.accepts(&protect_event)
+            })
+            .take(needed)
+            .collect();
+        // held until the restored copies are recorded
+        let (collision, _reservation) = collision(
+            &self.context.database,
+            &self.context.reservations,
+            &targets,
+            &protect_event,
+        )
+        .await?;
+        protect_event.collision = collision;
+
+        if self.config.dry_run {
+            for target in targets {
+                info!(
+                    event_id,
+                    part,
+                    target = target.name(),
+                    path = target.destination(&protect_event),
+                    "Dry run: would restore copy"
+                );
+            }
+            return Ok(present.len() as u32);
+        }
+
+        let video_data = match self.download_copy(present).await {
+            Some(video_data) => video_data,
+            None => {
+                let max_event_length = self
+                    .config
+                    .max_event_length(&camera_id, protect_event.camera_name.as_deref());
+                let (segment_start, segment_end) = segments(start_time, end_time, max_event_length)
+                    .get(part.saturating_sub(1) as usize)
+                    .copied()
+                    .ok_or_else(|| Error::Backup(format!("Event {event_id} has no part {part}")))?;
+                let quality = self
+                    .config
+                    .export_quality(&camera_id, protect_event.camera_name.as_deref());
+                download_segment(
+                    &self.context,
+                    &self.config,
+                    &camera_id,
+                    segment_start,
+                    segment_end,
+                    quality,
+                )
+                .await?
+            }
+        };
+
+        let checksum = sha256(&video_data);
+        let mut copies = present.len() as u32;
+        for target in targets {
+            let remote_path = target.backup(&protect_event, &video_data).await?;
+            let backup_time = self.context.clock.now();
+            if self.config.sidecars {
+                let sidecar = Sidecar::new(
+                    &protect_event,
+                    &self.context.protect_bootstrap.load().nvr.version,
+                    video_data.len() as u64,
+                    &checksum,
+                    backup_time,
+                );
+                sidecar::write(target.as_ref(), &remote_path, &sidecar).await;
+            }
+            self.context
+                .database
+                .insert_backup(&BackupRecord {
+                    event_id: event_id.to_string(),
+                    target: target.name(),
+                    part,
+                    remote_path,
+                    backup_time,
+                    size_bytes: video_data.len() as u64,
+                    sha256: Some(checksum.clone()),
+                })
+                .await?;
+            info!(event_id, part, target = target.name(), "Restored copy");
+            copies += 1;
+        }
+
+        Ok(copies)
+    }
+
+    /// The part's data from the first target able to return it intact.
+    async fn download_copy(&self, present: &[&BackupRecord]) -> Option<Vec<u8>> {
+        for backup in present {
+            let Some(target) = self.target(&backup.target) else {
+                continue;
+            };
+            match target.download(&backup.remote_path).await {
+                Ok(data)
+                    if backup
+                        .sha256
+                        .as_ref()
+                        .is_some_and(|sum| *sum != sha256(&data)) =>
+                {
+                    warn!(
+                        target = backup.target,
+                        remote_path = backup.remote_path,
+                        "Copy doesn't match its checksum, not restoring from it"
+                    )
+                }
+                Ok(data) => return Some(data),
+                Err(err) => warn!(
+                    err = ?err,
+                    target = backup.target,
+                    remote_path = backup.remote_path,
+                    "Failed to read back copy"
+                ),
+            }
+        }
+
+        None
+    }
+
+    fn target(&self, name: &str) -> Option<Arc<dyn Backup>> {
+        self.context
+            .backup_targets
+            .load()
+            .iter()
+            .find(|target| target.name() == name)
+            .cloned()
+    }
+}
+
+fn report(violations: &[Violation], min_copies: u32) -> String {
+    let mut report = format!(
+        "{} event part(s) had fewer than {min_copies} copies:\n\n",
+        violations.len()
+    );
+    for violation in violations {
+        report.push_str(&format!(
+            "{} part {}: {} copies, {}\n",
+            violation.event_id,
+            violation.part,
+            violation.verified,
+            if violation.repaired {
+                "restored"
+            } else {
+                "NOT restored"
+            }
+        ));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::testing::{self, CAMERA_ID, TestContext};
+
+    #[tokio::test]
+    async fn test_reload_min_copies() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let mut reconciler = Reconciler::new(
+            context.clone(),
+            context.backup_config.load().as_ref().clone(),
+        );
+
+        // recorded, but gone from its target
+        let start = (Utc::now() - chrono::Duration::hours(1)).timestamp_millis();
+        let mut event = testing::event("event", start, start + 10_000);
+        event.backed_up = true;
+        context.database.insert_event(&event).await.unwrap();
+        context
+            .database
+            .insert_backup(&BackupRecord {
+                event_id: "event".to_string(),
+                target: context.backup_targets.load()[0].name(),
+                part: 0,
+                remote_path: "event.mp4".to_string(),
+                backup_time: Utc::now(),
+                size_bytes: 5,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+        test.protect.set_export(CAMERA_ID, testing::video());
+
+        reconciler.pass().await;
+        assert!(test.protect.requested_exports().is_empty());
+
+        context
+            .reload(testing::config(&test.dir, "min-copies = 1"))
+            .await
+            .unwrap();
+        reconciler.pass().await;
+        assert_eq!(test.protect.requested_exports().len(), 1);
+        let backups = context.database.get_backups().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        let restored = std::fs::read(test.backup_dir().join(&backups[0].remote_path));
+        assert_eq!(restored.unwrap(), testing::video());
+    }
+}
@@ -0,0 +1,268 @@
+use std::{collections::BTreeMap, fmt, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Local, NaiveTime};
+use humantime_serde::re::humantime;
+use tracing::{error, info, warn};
+use unifi_protect_data::{Backlog, DoorbellInteraction, TargetFailures, TargetUsage};
+
+use crate::{Result, config::ReportConfig, context::Context, size::ByteSize};
+
+/// Once a day, sums up the last 24 hours: events captured per camera, doorbell interactions, what
+/// was uploaded to and failed on each target, and the backlog. The summary is emailed and uploaded
+/// to every backup target.
+pub struct Reporter {
+    context: Arc<Context>,
+    config: Option<ReportConfig>,
+    dry_run: bool,
+}
+
+impl Reporter {
+    pub fn new(context: Arc<Context>, config: Option<ReportConfig>, dry_run: bool) -> Self {
+        Self {
+            context,
+            config,
+            dry_run,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let Some(config) = self.config.clone() else {
+            return std::future::pending().await;
+        };
+
+        info!("Starting Reporter");
+
+        let status = &self.context.status.reporter;
+        let now = self.context.clock.now().with_timezone(&Local);
+        let mut wait = until_next(config.time, now);
+        status.waiting(wait);
+
+        loop {
+            tokio::time::sleep(wait).await;
+
+            status.running(1);
+            let result = self.report(&config).await;
+            wait = until_next(config.time, Local::now());
+            match result {
+                Ok(()) => status.waiting(wait),
+                Err(err) => {
+                    error!(err = ?err, "Failed to put together the daily summary");
+                    status.backoff(err, wait);
+                }
+            }
+        }
+    }
+
+    async fn report(&self, config: &ReportConfig) -> Result<()> {
+        let summary = self.summarize().await?;
+        let text = summary.to_string();
+        info!(
+            events = summary.cameras.values().sum::<i64>(),
+            backlog = summary.backlog.events,
+            "Daily summary"
+        );
+
+        if config.notify {
+            self.context
+                .notify(
+                    &format!("Daily summary for {}", summary.until.date_naive()),
+                    &text,
+                )
+                .await;
+        }
+
+        if !config.upload || self.dry_run {
+            return Ok(());
+        }
+        let path = format!("{}/{}.txt", config.prefix, summary.until.date_naive());
+        for target in self.context.backup_targets.load_full().iter() {
+            if let Err(err) = target.upload(&path, text.as_bytes()).await {
+                warn!(err = ?err, target = target.name(), path, "Failed to upload daily summary");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The 24 hours up to now
+    async fn summarize(&self) -> Result<Summary> {
+        let until = self.context.clock.now();
+        let since = until - chrono::Duration::days(1);
+        let database = &self.context.database;
+
+        // counted per UTC day, which the last 24 hours span two of
+        let mut cameras = BTreeMap::new();
+        for counts in database.events_per_camera_per_day(since).await? {
+            let camera_name = self.context.camera_name(&counts.camera_id).await?;
+            *cameras
+                .entry(camera_name.unwrap_or(counts.camera_id))
+                .or_default() += counts.events;
+        }
+
+        let mut doorbells: BTreeMap<String, Doorbell> = BTreeMap::new();
+        for interaction in database.get_doorbell_interactions(since).await? {
+            let device_id = interaction.device_id.clone();
+            let name = self.context.camera_name(&device_id).await?;
+            doorbells
+                .entry(name.unwrap_or(device_id))
+                .or_default()
+                .record(interaction);
+        }
+
+        Ok(Summary {
+            since: since.with_timezone(&Local),
+            until: until.with_timezone(&Local),
+            cameras,
+            doorbells,
+            uploads: database.uploads_per_target(since).await?,
+            failures: database.failures_per_target(since).await?,
+            backlog: database.backlog().await?,
+        })
+    }
+}
+
+/// What happened between `since` and `until`
+struct Summary {
+    since: DateTime<Local>,
+    until: DateTime<Local>,
+    /// Events captured, by camera name
+    cameras: BTreeMap<String, i64>,
+    /// Doorbell and chime interactions, by device name
+    doorbells: BTreeMap<String, Doorbell>,
+    uploads: Vec<TargetUsage>,
+    failures: Vec<TargetFailures>,
+    backlog: Backlog,
+}
+
+/// One doorbell's or chime's interactions
+#[derive(Default)]
+struct Doorbell {
+    rings: usize,
+    chimes: usize,
+    /// Rings answered with a message on the doorbell's screen
+    quick_replies: usize,
+    /// People identified at the door: when, how and who
+    identified: Vec<(DateTime<Local>, String, String)>,
+}
+
+impl Doorbell {
+    fn record(&mut self, interaction: DoorbellInteraction) {
+        match interaction.action.as_str() {
+            "ring" => self.rings += 1,
+            "chime" => self.chimes += 1,
+            "quick_reply" => self.quick_replies += 1,
+            _ => {
+                let time = DateTime::from_timestamp_millis(interaction.timestamp)
+                    .unwrap_or_default()
+                    .with_timezone(&Local);
+                let user = interaction.user_id.unwrap_or_else(|| "unknown".to_string());
+                self.identified.push((time, interaction.action, user));
+            }
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |time: DateTime<Local>| time.format("%Y-%m-%d %H:%M");
+        writeln!(f, "From {} to {}", time(self.since), time(self.until))?;
+
+        writeln!(f, "\nEvents captured per camera")?;
+        if self.cameras.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for (camera, events) in &self.cameras {
+            writeln!(f, "  {camera}: {events}")?;
+        }
+
+        if !self.doorbells.is_empty() {
+            writeln!(f, "\nDoorbell interactions")?;
+        }
+        for (device, doorbell) in &self.doorbells {
+            let counts = [
+                (doorbell.rings, "rings"),
+                (doorbell.quick_replies, "answered with a quick reply"),
+                (doorbell.chimes, "chimes"),
+                (doorbell.identified.len(), "people identified"),
+            ];
+            let counts: Vec<_> = counts
+                .iter()
+                .filter(|(count, _)| *count > 0)
+                .map(|(count, what)| format!("{count} {what}"))
+                .collect();
+            writeln!(f, "  {device}: {}", counts.join(", "))?;
+            for (time, action, user) in &doorbell.identified {
+                writeln!(f, "    {} {action}: {user}", time.format("%H:%M"))?;
+            }
+        }
+
+        writeln!(f, "\nUploaded per target")?;
+        if self.uploads.is_empty() {
+            writeln!(f, "  nothing")?;
+        }
+        for usage in &self.uploads {
+            writeln!(
+                f,
+                "  {}: {} backups, {}",
+                usage.target,
+                usage.backups,
+                ByteSize(usage.bytes.max(0) as u64)
+            )?;
+        }
+
+        writeln!(f, "\nFailures per target")?;
+        if self.failures.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for failures in &self.failures {
+            writeln!(f, "  {}: {}", failures.target, failures.failures)?;
+        }
+
+        match self.backlog.oldest {
+            Some(oldest) => {
+                let age = (self.until - oldest.with_timezone(&Local))
+                    .to_std()
+                    .unwrap_or_default();
+                writeln!(
+                    f,
+                    "\nBacklog: {} events, the oldest {} old",
+                    self.backlog.events,
+                    humantime::format_duration(Duration::from_secs(age.as_secs()))
+                )
+            }
+            None => writeln!(f, "\nBacklog: empty"),
+        }
+    }
+}
+
+/// How long from `now` until `time` next comes round. A time skipped by a DST change is taken as
+/// a day from now.
+fn until_next(time: NaiveTime, now: DateTime<Local>) -> Duration {
+    let mut date = now.date_naive();
+    if now.time() >= time {
+        date = date.succ_opt().unwrap_or(date);
+    }
+    let next = date
+        .and_time(time)
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap_or(now + chrono::Duration::days(1));
+    (next - now).to_std().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_until_next() {
+        let seven = NaiveTime::from_hms_opt(7, 0, 0).unwrap();
+        let at = |hour| Local.with_ymd_and_hms(2025, 8, 4, hour, 0, 0).unwrap();
+
+        assert_eq!(until_next(seven, at(6)), Duration::from_secs(60 * 60));
+        assert_eq!(until_next(seven, at(7)), Duration::from_secs(24 * 60 * 60));
+        assert_eq!(until_next(seven, at(8)), Duration::from_secs(23 * 60 * 60));
+    }
+}
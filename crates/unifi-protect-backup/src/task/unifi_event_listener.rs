@@ -1,30 +1,78 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
-use tracing::{info, warn};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
 
-use unifi_protect_client::events::{Kind, WebSocketAction, WebSocketMessage};
+use unifi_protect_client::{
+    events::{
+        DoorbellInteraction, EventLabels, EventType, Kind, ModelKey, WebSocketAction,
+        WebSocketMessage,
+    },
+    models::{Bootstrap, Camera, CameraUpdate, SensorUpdate},
+    retry::RetryConfig,
+};
 use unifi_protect_data::Event;
 
 use crate::{Result, context::Context, convert, convert::protect_event_from_parts};
 
+/// How far before the last message received ahead of a gap to look for missed events
+const RECONCILE_MARGIN_MS: i64 = 60_000;
+
+#[derive(Debug, Default, Serialize)]
+pub struct EventListenerMetrics {
+    /// Websocket messages missing from the connection's sequence
+    pub messages_lost: AtomicU64,
+    /// Events recovered from the events API after messages were lost
+    pub events_reconciled: AtomicU64,
+    /// Times the websocket was connected again after it closed or went quiet
+    pub reconnects: AtomicU64,
+}
+
 pub struct UnifiEventListener {
     context: Arc<Context>,
+    // camera state as of the last processed update, used to detect pause transitions
+    cameras: HashMap<String, Camera>,
+    last_sequence: u64,
+    last_message_time: i64,
 }
 
 impl UnifiEventListener {
     pub fn new(context: Arc<Context>) -> Self {
-        Self { context }
+        let cameras = context.protect_bootstrap.load().cameras.clone();
+        let last_message_time = context.clock.now().timestamp_millis();
+        Self {
+            context,
+            cameras,
+            last_sequence: 0,
+            last_message_time,
+        }
     }
 
     pub async fn run(&mut self) -> Result<()> {
         info!("Starting UniFi Protect Event Listener");
 
+        self.sync_camera_pauses().await?;
+        self.record_cameras().await?;
+
         let mut rx = self.context.protect_client.connect_websocket().await?;
         loop {
             let Some(ws_message) = rx.recv().await else {
+                rx = self.reconnect().await;
                 continue;
             };
 
+            self.check_sequence(ws_message.sequence).await;
+            let labels = ws_message
+                .event_labels()
+                .map(|(event_id, labels)| (event_id.to_string(), labels));
+
             match State::from(ws_message) {
                 State::NewMotionEvent(NewMotionEvent {
                     id,
@@ -43,27 +91,182 @@ impl UnifiEventListener {
                         .await?
                 }
 
-                State::Other => continue,
+                State::EventRemoved(event_id) => self.process_event_removed(event_id).await?,
+
+                State::CameraAdded(camera_id) => self.process_camera_added(camera_id).await?,
+
+                State::CameraRemoved(camera_id) => self.process_camera_removed(camera_id).await?,
+
+                State::CameraUpdate(camera_id, update) => {
+                    self.process_camera_update(camera_id, update).await?
+                }
+
+                State::SensorUpdate(sensor_id, update) => {
+                    self.process_sensor_update(sensor_id, update).await?
+                }
+
+                State::DoorbellInteraction(interaction, update) => {
+                    self.process_doorbell_interaction(interaction).await?;
+                    if let Some((camera_id, update)) = update {
+                        self.process_camera_update(camera_id, update).await?
+                    }
+                }
+
+                State::Other => {}
+            };
+
+            // after the event itself is recorded, which they're attached to
+            if let Some((event_id, labels)) = labels {
+                self.record_labels(&event_id, labels).await;
+            }
+        }
+    }
+
+    /// Record the tags and licence plates Protect read from an event's detections, for search
+    async fn record_labels(&self, event_id: &str, labels: EventLabels) {
+        let result = self
+            .context
+            .database
+            .add_event_labels(event_id, &labels.tags, &labels.plates)
+            .await;
+        if let Err(err) = result {
+            warn!(event_id, err = ?err, "Failed to record event labels");
+        }
+    }
+
+    /// Connect the websocket again once it's closed or gone quiet, e.g. across a controller
+    /// reboot, retrying with backoff until it succeeds, then look up any events that happened
+    /// while it was down.
+    async fn reconnect(&mut self) -> mpsc::Receiver<WebSocketMessage> {
+        warn!("Websocket disconnected, reconnecting");
+        let retry = RetryConfig::default();
+        let mut attempt = 0;
+        let rx = loop {
+            attempt += 1;
+            tokio::time::sleep(retry.backoff(attempt)).await;
+            // the session may not have survived whatever closed the connection
+            if let Err(err) = self.context.protect_client.login().await {
+                warn!(err = ?err, attempt, "Failed to log in again");
+                continue;
+            }
+            match self.context.protect_client.connect_websocket().await {
+                Ok(rx) => break rx,
+                Err(err) => warn!(err = ?err, attempt, "Failed to reconnect the websocket"),
+            }
+        };
+        info!(attempt, "Websocket reconnected");
+        self.context
+            .metrics
+            .event_listener
+            .reconnects
+            .fetch_add(1, Ordering::Relaxed);
+
+        // the new connection numbers its messages from 1 again
+        self.last_sequence = 0;
+        let now = self.context.clock.now().timestamp_millis();
+        let window_start = self.last_message_time - RECONCILE_MARGIN_MS;
+        if let Err(err) = self.reconcile(window_start, now).await {
+            warn!(err = ?err, "Failed to reconcile events after reconnecting");
+        }
+
+        rx
+    }
+
+    /// Detect lost messages from a gap in the sequence and look up any events that happened
+    /// since the last message received before the gap. The client numbers every frame it
+    /// receives, so a gap is a frame it couldn't parse; a connection that goes quiet instead is
+    /// closed by the client's idle timeout and caught up on by [`reconnect`](Self::reconnect).
+    async fn check_sequence(&mut self, sequence: u64) {
+        let now = self.context.clock.now().timestamp_millis();
+        let expected = self.last_sequence + 1;
+        let window_start = self.last_message_time - RECONCILE_MARGIN_MS;
+        self.last_sequence = sequence;
+        self.last_message_time = now;
+
+        if sequence <= expected {
+            return;
+        }
+
+        let lost = sequence - expected;
+        warn!(
+            lost,
+            sequence, "Websocket messages lost, reconciling with the events API"
+        );
+        self.context
+            .metrics
+            .event_listener
+            .messages_lost
+            .fetch_add(lost, Ordering::Relaxed);
+
+        if let Err(err) = self.reconcile(window_start, now).await {
+            warn!(err = ?err, "Failed to reconcile events after message loss");
+        }
+    }
+
+    /// Record events in `[start, end]` which the database is missing or hasn't seen finish.
+    #[tracing::instrument(skip(self))]
+    async fn reconcile(&self, start: i64, end: i64) -> Result<()> {
+        let records = self.context.protect_client.list_events(start, end).await?;
+        let bootstrap = self.context.protect_bootstrap.load();
+
+        let mut recovered = vec![];
+        for record in records {
+            let known = self.context.database.get_event_by_id(&record.id).await?;
+            if known.is_some_and(|event| event.end_time.is_some() || record.end.is_none()) {
+                continue;
+            }
+
+            let camera_name = record
+                .camera
+                .as_ref()
+                .and_then(|camera_id| bootstrap.cameras.get(camera_id))
+                .map(|camera| camera.name.clone());
+            let Some(event) = record.to_protect_event(camera_name) else {
+                continue;
             };
+
+            info!(id = event.id, "Recovered event missed by the websocket");
+            recovered.push(convert::protect_event_to_database_event(&event));
         }
+
+        self.context.database.insert_events(&recovered).await?;
+        if recovered.iter().any(|event| event.end_time.is_some()) {
+            self.context.event_finished.notify_one();
+        }
+        self.context
+            .metrics
+            .event_listener
+            .events_reconciled
+            .fetch_add(recovered.len() as u64, Ordering::Relaxed);
+
+        Ok(())
     }
 
-    #[tracing::instrument(skip(self, _ws_message))]
+    #[tracing::instrument(skip(self, ws_message))]
     async fn process_new_motion_event(
         &mut self,
         id: String,
         start_time: i64,
-        _ws_message: WebSocketMessage,
+        ws_message: WebSocketMessage,
     ) -> Result<()> {
+        // an audio detection's type is known from the start, other smart detections' at the end
+        let event_type = match ws_message.audio_detect_type() {
+            Some(kind) => EventType::Audio(kind).to_string(),
+            None => "Motion".to_string(),
+        };
         self.context
             .database
             .insert_event(&Event {
                 id,
-                event_type: "Motion".to_string(),
+                event_type,
                 camera_id: "".to_string(),
                 start_time,
                 end_time: None,
                 backed_up: false,
+                skip_reason: None,
+                smart_detect_types: String::new(),
+                thumbnail_id: None,
+                heatmap_id: None,
             })
             .await?;
         Ok(())
@@ -76,16 +279,13 @@ impl UnifiEventListener {
         _end_time: i64,
         ws_message: WebSocketMessage,
     ) -> Result<()> {
-        let bootstrap = &self.context.protect_bootstrap;
+        let bootstrap = self.context.protect_bootstrap.load();
 
         // it is a backup candidate!
         let Some(motion_detected_db_event) =
             self.context.database.get_event_by_id(id.as_str()).await?
         else {
-            warn!(
-                "We missed the start of this motion event and can't get the start time for it to export"
-            );
-            return Ok(());
+            return self.recover_missed_event(&id).await;
         };
 
         let motion_event_completed_ws_message = ws_message;
@@ -108,10 +308,358 @@ impl UnifiEventListener {
             );
             let database_event = convert::protect_event_to_database_event(&event);
             self.context.database.insert_event(&database_event).await?;
+            // no need to wake the poller for an event none of the targets take
+            if self
+                .context
+                .backup_targets
+                .load()
+                .iter()
+                .any(|target| target.accepts(&event))
+            {
+                self.context.event_finished.notify_one();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Look up an event whose start we missed with the events API, rather than dropping it
+    #[tracing::instrument(skip(self))]
+    async fn recover_missed_event(&self, id: &str) -> Result<()> {
+        let record = match self.context.protect_client.get_event(id).await {
+            Ok(record) => record,
+            Err(err) => {
+                warn!(err = ?err, "Missed the start of this event and failed to look it up");
+                return Ok(());
+            }
+        };
+
+        // only the kinds of event whose start would have been recorded
+        if !matches!(record.kind.as_str(), "motion" | "smartAudioDetect") {
+            debug!(
+                kind = record.kind,
+                "Missed the start of an event of a type not backed up"
+            );
+            return Ok(());
+        }
+
+        let bootstrap = self.context.protect_bootstrap.load();
+        let camera_name = record
+            .camera
+            .as_ref()
+            .and_then(|camera_id| bootstrap.cameras.get(camera_id))
+            .map(|camera| camera.name.clone());
+        let Some(event) = record.to_protect_event(camera_name) else {
+            debug!(
+                kind = record.kind,
+                "Missed the start of an event of a type not backed up"
+            );
+            return Ok(());
+        };
+
+        info!(
+            id = event.id,
+            camera_name = event.camera_name,
+            event_type = event.event_type.to_string(),
+            "Recovered event whose start the websocket missed"
+        );
+        let database_event = convert::protect_event_to_database_event(&event);
+        self.context.database.insert_event(&database_event).await?;
+        self.context
+            .metrics
+            .event_listener
+            .events_reconciled
+            .fetch_add(1, Ordering::Relaxed);
+        if event.is_finished
+            && self
+                .context
+                .backup_targets
+                .load()
+                .iter()
+                .any(|target| target.accepts(&event))
+        {
+            self.context.event_finished.notify_one();
+        }
+
+        Ok(())
+    }
+
+    /// Reconcile open pauses in the database with the camera state reported at startup, in case
+    /// a camera was paused or resumed while we weren't listening.
+    #[tracing::instrument(skip(self))]
+    async fn sync_camera_pauses(&mut self) -> Result<()> {
+        for camera in self.cameras.values() {
+            self.sync_camera_pause(camera).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn sync_camera_pause(&self, camera: &Camera) -> Result<()> {
+        let now = self.context.clock.now().timestamp_millis();
+        let open_pause = self
+            .context
+            .database
+            .get_open_camera_pause(camera.id.as_str())
+            .await?;
+
+        match (camera.pause_reason(), open_pause) {
+            (Some(reason), Some(pause)) if pause.reason == reason.to_string() => {}
+            (Some(reason), _) => {
+                self.context
+                    .database
+                    .start_camera_pause(camera.id.as_str(), reason.to_string().as_str(), now)
+                    .await?
+            }
+            (None, Some(_)) => {
+                self.context
+                    .database
+                    .end_camera_pause(camera.id.as_str(), now)
+                    .await?
+            }
+            (None, None) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Record every camera currently on the NVR, so names survive the camera being removed.
+    #[tracing::instrument(skip(self))]
+    async fn record_cameras(&self) -> Result<()> {
+        for camera in self.cameras.values() {
+            self.record_camera(camera).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn record_camera(&self, camera: &Camera) -> Result<()> {
+        let now = self.context.clock.now().timestamp_millis();
+        self.context
+            .database
+            .upsert_camera(&convert::camera_to_database_camera(camera, now))
+            .await?;
+        Ok(())
+    }
+
+    /// Record an event deleted on the NVR, and with `mirror-deletions` wake the pruner to delete
+    /// its backups too
+    #[tracing::instrument(skip(self))]
+    async fn process_event_removed(&mut self, event_id: String) -> Result<()> {
+        let now = self.context.clock.now().timestamp_millis();
+        let database = &self.context.database;
+        match database.get_event_by_id(&event_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                warn!(err = ?err, event_id, "Failed to look up event deleted on the NVR");
+                return Ok(());
+            }
+        }
+
+        info!(event_id, "Event deleted on the NVR");
+        if let Err(err) = database.mark_event_deleted(&event_id, now).await {
+            warn!(err = ?err, event_id, "Failed to record event deleted on the NVR");
+            return Ok(());
+        }
+
+        // the pruner deletes its backups, off the websocket's path
+        if self.context.backup_config.load().mirror_deletions {
+            self.context.event_deleted.notify_one();
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn process_camera_removed(&mut self, camera_id: String) -> Result<()> {
+        let now = self.context.clock.now().timestamp_millis();
+        let camera = self.cameras.remove(&camera_id);
+        info!(
+            camera_id,
+            camera_name = camera.as_ref().map(|c| c.name.as_str()),
+            "Camera removed"
+        );
+
+        // a removed camera can't resume, so close out any open pause
+        self.context
+            .database
+            .end_camera_pause(camera_id.as_str(), now)
+            .await?;
+        self.context
+            .database
+            .mark_camera_removed(camera_id.as_str(), now)
+            .await?;
+
+        self.context.protect_bootstrap.rcu(|bootstrap| {
+            let mut bootstrap = Bootstrap::clone(bootstrap);
+            bootstrap.cameras.remove(&camera_id);
+            bootstrap
+        });
+
+        Ok(())
+    }
+
+    /// Pick up a newly adopted camera from a fresh bootstrap so its events get a name and pass
+    /// the camera filters.
+    #[tracing::instrument(skip(self))]
+    async fn process_camera_added(&mut self, camera_id: String) -> Result<()> {
+        // the periodic refresh picks the camera up later; failing here would stop the listener
+        if let Err(err) = self.context.refresh_bootstrap().await {
+            warn!(err = ?err, camera_id, "Failed to refresh bootstrap for added camera");
+        }
+
+        let Some(camera) = self
+            .context
+            .protect_bootstrap
+            .load()
+            .cameras
+            .get(&camera_id)
+            .cloned()
+        else {
+            warn!(camera_id, "Camera added but missing from bootstrap");
+            return Ok(());
+        };
+
+        info!(camera_id, camera_name = camera.name, "Camera added");
+        self.sync_camera_pause(&camera).await?;
+        self.record_camera(&camera).await?;
+        self.cameras.insert(camera_id, camera);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, update))]
+    async fn process_camera_update(
+        &mut self,
+        camera_id: String,
+        update: CameraUpdate,
+    ) -> Result<()> {
+        if !self.cameras.contains_key(&camera_id) {
+            // an update for a camera we haven't seen, e.g. adopted before the last refresh
+            return self.process_camera_added(camera_id).await;
+        }
+
+        // keep the shared bootstrap current so renames show up in filenames straight away
+        self.context.protect_bootstrap.rcu(|bootstrap| {
+            let mut bootstrap = Bootstrap::clone(bootstrap);
+            if let Some(camera) = bootstrap.cameras.get_mut(&camera_id) {
+                camera.apply_update(&update);
+            }
+            bootstrap
+        });
+
+        let Some(camera) = self.cameras.get_mut(&camera_id) else {
+            return Ok(());
+        };
+
+        let before = camera.pause_reason();
+        let renamed = update
+            .name
+            .as_ref()
+            .is_some_and(|name| *name != camera.name);
+        camera.apply_update(&update);
+        let after = camera.pause_reason();
+        let camera = camera.clone();
+
+        if renamed {
+            info!(camera_id, camera_name = camera.name, "Camera renamed");
+            self.record_camera(&camera).await?;
+        }
+
+        if before == after {
+            return Ok(());
+        }
+
+        let now = self.context.clock.now().timestamp_millis();
+        match after {
+            Some(reason) => {
+                info!(
+                    camera_id,
+                    camera_name = camera.name,
+                    reason = reason.to_string(),
+                    "Camera detections paused"
+                );
+                self.context
+                    .database
+                    .start_camera_pause(camera_id.as_str(), reason.to_string().as_str(), now)
+                    .await?;
+            }
+            None => {
+                info!(
+                    camera_id,
+                    camera_name = camera.name,
+                    "Camera detections resumed"
+                );
+                self.context
+                    .database
+                    .end_camera_pause(camera_id.as_str(), now)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// With `sensor-clips` set, record an event for a clip from the sensor's camera around each
+    /// trigger it reports. The poller backs it up once the clip has ended.
+    #[tracing::instrument(skip(self, update))]
+    async fn process_sensor_update(
+        &mut self,
+        sensor_id: String,
+        update: SensorUpdate,
+    ) -> Result<()> {
+        let config = self.context.backup_config.load_full();
+        let Some(clips) = &config.sensor_clips else {
+            return Ok(());
+        };
+        let bootstrap = self.context.protect_bootstrap.load();
+        let now = self.context.clock.now().timestamp_millis();
+
+        for (trigger, time) in update.triggers() {
+            if !clips.triggers.contains(&trigger) {
+                continue;
+            }
+            let Some(camera_id) = clips.camera_for(&sensor_id, &bootstrap) else {
+                warn!(sensor_id, %trigger, "Sensor triggered, but has no camera to record from");
+                continue;
+            };
+
+            let event = clips.clip_event(&sensor_id, trigger, time.unwrap_or(now), &camera_id);
+            info!(
+                id = event.id,
+                sensor_id,
+                camera_id,
+                %trigger,
+                "Sensor triggered. Persisting clip pending backup."
+            );
+            self.context.database.insert_event(&event).await?;
         }
 
         Ok(())
     }
+
+    /// Keep a record of a doorbell or chime interaction for the daily summary
+    #[tracing::instrument(skip(self))]
+    async fn process_doorbell_interaction(&self, interaction: DoorbellInteraction) -> Result<()> {
+        let now = self.context.clock.now().timestamp_millis();
+        info!(
+            device_id = interaction.device_id,
+            action = %interaction.action,
+            user_id = interaction.user_id,
+            "Doorbell interaction"
+        );
+        self.context
+            .database
+            .insert_doorbell_interaction(&unifi_protect_data::DoorbellInteraction {
+                device_id: interaction.device_id,
+                action: interaction.action.to_string(),
+                user_id: interaction.user_id,
+                detail: interaction.detail,
+                timestamp: interaction.time.unwrap_or(now),
+            })
+            .await?;
+        Ok(())
+    }
 }
 
 struct NewMotionEvent {
@@ -128,11 +676,46 @@ struct CompletedMotionEvent {
 enum State {
     NewMotionEvent(NewMotionEvent),
     CompletedMotionEvent(CompletedMotionEvent),
+    EventRemoved(String),
+    CameraAdded(String),
+    CameraRemoved(String),
+    CameraUpdate(String, CameraUpdate),
+    SensorUpdate(String, SensorUpdate),
+    /// With the camera update it came in, if any
+    DoorbellInteraction(DoorbellInteraction, Option<(String, CameraUpdate)>),
     Other,
 }
 
 impl From<WebSocketMessage> for State {
     fn from(ws_message: WebSocketMessage) -> Self {
+        if let Some(event_id) = ws_message.event_removed() {
+            return Self::EventRemoved(event_id.to_string());
+        }
+
+        if let Some(camera_id) = ws_message.camera_added() {
+            return Self::CameraAdded(camera_id.to_string());
+        }
+
+        if let Some(camera_id) = ws_message.camera_removed() {
+            return Self::CameraRemoved(camera_id.to_string());
+        }
+
+        // a doorbell's quick reply is a camera update, which may change other fields as well
+        if let Some(interaction) = ws_message.doorbell_interaction() {
+            let update = ws_message
+                .camera_update()
+                .map(|update| (ws_message.action_frame.id.clone(), update));
+            return Self::DoorbellInteraction(interaction, update);
+        }
+
+        if let Some(update) = ws_message.camera_update() {
+            return Self::CameraUpdate(ws_message.action_frame.id.clone(), update);
+        }
+
+        if let Some((sensor_id, update)) = ws_message.sensor_update() {
+            return Self::SensorUpdate(sensor_id.to_string(), update);
+        }
+
         match (
             &ws_message.action_frame.action,
             &ws_message.action_frame.record_id,
@@ -141,14 +724,27 @@ impl From<WebSocketMessage> for State {
             &ws_message.data_frame.start,
             &ws_message.data_frame.end,
         ) {
-            (WebSocketAction::Add, _, Some(Kind::Motion), Some(id), Some(start_time), _) => {
-                Self::NewMotionEvent(NewMotionEvent {
-                    id: id.clone(),
-                    start_time: *start_time,
-                    ws_message: ws_message.clone(),
-                })
-            }
-            (WebSocketAction::Update, _, _, _, _, Some(end_time)) => {
+            (
+                WebSocketAction::Add,
+                _,
+                Some(Kind::Motion | Kind::SmartAudioDetect),
+                Some(id),
+                Some(start_time),
+                _,
+            ) => Self::NewMotionEvent(NewMotionEvent {
+                id: id.clone(),
+                start_time: *start_time,
+                ws_message: ws_message.clone(),
+            }),
+            // an update usually only has what changed, so it may not say what kind of event it is
+            (
+                WebSocketAction::Update,
+                _,
+                None | Some(Kind::Motion | Kind::SmartAudioDetect),
+                _,
+                _,
+                Some(end_time),
+            ) if ws_message.action_frame.model_key == ModelKey::Event => {
                 Self::CompletedMotionEvent(CompletedMotionEvent {
                     id: ws_message.action_frame.id.clone(),
                     end_time: *end_time,
@@ -159,3 +755,259 @@ impl From<WebSocketMessage> for State {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::testing::{CAMERA_ID, TestContext, bootstrap, event_record};
+
+    #[tokio::test]
+    async fn test_check_sequence() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let mut listener = UnifiEventListener::new(context.clone());
+        let now = Utc::now().timestamp_millis();
+        // only ever announced in the message that was lost
+        test.protect
+            .add_event(event_record("missed", now - 10_000, now - 5_000));
+
+        listener.check_sequence(1).await;
+        listener.check_sequence(2).await;
+        let metrics = &context.metrics.event_listener;
+        assert_eq!(metrics.messages_lost.load(Ordering::Relaxed), 0);
+        assert!(
+            context
+                .database
+                .get_event_by_id("missed")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        listener.check_sequence(4).await;
+        assert_eq!(metrics.messages_lost.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.events_reconciled.load(Ordering::Relaxed), 1);
+        let missed = context.database.get_event_by_id("missed").await.unwrap();
+        assert_eq!(missed.unwrap().end_time, Some(now - 5_000));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_wakes_poller() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let listener = UnifiEventListener::new(context.clone());
+        let now = Utc::now().timestamp_millis();
+        let woken = || context.event_finished.notified().now_or_never().is_some();
+
+        // nothing to back up until an event finishes
+        let mut ongoing = event_record("ongoing", now - 10_000, now);
+        ongoing.end = None;
+        test.protect.add_event(ongoing);
+        listener.reconcile(now - 60_000, now).await.unwrap();
+        assert!(!woken());
+
+        test.protect
+            .add_event(event_record("finished", now - 10_000, now - 5_000));
+        listener.reconcile(now - 60_000, now).await.unwrap();
+        assert!(woken());
+        assert!(!woken());
+    }
+
+    #[tokio::test]
+    async fn test_camera_added() {
+        let test = TestContext::new("").await;
+        let mut listener = UnifiEventListener::new(test.context.clone());
+
+        // not in the bootstrap yet, so it's tried again on its next update
+        listener
+            .process_camera_update("garage".to_string(), CameraUpdate::default())
+            .await
+            .unwrap();
+        assert!(!listener.cameras.contains_key("garage"));
+
+        let mut bootstrap = bootstrap();
+        let mut garage = bootstrap.cameras["camera"].clone();
+        garage.id = "garage".to_string();
+        garage.name = "Garage".to_string();
+        bootstrap.cameras.insert("garage".to_string(), garage);
+        test.protect.set_bootstrap(bootstrap);
+        listener
+            .process_camera_update("garage".to_string(), CameraUpdate::default())
+            .await
+            .unwrap();
+        assert_eq!(listener.cameras["garage"].name, "Garage");
+        assert!(
+            test.context
+                .protect_bootstrap
+                .load()
+                .cameras
+                .contains_key("garage")
+        );
+    }
+
+    #[test]
+    fn test_doorbell_quick_reply_with_rename() {
+        let ws_message = WebSocketMessage {
+            action_frame: serde_json::from_value(serde_json::json!({
+                "action": "update",
+                "newUpdateId": "6a1e4f36-2f52-4d5c-9d0e-3f6b1d1f0c9a",
+                "modelKey": "camera",
+                "id": "camera"
+            }))
+            .unwrap(),
+            data_frame: serde_json::from_value(serde_json::json!({
+                "lcdMessage": { "type": "LEAVE_PACKAGE_AT_DOOR", "text": "Leave it at the door" },
+                "name": "Porch"
+            }))
+            .unwrap(),
+            sequence: 1,
+        };
+
+        let State::DoorbellInteraction(interaction, Some((camera_id, update))) =
+            State::from(ws_message)
+        else {
+            panic!("expected a doorbell interaction with its camera update");
+        };
+        assert_eq!(interaction.detail.as_deref(), Some("Leave it at the door"));
+        assert_eq!(camera_id, "camera");
+        assert_eq!(update.name.as_deref(), Some("Porch"));
+    }
+
+    #[tokio::test]
+    async fn test_event_removed() {
+        let test = TestContext::new("mirror-deletions = true").await;
+        let context = &test.context;
+        let database = &context.database;
+        let mut listener = UnifiEventListener::new(context.clone());
+        database
+            .insert_event(&crate::testing::event("event", 0, 10_000))
+            .await
+            .unwrap();
+        database
+            .insert_backup(&unifi_protect_data::Backup {
+                event_id: "event".to_string(),
+                target: context.backup_targets.load()[0].name(),
+                part: 0,
+                remote_path: "event.mp4".to_string(),
+                backup_time: Utc::now(),
+                size_bytes: 5,
+                sha256: None,
+            })
+            .await
+            .unwrap();
+
+        // unknown events are ignored
+        listener
+            .process_event_removed("unknown".to_string())
+            .await
+            .unwrap();
+        listener
+            .process_event_removed("event".to_string())
+            .await
+            .unwrap();
+
+        // left for the pruner, which is woken to delete it
+        let backups = database.get_backups_of_deleted_events().await.unwrap();
+        assert_eq!(backups.len(), 1);
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            context.event_deleted.notified(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recover_missed_event() {
+        let test = TestContext::new("").await;
+        let context = &test.context;
+        let mut listener = UnifiEventListener::new(context.clone());
+        test.protect.add_event(event_record("missed", 1_000, 5_000));
+        let update = |id: &str| WebSocketMessage {
+            action_frame: serde_json::from_value(serde_json::json!({
+                "action": "update",
+                "newUpdateId": "6a1e4f36-2f52-4d5c-9d0e-3f6b1d1f0c9a",
+                "modelKey": "event",
+                "id": id
+            }))
+            .unwrap(),
+            data_frame: serde_json::from_value(serde_json::json!({ "end": 5_000 })).unwrap(),
+            sequence: 1,
+        };
+
+        // the add was missed, so the start comes from the events API
+        listener
+            .process_completed_motion_event("missed".to_string(), 5_000, update("missed"))
+            .await
+            .unwrap();
+        let missed = context
+            .database
+            .get_event_by_id("missed")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(missed.start_time, 1_000);
+        assert_eq!(missed.end_time, Some(5_000));
+        assert_eq!(missed.camera_id, CAMERA_ID);
+
+        // an event the NVR has no record of either is dropped
+        listener
+            .process_completed_motion_event("unknown".to_string(), 5_000, update("unknown"))
+            .await
+            .unwrap();
+        assert!(
+            context
+                .database
+                .get_event_by_id("unknown")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_completed_event_recovery() {
+        let update = |model_key: &str, kind: Option<&str>| WebSocketMessage {
+            action_frame: serde_json::from_value(serde_json::json!({
+                "action": "update",
+                "newUpdateId": "6a1e4f36-2f52-4d5c-9d0e-3f6b1d1f0c9a",
+                "modelKey": model_key,
+                "id": "event"
+            }))
+            .unwrap(),
+            data_frame: serde_json::from_value(serde_json::json!({
+                "type": kind,
+                "end": 1_000
+            }))
+            .unwrap(),
+            sequence: 1,
+        };
+
+        // only updates ending events of the kinds recorded when they're added
+        for (model_key, kind) in [("event", None), ("event", Some("motion"))] {
+            let state = State::from(update(model_key, kind));
+            assert!(matches!(state, State::CompletedMotionEvent(_)));
+        }
+        for (model_key, kind) in [("camera", None), ("event", Some("ring"))] {
+            assert!(matches!(State::from(update(model_key, kind)), State::Other));
+        }
+
+        // nor is an event of another kind recovered when its update doesn't say
+        let test = TestContext::new("").await;
+        let listener = UnifiEventListener::new(test.context.clone());
+        let now = Utc::now().timestamp_millis();
+        let mut ring = event_record("ring", now - 10_000, now - 5_000);
+        ring.kind = "ring".to_string();
+        test.protect.add_event(ring);
+        test.protect
+            .add_event(event_record("motion", now - 10_000, now - 5_000));
+        listener.recover_missed_event("ring").await.unwrap();
+        listener.recover_missed_event("motion").await.unwrap();
+        let database = &test.context.database;
+        assert!(database.get_event_by_id("ring").await.unwrap().is_none());
+        assert!(database.get_event_by_id("motion").await.unwrap().is_some());
+    }
+}
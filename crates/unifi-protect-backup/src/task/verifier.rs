@@ -0,0 +1,214 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::time::interval;
+use tracing::{info, warn};
+use unifi_protect_data::Backup as BackupRecord;
+
+use crate::{Result, backup::STRAY_PARTIAL_AGE, context::Context};
+
+/// Periodically re-hashes every backed up copy and compares it with the checksum recorded at
+/// upload, catching bit-rot and truncated uploads.
+pub struct Verifier {
+    context: Arc<Context>,
+    verify_interval: Option<Duration>,
+}
+
+/// The outcome of re-hashing a set of backups
+#[derive(Debug, Default)]
+pub struct Verification {
+    /// Copies whose contents still match their checksum
+    pub intact: usize,
+    /// Copies with no recorded checksum or on a target that's no longer configured
+    pub skipped: usize,
+    /// Copies whose contents changed, with the checksum they have now
+    pub corrupt: Vec<(BackupRecord, String)>,
+    /// Copies no longer on their target
+    pub missing: Vec<BackupRecord>,
+    /// Copies which couldn't be read, e.g. because the target was unreachable
+    pub failed: usize,
+    /// Uploads left incomplete for longer than [`STRAY_PARTIAL_AGE`], by target and path
+    pub stray_partials: Vec<(String, String)>,
+}
+
+impl Verification {
+    pub fn problems(&self) -> usize {
+        self.corrupt.len() + self.missing.len() + self.stray_partials.len()
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{} intact, {} corrupt, {} missing, {} unreadable, {} without a checksum, {} stray \
+             partial upload(s)\n",
+            self.intact,
+            self.corrupt.len(),
+            self.missing.len(),
+            self.failed,
+            self.skipped,
+            self.stray_partials.len()
+        );
+        for (backup, actual) in &self.corrupt {
+            report.push_str(&format!(
+                "corrupt: {} part {} on {} at {} (expected {}, found {actual})\n",
+                backup.event_id,
+                backup.part,
+                backup.target,
+                backup.remote_path,
+                backup.sha256.as_deref().unwrap_or_default()
+            ));
+        }
+        for backup in &self.missing {
+            report.push_str(&format!(
+                "missing: {} part {} on {} at {}\n",
+                backup.event_id, backup.part, backup.target, backup.remote_path
+            ));
+        }
+        for (target, path) in &self.stray_partials {
+            report.push_str(&format!("stray partial upload: on {target} at {path}\n"));
+        }
+        report
+    }
+}
+
+impl Verifier {
+    pub fn new(context: Arc<Context>, verify_interval: Option<Duration>) -> Self {
+        Self {
+            context,
+            verify_interval,
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let Some(verify_interval) = self.verify_interval else {
+            return std::future::pending().await;
+        };
+
+        info!("Starting Verifier");
+
+        let mut interval = interval(verify_interval);
+        // don't compete with the backlog the other tasks work through at startup
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let status = &self.context.status.verifier;
+            let backups = match self.context.database.get_backups().await {
+                Ok(backups) => backups,
+                Err(err) => {
+                    warn!(err = ?err, "Failed to load backups to verify");
+                    status.backoff(err, verify_interval);
+                    continue;
+                }
+            };
+
+            status.running(backups.len());
+            let mut verification =
+                verify_backups(&self.context, backups, |done| status.progress(done)).await;
+            find_stray_partials(&self.context, None, &mut verification).await;
+            info!(
+                intact = verification.intact,
+                corrupt = verification.corrupt.len(),
+                missing = verification.missing.len(),
+                failed = verification.failed,
+                skipped = verification.skipped,
+                stray_partials = verification.stray_partials.len(),
+                "Verified backups"
+            );
+
+            if verification.problems() > 0 {
+                self.context
+                    .notify("Backups failed verification", &verification.report())
+                    .await;
+                status.backoff(
+                    format!("{} backup(s) failed verification", verification.problems()),
+                    verify_interval,
+                );
+            } else {
+                status.waiting(verify_interval);
+            }
+        }
+    }
+}
+
+/// Re-hash each backup with a recorded checksum on its target, calling `progress` with the
+/// number of backups done so far.
+pub(crate) async fn verify_backups(
+    context: &Context,
+    backups: Vec<BackupRecord>,
+    progress: impl Fn(usize),
+) -> Verification {
+    let mut verification = Verification::default();
+
+    let backup_targets = context.backup_targets.load_full();
+    for (done, backup) in backups.into_iter().enumerate() {
+        progress(done);
+
+        let target = backup_targets
+            .iter()
+            .find(|target| target.name() == backup.target);
+        let (Some(target), Some(expected)) = (target, &backup.sha256) else {
+            verification.skipped += 1;
+            continue;
+        };
+
+        match target.sha256(&backup.remote_path).await {
+            Ok(Some(actual)) if actual == *expected => verification.intact += 1,
+            Ok(Some(actual)) => {
+                warn!(
+                    event_id = backup.event_id,
+                    target = backup.target,
+                    remote_path = backup.remote_path,
+                    expected,
+                    actual,
+                    "Backup doesn't match its checksum"
+                );
+                verification.corrupt.push((backup, actual));
+            }
+            Ok(None) => {
+                warn!(
+                    event_id = backup.event_id,
+                    target = backup.target,
+                    remote_path = backup.remote_path,
+                    "Backup is missing from its target"
+                );
+                verification.missing.push(backup);
+            }
+            Err(err) => {
+                warn!(
+                    err = ?err,
+                    event_id = backup.event_id,
+                    target = backup.target,
+                    "Failed to hash backup"
+                );
+                verification.failed += 1;
+            }
+        }
+    }
+
+    verification
+}
+
+/// Flag uploads left incomplete on each target, or on those whose name starts with `target`
+pub(crate) async fn find_stray_partials(
+    context: &Context,
+    target: Option<&str>,
+    verification: &mut Verification,
+) {
+    let older_than = context.clock.ago(STRAY_PARTIAL_AGE);
+    for backup_target in context.backup_targets.load_full().iter() {
+        let name = backup_target.name();
+        if target.is_some_and(|target| !name.starts_with(target)) {
+            continue;
+        }
+
+        match backup_target.stray_partials(older_than).await {
+            Ok(partials) => {
+                for path in partials {
+                    warn!(target = name, path, "Upload was left incomplete");
+                    verification.stray_partials.push((name.clone(), path));
+                }
+            }
+            Err(err) => warn!(err = ?err, target = name, "Failed to look for stray partials"),
+        }
+    }
+}
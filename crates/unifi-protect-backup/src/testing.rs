@@ -0,0 +1,139 @@
+//! Setup shared by the tests which run tasks against a mock NVR and a real database.
+
+use std::{path::PathBuf, sync::Arc};
+
+use tempfile::TempDir;
+use unifi_protect_client::{events::EventRecord, mock::MockProtectClient, models::Bootstrap};
+use unifi_protect_data::Event;
+
+use crate::{config::Config, context::Context};
+
+pub const CAMERA_ID: &str = "camera";
+pub const CAMERA_NAME: &str = "Front Door";
+
+/// A [`Context`] for a mock NVR with one camera, backing up to a local target and keeping its
+/// database in a temporary directory. It's removed when this is dropped.
+pub struct TestContext {
+    pub context: Arc<Context>,
+    pub protect: Arc<MockProtectClient>,
+    pub dir: TempDir,
+}
+
+impl TestContext {
+    /// With `backup` added to the defaults in `[backup]`, as TOML
+    pub async fn new(backup: &str) -> Self {
+        let dir = tempfile::tempdir().expect("temporary directory");
+        std::fs::create_dir_all(dir.path().join("backups")).expect("backup directory");
+        let config = config(&dir, backup);
+        let protect = Arc::new(MockProtectClient::new(bootstrap()));
+        let context = Context::with_client(config, protect.clone())
+            .await
+            .expect("context");
+        Self {
+            context: Arc::new(context),
+            protect,
+            dir,
+        }
+    }
+
+    /// Where the local backup target keeps its files
+    pub fn backup_dir(&self) -> PathBuf {
+        self.dir.path().join("backups")
+    }
+}
+
+/// The config [`TestContext::new`] runs with, for tests which need to change it
+pub fn config(dir: &TempDir, backup: &str) -> Config {
+    let dir = dir.path().display();
+    let toml = format!(
+        r#"
+        [unifi]
+        address = "127.0.0.1"
+        port = 443
+        username = "backup"
+        password = "password"
+        verify-ssl = false
+
+        [database]
+        path = "{dir}/events.db"
+
+        [backup]
+        retention-period = "30d"
+        poll-interval = "1m"
+        max-event-length = "1h"
+        purge-interval = "1d"
+        file-structure-format = "{{camera_name}}/{{date}}/{{time}}_{{detection_type}}.mp4"
+        detection-types = ["motion", "person", "vehicle", "ring"]
+        ignore-cameras = []
+        cameras = []
+        download-buffer-size = "8KiB"
+        parallel-uploads = 1
+        skip-missing = false
+        {backup}
+
+        [[backup.remote]]
+        local = {{ path-buf = "{dir}/backups" }}
+
+        [archive]
+        archive-interval = "1d"
+        retention-period = "90d"
+        purge-interval = "1d"
+        remote = []
+        "#
+    );
+    toml::from_str::<Config>(&toml)
+        .expect("valid test config")
+        .with_camera_configs()
+}
+
+/// One connected camera, [`CAMERA_ID`] named [`CAMERA_NAME`]
+pub fn bootstrap() -> Bootstrap {
+    serde_json::from_value(serde_json::json!({
+        "cameras": {
+            "camera": {
+                "id": "camera", "name": "Front Door", "mac": "AA", "model": null,
+                "isConnected": true
+            }
+        },
+        "nvr": { "id": "nvr", "name": "NVR", "version": "5.0.0", "timezone": "UTC" }
+    }))
+    .expect("valid bootstrap")
+}
+
+/// A finished motion event from [`CAMERA_ID`], as the events API lists it
+pub fn event_record(id: &str, start: i64, end: i64) -> EventRecord {
+    EventRecord {
+        id: id.to_string(),
+        kind: "motion".to_string(),
+        camera: Some(CAMERA_ID.to_string()),
+        start,
+        end: Some(end),
+        smart_detect_types: vec![],
+        thumbnail: None,
+        heatmap: None,
+    }
+}
+
+/// A motion event from [`CAMERA_ID`], as recorded in the database, not yet backed up
+pub fn event(id: &str, start_time: i64, end_time: i64) -> Event {
+    Event {
+        id: id.to_string(),
+        event_type: "motion".to_string(),
+        camera_id: CAMERA_ID.to_string(),
+        start_time,
+        end_time: Some(end_time),
+        backed_up: false,
+        skip_reason: None,
+        smart_detect_types: String::new(),
+        thumbnail_id: None,
+        heatmap_id: None,
+    }
+}
+
+/// An export which passes validation for events of up to a minute: an MP4 `ftyp` header, padded
+/// out past the default `min-export-bytes-per-second`
+pub fn video() -> Vec<u8> {
+    let mut video = b"\0\0\0\x18ftypmp42".to_vec();
+    video.resize(1024 * 1024, 0);
+    video
+}
@@ -0,0 +1,399 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tempfile::TempPath;
+use tokio::{fs::File, io::AsyncWriteExt, process::Command};
+use tracing::debug;
+
+use crate::{Error, Result};
+
+/// Largest `moov` box kept for [`ExportVerification::Mp4`]; an export with a larger one is
+/// rejected rather than held in memory
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How closely exports are checked before they're marked backed up, on top of the header and
+/// size checks of [`validate_export`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportVerification {
+    /// Only the header and size checks
+    #[default]
+    Basic,
+    /// Also read the duration from the MP4's movie header
+    Mp4,
+    /// Also have `ffprobe` read the whole export and report its duration
+    Ffprobe,
+}
+
+/// Sanity check an export downloaded from Protect before it is uploaded anywhere. Rejects empty
+/// downloads, anything that isn't an MP4 container and files too small to plausibly hold
+/// `duration_ms` of video.
+pub fn validate_export(
+    video_data: &[u8],
+    duration_ms: i64,
+    min_bytes_per_second: u64,
+) -> Result<()> {
+    validate_header(video_data)?;
+    validate_length(video_data.len() as u64, duration_ms, min_bytes_per_second)
+}
+
+/// The checks of [`validate_export`] that only need the start of the export, so a streamed
+/// export can be rejected before anything is uploaded.
+pub fn validate_header(video_data: &[u8]) -> Result<()> {
+    if video_data.is_empty() {
+        return Err(Error::InvalidExport("export is empty".to_string()));
+    }
+
+    // MP4 files start with an `ftyp` box: a 4 byte size followed by the box type
+    if video_data.len() < 8 || &video_data[4..8] != b"ftyp" {
+        return Err(Error::InvalidExport(
+            "export is not an MP4 (missing ftyp header)".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// The check of [`validate_export`] that needs the whole export, by its length in bytes
+pub fn validate_length(len: u64, duration_ms: i64, min_bytes_per_second: u64) -> Result<()> {
+    let duration_secs = (duration_ms.max(0) as u64) / 1000;
+    let min_size = duration_secs.saturating_mul(min_bytes_per_second);
+    if len < min_size {
+        return Err(Error::InvalidExport(format!(
+            "export is {} bytes, expected at least {min_size} bytes for {duration_secs}s of video",
+            len
+        )));
+    }
+
+    Ok(())
+}
+
+/// The duration an MP4 declares in its movie header (`moov/mvhd`), or `None` if it has no header
+/// or the header gives no duration.
+pub fn mp4_duration(video_data: &[u8]) -> Option<Duration> {
+    movie_duration(find_box(video_data, b"moov")?)
+}
+
+/// The duration declared by the contents of a `moov` box: its `mvhd`'s, or for a fragmented MP4
+/// whose `mvhd` gives none, its `mvex/mehd`'s
+fn movie_duration(moov: &[u8]) -> Option<Duration> {
+    let mvhd = find_box(moov, b"mvhd")?;
+
+    // version 1 headers have 64 bit creation and modification times and duration
+    let (timescale, mut duration) = match *mvhd.first()? {
+        0 => (read_u32(mvhd, 12)?, read_u32(mvhd, 16)? as u64),
+        1 => (read_u32(mvhd, 20)?, read_u64(mvhd, 24)?),
+        _ => return None,
+    };
+    if duration == 0 {
+        let mehd = find_box(find_box(moov, b"mvex")?, b"mehd")?;
+        duration = match *mehd.first()? {
+            0 => read_u32(mehd, 4)? as u64,
+            1 => read_u64(mehd, 4)?,
+            _ => return None,
+        };
+    }
+    if timescale == 0 || duration == 0 {
+        return None;
+    }
+
+    Some(Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+/// Checks how long an export plays for as it passes by chunk by chunk, see
+/// [`ExportVerification`]. Exports shorter than `duration_ms` by more than `tolerance` are
+/// rejected.
+pub struct ExportVerifier {
+    verification: ExportVerification,
+    duration_ms: i64,
+    tolerance: Duration,
+    boxes: BoxScanner,
+    /// Where the export is written for `ffprobe`, deleted on drop
+    file: Option<(File, TempPath)>,
+}
+
+impl ExportVerifier {
+    pub fn new(config: &crate::backup::Config, duration_ms: i64) -> Result<Self> {
+        let file = match config.verify_exports {
+            ExportVerification::Ffprobe => {
+                let (file, path) = tempfile::NamedTempFile::new()?.into_parts();
+                Some((File::from_std(file), path))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            verification: config.verify_exports,
+            duration_ms,
+            tolerance: config.integrity_drift_tolerance,
+            boxes: BoxScanner::default(),
+            file,
+        })
+    }
+
+    /// Take in the next chunk of the export
+    pub async fn feed(&mut self, chunk: &[u8]) -> Result<()> {
+        match self.verification {
+            ExportVerification::Basic => {}
+            ExportVerification::Mp4 => self.boxes.feed(chunk),
+            ExportVerification::Ffprobe => {
+                if let Some((file, _)) = &mut self.file {
+                    file.write_all(chunk).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the export now that all of it has been fed in
+    pub async fn finish(mut self) -> Result<()> {
+        let duration = match self.verification {
+            ExportVerification::Basic => return Ok(()),
+            ExportVerification::Mp4 => {
+                let moov = self.boxes.moov.take().ok_or_else(|| {
+                    Error::InvalidExport("export has no movie header (moov)".to_string())
+                })?;
+                movie_duration(&moov).ok_or_else(|| {
+                    Error::InvalidExport("export's movie header gives no duration".to_string())
+                })?
+            }
+            ExportVerification::Ffprobe => {
+                let Some((mut file, path)) = self.file.take() else {
+                    return Ok(());
+                };
+                file.flush().await?;
+                drop(file);
+                ffprobe_duration(&path).await?
+            }
+        };
+
+        let stored_ms = duration.as_millis() as i64;
+        debug!(
+            stored_ms,
+            expected_ms = self.duration_ms,
+            "Verified export duration"
+        );
+        if self.duration_ms - stored_ms > self.tolerance.as_millis() as i64 {
+            return Err(Error::InvalidExport(format!(
+                "export plays for {stored_ms}ms, expected {}ms",
+                self.duration_ms
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// How long `ffprobe` says the video at `path` plays for
+async fn ffprobe_duration(path: &std::path::Path) -> Result<Duration> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .output()
+        .await
+        .map_err(|e| Error::General(format!("Failed to run ffprobe: {e}")))?;
+
+    if !output.status.success() {
+        return Err(Error::InvalidExport(format!(
+            "ffprobe can't read the export: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| Error::InvalidExport("ffprobe reports no duration".to_string()))
+}
+
+/// Follows the top-level boxes of an MP4 as it streams past, keeping only the `moov` box
+#[derive(Default)]
+struct BoxScanner {
+    /// The header of the next box, while it's incomplete
+    header: Vec<u8>,
+    /// Bytes left of the current box
+    remaining: u64,
+    /// Whether the current box is the `moov` box, being kept
+    keeping: bool,
+    moov: Option<Vec<u8>>,
+    /// Set once the boxes stop making sense; the export then has no `moov` as far as we know
+    invalid: bool,
+}
+
+impl BoxScanner {
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() && !self.invalid {
+            if self.remaining > 0 {
+                let n = self.remaining.min(data.len() as u64) as usize;
+                if self.keeping {
+                    self.moov
+                        .get_or_insert_default()
+                        .extend_from_slice(&data[..n]);
+                }
+                self.remaining -= n as u64;
+                self.keeping &= self.remaining > 0;
+                data = &data[n..];
+                continue;
+            }
+
+            // a size of 1 means a 64 bit size follows the type
+            let header_len = match read_u32(&self.header, 0) {
+                Some(1) => 16,
+                _ => 8,
+            };
+            let n = (header_len - self.header.len()).min(data.len());
+            self.header.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.header.len() < 8 || (header_len == 16 && self.header.len() < 16) {
+                continue;
+            }
+            if header_len == 8 && read_u32(&self.header, 0) == Some(1) {
+                continue;
+            }
+
+            let size = match read_u32(&self.header, 0) {
+                // the box runs to the end of the file
+                Some(0) => u64::MAX,
+                Some(1) => read_u64(&self.header, 8).unwrap_or_default(),
+                size => size.unwrap_or_default() as u64,
+            };
+            let header_len = self.header.len() as u64;
+            if size < header_len {
+                self.invalid = true;
+                self.moov = None;
+                return;
+            }
+
+            self.keeping = &self.header[4..8] == b"moov";
+            if self.keeping && size - header_len > MAX_MOOV_BYTES {
+                self.invalid = true;
+                self.moov = None;
+                return;
+            }
+            if self.keeping {
+                self.moov = Some(Vec::with_capacity((size - header_len) as usize));
+            }
+            self.remaining = size - header_len;
+            self.header.clear();
+        }
+    }
+}
+
+/// The contents of the first box of type `kind` among the boxes making up `data`
+fn find_box<'a>(mut data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    while data.len() >= 8 {
+        let (header, size) = match read_u32(data, 0)? {
+            // the box runs to the end of its parent
+            0 => (8, data.len() as u64),
+            1 => (16, read_u64(data, 8)?),
+            size => (8, size as u64),
+        };
+        if size < header as u64 || size > data.len() as u64 {
+            return None;
+        }
+
+        let size = size as usize;
+        if &data[4..8] == kind {
+            return Some(&data[header..size]);
+        }
+        data = &data[size..];
+    }
+
+    None
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4(len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len.max(8)];
+        data[4..8].copy_from_slice(b"ftyp");
+        data
+    }
+
+    #[test]
+    fn test_validate_export() {
+        assert!(validate_export(&[], 10_000, 1024).is_err());
+        assert!(validate_export(&[0u8; 64], 10_000, 0).is_err());
+        assert!(validate_export(&mp4(1024), 10_000, 1024).is_err());
+        assert!(validate_export(&mp4(10 * 1024), 10_000, 1024).is_ok());
+    }
+
+    fn mp4_box(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut data = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    /// An MP4 whose movie header declares 30s
+    fn mp4_30s() -> Vec<u8> {
+        // version 0: flags, creation and modification time, then a timescale of 1000 and 30s
+        let mut mvhd = vec![0u8; 12];
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&30_000u32.to_be_bytes());
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)));
+        data
+    }
+
+    #[test]
+    fn test_mp4_duration() {
+        let mut data = mp4_30s();
+        assert_eq!(mp4_duration(&data), Some(Duration::from_secs(30)));
+        assert_eq!(mp4_duration(&mp4(1024)), None);
+
+        // streamed in chunks that split the box headers
+        data.extend(mp4_box(b"mdat", &[0u8; 100]));
+        let mut boxes = BoxScanner::default();
+        for chunk in data.chunks(3) {
+            boxes.feed(chunk);
+        }
+        let moov = boxes.moov.unwrap();
+        assert_eq!(movie_duration(&moov), Some(Duration::from_secs(30)));
+    }
+
+    #[tokio::test]
+    async fn test_export_verifier() {
+        let dir = tempfile::tempdir().unwrap();
+        let verify = |verification: &str, video: Vec<u8>, duration_ms: i64| {
+            let config = crate::testing::config(
+                &dir,
+                &format!("verify-exports = \"{verification}\"\nintegrity-drift-tolerance = \"2s\""),
+            )
+            .backup;
+            async move {
+                let mut verifier = ExportVerifier::new(&config, duration_ms)?;
+                for chunk in video.chunks(5) {
+                    verifier.feed(chunk).await?;
+                }
+                verifier.finish().await
+            }
+        };
+
+        // within the tolerance of the event's duration, or longer
+        let mut video = mp4_30s();
+        video.extend(mp4_box(b"mdat", &[0u8; 100]));
+        assert!(verify("mp4", video.clone(), 31_000).await.is_ok());
+        assert!(verify("mp4", video.clone(), 20_000).await.is_ok());
+        assert!(verify("mp4", video, 33_000).await.is_err());
+
+        // without a movie header it can't be told how long it plays for
+        let headerless = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        assert!(verify("mp4", headerless.clone(), 30_000).await.is_err());
+        assert!(verify("basic", headerless, 30_000).await.is_ok());
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use crate::{
+    ProtectClient, VideoStream,
+    error::Result,
+    events::{EventRecord, WebSocketMessage},
+    models::{Bootstrap, ExportQuality},
+    progress::{self, ProgressCallback},
+};
+
+/// The NVR as the backup service uses it. [`ProtectClient`] talks to a real one; tests can stand
+/// in [`MockProtectClient`](crate::mock::MockProtectClient) with the `mock` feature.
+#[async_trait]
+pub trait ProtectApi: Send + Sync {
+    async fn login(&self) -> Result<()>;
+    async fn get_bootstrap(&self) -> Result<Bootstrap>;
+    /// Export `[start, end]` (epoch milliseconds) of the camera's footage in one piece
+    async fn download_event_video(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+    ) -> Result<Vec<u8>>;
+    /// Like [`download_event_video`](Self::download_event_video), but yields the export as it
+    /// arrives
+    async fn stream_event_video(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+    ) -> Result<VideoStream>;
+    /// Like [`download_event_video`](Self::download_event_video), but calling `on_progress` as
+    /// each chunk of the export arrives, so a download that's stalled can be told from one
+    /// that's slow
+    async fn download_event_video_with_progress(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+        on_progress: ProgressCallback,
+    ) -> Result<Vec<u8>> {
+        let video = self
+            .stream_event_video(camera_id, start, end, quality)
+            .await?;
+        progress::collect(progress::track(video, on_progress)).await
+    }
+    /// Like [`download_event_video`](Self::download_event_video), but through an NVR export
+    /// job polled every `poll_interval` until it completes or `timeout` elapses
+    async fn export_video_via_job(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<Vec<u8>>;
+    /// Events overlapping `[start, end]` (epoch milliseconds)
+    async fn list_events(&self, start: i64, end: i64) -> Result<Vec<EventRecord>>;
+    /// A single event, by id
+    async fn get_event(&self, event_id: &str) -> Result<EventRecord>;
+    /// The JPEG thumbnail the NVR keeps for an event
+    async fn get_event_thumbnail(&self, event_id: &str) -> Result<Vec<u8>>;
+    /// A JPEG snapshot of the camera's recording at `ts` (epoch milliseconds)
+    async fn get_camera_snapshot(&self, camera_id: &str, ts: i64) -> Result<Vec<u8>>;
+    /// Messages from the NVR's updates websocket, in the order they arrive
+    async fn connect_websocket(&self) -> Result<mpsc::Receiver<WebSocketMessage>>;
+}
+
+#[async_trait]
+impl ProtectApi for ProtectClient {
+    async fn login(&self) -> Result<()> {
+        ProtectClient::login(self).await
+    }
+
+    async fn get_bootstrap(&self) -> Result<Bootstrap> {
+        ProtectClient::get_bootstrap(self).await
+    }
+
+    async fn download_event_video(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+    ) -> Result<Vec<u8>> {
+        ProtectClient::download_event_video(self, camera_id, start, end, quality).await
+    }
+
+    async fn stream_event_video(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+    ) -> Result<VideoStream> {
+        ProtectClient::stream_event_video(self, camera_id, start, end, quality).await
+    }
+
+    async fn export_video_via_job(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        ProtectClient::export_video_via_job(
+            self,
+            camera_id,
+            start,
+            end,
+            quality,
+            poll_interval,
+            timeout,
+        )
+        .await
+    }
+
+    async fn list_events(&self, start: i64, end: i64) -> Result<Vec<EventRecord>> {
+        ProtectClient::list_events(self, start, end).await
+    }
+
+    async fn get_event(&self, event_id: &str) -> Result<EventRecord> {
+        ProtectClient::get_event(self, event_id).await
+    }
+
+    async fn get_event_thumbnail(&self, event_id: &str) -> Result<Vec<u8>> {
+        ProtectClient::get_event_thumbnail(self, event_id).await
+    }
+
+    async fn get_camera_snapshot(&self, camera_id: &str, ts: i64) -> Result<Vec<u8>> {
+        ProtectClient::get_camera_snapshot(self, camera_id, ts).await
+    }
+
+    async fn connect_websocket(&self) -> Result<mpsc::Receiver<WebSocketMessage>> {
+        ProtectClient::connect_websocket(self).await
+    }
+}
@@ -1,4 +1,8 @@
-use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{rate_limit::RateLimitConfig, retry::RetryConfig};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(deserialize = "kebab-case"))]
@@ -6,6 +10,105 @@ pub struct UnifiConfig {
     pub address: String,
     pub port: u16,
     pub username: String,
-    pub password: String,
+    pub password: Secret<String>,
     pub verify_ssl: bool,
+    /// PEM-encoded CA certificate to trust in addition to the system roots, for controllers
+    /// using a self-signed certificate
+    #[serde(default)]
+    pub ca_cert_path: Option<PathBuf>,
+    #[serde(default, with = "humantime_serde")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub read_timeout: Option<Duration>,
+    /// HTTP proxy used for both API requests and the websocket, e.g. `http://proxy:3128`
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Requests per second to the controller, unlimited if unset
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// How often the bootstrap (camera names, settings) is fetched again to pick up changes
+    #[serde(
+        default = "default_bootstrap_refresh_interval",
+        with = "humantime_serde"
+    )]
+    pub bootstrap_refresh_interval: Duration,
+    /// How often a ping is sent over the updates websocket to keep it open
+    #[serde(default = "default_websocket_ping_interval", with = "humantime_serde")]
+    pub websocket_ping_interval: Duration,
+    /// How long the updates websocket can go without any traffic, pongs included, before it's
+    /// taken for dead and closed. A controller that reboots can leave the socket half open.
+    #[serde(default = "default_websocket_idle_timeout", with = "humantime_serde")]
+    pub websocket_idle_timeout: Duration,
+}
+
+pub(crate) fn default_bootstrap_refresh_interval() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+pub(crate) fn default_websocket_ping_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub(crate) fn default_websocket_idle_timeout() -> Duration {
+    Duration::from_secs(90)
+}
+
+/// A config value kept out of logs and traces, such as a password. It's shown and serialized as
+/// `<redacted>`; [`expose`](Self::expose) it only where the value itself is sent or used.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"<redacted>\"")
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_redacted() {
+        let secret = Secret::new("hunter2".to_string());
+
+        assert_eq!(format!("{secret:?}"), "\"<redacted>\"");
+        assert_eq!(
+            serde_json::to_string(&secret).expect("serializes"),
+            "\"<redacted>\""
+        );
+        assert_eq!(secret.expose(), "hunter2");
+
+        let secret: Secret<String> = serde_json::from_str("\"hunter2\"").expect("deserializes");
+        assert_eq!(secret.into_inner(), "hunter2");
+    }
 }
@@ -22,9 +22,16 @@ pub enum Error {
     #[error(transparent)]
     Toml(#[from] toml::de::Error),
 
+    #[cfg(feature = "native-tls")]
     #[error(transparent)]
     NativeTls(#[from] native_tls::Error),
 
+    #[error("TLS error: {0}")]
+    Tls(String),
+
+    #[error("Timeout: {0}")]
+    Timeout(String),
+
     #[error("Backup process failed: {0}")]
     Backup(String),
 
@@ -34,6 +41,9 @@ pub enum Error {
     #[error("API error: {0}")]
     Api(String),
 
+    #[error("Export not ready: {0}")]
+    ExportNotReady(String),
+
     #[error("Event processing error: {0}")]
     Event(String),
 
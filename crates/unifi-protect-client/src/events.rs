@@ -1,16 +1,28 @@
-use chrono::{DateTime, Utc};
+use chrono::{
+    DateTime, Utc,
+    format::{Item, StrftimeItems},
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::Error;
+use crate::{
+    Error,
+    models::{CameraUpdate, SensorUpdate},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtectEvent {
     pub id: String,
     pub camera_id: String,
     pub camera_name: Option<String>,
+    #[serde(default)]
+    pub camera_mac: Option<String>,
+    /// Name of the NVR the event was recorded on
+    #[serde(default)]
+    pub nvr_name: Option<String>,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
     pub event_type: EventType,
@@ -18,14 +30,27 @@ pub struct ProtectEvent {
     pub thumbnail_id: Option<String>,
     pub heatmap_id: Option<String>,
     pub is_finished: bool,
+    /// 1-based part number when a long event is exported in several parts
+    #[serde(default)]
+    pub part: Option<u32>,
+    /// Numbered from 2 when another event's backup already has the filename this one would get
+    #[serde(default)]
+    pub collision: Option<u32>,
 }
 
+/// Extension of the video exported for an event, `{ext}` in a filename format
+pub const VIDEO_EXTENSION: &str = "mp4";
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum EventType {
     Motion,
     Ring,
     Line,
     SmartDetect,
+    /// A `smartAudioDetect` event, e.g. a smoke alarm heard by the camera
+    Audio(AudioDetectType),
+    /// A clip recorded around a UP Sense sensor triggering, not an event on the NVR
+    Sensor(SensorTrigger),
 }
 
 impl Display for EventType {
@@ -35,6 +60,30 @@ impl Display for EventType {
             EventType::Ring => write!(f, "ring"),
             EventType::Line => write!(f, "line"),
             EventType::SmartDetect => write!(f, "smartdetect"),
+            EventType::Audio(kind) => write!(f, "audio_{kind}"),
+            EventType::Sensor(trigger) => write!(f, "sensor_{trigger}"),
+        }
+    }
+}
+
+impl FromStr for EventType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "motion" => Ok(EventType::Motion),
+            "ring" => Ok(EventType::Ring),
+            "line" => Ok(EventType::Line),
+            "smartdetect" | "smartdetectzone" => Ok(EventType::SmartDetect),
+            other => {
+                if let Some(kind) = other.strip_prefix("audio_") {
+                    Ok(EventType::Audio(kind.parse()?))
+                } else if let Some(trigger) = other.strip_prefix("sensor_") {
+                    Ok(EventType::Sensor(trigger.parse()?))
+                } else {
+                    Err(Error::Event(format!("Unknown event type: {s}")))
+                }
+            }
         }
     }
 }
@@ -49,6 +98,226 @@ pub enum SmartDetectType {
     LicensePlate,
 }
 
+impl Display for SmartDetectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmartDetectType::Person => write!(f, "person"),
+            SmartDetectType::Vehicle => write!(f, "vehicle"),
+            SmartDetectType::Package => write!(f, "package"),
+            SmartDetectType::Animal => write!(f, "animal"),
+            SmartDetectType::Face => write!(f, "face"),
+            SmartDetectType::LicensePlate => write!(f, "license_plate"),
+        }
+    }
+}
+
+impl FromStr for SmartDetectType {
+    type Err = Error;
+
+    /// Accepts both our `license_plate` form and Protect's `licensePlate`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "").as_str() {
+            "person" => Ok(SmartDetectType::Person),
+            "vehicle" => Ok(SmartDetectType::Vehicle),
+            "package" => Ok(SmartDetectType::Package),
+            "animal" => Ok(SmartDetectType::Animal),
+            "face" => Ok(SmartDetectType::Face),
+            "licenseplate" => Ok(SmartDetectType::LicensePlate),
+            _ => Err(Error::Event(format!("Unknown smart detect type: {s}"))),
+        }
+    }
+}
+
+/// What an audio detection heard
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum AudioDetectType {
+    Smoke,
+    CarbonMonoxide,
+    Siren,
+    GlassBreak,
+    BabyCry,
+    Bark,
+    Speak,
+    CarHorn,
+    Burglar,
+}
+
+impl Display for AudioDetectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioDetectType::Smoke => write!(f, "smoke"),
+            AudioDetectType::CarbonMonoxide => write!(f, "co"),
+            AudioDetectType::Siren => write!(f, "siren"),
+            AudioDetectType::GlassBreak => write!(f, "glass_break"),
+            AudioDetectType::BabyCry => write!(f, "baby_cry"),
+            AudioDetectType::Bark => write!(f, "bark"),
+            AudioDetectType::Speak => write!(f, "speak"),
+            AudioDetectType::CarHorn => write!(f, "car_horn"),
+            AudioDetectType::Burglar => write!(f, "burglar"),
+        }
+    }
+}
+
+impl FromStr for AudioDetectType {
+    type Err = Error;
+
+    /// Accepts both our `glass_break` form and Protect's `alrmGlassBreak`, and its older `smoke`
+    /// and `cmonx`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let kind = s.to_lowercase().replace('_', "");
+        match kind.strip_prefix("alrm").unwrap_or(&kind) {
+            "smoke" | "smokecmonx" => Ok(AudioDetectType::Smoke),
+            "co" | "cmonx" => Ok(AudioDetectType::CarbonMonoxide),
+            "siren" => Ok(AudioDetectType::Siren),
+            "glassbreak" => Ok(AudioDetectType::GlassBreak),
+            "babycry" => Ok(AudioDetectType::BabyCry),
+            "bark" => Ok(AudioDetectType::Bark),
+            "speak" => Ok(AudioDetectType::Speak),
+            "carhorn" => Ok(AudioDetectType::CarHorn),
+            "burglar" => Ok(AudioDetectType::Burglar),
+            _ => Err(Error::Event(format!("Unknown audio detect type: {s}"))),
+        }
+    }
+}
+
+/// What a UP Sense sensor reported
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorTrigger {
+    /// A door or window opened
+    Open,
+    /// A door or window closed
+    Close,
+    Motion,
+}
+
+impl Display for SensorTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SensorTrigger::Open => write!(f, "open"),
+            SensorTrigger::Close => write!(f, "close"),
+            SensorTrigger::Motion => write!(f, "motion"),
+        }
+    }
+}
+
+impl FromStr for SensorTrigger {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "open" => Ok(SensorTrigger::Open),
+            "close" => Ok(SensorTrigger::Close),
+            "motion" => Ok(SensorTrigger::Motion),
+            _ => Err(Error::Event(format!("Unknown sensor trigger: {s}"))),
+        }
+    }
+}
+
+impl SensorUpdate {
+    /// What the sensor reported in this update, each with when it happened if the update says
+    pub fn triggers(&self) -> Vec<(SensorTrigger, Option<i64>)> {
+        let mut triggers = vec![];
+        match self.is_opened {
+            Some(true) => triggers.push((SensorTrigger::Open, self.open_status_changed_at)),
+            Some(false) => triggers.push((SensorTrigger::Close, self.open_status_changed_at)),
+            None => {}
+        }
+        if self.is_motion_detected == Some(true) {
+            triggers.push((SensorTrigger::Motion, self.motion_detected_at));
+        }
+        triggers
+    }
+}
+
+/// How someone interacted with a doorbell or chime
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DoorbellAction {
+    Ring,
+    /// A chime sounded for a ring
+    Chime,
+    /// A message was shown on the doorbell's screen in answer to a ring
+    QuickReply,
+    Fingerprint,
+    Nfc,
+}
+
+impl Display for DoorbellAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DoorbellAction::Ring => write!(f, "ring"),
+            DoorbellAction::Chime => write!(f, "chime"),
+            DoorbellAction::QuickReply => write!(f, "quick_reply"),
+            DoorbellAction::Fingerprint => write!(f, "fingerprint"),
+            DoorbellAction::Nfc => write!(f, "nfc"),
+        }
+    }
+}
+
+/// A doorbell or chime interaction reported over the websocket
+#[derive(Debug, Clone)]
+pub struct DoorbellInteraction {
+    /// The doorbell camera, or the chime
+    pub device_id: String,
+    pub action: DoorbellAction,
+    /// Who was identified, for fingerprints and NFC cards
+    pub user_id: Option<String>,
+    /// The message shown, for quick replies
+    pub detail: Option<String>,
+    /// When it happened, in milliseconds since the epoch, if the message says
+    pub time: Option<i64>,
+}
+
+/// Labels Protect read from an event's detections, such as a vehicle's colour or type, and
+/// the text of licence plates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventLabels {
+    pub tags: Vec<String>,
+    pub plates: Vec<String>,
+}
+
+impl EventLabels {
+    /// From an event's `metadata`: plates from `licensePlate` and the names of detected
+    /// thumbnails, tags from the values of those thumbnails' attributes
+    pub fn from_metadata(metadata: &Value) -> Self {
+        let mut labels = Self::default();
+        let text = |value: Option<&Value>| {
+            value
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .map(ToString::to_string)
+        };
+
+        labels
+            .plates
+            .extend(text(metadata.pointer("/licensePlate/name")));
+        let thumbnails = metadata.get("detectedThumbnails").and_then(Value::as_array);
+        for thumbnail in thumbnails.into_iter().flatten() {
+            labels.plates.extend(text(thumbnail.get("name")));
+            let attributes = thumbnail.get("attributes").and_then(Value::as_object);
+            for attribute in attributes.into_iter().flat_map(|a| a.values()) {
+                labels.tags.extend(text(attribute.get("val")));
+            }
+        }
+
+        for values in [&mut labels.tags, &mut labels.plates] {
+            values.sort();
+            values.dedup();
+        }
+        labels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.plates.is_empty()
+    }
+}
+
+/// The first audio detection type among `types`, as Protect lists them in `smartDetectTypes`
+fn audio_detect_type<'a>(types: impl IntoIterator<Item = &'a str>) -> Option<AudioDetectType> {
+    types.into_iter().find_map(|t| t.parse().ok())
+}
+
 impl ProtectEvent {
     #[tracing::instrument(skip(self))]
     pub fn should_backup(&self, detection_types: &[String]) -> bool {
@@ -60,22 +329,17 @@ impl ProtectEvent {
             EventType::Motion => detection_types.contains(&"motion".to_string()),
             EventType::Ring => detection_types.contains(&"ring".to_string()),
             EventType::Line => detection_types.contains(&"line".to_string()),
-            EventType::SmartDetect => {
-                for smart_type in &self.smart_detect_types {
-                    let type_str = match smart_type {
-                        SmartDetectType::Person => "person",
-                        SmartDetectType::Vehicle => "vehicle",
-                        SmartDetectType::Package => "package",
-                        SmartDetectType::Animal => "animal",
-                        SmartDetectType::Face => "face",
-                        SmartDetectType::LicensePlate => "license_plate",
-                    };
-
-                    if detection_types.contains(&type_str.to_string()) {
-                        return true;
-                    }
-                }
-                false
+            EventType::SmartDetect => self
+                .smart_detect_types
+                .iter()
+                .any(|smart_type| detection_types.contains(&smart_type.to_string())),
+            EventType::Audio(kind) => {
+                detection_types.contains(&"audio".to_string())
+                    || detection_types.contains(&kind.to_string())
+            }
+            EventType::Sensor(_) => {
+                detection_types.contains(&"sensor".to_string())
+                    || detection_types.contains(&self.event_type.to_string())
             }
         }
     }
@@ -93,24 +357,21 @@ impl ProtectEvent {
                     let types: Vec<String> = self
                         .smart_detect_types
                         .iter()
-                        .map(|t| match t {
-                            SmartDetectType::Person => "person",
-                            SmartDetectType::Vehicle => "vehicle",
-                            SmartDetectType::Package => "package",
-                            SmartDetectType::Animal => "animal",
-                            SmartDetectType::Face => "face",
-                            SmartDetectType::LicensePlate => "license_plate",
-                        })
-                        .map(|s| s.to_string())
+                        .map(ToString::to_string)
                         .collect();
                     types.join("_")
                 }
             }
+            EventType::Audio(kind) => kind.to_string(),
+            EventType::Sensor(_) => self.event_type.to_string(),
         }
     }
 
+    /// `format_string` with the event's details in place of its `{placeholders}` and strftime
+    /// specifiers, which are filled in from the start time in UTC. Characters which can't be in a
+    /// path component are swapped for `replacement` in the details, see [`sanitize`].
     #[tracing::instrument(skip(self))]
-    pub fn format_filename(&self, format_string: &str) -> String {
+    pub fn format_filename(&self, format_string: &str, replacement: char) -> String {
         let start_time = self.start_time.map_or_else(Utc::now, |t| {
             DateTime::<Utc>::from_timestamp_millis(t).unwrap_or_else(Utc::now)
         });
@@ -119,27 +380,87 @@ impl ProtectEvent {
             .map(|t| DateTime::<Utc>::from_timestamp_millis(t).unwrap_or_else(Utc::now));
 
         let detection_type = self.format_detection_type();
-        let start_date = start_time.format("%Y-%m-%d");
-        let start_time = start_time.format("%H-%M-%S");
-        let end_time = end_time
+        let duration = end_time
+            .map(|end| (end - start_time).num_seconds().max(0).to_string())
+            .unwrap_or_else(|| "ongoing".to_string());
+        let end = end_time
             .map(|e| e.format("%H-%M-%S").to_string())
             .unwrap_or_else(|| "ongoing".to_string());
 
-        format_string
-            .replace(
-                "{camera_name}",
-                &self
-                    .camera_name
-                    .clone()
-                    .unwrap_or_else(|| "Unknown".to_string()),
-            )
-            .replace("{camera_id}", &self.camera_id)
-            .replace("{date}", &start_date.to_string())
-            .replace("{time}", &start_time.to_string())
-            .replace("{end_time}", &end_time)
+        // parts and colliding events must never overwrite each other, even if the format doesn't
+        // mention them
+        let mut format_string = format_string.to_string();
+        if self.part.is_some() && !format_string.contains("{part}") {
+            format_string = with_suffix(&format_string, "_part{part}");
+        }
+        if self.collision.is_some() && !format_string.contains("{collision}") {
+            format_string = with_suffix(&format_string, "_{collision}");
+        }
+        let part = self.part.map(|p| p.to_string()).unwrap_or_default();
+        let collision = self.collision.map(|c| c.to_string()).unwrap_or_default();
+        let detail =
+            |value: Option<&String>| sanitize(value.map_or("Unknown", String::as_str), replacement);
+
+        // before the placeholders, so a `%` in a camera name isn't taken for a specifier
+        strftime(&format_string, start_time)
+            .replace("{camera_name}", &detail(self.camera_name.as_ref()))
+            .replace("{camera_id}", &detail(Some(&self.camera_id)))
+            .replace("{camera_mac}", &detail(self.camera_mac.as_ref()))
+            .replace("{nvr_name}", &detail(self.nvr_name.as_ref()))
+            .replace("{date}", &start_time.format("%Y-%m-%d").to_string())
+            .replace("{time}", &start_time.format("%H-%M-%S").to_string())
+            .replace("{end_time}", &end)
+            .replace("{duration}", &duration)
+            .replace("{event_type}", &self.event_type.to_string())
             .replace("{detection_type}", &detection_type)
-            .replace("{event_id}", &self.id)
+            .replace("{event_id}", &detail(Some(&self.id)))
+            .replace("{part}", &part)
+            .replace("{collision}", &collision)
+            .replace("{ext}", VIDEO_EXTENSION)
+    }
+}
+
+/// `format` with `suffix` before the extension of its last path component, or at the end if that
+/// has none. A dot in a directory, e.g. `%Y.%m.%d/{event_id}`, isn't taken for an extension.
+fn with_suffix(format: &str, suffix: &str) -> String {
+    let name_start = format.rfind('/').map_or(0, |slash| slash + 1);
+    match format[name_start..].rfind('.') {
+        // a leading dot names a hidden file rather than starting an extension
+        Some(dot) if dot > 0 => {
+            let (stem, extension) = format.split_at(name_start + dot);
+            format!("{stem}{suffix}{extension}")
+        }
+        _ => format!("{format}{suffix}"),
+    }
+}
+
+/// `value` made safe to use as (part of) one path component: path separators, `:` and control
+/// characters are swapped for `replacement`, as is a value of only dots
+pub fn sanitize(value: &str, replacement: char) -> String {
+    if !value.is_empty() && value.chars().all(|c| c == '.') {
+        return replacement.to_string().repeat(value.chars().count());
+    }
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' => replacement,
+            c if c.is_control() => replacement,
+            c => c,
+        })
+        .collect()
+}
+
+/// `format` with its strftime specifiers filled in from `time`. A format with an invalid
+/// specifier is left as it is, rather than panicking.
+fn strftime(format: &str, time: DateTime<Utc>) -> String {
+    if !format.contains('%') {
+        return format.to_string();
+    }
+    let items = StrftimeItems::new(format);
+    if items.clone().any(|item| matches!(item, Item::Error)) {
+        return format.to_string();
     }
+    time.format_with_items(items).to_string()
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -147,6 +468,9 @@ impl ProtectEvent {
 pub struct WebSocketMessage {
     pub action_frame: WebSocketActionFrame,
     pub data_frame: WebSocketDataFrame,
+    /// 1-based position of this message's frame on its connection. Frames which couldn't be
+    /// parsed still take a number, so a gap means messages were lost.
+    pub sequence: u64,
 }
 
 impl WebSocketMessage {
@@ -159,6 +483,221 @@ impl WebSocketMessage {
         Ok(WebSocketMessage {
             action_frame,
             data_frame,
+            sequence: 0,
+        })
+    }
+
+    /// Smart detection types reported on an event update; unrecognised types are dropped.
+    pub fn smart_detect_types(&self) -> Vec<SmartDetectType> {
+        self.data_frame
+            .extra_fields
+            .get("smartDetectTypes")
+            .and_then(Value::as_array)
+            .map(|types| {
+                types
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .filter_map(|t| t.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// What an audio detection heard, from its `smartDetectTypes`
+    pub fn audio_detect_type(&self) -> Option<AudioDetectType> {
+        let types = self
+            .data_frame
+            .extra_fields
+            .get("smartDetectTypes")
+            .and_then(Value::as_array)?;
+        audio_detect_type(types.iter().filter_map(Value::as_str))
+    }
+
+    pub fn thumbnail_id(&self) -> Option<String> {
+        self.extra_string("thumbnail")
+    }
+
+    pub fn heatmap_id(&self) -> Option<String> {
+        self.extra_string("heatmap")
+    }
+
+    fn extra_string(&self, key: &str) -> Option<String> {
+        self.data_frame
+            .extra_fields
+            .get(key)
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+    }
+
+    /// If this message announces a newly adopted camera, its id.
+    pub fn camera_added(&self) -> Option<&str> {
+        (self.action_frame.action == WebSocketAction::Add
+            && self.action_frame.model_key == ModelKey::Camera)
+            .then_some(self.action_frame.id.as_str())
+    }
+
+    /// If this message announces a camera being removed from the NVR, its id.
+    pub fn camera_removed(&self) -> Option<&str> {
+        (self.action_frame.action == WebSocketAction::Remove
+            && self.action_frame.model_key == ModelKey::Camera)
+            .then_some(self.action_frame.id.as_str())
+    }
+
+    /// If this message announces an event being deleted on the NVR, its id.
+    pub fn event_removed(&self) -> Option<&str> {
+        (self.action_frame.action == WebSocketAction::Remove
+            && self.action_frame.model_key == ModelKey::Event)
+            .then_some(self.action_frame.id.as_str())
+    }
+
+    /// If this message reports a doorbell ring, a person identified at a doorbell, a quick reply
+    /// shown on one or a chime sounding, what happened.
+    pub fn doorbell_interaction(&self) -> Option<DoorbellInteraction> {
+        let interaction = |device_id: &str, action, time| DoorbellInteraction {
+            device_id: device_id.to_string(),
+            action,
+            user_id: None,
+            detail: None,
+            time,
+        };
+        let fields = &self.data_frame.extra_fields;
+
+        match (&self.action_frame.action, &self.action_frame.model_key) {
+            (WebSocketAction::Add, ModelKey::Event) => {
+                let device_id = self
+                    .extra_string("camera")
+                    .or_else(|| self.action_frame.record_id.clone())?;
+                let metadata = fields.get("metadata");
+                let user = |path: &[&str]| {
+                    path.iter()
+                        .try_fold(metadata?, |value, key| value.get(key))
+                        .and_then(Value::as_str)
+                        .map(ToString::to_string)
+                };
+                let (action, user_id) = match self.data_frame.kind.as_ref()? {
+                    Kind::Ring => (DoorbellAction::Ring, None),
+                    Kind::FingerprintIdentified => {
+                        (DoorbellAction::Fingerprint, user(&["fingerprint", "ulpId"]))
+                    }
+                    Kind::NfcCardScanned => (
+                        DoorbellAction::Nfc,
+                        user(&["nfc", "userId"]).or_else(|| user(&["nfc", "nfcId"])),
+                    ),
+                    _ => return None,
+                };
+                Some(DoorbellInteraction {
+                    user_id,
+                    ..interaction(&device_id, action, self.data_frame.start)
+                })
+            }
+            (WebSocketAction::Update, ModelKey::Chime) => {
+                let last_ring = fields.get("lastRing").and_then(Value::as_i64)?;
+                Some(interaction(
+                    &self.action_frame.id,
+                    DoorbellAction::Chime,
+                    Some(last_ring),
+                ))
+            }
+            (WebSocketAction::Update, ModelKey::Camera) => {
+                // cleared messages come through as null, or without a type
+                let message = fields.get("lcdMessage")?;
+                let kind = message.get("type").and_then(Value::as_str)?;
+                let text = message.get("text").and_then(Value::as_str).unwrap_or(kind);
+                Some(DoorbellInteraction {
+                    detail: Some(text.to_string()),
+                    ..interaction(&self.action_frame.id, DoorbellAction::QuickReply, None)
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// If this message carries labels for an event's detections, the event's id and them.
+    pub fn event_labels(&self) -> Option<(&str, EventLabels)> {
+        if self.action_frame.model_key != ModelKey::Event {
+            return None;
+        }
+        let metadata = self.data_frame.extra_fields.get("metadata")?;
+        let labels = EventLabels::from_metadata(metadata);
+        (!labels.is_empty()).then_some((self.action_frame.id.as_str(), labels))
+    }
+
+    /// If this message is an update to a sensor, its id and the subset of fields we track.
+    pub fn sensor_update(&self) -> Option<(&str, SensorUpdate)> {
+        if self.action_frame.action != WebSocketAction::Update
+            || self.action_frame.model_key != ModelKey::Sensor
+        {
+            return None;
+        }
+
+        let fields = Value::Object(self.data_frame.extra_fields.clone().into_iter().collect());
+        serde_json::from_value(fields)
+            .inspect_err(|e| warn!(error = ?e, "Error parsing sensor update"))
+            .ok()
+            .map(|update| (self.action_frame.id.as_str(), update))
+    }
+
+    /// If this message is an update to a camera, the subset of fields we track.
+    pub fn camera_update(&self) -> Option<CameraUpdate> {
+        if self.action_frame.action != WebSocketAction::Update
+            || self.action_frame.model_key != ModelKey::Camera
+        {
+            return None;
+        }
+
+        let fields = Value::Object(self.data_frame.extra_fields.clone().into_iter().collect());
+        serde_json::from_value(fields)
+            .inspect_err(|e| warn!(error = ?e, "Error parsing camera update"))
+            .ok()
+    }
+}
+
+/// An event as returned by the events API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct EventRecord {
+    pub id: String,
+    #[serde(rename(deserialize = "type"))]
+    pub kind: String,
+    pub camera: Option<String>,
+    pub start: i64,
+    pub end: Option<i64>,
+    #[serde(default)]
+    pub smart_detect_types: Vec<String>,
+    pub thumbnail: Option<String>,
+    pub heatmap: Option<String>,
+}
+
+impl EventRecord {
+    /// `None` for events without a camera or of a type we don't back up.
+    pub fn to_protect_event(&self, camera_name: Option<String>) -> Option<ProtectEvent> {
+        let camera_id = self.camera.clone()?;
+        let event_type = match self.kind.as_str() {
+            "smartAudioDetect" => EventType::Audio(audio_detect_type(
+                self.smart_detect_types.iter().map(String::as_str),
+            )?),
+            kind => kind.parse().ok()?,
+        };
+
+        Some(ProtectEvent {
+            id: self.id.clone(),
+            camera_id,
+            camera_name,
+            camera_mac: None,
+            nvr_name: None,
+            start_time: Some(self.start),
+            end_time: self.end,
+            event_type,
+            smart_detect_types: self
+                .smart_detect_types
+                .iter()
+                .filter_map(|t| t.parse().ok())
+                .collect(),
+            thumbnail_id: self.thumbnail.clone(),
+            heatmap_id: self.heatmap.clone(),
+            is_finished: self.end.is_some(),
+            part: None,
+            collision: None,
         })
     }
 }
@@ -169,6 +708,20 @@ pub struct ProtectWebSocketRawFrames {
     pub data: String,
 }
 
+impl ProtectWebSocketRawFrames {
+    /// Encode as the NVR sends them: each frame is an 8-byte header (type, JSON format,
+    /// uncompressed, reserved, big-endian length) followed by its JSON
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut binary = Vec::with_capacity(16 + self.action.len() + self.data.len());
+        for (packet_type, json) in [(1u8, &self.action), (2u8, &self.data)] {
+            binary.extend_from_slice(&[packet_type, 1, 0, 0]);
+            binary.extend_from_slice(&(json.len() as u32).to_be_bytes());
+            binary.extend_from_slice(json.as_bytes());
+        }
+        binary
+    }
+}
+
 impl TryFrom<&[u8]> for ProtectWebSocketRawFrames {
     type Error = Error;
 
@@ -271,6 +824,10 @@ pub enum ModelKey {
 #[serde(rename_all(deserialize = "camelCase"))]
 pub enum Kind {
     Motion,
+    SmartAudioDetect,
+    Ring,
+    FingerprintIdentified,
+    NfcCardScanned,
     #[serde(untagged)]
     Unknown(String),
 }
@@ -293,4 +850,37 @@ pub enum WebSocketAction {
     Add,
     #[serde(rename = "update")]
     Update,
+    #[serde(rename = "remove")]
+    Remove,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_suffix_goes_before_the_file_extension() {
+        let cases = [
+            (
+                "{camera_name}/{date}_{time}.mp4",
+                "{camera_name}/{date}_{time}_part{part}.mp4",
+            ),
+            (
+                "{camera_name}/{event_id}",
+                "{camera_name}/{event_id}_part{part}",
+            ),
+            ("%Y.%m.%d/{event_id}", "%Y.%m.%d/{event_id}_part{part}"),
+            (
+                "%Y.%m.%d/{event_id}.{ext}",
+                "%Y.%m.%d/{event_id}_part{part}.{ext}",
+            ),
+            (
+                "{camera_name}/.{event_id}",
+                "{camera_name}/.{event_id}_part{part}",
+            ),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(with_suffix(format, "_part{part}"), expected, "{format}");
+        }
+    }
 }
@@ -1,26 +1,39 @@
-use std::sync::Arc;
+use std::{pin::Pin, sync::Arc, time::Duration};
 
 use arc_swap::ArcSwap;
-use futures_util::StreamExt;
-use reqwest::{Client, RequestBuilder, Response, Url};
+use bytes::Bytes;
+use futures_util::{SinkExt, Stream, StreamExt};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
 use serde_json::Value;
 use tokio::sync::{Mutex, mpsc};
-use tokio_tungstenite::{
-    Connector, connect_async, connect_async_tls_with_config, tungstenite::Message,
-};
-use tracing::{error, info, warn};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, error, info, warn};
 
 use crate::{
     config::UnifiConfig,
     error::{Error, Result},
-    events::WebSocketMessage,
-    models::{Bootstrap, BootstrapRawResponse},
+    events::{EventRecord, WebSocketMessage},
+    models::{Bootstrap, BootstrapRawResponse, ExportJob, ExportJobStatus, ExportQuality},
+    rate_limit::RateLimiter,
 };
 
+pub mod api;
 pub mod config;
 pub mod error;
 pub mod events;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+#[cfg(feature = "mock-server")]
+pub mod mock_server;
 pub mod models;
+mod net;
+pub mod progress;
+pub mod rate_limit;
+pub mod retry;
+mod tls;
+
+/// A video export as it downloads, chunk by chunk
+pub type VideoStream = Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>;
 
 pub struct ProtectClient {
     client: Client,
@@ -29,6 +42,7 @@ pub struct ProtectClient {
     auth: ArcSwap<Auth>,
     // Mutex to prevent concurrent reauthentication attempts
     auth_mutex: Mutex<()>,
+    rate_limiter: RateLimiter,
 }
 
 struct Auth {
@@ -39,8 +53,8 @@ struct Auth {
 impl ProtectClient {
     #[tracing::instrument(skip(config))]
     pub fn new(config: UnifiConfig) -> Result<Self> {
-        let client = Client::builder()
-            .danger_accept_invalid_certs(!config.verify_ssl)
+        let client = tls::configure_http_client(Client::builder(), &config)
+            .and_then(|builder| net::configure_http_client(builder, &config))?
             .build()?;
 
         let base_url = Url::parse(&format!("https://{}:{}", config.address, config.port))
@@ -49,6 +63,7 @@ impl ProtectClient {
         Ok(ProtectClient {
             client,
             base_url,
+            rate_limiter: RateLimiter::new(config.rate_limit.clone()),
             config,
             auth: ArcSwap::new(Arc::new(Auth {
                 csrf_token: None,
@@ -67,12 +82,18 @@ impl ProtectClient {
 
         let login_data = serde_json::json!({
             "username": self.config.username,
-            "password": self.config.password,
+            "password": self.config.password.expose(),
             "remember": false
         });
 
+        self.rate_limiter.acquire().await;
         let response = self.client.post(login_url).json(&login_data).send().await?;
 
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            // repeated logins are what get an account locked, so back off before any more
+            let delay = retry::retry_after(&response).unwrap_or(self.config.retry.max_backoff);
+            self.rate_limiter.pause(delay);
+        }
         if !response.status().is_success() {
             return Err(Error::Auth(format!("Login failed: {}", response.status())));
         }
@@ -121,31 +142,57 @@ impl ProtectClient {
         builder
     }
 
-    /// Execute a request with automatic reauthentication on 401
+    /// Execute a request with automatic reauthentication on 401, retrying transient failures
+    /// (429/5xx, timeouts and connection errors) with jittered exponential backoff
     #[tracing::instrument(skip(self, request_fn))]
     async fn execute_with_retry<F, Fut>(&self, request_fn: F) -> Result<Response>
     where
         F: Fn() -> Fut,
         Fut: std::future::Future<Output = Result<Response>>,
     {
-        const MAX_RETRIES: usize = 2;
+        const MAX_REAUTHENTICATIONS: usize = 2;
+
+        let retry = &self.config.retry;
+        let mut reauthentications = 0;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            self.rate_limiter.acquire().await;
+            let response = match request_fn().await {
+                Ok(response) => response,
+                Err(err) if retry::is_transient_error(&err) && attempt < retry.max_attempts => {
+                    let delay = retry.backoff(attempt);
+                    warn!(
+                        err = ?err,
+                        attempt = attempt,
+                        max_attempts = retry.max_attempts,
+                        delay = ?delay,
+                        "Transient error, retrying",
+                    );
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
 
-        for attempt in 0..=MAX_RETRIES {
-            let response = request_fn().await?;
+            if response.status().as_u16() == 401 && reauthentications < MAX_REAUTHENTICATIONS {
+                reauthentications += 1;
 
-            if response.status().as_u16() == 401 && attempt < MAX_RETRIES {
                 // Use mutex to prevent concurrent reauthentication
                 let _guard = self.auth_mutex.lock().await;
 
                 // Check if another thread already reauthenticated
+                self.rate_limiter.acquire().await;
                 let test_response = request_fn().await?;
                 if test_response.status().as_u16() != 401 {
                     return Ok(test_response);
                 }
 
                 info!(
-                    attempt = attempt,
-                    max_retries = MAX_RETRIES,
+                    attempt = reauthentications,
+                    max_retries = MAX_REAUTHENTICATIONS,
                     "Session expired, attempting re-authentication",
                 );
 
@@ -153,8 +200,8 @@ impl ProtectClient {
                 self.login().await.inspect_err(|e| {
                     error!(
                         err = ?e,
-                        attempt = attempt,
-                        max_retries = MAX_RETRIES,
+                        attempt = reauthentications,
+                        max_retries = MAX_REAUTHENTICATIONS,
                         "Failed to re-authenticate"
                     )
                 })?;
@@ -162,10 +209,34 @@ impl ProtectClient {
                 continue;
             }
 
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                // every request waits out the controller's rate limit, not just this one, and
+                // for as long as it asks
+                let delay = retry::retry_after(&response).unwrap_or_else(|| retry.backoff(attempt));
+                warn!(delay = ?delay, "Rate limited by the controller, pausing requests");
+                self.rate_limiter.pause(delay);
+                if attempt < retry.max_attempts {
+                    continue;
+                }
+            }
+
+            if retry::is_transient_status(response.status()) && attempt < retry.max_attempts {
+                let delay = retry::retry_after(&response)
+                    .map(|delay| delay.min(retry.max_backoff))
+                    .unwrap_or_else(|| retry.backoff(attempt));
+                warn!(
+                    status = %response.status(),
+                    attempt = attempt,
+                    max_attempts = retry.max_attempts,
+                    delay = ?delay,
+                    "Transient response, retrying",
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+
             return Ok(response);
         }
-
-        unreachable!("Loop should have returned by now")
     }
 
     #[tracing::instrument(skip(self))]
@@ -203,11 +274,43 @@ impl ProtectClient {
         camera_id: &str,
         start: i64,
         end: i64,
+        quality: ExportQuality,
     ) -> Result<Vec<u8>> {
+        let response = self.request_export(camera_id, start, end, quality).await?;
+        let video_data = response.bytes().await?;
+        Ok(video_data.to_vec())
+    }
+
+    /// Like [`download_event_video`](Self::download_event_video), but yields the export as it
+    /// arrives rather than holding all of it in memory.
+    #[tracing::instrument(skip(self))]
+    pub async fn stream_event_video(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+    ) -> Result<VideoStream> {
+        let response = self.request_export(camera_id, start, end, quality).await?;
+        Ok(Box::pin(
+            response
+                .bytes_stream()
+                .map(|chunk| chunk.map_err(Into::into)),
+        ))
+    }
+
+    async fn request_export(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+    ) -> Result<Response> {
         let download_url = self
             .base_url
             .join(&format!(
-                "/proxy/protect/api/video/export?camera={camera_id}&start={start}&end={end}",
+                "/proxy/protect/api/video/export?camera={camera_id}&start={start}&end={end}&channel={}",
+                quality.channel()
             ))
             .map_err(|e| Error::General(format!("Invalid URL: {e}")))?;
 
@@ -220,15 +323,264 @@ impl ProtectClient {
             .await?;
 
         if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+
+            // Protect can't export footage it hasn't flushed to disk yet
+            if status == StatusCode::NOT_FOUND || is_export_not_ready(&body) {
+                return Err(Error::ExportNotReady(format!(
+                    "{status} for camera {camera_id}: {body}"
+                )));
+            }
+
             return Err(Error::Api(format!(
-                "Video download failed: {} for camera {}",
-                response.status(),
-                camera_id
+                "Video download failed: {status} for camera {camera_id}: {body}"
             )));
         }
 
-        let video_data = response.bytes().await?;
-        Ok(video_data.to_vec())
+        Ok(response)
+    }
+
+    /// Events overlapping `[start, end]` (epoch milliseconds)
+    #[tracing::instrument(skip(self))]
+    pub async fn list_events(&self, start: i64, end: i64) -> Result<Vec<EventRecord>> {
+        let url = self.api_url(&format!(
+            "/proxy/protect/api/events?start={start}&end={end}"
+        ))?;
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.get(url.clone()));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Api(format!(
+                "Listing events failed: {}",
+                response.status()
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// A single event, by id
+    #[tracing::instrument(skip(self))]
+    pub async fn get_event(&self, event_id: &str) -> Result<EventRecord> {
+        let url = self.api_url(&format!("/proxy/protect/api/events/{event_id}"))?;
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.get(url.clone()));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Api(format!(
+                "Fetching event {event_id} failed: {}",
+                response.status()
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// The JPEG thumbnail the NVR keeps for an event
+    #[tracing::instrument(skip(self))]
+    pub async fn get_event_thumbnail(&self, event_id: &str) -> Result<Vec<u8>> {
+        let url = self.api_url(&format!("/proxy/protect/api/events/{event_id}/thumbnail"))?;
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.get(url.clone()));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Api(format!(
+                "Thumbnail request failed: {} for event {event_id}",
+                response.status()
+            )));
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// A JPEG snapshot of the camera's recording at `ts` (epoch milliseconds)
+    #[tracing::instrument(skip(self))]
+    pub async fn get_camera_snapshot(&self, camera_id: &str, ts: i64) -> Result<Vec<u8>> {
+        let url = self.api_url(&format!(
+            "/proxy/protect/api/cameras/{camera_id}/snapshot?ts={ts}&force=true"
+        ))?;
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.get(url.clone()));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Api(format!(
+                "Snapshot request failed: {} for camera {camera_id}",
+                response.status()
+            )));
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn list_export_jobs(&self) -> Result<Vec<ExportJob>> {
+        let url = self.api_url("/proxy/protect/api/exports")?;
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.get(url.clone()));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Api(format!(
+                "Listing export jobs failed: {}",
+                response.status()
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn create_export_job(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+    ) -> Result<ExportJob> {
+        let url = self.api_url("/proxy/protect/api/exports")?;
+        let body = serde_json::json!({
+            "camera": camera_id,
+            "start": start,
+            "end": end,
+            "channel": quality.channel(),
+        });
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.post(url.clone()).json(&body));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::Api(format!(
+                "Creating export job failed: {status} for camera {camera_id}: {body}"
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_export_job(&self, id: &str) -> Result<ExportJob> {
+        let url = self.api_url(&format!("/proxy/protect/api/exports/{id}"))?;
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.get(url.clone()));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Api(format!(
+                "Export job {id} request failed: {}",
+                response.status()
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn download_export_job(&self, id: &str) -> Result<Vec<u8>> {
+        let url = self.api_url(&format!("/proxy/protect/api/exports/{id}/download"))?;
+
+        let response = self
+            .execute_with_retry(|| {
+                let request = self.add_headers(self.client.get(url.clone()));
+                async move { request.send().await.map_err(Into::into) }
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Api(format!(
+                "Export job {id} download failed: {}",
+                response.status()
+            )));
+        }
+
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Export a range through an NVR export job: create the job, poll it every `poll_interval`
+    /// until it completes (or `timeout` elapses) and download the result.
+    #[tracing::instrument(skip(self))]
+    pub async fn export_video_via_job(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        quality: ExportQuality,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        let mut job = self
+            .create_export_job(camera_id, start, end, quality)
+            .await?;
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            match job.status {
+                ExportJobStatus::Completed => return self.download_export_job(&job.id).await,
+                ExportJobStatus::Failed => {
+                    return Err(Error::Api(format!(
+                        "Export job {} failed: {}",
+                        job.id,
+                        job.error.unwrap_or_default()
+                    )));
+                }
+                _ if tokio::time::Instant::now() >= deadline => {
+                    return Err(Error::Timeout(format!(
+                        "Export job {} did not complete within {timeout:?}",
+                        job.id
+                    )));
+                }
+                _ => {}
+            }
+
+            tokio::time::sleep(poll_interval).await;
+            job = self.get_export_job(&job.id).await?;
+            debug!(
+                job_id = job.id,
+                status = ?job.status,
+                progress = job.progress,
+                "Polled export job"
+            );
+        }
+    }
+
+    fn api_url(&self, path: &str) -> Result<Url> {
+        self.base_url
+            .join(path)
+            .map_err(|e| Error::General(format!("Invalid URL: {e}")))
     }
 
     // async fn authenticated_request(&self, request_builder: RequestBuilder) -> Result<Response> {
@@ -269,34 +621,50 @@ impl ProtectClient {
             );
         }
 
-        let (ws_stream, _) = match self.config.verify_ssl {
-            true => connect_async(request).await?,
-            false => {
-                // Create TLS connector that accepts invalid certificates
-                let tls_connector = native_tls::TlsConnector::builder()
-                    .danger_accept_invalid_certs(true)
-                    .danger_accept_invalid_hostnames(true)
-                    .build()?;
-
-                let connector = Connector::NativeTls(tls_connector);
-                connect_async_tls_with_config(request, None, false, Some(connector)).await?
-            }
-        };
+        let ws_stream = net::connect_websocket(&self.config, request).await?;
 
-        let (_ws_sender, mut ws_receiver) = ws_stream.split();
+        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
         let (tx, rx) = mpsc::channel(100);
+        let ping_interval = self.config.websocket_ping_interval;
+        let idle_timeout = self.config.websocket_idle_timeout;
 
         // Spawn background task with proper error handlting
         tokio::spawn(async move {
-            while let Some(message) = ws_receiver.next().await {
+            let mut sequence = 0;
+            let mut ping = tokio::time::interval(ping_interval);
+            ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut last_traffic = tokio::time::Instant::now();
+            loop {
+                let message = tokio::select! {
+                    message = ws_receiver.next() => message,
+                    _ = ping.tick() => {
+                        if let Err(e) = ws_sender.send(Message::Ping(Bytes::new())).await {
+                            error!("Failed to send WebSocket ping: {}", e);
+                            break;
+                        }
+                        continue;
+                    }
+                    _ = tokio::time::sleep_until(last_traffic + idle_timeout) => {
+                        warn!(?idle_timeout, "No WebSocket traffic, closing stale connection");
+                        break;
+                    }
+                };
+                let Some(message) = message else {
+                    info!("WebSocket connection ended");
+                    break;
+                };
+                last_traffic = tokio::time::Instant::now();
+
                 match message {
                     Ok(Message::Binary(binary)) => {
-                        let Ok(ws_message) = WebSocketMessage::from_binary(&binary)
-                            .inspect_err(|e| warn!(error = ?e, "Error parsing message"))
+                        sequence += 1;
+                        let Ok(mut ws_message) = WebSocketMessage::from_binary(&binary)
+                            .inspect_err(|e| warn!(error = ?e, sequence, "Error parsing message"))
                         else {
                             continue;
                         };
+                        ws_message.sequence = sequence;
 
                         if let Err(e) = tx.send(ws_message).await {
                             error!("Failed to send event through channel: {}", e);
@@ -320,6 +688,13 @@ impl ProtectClient {
     }
 }
 
+fn is_export_not_ready(body: &str) -> bool {
+    let body = body.to_lowercase();
+    ["no video", "not ready", "not available", "no recording"]
+        .iter()
+        .any(|pattern| body.contains(pattern))
+}
+
 #[tracing::instrument(skip(cookie_str))]
 fn extract_auth_cookie(cookie_str: &str) -> Option<String> {
     // Parse the Set-Cookie header to extract the auth token
@@ -360,4 +735,200 @@ mod tests {
         assert!(bootstrap_raw.is_ok());
         let _ = Bootstrap::from(bootstrap_raw.expect("infallible"));
     }
+
+    #[test]
+    fn test_camera_pause_reason() {
+        let data = r#"{
+            "id": "1",
+            "name": "Test Camera",
+            "mac": "",
+            "model": "",
+            "isConnected": true,
+            "recordingSettings": { "mode": "detections" },
+            "privacyZones": [
+                { "id": 0, "name": "Driveway", "points": [[0.1, 0.1], [0.4, 0.1], [0.4, 0.4]] }
+            ]
+        }"#;
+
+        let mut camera = serde_json::from_str::<models::Camera>(data).expect("valid camera");
+        assert_eq!(camera.pause_reason(), None);
+
+        camera.apply_update(
+            &serde_json::from_str(r#"{ "recordingSettings": { "mode": "never" } }"#)
+                .expect("valid update"),
+        );
+        assert_eq!(
+            camera.pause_reason(),
+            Some(models::PauseReason::DetectionsPaused)
+        );
+
+        camera.apply_update(
+            &serde_json::from_str(
+                r#"{ "privacyZones": [{ "points": [[0, 0], [1, 0], [1, 1], [0, 1]] }] }"#,
+            )
+            .expect("valid update"),
+        );
+        assert_eq!(
+            camera.pause_reason(),
+            Some(models::PauseReason::PrivacyMode)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_export_job() {
+        let data = r#"{
+            "id": "job1",
+            "camera": "cam1",
+            "start": 1000,
+            "end": 2000,
+            "status": "processing",
+            "progress": 42.5
+        }"#;
+
+        let job = serde_json::from_str::<ExportJob>(data).expect("valid export job");
+        assert_eq!(job.camera_id.as_deref(), Some("cam1"));
+        assert_eq!(job.status, ExportJobStatus::Processing);
+
+        let job = serde_json::from_str::<ExportJob>(r#"{ "id": "job2", "status": "archived" }"#)
+            .expect("unknown status");
+        assert_eq!(job.status, ExportJobStatus::Unknown("archived".to_string()));
+    }
+
+    #[test]
+    fn test_event_record_to_protect_event() {
+        let data = r#"{
+            "id": "event1",
+            "type": "smartDetectZone",
+            "camera": "cam1",
+            "start": 1000,
+            "end": 2000,
+            "smartDetectTypes": ["person", "licensePlate"],
+            "thumbnail": "e-thumb",
+            "heatmap": null
+        }"#;
+
+        let record = serde_json::from_str::<events::EventRecord>(data).expect("valid event");
+        let event = record
+            .to_protect_event(Some("Driveway".to_string()))
+            .expect("backed up event type");
+        assert_eq!(event.event_type, events::EventType::SmartDetect);
+        assert_eq!(event.smart_detect_types.len(), 2);
+        assert!(event.is_finished);
+
+        let record = serde_json::from_str::<events::EventRecord>(
+            r#"{ "id": "event2", "type": "disconnect", "camera": "cam1", "start": 1000 }"#,
+        )
+        .expect("valid event");
+        assert!(record.to_protect_event(None).is_none());
+
+        let record = serde_json::from_str::<events::EventRecord>(
+            r#"{ "id": "event3", "type": "smartAudioDetect", "camera": "cam1", "start": 1000,
+                 "smartDetectTypes": ["alrmSmoke"] }"#,
+        )
+        .expect("valid event");
+        let event = record.to_protect_event(None).expect("backed up event type");
+        assert_eq!(
+            event.event_type,
+            events::EventType::Audio(events::AudioDetectType::Smoke)
+        );
+        assert!(event.should_backup(&["audio".to_string()]));
+        assert!(!event.should_backup(&["person".to_string()]));
+    }
+
+    #[test]
+    fn test_event_labels() {
+        let metadata = serde_json::json!({
+            "licensePlate": { "name": "ABC123", "confidenceLevel": 90 },
+            "detectedThumbnails": [
+                {
+                    "type": "vehicle",
+                    "name": "ABC123",
+                    "attributes": {
+                        "color": { "val": "red", "confidence": 80 },
+                        "vehicleType": { "val": "suv", "confidence": 70 }
+                    }
+                },
+                { "type": "person", "name": "", "attributes": {} }
+            ]
+        });
+
+        let labels = events::EventLabels::from_metadata(&metadata);
+        assert_eq!(labels.tags, vec!["red", "suv"]);
+        assert_eq!(labels.plates, vec!["ABC123"]);
+        assert!(events::EventLabels::from_metadata(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_doorbell_interaction() {
+        use events::{DoorbellAction, ProtectWebSocketRawFrames};
+
+        let message = |action: &str, model_key: &str, id: &str, data: &str| {
+            let action = format!(
+                r#"{{ "action": "{action}", "newUpdateId": "{}", "modelKey": "{model_key}",
+                      "id": "{id}" }}"#,
+                uuid::Uuid::nil()
+            );
+            let frames = ProtectWebSocketRawFrames {
+                action,
+                data: data.to_string(),
+            };
+            WebSocketMessage::from_binary(&frames.to_binary()).expect("valid message")
+        };
+
+        let fingerprint = message(
+            "add",
+            "event",
+            "e1",
+            r#"{ "type": "fingerprintIdentified", "camera": "doorbell", "start": 1000,
+                 "metadata": { "fingerprint": { "ulpId": "alice" } } }"#,
+        )
+        .doorbell_interaction()
+        .expect("fingerprint");
+        assert_eq!(fingerprint.action, DoorbellAction::Fingerprint);
+        assert_eq!(fingerprint.device_id, "doorbell");
+        assert_eq!(fingerprint.user_id.as_deref(), Some("alice"));
+
+        let reply = message(
+            "update",
+            "camera",
+            "doorbell",
+            r#"{ "lcdMessage": { "type": "LEAVE_PACKAGE_AT_DOOR" } }"#,
+        )
+        .doorbell_interaction()
+        .expect("quick reply");
+        assert_eq!(reply.action, DoorbellAction::QuickReply);
+        assert_eq!(reply.detail.as_deref(), Some("LEAVE_PACKAGE_AT_DOOR"));
+
+        let cleared = message("update", "camera", "doorbell", r#"{ "lcdMessage": {} }"#);
+        assert!(cleared.doorbell_interaction().is_none());
+        let motion = message("add", "event", "e2", r#"{ "type": "motion" }"#);
+        assert!(motion.doorbell_interaction().is_none());
+    }
+
+    #[test]
+    fn test_parse_detection_types() {
+        use events::{AudioDetectType, EventType, SmartDetectType};
+
+        assert_eq!(
+            "licensePlate".parse::<SmartDetectType>().ok(),
+            Some(SmartDetectType::LicensePlate)
+        );
+        for smart_type in [SmartDetectType::Person, SmartDetectType::LicensePlate] {
+            assert_eq!(smart_type.to_string().parse().ok(), Some(smart_type));
+        }
+
+        assert_eq!("Motion".parse::<EventType>().ok(), Some(EventType::Motion));
+        assert_eq!(
+            EventType::SmartDetect.to_string().parse::<EventType>().ok(),
+            Some(EventType::SmartDetect)
+        );
+
+        let glass_break = EventType::Audio(AudioDetectType::GlassBreak);
+        assert_eq!(glass_break.to_string(), "audio_glass_break");
+        assert_eq!(glass_break.to_string().parse().ok(), Some(glass_break));
+        assert_eq!(
+            "cmonx".parse::<AudioDetectType>().ok(),
+            Some(AudioDetectType::CarbonMonoxide)
+        );
+    }
 }
@@ -0,0 +1,255 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream;
+use tokio::sync::mpsc;
+
+use crate::{
+    VideoStream,
+    api::ProtectApi,
+    error::{Error, Result},
+    events::{EventRecord, WebSocketMessage},
+    models::{Bootstrap, ExportQuality},
+};
+
+/// How many websocket messages can be sent before the listener takes any
+const WEBSOCKET_BUFFER: usize = 1024;
+
+/// A [`ProtectApi`] without an NVR behind it, for tests. It serves the bootstrap, events and
+/// exports it's given, and passes on websocket messages sent with [`send`](Self::send).
+pub struct MockProtectClient {
+    bootstrap: Mutex<Bootstrap>,
+    events: Mutex<Vec<EventRecord>>,
+    /// Returned for every export of the camera, whatever its range
+    exports: Mutex<HashMap<String, Vec<u8>>>,
+    requested: Mutex<Vec<(String, i64, i64)>>,
+    /// Event thumbnails by event id, and camera snapshots by camera id
+    thumbnails: Mutex<HashMap<String, Vec<u8>>>,
+    snapshots: Mutex<HashMap<String, Vec<u8>>>,
+    websocket: mpsc::Sender<WebSocketMessage>,
+    receiver: Mutex<Option<mpsc::Receiver<WebSocketMessage>>>,
+}
+
+impl MockProtectClient {
+    pub fn new(bootstrap: Bootstrap) -> Self {
+        let (websocket, receiver) = mpsc::channel(WEBSOCKET_BUFFER);
+        Self {
+            bootstrap: Mutex::new(bootstrap),
+            events: Mutex::default(),
+            exports: Mutex::default(),
+            requested: Mutex::default(),
+            thumbnails: Mutex::default(),
+            snapshots: Mutex::default(),
+            websocket,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    /// Replace the bootstrap, e.g. to rename or adopt cameras
+    pub fn set_bootstrap(&self, bootstrap: Bootstrap) {
+        *self.bootstrap.lock().expect("lock poisoned") = bootstrap;
+    }
+
+    /// Have the events API list `event`
+    pub fn add_event(&self, event: EventRecord) {
+        self.events.lock().expect("lock poisoned").push(event);
+    }
+
+    /// Serve `video` for exports of `camera_id`. Exports of other cameras fail as the NVR's do when
+    /// it has no footage for them, with [`Error::ExportNotReady`].
+    pub fn set_export(&self, camera_id: &str, video: Vec<u8>) {
+        let mut exports = self.exports.lock().expect("lock poisoned");
+        exports.insert(camera_id.to_string(), video);
+    }
+
+    /// Serve `jpeg` as the thumbnail of `event_id`. Other events have none.
+    pub fn set_thumbnail(&self, event_id: &str, jpeg: Vec<u8>) {
+        let mut thumbnails = self.thumbnails.lock().expect("lock poisoned");
+        thumbnails.insert(event_id.to_string(), jpeg);
+    }
+
+    /// Serve `jpeg` as every snapshot of `camera_id`. Other cameras have none.
+    pub fn set_snapshot(&self, camera_id: &str, jpeg: Vec<u8>) {
+        let mut snapshots = self.snapshots.lock().expect("lock poisoned");
+        snapshots.insert(camera_id.to_string(), jpeg);
+    }
+
+    /// Every export asked for so far, as `(camera_id, start, end)`
+    pub fn requested_exports(&self) -> Vec<(String, i64, i64)> {
+        self.requested.lock().expect("lock poisoned").clone()
+    }
+
+    /// Deliver `message` to whoever [connected](ProtectApi::connect_websocket) to the websocket
+    pub async fn send(&self, message: WebSocketMessage) {
+        self.websocket.send(message).await.ok();
+    }
+
+    fn export(&self, camera_id: &str, start: i64, end: i64) -> Result<Vec<u8>> {
+        let mut requested = self.requested.lock().expect("lock poisoned");
+        requested.push((camera_id.to_string(), start, end));
+        self.exports
+            .lock()
+            .expect("lock poisoned")
+            .get(camera_id)
+            .cloned()
+            .ok_or_else(|| Error::ExportNotReady(format!("No export for camera {camera_id}")))
+    }
+}
+
+#[async_trait]
+impl ProtectApi for MockProtectClient {
+    async fn login(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn get_bootstrap(&self) -> Result<Bootstrap> {
+        Ok(self.bootstrap.lock().expect("lock poisoned").clone())
+    }
+
+    async fn download_event_video(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        _quality: ExportQuality,
+    ) -> Result<Vec<u8>> {
+        self.export(camera_id, start, end)
+    }
+
+    async fn stream_event_video(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        _quality: ExportQuality,
+    ) -> Result<VideoStream> {
+        let video = self.export(camera_id, start, end)?;
+        Ok(Box::pin(stream::iter([Ok(Bytes::from(video))])))
+    }
+
+    async fn export_video_via_job(
+        &self,
+        camera_id: &str,
+        start: i64,
+        end: i64,
+        _quality: ExportQuality,
+        _poll_interval: Duration,
+        _timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        self.export(camera_id, start, end)
+    }
+
+    async fn list_events(&self, start: i64, end: i64) -> Result<Vec<EventRecord>> {
+        let events = self.events.lock().expect("lock poisoned");
+        Ok(events
+            .iter()
+            .filter(|event| event.start <= end && event.end.is_none_or(|e| e >= start))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_event(&self, event_id: &str) -> Result<EventRecord> {
+        let events = self.events.lock().expect("lock poisoned");
+        events
+            .iter()
+            .find(|event| event.id == event_id)
+            .cloned()
+            .ok_or_else(|| Error::Api(format!("No event {event_id}")))
+    }
+
+    async fn get_event_thumbnail(&self, event_id: &str) -> Result<Vec<u8>> {
+        self.thumbnails
+            .lock()
+            .expect("lock poisoned")
+            .get(event_id)
+            .cloned()
+            .ok_or_else(|| Error::Api(format!("No thumbnail for event {event_id}")))
+    }
+
+    async fn get_camera_snapshot(&self, camera_id: &str, _ts: i64) -> Result<Vec<u8>> {
+        self.snapshots
+            .lock()
+            .expect("lock poisoned")
+            .get(camera_id)
+            .cloned()
+            .ok_or_else(|| Error::Api(format!("No snapshot for camera {camera_id}")))
+    }
+
+    async fn connect_websocket(&self) -> Result<mpsc::Receiver<WebSocketMessage>> {
+        self.receiver
+            .lock()
+            .expect("lock poisoned")
+            .take()
+            .ok_or_else(|| Error::General("Mock websocket is already connected".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::models::Nvr;
+
+    fn event(id: &str, start: i64, end: Option<i64>) -> EventRecord {
+        EventRecord {
+            id: id.to_string(),
+            kind: "motion".to_string(),
+            camera: Some("camera".to_string()),
+            start,
+            end,
+            smart_detect_types: vec![],
+            thumbnail: None,
+            heatmap: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_protect_client() {
+        let mock = MockProtectClient::new(Bootstrap {
+            cameras: HashMap::new(),
+            nvr: Nvr {
+                id: "nvr".to_string(),
+                name: "NVR".to_string(),
+                version: "5.0.0".to_string(),
+                timezone: "UTC".to_string(),
+                recording_retention_duration_ms: None,
+            },
+            sensors: HashMap::new(),
+        });
+        mock.add_event(event("done", 1_000, Some(2_000)));
+        mock.add_event(event("ongoing", 5_000, None));
+        mock.set_export("camera", b"video".to_vec());
+        let api: &dyn ProtectApi = &mock;
+
+        let ids = |events: Vec<EventRecord>| events.into_iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(ids(api.list_events(0, 1_500).await.unwrap()), ["done"]);
+        assert_eq!(
+            ids(api.list_events(2_500, 9_000).await.unwrap()),
+            ["ongoing"]
+        );
+        assert_eq!(api.get_event("ongoing").await.unwrap().start, 5_000);
+
+        let mut video = api
+            .stream_event_video("camera", 1_000, 2_000, ExportQuality::default())
+            .await
+            .unwrap();
+        assert_eq!(video.next().await.unwrap().unwrap(), &b"video"[..]);
+        assert!(
+            api.download_event_video("other", 1_000, 2_000, ExportQuality::default())
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            mock.requested_exports(),
+            [
+                ("camera".to_string(), 1_000, 2_000),
+                ("other".to_string(), 1_000, 2_000)
+            ]
+        );
+
+        assert!(api.connect_websocket().await.is_ok());
+        assert!(api.connect_websocket().await.is_err());
+    }
+}
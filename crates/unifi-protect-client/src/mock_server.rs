@@ -0,0 +1,550 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, Response, StatusCode, body::Incoming, service::service_fn};
+use hyper_util::rt::TokioIo;
+use reqwest::Url;
+use rustls::{
+    ServerConfig,
+    crypto::ring,
+    pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+};
+use serde_json::Value;
+use tokio::{net::TcpListener, sync::mpsc, task::JoinHandle};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{Message, handshake::derive_accept_key, protocol::Role},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    config::{
+        Secret, UnifiConfig, default_bootstrap_refresh_interval, default_websocket_idle_timeout,
+        default_websocket_ping_interval,
+    },
+    error::{Error, Result},
+    events::ProtectWebSocketRawFrames,
+    retry::RetryConfig,
+};
+
+const USERNAME: &str = "mock";
+const PASSWORD: &str = "mock";
+const AUTH_COOKIE: &str = "TOKEN=mock-token";
+
+type Body = Full<Bytes>;
+
+/// A local stand-in for the NVR's HTTPS API and updates websocket, for end-to-end tests of
+/// [`ProtectClient`](crate::ProtectClient) and the backup service. It serves recorded JSON
+/// (bootstrap, events) and export videos, and passes binary websocket frames on to whoever is
+/// connected. The certificate is self-signed, so [`config`](Self::config) turns verification
+/// off; [`certificate_pem`](Self::certificate_pem) is there for tests that want it on.
+pub struct MockProtectServer {
+    address: SocketAddr,
+    certificate_pem: String,
+    state: Arc<State>,
+    task: JoinHandle<()>,
+}
+
+struct State {
+    bootstrap: Value,
+    events: Mutex<Vec<Value>>,
+    exports: Mutex<HashMap<String, Bytes>>,
+    /// Event thumbnails by event id, and camera snapshots by camera id
+    thumbnails: Mutex<HashMap<String, Bytes>>,
+    snapshots: Mutex<HashMap<String, Bytes>>,
+    /// Export jobs by id, to the camera they export
+    jobs: Mutex<HashMap<String, String>>,
+    requests: Mutex<Vec<String>>,
+    frames: mpsc::UnboundedSender<Vec<u8>>,
+    /// Held by the connected websocket, so frames sent while nobody is connected wait for the
+    /// next connection
+    pending_frames: tokio::sync::Mutex<mpsc::UnboundedReceiver<Vec<u8>>>,
+}
+
+impl MockProtectServer {
+    /// Listen on a free port on localhost, serving `bootstrap` as recorded from
+    /// `/proxy/protect/api/bootstrap`
+    pub async fn start(bootstrap: Value) -> Result<Self> {
+        let certified = rcgen::generate_simple_self_signed(["localhost".to_string()])
+            .map_err(|e| Error::Tls(format!("Failed to generate certificate: {e}")))?;
+        let key =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+        let tls_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| {
+                builder
+                    .with_no_client_auth()
+                    .with_single_cert(vec![certified.cert.der().clone()], key)
+            })
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        let (frames, pending_frames) = mpsc::unbounded_channel();
+        let state = Arc::new(State {
+            bootstrap,
+            events: Mutex::default(),
+            exports: Mutex::default(),
+            thumbnails: Mutex::default(),
+            snapshots: Mutex::default(),
+            jobs: Mutex::default(),
+            requests: Mutex::default(),
+            frames,
+            pending_frames: tokio::sync::Mutex::new(pending_frames),
+        });
+
+        let task = tokio::spawn(serve(listener, acceptor, state.clone()));
+
+        Ok(Self {
+            address,
+            certificate_pem: certified.cert.pem(),
+            state,
+            task,
+        })
+    }
+
+    /// A client config for this server, with its credentials and certificate checks off
+    pub fn config(&self) -> UnifiConfig {
+        UnifiConfig {
+            address: self.address.ip().to_string(),
+            port: self.address.port(),
+            username: USERNAME.to_string(),
+            password: Secret::new(PASSWORD.to_string()),
+            verify_ssl: false,
+            ca_cert_path: None,
+            connect_timeout: None,
+            read_timeout: None,
+            proxy: None,
+            retry: RetryConfig::default(),
+            rate_limit: None,
+            bootstrap_refresh_interval: default_bootstrap_refresh_interval(),
+            websocket_ping_interval: default_websocket_ping_interval(),
+            websocket_idle_timeout: default_websocket_idle_timeout(),
+        }
+    }
+
+    /// The server's self-signed certificate, issued for `localhost`
+    pub fn certificate_pem(&self) -> &str {
+        &self.certificate_pem
+    }
+
+    /// Have the events API list `event`, as recorded from `/proxy/protect/api/events`
+    pub fn add_event(&self, event: Value) {
+        self.state.events.lock().expect("lock poisoned").push(event);
+    }
+
+    /// Serve `video` for exports of `camera_id`. Exports of other cameras get a 404, as for
+    /// footage the NVR doesn't have yet.
+    pub fn set_export(&self, camera_id: &str, video: impl Into<Bytes>) {
+        let mut exports = self.state.exports.lock().expect("lock poisoned");
+        exports.insert(camera_id.to_string(), video.into());
+    }
+
+    /// Serve `jpeg` as the thumbnail of `event_id`
+    pub fn set_thumbnail(&self, event_id: &str, jpeg: impl Into<Bytes>) {
+        let mut thumbnails = self.state.thumbnails.lock().expect("lock poisoned");
+        thumbnails.insert(event_id.to_string(), jpeg.into());
+    }
+
+    /// Serve `jpeg` as every snapshot of `camera_id`
+    pub fn set_snapshot(&self, camera_id: &str, jpeg: impl Into<Bytes>) {
+        let mut snapshots = self.state.snapshots.lock().expect("lock poisoned");
+        snapshots.insert(camera_id.to_string(), jpeg.into());
+    }
+
+    /// Send a binary frame, as recorded from the updates websocket
+    pub fn send_frame(&self, frame: Vec<u8>) {
+        self.state.frames.send(frame).ok();
+    }
+
+    /// Send an action and data frame pair over the updates websocket
+    pub fn send(&self, action: &Value, data: &Value) {
+        let frames = ProtectWebSocketRawFrames {
+            action: action.to_string(),
+            data: data.to_string(),
+        };
+        self.send_frame(frames.to_binary());
+    }
+
+    /// Every request served so far, as `METHOD /path?query`
+    pub fn requests(&self) -> Vec<String> {
+        self.state.requests.lock().expect("lock poisoned").clone()
+    }
+}
+
+impl Drop for MockProtectServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(listener: TcpListener, acceptor: TlsAcceptor, state: Arc<State>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!(error = ?e, "Mock server failed to accept a connection");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!(error = ?e, "Mock server TLS handshake failed");
+                    return;
+                }
+            };
+            let service = service_fn(move |req| handle_request(req, state.clone()));
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await
+            {
+                debug!(error = ?e, "Mock server connection failed");
+            }
+        });
+    }
+}
+
+async fn handle_request(
+    req: Request<Incoming>,
+    state: Arc<State>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let uri = req.uri().to_string();
+    let query: HashMap<String, String> = Url::parse(&format!("https://mock{uri}"))
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default();
+    state
+        .requests
+        .lock()
+        .expect("lock poisoned")
+        .push(format!("{} {uri}", req.method()));
+
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+
+    if (&method, path.as_str()) == (&Method::POST, "/api/auth/login") {
+        return Ok(login(req).await);
+    }
+
+    let authenticated = req
+        .headers()
+        .get("cookie")
+        .and_then(|cookie| cookie.to_str().ok())
+        .is_some_and(|cookie| cookie.contains(AUTH_COOKIE));
+    if !authenticated {
+        return Ok(status(StatusCode::UNAUTHORIZED));
+    }
+
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let response = match (method, segments.as_slice()) {
+        (Method::GET, ["proxy", "protect", "api", "bootstrap"]) => json(&state.bootstrap),
+        (Method::GET, ["proxy", "protect", "api", "events"]) => {
+            let bound = |key: &str| query.get(key).and_then(|v| v.parse::<i64>().ok());
+            let (start, end) = (
+                bound("start").unwrap_or(0),
+                bound("end").unwrap_or(i64::MAX),
+            );
+            let events = state.events.lock().expect("lock poisoned");
+            let overlapping: Vec<Value> = events
+                .iter()
+                .filter(|event| {
+                    event["start"].as_i64().is_some_and(|s| s <= end)
+                        && event["end"].as_i64().is_none_or(|e| e >= start)
+                })
+                .cloned()
+                .collect();
+            json(&Value::from(overlapping))
+        }
+        (Method::GET, ["proxy", "protect", "api", "events", id]) => {
+            let events = state.events.lock().expect("lock poisoned");
+            match events.iter().find(|event| event["id"] == *id) {
+                Some(event) => json(event),
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        (Method::GET, ["proxy", "protect", "api", "video", "export"]) => export(
+            &state,
+            query.get("camera").map(String::as_str).unwrap_or_default(),
+        ),
+        (Method::GET, ["proxy", "protect", "api", "events", id, "thumbnail"]) => {
+            image(state.thumbnails.lock().expect("lock poisoned").get(*id))
+        }
+        (Method::GET, ["proxy", "protect", "api", "cameras", id, "snapshot"]) => {
+            image(state.snapshots.lock().expect("lock poisoned").get(*id))
+        }
+        (Method::POST, ["proxy", "protect", "api", "exports"]) => {
+            let body = req.into_body().collect().await.map(|b| b.to_bytes());
+            let request: Value = body
+                .ok()
+                .and_then(|body| serde_json::from_slice(&body).ok())
+                .unwrap_or_default();
+            let camera = request["camera"].as_str().unwrap_or_default().to_string();
+            let mut jobs = state.jobs.lock().expect("lock poisoned");
+            let id = format!("job-{}", jobs.len() + 1);
+            jobs.insert(id.clone(), camera.clone());
+            json(&serde_json::json!({
+                "id": id,
+                "camera": camera,
+                "start": request["start"],
+                "end": request["end"],
+                "status": "completed",
+                "progress": 100.0,
+            }))
+        }
+        (Method::GET, ["proxy", "protect", "api", "exports", id, "download"]) => {
+            let camera = state.jobs.lock().expect("lock poisoned").get(*id).cloned();
+            match camera {
+                Some(camera) => export(&state, &camera),
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+        (Method::GET, ["proxy", "protect", "ws", "updates"]) => upgrade_websocket(req, state),
+        _ => status(StatusCode::NOT_FOUND),
+    };
+
+    Ok(response)
+}
+
+async fn login(req: Request<Incoming>) -> Response<Body> {
+    let body = req.into_body().collect().await.map(|b| b.to_bytes());
+    let credentials: Value = body
+        .ok()
+        .and_then(|body| serde_json::from_slice(&body).ok())
+        .unwrap_or_default();
+
+    if credentials["username"] != USERNAME || credentials["password"] != PASSWORD {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+
+    let mut response = json(&serde_json::json!({ "csrfToken": "mock-csrf-token" }));
+    response.headers_mut().insert(
+        "set-cookie",
+        format!("{AUTH_COOKIE}; path=/; HttpOnly")
+            .parse()
+            .expect("valid header"),
+    );
+    response
+}
+
+fn export(state: &State, camera_id: &str) -> Response<Body> {
+    match state.exports.lock().expect("lock poisoned").get(camera_id) {
+        Some(video) => Response::builder()
+            .header("content-type", "video/mp4")
+            .body(Full::new(video.clone()))
+            .expect("valid response"),
+        None => status(StatusCode::NOT_FOUND),
+    }
+}
+
+fn image(jpeg: Option<&Bytes>) -> Response<Body> {
+    match jpeg {
+        Some(jpeg) => Response::builder()
+            .header("content-type", "image/jpeg")
+            .body(Full::new(jpeg.clone()))
+            .expect("valid response"),
+        None => status(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Complete the websocket handshake and, once upgraded, forward sent frames until the client
+/// goes away
+fn upgrade_websocket(req: Request<Incoming>, state: Arc<State>) -> Response<Body> {
+    let Some(key) = req.headers().get("sec-websocket-key") else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+    let accept = derive_accept_key(key.as_bytes());
+
+    tokio::spawn(async move {
+        let upgraded = match hyper::upgrade::on(req).await {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                warn!(error = ?e, "Mock server websocket upgrade failed");
+                return;
+            }
+        };
+        let websocket =
+            WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+        let (mut sender, mut receiver) = websocket.split();
+        let mut frames = state.pending_frames.lock().await;
+
+        loop {
+            tokio::select! {
+                frame = frames.recv() => {
+                    let Some(frame) = frame else { break };
+                    if sender.send(Message::binary(frame)).await.is_err() {
+                        break;
+                    }
+                }
+                message = receiver.next() => {
+                    if matches!(message, None | Some(Err(_)) | Some(Ok(Message::Close(_)))) {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header("connection", "Upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-accept", accept)
+        .body(Body::default())
+        .expect("valid response")
+}
+
+fn json(value: &Value) -> Response<Body> {
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from(value.to_string())))
+        .expect("valid response")
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::default())
+        .expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{ProtectClient, events::WebSocketAction, models::ExportQuality};
+
+    #[tokio::test]
+    async fn test_mock_protect_server() {
+        let server = MockProtectServer::start(json!({
+            "cameras": [
+                {
+                    "id": "camera", "name": "Front Door", "mac": "", "model": "",
+                    "isConnected": true
+                }
+            ],
+            "nvr": { "id": "nvr", "name": "NVR", "version": "5.0.0", "timezone": "UTC" }
+        }))
+        .await
+        .unwrap();
+        server.add_event(json!({
+            "id": "event", "type": "motion", "camera": "camera", "start": 1_000, "end": 2_000
+        }));
+        server.set_export("camera", &b"video"[..]);
+
+        let client = ProtectClient::new(server.config()).unwrap();
+        client.login().await.unwrap();
+        let bootstrap = client.get_bootstrap().await.unwrap();
+        assert_eq!(bootstrap.cameras["camera"].name, "Front Door");
+
+        let events = client.list_events(0, 5_000).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(client.list_events(3_000, 5_000).await.unwrap().is_empty());
+        assert_eq!(client.get_event("event").await.unwrap().start, 1_000);
+        assert!(client.get_event("missing").await.is_err());
+
+        let video = client
+            .download_event_video("camera", 1_000, 2_000, ExportQuality::default())
+            .await
+            .unwrap();
+        assert_eq!(video, b"video");
+        let video = client
+            .export_video_via_job(
+                "camera",
+                1_000,
+                2_000,
+                ExportQuality::default(),
+                std::time::Duration::from_millis(10),
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(video, b"video");
+
+        let mut messages = client.connect_websocket().await.unwrap();
+        server.send(
+            &json!({
+                "action": "update",
+                "newUpdateId": "6a1e4f36-2f52-4d5c-9d0e-3f6b1d1f0c9a",
+                "modelKey": "event",
+                "id": "event"
+            }),
+            &json!({ "type": "motion", "start": 1_000, "end": 2_000 }),
+        );
+        let message = messages.recv().await.unwrap();
+        assert_eq!(message.action_frame.action, WebSocketAction::Update);
+        assert_eq!(message.action_frame.id, "event");
+        assert_eq!(message.data_frame.end, Some(2_000));
+
+        assert!(
+            server
+                .requests()
+                .contains(&"GET /proxy/protect/api/bootstrap".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_websocket_idle_timeout() {
+        let server = MockProtectServer::start(json!({
+            "cameras": [],
+            "nvr": { "id": "nvr", "name": "NVR", "version": "5.0.0", "timezone": "UTC" }
+        }))
+        .await
+        .unwrap();
+        let config = UnifiConfig {
+            websocket_ping_interval: std::time::Duration::from_secs(3600),
+            websocket_idle_timeout: std::time::Duration::from_millis(100),
+            ..server.config()
+        };
+        let client = ProtectClient::new(config).unwrap();
+        client.login().await.unwrap();
+
+        // only the first ping is answered, after which the connection goes quiet
+        let mut messages = client.connect_websocket().await.unwrap();
+        let closed = tokio::time::timeout(std::time::Duration::from_secs(5), messages.recv());
+        assert!(closed.await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_websocket_sequence() {
+        let server = MockProtectServer::start(json!({
+            "cameras": [],
+            "nvr": { "id": "nvr", "name": "NVR", "version": "5.0.0", "timezone": "UTC" }
+        }))
+        .await
+        .unwrap();
+        let client = ProtectClient::new(server.config()).unwrap();
+        client.login().await.unwrap();
+        let mut messages = client.connect_websocket().await.unwrap();
+
+        let action = json!({
+            "action": "update",
+            "newUpdateId": "6a1e4f36-2f52-4d5c-9d0e-3f6b1d1f0c9a",
+            "modelKey": "event",
+            "id": "event"
+        });
+        let data = json!({ "type": "motion", "start": 1_000 });
+        server.send(&action, &data);
+        // dropped by the client, so the next message's sequence skips it
+        server.send_frame(b"not a frame".to_vec());
+        server.send(&action, &data);
+
+        assert_eq!(messages.recv().await.unwrap().sequence, 1);
+        assert_eq!(messages.recv().await.unwrap().sequence, 3);
+    }
+}
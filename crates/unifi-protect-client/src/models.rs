@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +6,9 @@ use serde::{Deserialize, Serialize};
 pub struct Bootstrap {
     pub cameras: HashMap<String, Camera>,
     pub nvr: Nvr,
+    /// UP Sense sensors, by id
+    #[serde(default)]
+    pub sensors: HashMap<String, Sensor>,
 }
 
 impl From<BootstrapRawResponse> for Bootstrap {
@@ -17,6 +20,11 @@ impl From<BootstrapRawResponse> for Bootstrap {
                 .into_iter()
                 .map(|c| (c.id.clone(), c))
                 .collect(),
+            sensors: value
+                .sensors
+                .into_iter()
+                .map(|s| (s.id.clone(), s))
+                .collect(),
         }
     }
 }
@@ -26,6 +34,8 @@ impl From<BootstrapRawResponse> for Bootstrap {
 pub(crate) struct BootstrapRawResponse {
     pub cameras: Vec<Camera>,
     pub nvr: Nvr,
+    #[serde(default)]
+    pub sensors: Vec<Sensor>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +46,196 @@ pub struct Camera {
     pub mac: String,
     pub model: Option<String>,
     pub is_connected: bool,
+    /// When the camera last disconnected from the NVR, in milliseconds since the epoch
+    #[serde(default)]
+    pub last_disconnect: Option<i64>,
+    #[serde(default)]
+    pub recording_settings: Option<RecordingSettings>,
+    #[serde(default)]
+    pub privacy_zones: Vec<PrivacyZone>,
+}
+
+impl Camera {
+    /// Why (if at all) the camera is intentionally not producing detections right now.
+    pub fn pause_reason(&self) -> Option<PauseReason> {
+        if self
+            .privacy_zones
+            .iter()
+            .any(PrivacyZone::covers_full_frame)
+        {
+            return Some(PauseReason::PrivacyMode);
+        }
+
+        match self
+            .recording_settings
+            .as_ref()
+            .and_then(|r| r.mode.as_ref())
+        {
+            Some(RecordingMode::Never) => Some(PauseReason::DetectionsPaused),
+            _ => None,
+        }
+    }
+
+    /// Apply a partial camera update received over the websocket.
+    pub fn apply_update(&mut self, update: &CameraUpdate) {
+        if let Some(name) = &update.name {
+            self.name = name.clone();
+        }
+        if let Some(is_connected) = update.is_connected {
+            self.is_connected = is_connected;
+        }
+        if let Some(last_disconnect) = update.last_disconnect {
+            self.last_disconnect = Some(last_disconnect);
+        }
+        if let Some(mode) = update
+            .recording_settings
+            .as_ref()
+            .and_then(|r| r.mode.clone())
+        {
+            self.recording_settings = Some(RecordingSettings { mode: Some(mode) });
+        }
+        if let Some(privacy_zones) = &update.privacy_zones {
+            self.privacy_zones = privacy_zones.clone();
+        }
+    }
+}
+
+/// Subset of camera fields which may be present in a websocket `update` data frame.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct CameraUpdate {
+    pub name: Option<String>,
+    pub is_connected: Option<bool>,
+    pub last_disconnect: Option<i64>,
+    pub recording_settings: Option<RecordingSettings>,
+    pub privacy_zones: Option<Vec<PrivacyZone>>,
+}
+
+/// A UP Sense door/window and motion sensor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct Sensor {
+    pub id: String,
+    pub name: String,
+    pub mac: String,
+    /// The camera the sensor is paired with in Protect, if any
+    #[serde(default)]
+    pub camera: Option<String>,
+}
+
+/// Subset of sensor fields which may be present in a websocket `update` data frame. Times are in
+/// milliseconds since the epoch.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct SensorUpdate {
+    pub is_opened: Option<bool>,
+    pub open_status_changed_at: Option<i64>,
+    pub is_motion_detected: Option<bool>,
+    pub motion_detected_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct RecordingSettings {
+    #[serde(default)]
+    pub mode: Option<RecordingMode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub enum RecordingMode {
+    Always,
+    Never,
+    Detections,
+    Schedule,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct PrivacyZone {
+    #[serde(default)]
+    pub points: Vec<[f64; 2]>,
+}
+
+impl PrivacyZone {
+    /// Protect implements privacy mode as a single zone masking the whole frame. Points are
+    /// normalised to the 0..1 range.
+    pub fn covers_full_frame(&self) -> bool {
+        const EPSILON: f64 = 0.001;
+
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        corners.iter().all(|corner| {
+            self.points.iter().any(|point| {
+                (point[0] - corner[0]).abs() < EPSILON && (point[1] - corner[1]).abs() < EPSILON
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PauseReason {
+    PrivacyMode,
+    DetectionsPaused,
+}
+
+impl Display for PauseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PauseReason::PrivacyMode => write!(f, "privacy_mode"),
+            PauseReason::DetectionsPaused => write!(f, "detections_paused"),
+        }
+    }
+}
+
+/// An asynchronous export created on the NVR, used by newer Protect versions for long ranges
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ExportJob {
+    pub id: String,
+    #[serde(alias = "camera")]
+    pub camera_id: Option<String>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub status: ExportJobStatus,
+    /// Percentage complete, when reported
+    #[serde(default)]
+    pub progress: Option<f64>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub enum ExportJobStatus {
+    Queued,
+    Processing,
+    Completed,
+    Failed,
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+/// Which of the camera's recorded streams an export is taken from
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportQuality {
+    #[default]
+    High,
+    Medium,
+    Low,
+}
+
+impl ExportQuality {
+    /// The `channel` parameter of the export API
+    pub fn channel(&self) -> u8 {
+        match self {
+            ExportQuality::High => 0,
+            ExportQuality::Medium => 1,
+            ExportQuality::Low => 2,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,4 +245,15 @@ pub struct Nvr {
     pub name: String,
     pub version: String,
     pub timezone: String,
+    /// How long the NVR keeps recordings, if it's limited by time rather than only by disk space
+    #[serde(default)]
+    pub recording_retention_duration_ms: Option<i64>,
+}
+
+impl Nvr {
+    pub fn recording_retention(&self) -> Option<Duration> {
+        self.recording_retention_duration_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| Duration::from_millis(ms as u64))
+    }
 }
@@ -0,0 +1,133 @@
+use std::future::Future;
+
+use base64::Engine;
+use reqwest::{ClientBuilder, Proxy, Url};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, client_async_tls_with_config, connect_async_tls_with_config,
+    tungstenite::http::Request,
+};
+
+use crate::{
+    config::UnifiConfig,
+    error::{Error, Result},
+    tls,
+};
+
+pub(crate) type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub(crate) fn configure_http_client(
+    mut builder: ClientBuilder,
+    config: &UnifiConfig,
+) -> Result<ClientBuilder> {
+    if let Some(connect_timeout) = config.connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+    if let Some(read_timeout) = config.read_timeout {
+        builder = builder.read_timeout(read_timeout);
+    }
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::https(proxy)?);
+    }
+
+    Ok(builder)
+}
+
+/// Open the websocket, tunnelling through the configured proxy if any. The whole connection
+/// (TCP, proxy tunnel, TLS and upgrade) is bounded by `connect-timeout`.
+pub(crate) async fn connect_websocket(
+    config: &UnifiConfig,
+    request: Request<()>,
+) -> Result<WsStream> {
+    let connector = tls::websocket_connector(config)?;
+
+    let connect = async {
+        let (ws_stream, _) = match &config.proxy {
+            None => connect_async_tls_with_config(request, None, false, connector).await?,
+            Some(proxy) => {
+                let stream = proxy_tunnel(proxy, config).await?;
+                client_async_tls_with_config(request, stream, None, connector).await?
+            }
+        };
+        Ok(ws_stream)
+    };
+
+    with_timeout(config.connect_timeout, "WebSocket connect", connect).await
+}
+
+/// Establish a TCP tunnel to the controller through an HTTP proxy using `CONNECT`.
+async fn proxy_tunnel(proxy: &str, config: &UnifiConfig) -> Result<TcpStream> {
+    let (host, port) = (&config.address, config.port);
+    let proxy_url =
+        Url::parse(proxy).map_err(|e| Error::General(format!("Invalid proxy URL: {e}")))?;
+    if proxy_url.scheme() != "http" {
+        return Err(Error::General(format!(
+            "Unsupported proxy scheme for WebSocket: {}",
+            proxy_url.scheme()
+        )));
+    }
+
+    let proxy_host = proxy_url
+        .host_str()
+        .ok_or_else(|| Error::General("Proxy URL has no host".to_string()))?;
+    let proxy_port = proxy_url.port_or_known_default().unwrap_or(80);
+
+    let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+
+    let mut connect_request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if !proxy_url.username().is_empty() {
+        let credentials = format!(
+            "{}:{}",
+            proxy_url.username(),
+            proxy_url.password().unwrap_or_default()
+        );
+        connect_request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64::prelude::BASE64_STANDARD.encode(credentials)
+        ));
+    }
+    connect_request.push_str("\r\n");
+    stream.write_all(connect_request.as_bytes()).await?;
+
+    let read_response = async {
+        let mut reader = BufReader::new(&mut stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).await?;
+
+        // drain the remaining headers; the tunnel starts after the blank line
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 || line == "\r\n" {
+                break;
+            }
+        }
+
+        Ok::<_, Error>(status_line)
+    };
+    let status_line = with_timeout(config.read_timeout, "Proxy CONNECT", read_response).await?;
+
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => Ok(stream),
+        _ => Err(Error::Api(format!(
+            "Proxy CONNECT failed: {}",
+            status_line.trim()
+        ))),
+    }
+}
+
+async fn with_timeout<T>(
+    timeout: Option<std::time::Duration>,
+    operation: &str,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fut)
+            .await
+            .map_err(|_| Error::Timeout(format!("{operation} timed out after {timeout:?}")))?,
+        None => fut.await,
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+
+use crate::{VideoStream, error::Result};
+
+/// Called with the size in bytes of each chunk of an export as it arrives
+pub type ProgressCallback = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// Pass `video` through, calling `on_progress` as each chunk arrives. The callback is dropped
+/// with the stream, so anything it holds lives as long as the download.
+pub fn track(video: VideoStream, on_progress: ProgressCallback) -> VideoStream {
+    Box::pin(video.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            on_progress(chunk.len());
+        }
+    }))
+}
+
+/// All of `video`, held in memory
+pub async fn collect(mut video: VideoStream) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = video.next().await {
+        data.extend_from_slice(&chunk?);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bytes::Bytes;
+    use futures_util::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_track() {
+        let received = Arc::new(AtomicUsize::new(0));
+        let on_progress: ProgressCallback = {
+            let received = received.clone();
+            Arc::new(move |bytes| {
+                received.fetch_add(bytes, Ordering::Relaxed);
+            })
+        };
+        let video: VideoStream = Box::pin(stream::iter([
+            Ok(Bytes::from_static(b"vid")),
+            Ok(Bytes::from_static(b"eo")),
+        ]));
+
+        let data = collect(track(video, on_progress)).await.unwrap();
+        assert_eq!(data, b"video");
+        assert_eq!(received.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,112 @@
+use std::{sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{Instant, sleep_until};
+
+/// A client-side limit on requests to the controller, so a large backfill stays under its
+/// login and API rate limits rather than getting the backup account locked out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"))]
+pub struct RateLimitConfig {
+    /// Sustained requests per second
+    pub requests_per_second: f64,
+    /// Requests that can go at once after a quiet spell
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+    5
+}
+
+/// A token bucket shared by every request the client makes, which also holds them all back
+/// while the controller has asked for a pause with a `429`
+pub(crate) struct RateLimiter {
+    config: Option<RateLimitConfig>,
+    state: Mutex<State>,
+}
+
+struct State {
+    tokens: f64,
+    updated: Instant,
+    paused_until: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: Option<RateLimitConfig>) -> Self {
+        let now = Instant::now();
+        let tokens = config
+            .as_ref()
+            .map_or(0.0, |config| config.burst.max(1) as f64);
+        Self {
+            config,
+            state: Mutex::new(State {
+                tokens,
+                updated: now,
+                paused_until: now,
+            }),
+        }
+    }
+
+    /// Wait until a request is allowed: any pause is over and the bucket has a token for it.
+    pub async fn acquire(&self) {
+        while let Some(until) = self.try_acquire(Instant::now()) {
+            sleep_until(until).await;
+        }
+    }
+
+    /// Take a token at `now`, or if there's none to take, when to try again
+    fn try_acquire(&self, now: Instant) -> Option<Instant> {
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+        if state.paused_until > now {
+            return Some(state.paused_until);
+        }
+        let config = self.config.as_ref()?;
+        let rate = config.requests_per_second;
+        if rate <= 0.0 {
+            return None;
+        }
+
+        let refilled = now.saturating_duration_since(state.updated).as_secs_f64() * rate;
+        state.tokens = (state.tokens + refilled).min(config.burst.max(1) as f64);
+        state.updated = now;
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            None
+        } else {
+            Some(now + Duration::from_secs_f64((1.0 - state.tokens) / rate))
+        }
+    }
+
+    /// Hold back every request for `delay`, as a `429` with `Retry-After` asks
+    pub fn pause(&self, delay: Duration) {
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+        state.paused_until = state.paused_until.max(Instant::now() + delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(Some(RateLimitConfig {
+            requests_per_second: 2.0,
+            burst: 2,
+        }));
+        let now = Instant::now();
+        assert_eq!(limiter.try_acquire(now), None);
+        assert_eq!(limiter.try_acquire(now), None);
+        assert_eq!(
+            limiter.try_acquire(now),
+            Some(now + Duration::from_millis(500))
+        );
+        assert_eq!(limiter.try_acquire(now + Duration::from_millis(500)), None);
+
+        let unlimited = RateLimiter::new(None);
+        assert_eq!(unlimited.try_acquire(now), None);
+        unlimited.pause(Duration::from_secs(60));
+        assert!(unlimited.try_acquire(Instant::now()).is_some());
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{Response, StatusCode, header::RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "kebab-case"), default)]
+pub struct RetryConfig {
+    /// Total attempts (including the first) for requests failing with a transient error
+    pub max_attempts: u32,
+    #[serde(with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Exponential backoff for the given (1-based) attempt with equal jitter: half the delay is
+    /// fixed and half is random, so concurrent requests don't retry in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        let half = exponential / 2;
+        half + rand::rng().random_range(Duration::ZERO..=half)
+    }
+}
+
+pub(crate) fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+pub(crate) fn is_transient_error(error: &Error) -> bool {
+    match error {
+        Error::Http(e) => e.is_timeout() || e.is_connect(),
+        Error::Timeout(_) => true,
+        _ => false,
+    }
+}
+
+/// The delay requested by the server via `Retry-After`, either in seconds or as an HTTP date.
+pub(crate) fn retry_after(response: &Response) -> Option<Duration> {
+    parse_retry_after(response.headers().get(RETRY_AFTER)?.to_str().ok()?)
+}
+
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_bounded() {
+        let config = RetryConfig::default();
+        for attempt in 1..=10 {
+            let backoff = config.backoff(attempt);
+            assert!(backoff <= config.max_backoff);
+            assert!(backoff >= (config.initial_backoff / 2).min(config.max_backoff / 2));
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None); // in the past
+        assert!(parse_retry_after("not a date").is_none());
+    }
+}
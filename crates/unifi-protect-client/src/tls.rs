@@ -0,0 +1,183 @@
+use reqwest::ClientBuilder;
+use tokio_tungstenite::Connector;
+
+use crate::{
+    config::UnifiConfig,
+    error::{Error, Result},
+};
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("one of the `native-tls` or `rustls` features must be enabled");
+
+/// Read the PEM-encoded CA certificate used to verify the controller, if one is configured.
+fn read_ca_cert(config: &UnifiConfig) -> Result<Option<Vec<u8>>> {
+    config
+        .ca_cert_path
+        .as_ref()
+        .map(|path| {
+            std::fs::read(path).map_err(|e| {
+                Error::Tls(format!(
+                    "Failed to read CA certificate {}: {e}",
+                    path.display()
+                ))
+            })
+        })
+        .transpose()
+}
+
+pub(crate) fn configure_http_client(
+    mut builder: ClientBuilder,
+    config: &UnifiConfig,
+) -> Result<ClientBuilder> {
+    #[cfg(feature = "rustls")]
+    {
+        builder = builder.use_rustls_tls();
+    }
+
+    if let Some(pem) = read_ca_cert(config)? {
+        builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+    }
+
+    Ok(builder.danger_accept_invalid_certs(!config.verify_ssl))
+}
+
+/// Build the TLS connector for the websocket, trusting the webpki roots and any configured CA.
+#[cfg(feature = "rustls")]
+pub(crate) fn websocket_connector(config: &UnifiConfig) -> Result<Option<Connector>> {
+    use std::sync::Arc;
+
+    use rustls::{ClientConfig, RootCertStore, crypto::ring};
+
+    // always built: tokio-tungstenite's own rustls connector has no roots to verify against
+    let ca_cert = read_ca_cert(config)?;
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Tls(e.to_string()))?;
+
+    let client_config = if config.verify_ssl {
+        let mut roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        for cert in rustls::pki_types::pem::PemObject::pem_slice_iter(
+            ca_cert.as_deref().unwrap_or_default(),
+        ) {
+            roots
+                .add(cert.map_err(|e| Error::Tls(format!("Invalid CA certificate: {e}")))?)
+                .map_err(|e| Error::Tls(format!("Invalid CA certificate: {e}")))?;
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    } else {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(danger::NoVerification(provider)))
+            .with_no_client_auth()
+    };
+
+    Ok(Some(Connector::Rustls(Arc::new(client_config))))
+}
+
+/// Build the TLS connector for the websocket. `None` means the default connector is sufficient.
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub(crate) fn websocket_connector(config: &UnifiConfig) -> Result<Option<Connector>> {
+    let ca_cert = read_ca_cert(config)?;
+    if config.verify_ssl && ca_cert.is_none() {
+        return Ok(None);
+    }
+
+    let mut builder = native_tls::TlsConnector::builder();
+    if let Some(pem) = ca_cert {
+        builder.add_root_certificate(native_tls::Certificate::from_pem(&pem)?);
+    }
+    if !config.verify_ssl {
+        // accept self-signed certificates
+        builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+
+    Ok(Some(Connector::NativeTls(builder.build()?)))
+}
+
+#[cfg(all(test, feature = "rustls"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_websocket_connector_has_roots() {
+        let config: UnifiConfig = serde_json::from_value(serde_json::json!({
+            "address": "unifi.example.com",
+            "port": 443,
+            "username": "backup",
+            "password": "password",
+            "verify-ssl": true,
+        }))
+        .unwrap();
+
+        // rather than leaving tokio-tungstenite to connect with an empty root store
+        assert!(matches!(
+            websocket_connector(&config).unwrap(),
+            Some(Connector::Rustls(_))
+        ));
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod danger {
+    use std::sync::Arc;
+
+    use rustls::{
+        DigitallySignedStruct, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+    };
+
+    /// Accepts any server certificate; the rustls equivalent of `danger_accept_invalid_certs`.
+    /// Signatures are still checked so the handshake itself remains sound.
+    #[derive(Debug)]
+    pub(super) struct NoVerification(pub(super) Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for NoVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
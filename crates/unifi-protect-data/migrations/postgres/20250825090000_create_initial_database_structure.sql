@@ -0,0 +1,55 @@
+-- Postgres starts from the schema the SQLite migrations have built up to this point; later schema
+-- changes need a migration in both directories
+CREATE TABLE IF NOT EXISTS events (
+    id TEXT PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    camera_id TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT,
+    backed_up BOOLEAN NOT NULL DEFAULT FALSE,
+    skip_reason TEXT,
+    smart_detect_types TEXT NOT NULL DEFAULT '',
+    thumbnail_id TEXT,
+    heatmap_id TEXT
+);
+
+CREATE TABLE IF NOT EXISTS backups (
+    event_id TEXT NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    target TEXT NOT NULL,
+    part INTEGER NOT NULL DEFAULT 0,
+    remote_path TEXT NOT NULL,
+    backup_time BIGINT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (event_id, target, part)
+);
+
+CREATE TABLE IF NOT EXISTS camera_pauses (
+    id BIGSERIAL PRIMARY KEY,
+    camera_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    end_time BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS idx_camera_pauses_camera_id ON camera_pauses (camera_id, start_time);
+
+CREATE TABLE IF NOT EXISTS failures (
+    id BIGSERIAL PRIMARY KEY,
+    subject TEXT NOT NULL,
+    target TEXT NOT NULL,
+    error TEXT NOT NULL,
+    output TEXT NOT NULL,
+    failure_time BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_failures_subject_target ON failures (subject, target, failure_time);
+
+CREATE TABLE IF NOT EXISTS cameras (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    mac TEXT NOT NULL,
+    model TEXT,
+    first_seen BIGINT NOT NULL,
+    last_updated BIGINT NOT NULL,
+    removed_at BIGINT
+);
@@ -0,0 +1,3 @@
+-- Event search filters by time range, optionally per camera, newest first
+CREATE INDEX IF NOT EXISTS idx_events_start_time ON events (start_time);
+CREATE INDEX IF NOT EXISTS idx_events_camera_id ON events (camera_id, start_time);
@@ -0,0 +1,11 @@
+-- Uploads that have started but not finished, so a restart can pick up where the last run stopped
+-- rather than starting the event over
+CREATE TABLE IF NOT EXISTS in_flight_uploads (
+    event_id TEXT NOT NULL,
+    target TEXT NOT NULL,
+    part INTEGER NOT NULL,
+    spool_path TEXT,
+    bytes_sent BIGINT NOT NULL DEFAULT 0,
+    started_at BIGINT NOT NULL,
+    PRIMARY KEY (event_id, target, part)
+);
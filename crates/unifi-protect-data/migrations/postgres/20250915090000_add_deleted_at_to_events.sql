@@ -0,0 +1,3 @@
+-- When the event was deleted on the NVR, in milliseconds since the epoch; NULL while it's still
+-- there. Deleted events that weren't backed up yet never will be.
+ALTER TABLE events ADD COLUMN deleted_at BIGINT;
@@ -0,0 +1,3 @@
+-- Events still to be backed up are fetched on every poll, oldest first
+CREATE INDEX IF NOT EXISTS idx_events_pending ON events (start_time)
+    WHERE backed_up = FALSE AND skip_reason IS NULL AND deleted_at IS NULL;
@@ -0,0 +1,2 @@
+-- Backups are looked up by path to tell when two events would be stored under the same name
+CREATE INDEX IF NOT EXISTS idx_backups_remote_path ON backups (target, remote_path);
@@ -0,0 +1,11 @@
+-- Intentional detection pauses (privacy mode, recording disabled) so gaps in coverage can be
+-- told apart from failures
+CREATE TABLE IF NOT EXISTS camera_pauses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    camera_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    start_time INTEGER NOT NULL,
+    end_time INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_camera_pauses_camera_id ON camera_pauses (camera_id, start_time);
@@ -0,0 +1,17 @@
+-- The same filename is written to every target, so backups must be keyed by target as well
+CREATE TABLE IF NOT EXISTS backups_new (
+    event_id TEXT NOT NULL,
+    target TEXT NOT NULL,
+    remote_path TEXT NOT NULL,
+    backup_time INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    PRIMARY KEY (event_id, target),
+    FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
+
+INSERT OR IGNORE INTO backups_new (event_id, target, remote_path, backup_time, size_bytes)
+SELECT event_id, '', remote_path, backup_time, size_bytes FROM backups;
+
+DROP TABLE backups;
+
+ALTER TABLE backups_new RENAME TO backups;
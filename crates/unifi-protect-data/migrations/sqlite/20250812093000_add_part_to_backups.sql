@@ -0,0 +1,18 @@
+-- Long events are exported in several parts, each backed up separately. Part 0 is a whole event.
+CREATE TABLE IF NOT EXISTS backups_new (
+    event_id TEXT NOT NULL,
+    target TEXT NOT NULL,
+    part INTEGER NOT NULL DEFAULT 0,
+    remote_path TEXT NOT NULL,
+    backup_time INTEGER NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    PRIMARY KEY (event_id, target, part),
+    FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
+
+INSERT OR IGNORE INTO backups_new (event_id, target, part, remote_path, backup_time, size_bytes)
+SELECT event_id, target, 0, remote_path, backup_time, size_bytes FROM backups;
+
+DROP TABLE backups;
+
+ALTER TABLE backups_new RENAME TO backups;
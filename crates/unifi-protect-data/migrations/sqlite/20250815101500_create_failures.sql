@@ -0,0 +1,12 @@
+-- The tail of subprocess output for failed backup/archive operations, so failures can be
+-- inspected without rerunning them
+CREATE TABLE IF NOT EXISTS failures (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    subject TEXT NOT NULL,
+    target TEXT NOT NULL,
+    error TEXT NOT NULL,
+    output TEXT NOT NULL,
+    failure_time INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_failures_subject_target ON failures (subject, target, failure_time);
@@ -0,0 +1,3 @@
+-- Why an event was deliberately not backed up (e.g. privacy hours); NULL for events pending or
+-- backed up normally
+ALTER TABLE events ADD COLUMN skip_reason TEXT;
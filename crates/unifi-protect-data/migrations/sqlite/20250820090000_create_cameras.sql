@@ -0,0 +1,11 @@
+-- Every camera seen on the NVR, including ones since removed, so events can still be resolved
+-- to a camera name after the camera is gone from the bootstrap
+CREATE TABLE IF NOT EXISTS cameras (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    mac TEXT NOT NULL,
+    model TEXT,
+    first_seen INTEGER NOT NULL,
+    last_updated INTEGER NOT NULL,
+    removed_at INTEGER
+);
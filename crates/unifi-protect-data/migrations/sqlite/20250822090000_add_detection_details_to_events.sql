@@ -0,0 +1,4 @@
+-- Keep enough of the original event to rebuild it faithfully when backing it up
+ALTER TABLE events ADD COLUMN smart_detect_types TEXT NOT NULL DEFAULT '';
+ALTER TABLE events ADD COLUMN thumbnail_id TEXT;
+ALTER TABLE events ADD COLUMN heatmap_id TEXT;
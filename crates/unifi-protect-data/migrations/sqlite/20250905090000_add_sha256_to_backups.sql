@@ -0,0 +1,3 @@
+-- Hex SHA-256 of the data as uploaded, for verifying copies later. Backups recorded before this
+-- (or imported from elsewhere) have none.
+ALTER TABLE backups ADD COLUMN sha256 TEXT;
@@ -0,0 +1,12 @@
+-- Doorbell rings, quick replies and people identified at the door, and chimes sounding: audit data
+-- which isn't footage, kept until deleted by hand
+CREATE TABLE IF NOT EXISTS doorbell_interactions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    user_id TEXT,
+    detail TEXT,
+    timestamp INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_doorbell_interactions_timestamp ON doorbell_interactions (timestamp);
@@ -0,0 +1,10 @@
+-- Tags and licence plates Protect read from events' detections, for search
+CREATE TABLE IF NOT EXISTS event_labels (
+    event_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (event_id, kind, value),
+    FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_event_labels_value ON event_labels (kind, value);
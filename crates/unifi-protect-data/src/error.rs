@@ -8,4 +8,14 @@ pub enum Error {
     Sqlx(#[from] sqlx::Error),
     #[error("Migration error: {0}")]
     Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("Unsupported database URL scheme '{0}'")]
+    UnsupportedUrl(String),
+    #[error("{0} is not supported")]
+    Unsupported(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
 }
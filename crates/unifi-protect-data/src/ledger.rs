@@ -0,0 +1,81 @@
+//! Export and import of the event ledger, i.e. the `events` and `backups` tables, for moving a
+//! database between hosts or feeding external reporting.
+
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{Backup, Database, Event, error::Result};
+
+/// File names used for the two tables in a CSV export directory
+pub const EVENTS_CSV: &str = "events.csv";
+pub const BACKUPS_CSV: &str = "backups.csv";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    pub events: Vec<Event>,
+    pub backups: Vec<Backup>,
+}
+
+impl Ledger {
+    pub fn write_json(&self, writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn read_json(reader: impl Read) -> Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write [`EVENTS_CSV`] and [`BACKUPS_CSV`] to `dir`, creating it if needed.
+    pub fn write_csv(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        write_csv(&dir.join(EVENTS_CSV), &self.events)?;
+        write_csv(&dir.join(BACKUPS_CSV), &self.backups)?;
+        Ok(())
+    }
+
+    pub fn read_csv(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            events: read_csv(&dir.join(EVENTS_CSV))?,
+            backups: read_csv(&dir.join(BACKUPS_CSV))?,
+        })
+    }
+}
+
+fn write_csv<T: Serialize>(path: &Path, rows: &[T]) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_csv<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let rows = csv::Reader::from_path(path)?
+        .deserialize()
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(rows)
+}
+
+impl Database {
+    pub async fn export_ledger(&self) -> Result<Ledger> {
+        Ok(Ledger {
+            events: self.get_events().await?,
+            backups: self.get_backups().await?,
+        })
+    }
+
+    /// Insert every event and then every backup from `ledger`, updating rows with the same key.
+    /// Rows already in the database but not in the ledger are kept, including the backups of
+    /// events the ledger updates.
+    pub async fn import_ledger(&self, ledger: &Ledger) -> Result<()> {
+        self.insert_events(&ledger.events).await?;
+        self.record_backups(&ledger.backups).await
+    }
+}
@@ -0,0 +1,77 @@
+//! Reading the SQLite database of the Python
+//! [unifi-protect-backup](https://github.com/ep1cman/unifi-protect-backup), so users switching
+//! over don't upload everything again.
+
+use std::{collections::HashSet, path::Path};
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use crate::{Event, error::Result};
+
+/// A row of the Python tool's `backups` table
+#[derive(Debug, Clone)]
+pub struct LegacyBackup {
+    pub event_id: String,
+    /// The rclone destination the file was uploaded to, e.g. `gdrive:/unifi_protect`
+    pub remote: String,
+    /// Path of the file relative to `remote`
+    pub path: String,
+}
+
+#[derive(Debug, Default)]
+pub struct LegacyDatabase {
+    /// Events as recorded by the Python tool; `event_type` is its raw type, e.g.
+    /// `smartDetectZone`, and `backed_up` is set for events with at least one backup
+    pub events: Vec<Event>,
+    pub backups: Vec<LegacyBackup>,
+}
+
+/// Read the `events` and `backups` tables of a Python unifi-protect-backup database, without
+/// modifying it.
+pub async fn read_legacy_database(path: &Path) -> Result<LegacyDatabase> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(SqliteConnectOptions::new().filename(path).read_only(true))
+        .await?;
+
+    let backups: Vec<LegacyBackup> =
+        sqlx::query_as::<_, (String, String, String)>("SELECT id, remote, path FROM backups")
+            .fetch_all(&pool)
+            .await?
+            .into_iter()
+            .map(|(event_id, remote, path)| LegacyBackup {
+                event_id,
+                remote,
+                path,
+            })
+            .collect();
+
+    let backed_up: HashSet<_> = backups
+        .iter()
+        .map(|backup| backup.event_id.clone())
+        .collect();
+
+    // times are stored as fractional epoch seconds
+    let events = sqlx::query_as::<_, (String, String, String, f64, Option<f64>)>(
+        "SELECT id, type, camera_id, start, end FROM events",
+    )
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(|(id, event_type, camera_id, start, end)| Event {
+        backed_up: backed_up.contains(&id),
+        id,
+        event_type,
+        camera_id,
+        start_time: (start * 1000.0) as i64,
+        end_time: end.map(|end| (end * 1000.0) as i64),
+        skip_reason: None,
+        smart_detect_types: String::new(),
+        thumbnail_id: None,
+        heatmap_id: None,
+    })
+    .collect();
+
+    pool.close().await;
+    Ok(LegacyDatabase { events, backups })
+}
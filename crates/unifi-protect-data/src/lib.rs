@@ -1,12 +1,28 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc, time::Duration};
 
 use chrono::{DateTime, Utc};
+use metered::{ErrorCount, HitCount, ResponseTime};
 use serde::{Deserialize, Serialize};
-use sqlx::{SqlitePool, migrate::MigrateDatabase, sqlite::SqlitePoolOptions};
+use sqlx::{
+    QueryBuilder, Sqlite, SqlitePool,
+    migrate::MigrateDatabase,
+    sqlite::{SqliteAutoVacuum, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+};
 
 pub mod error;
+pub mod ledger;
+pub mod legacy;
+#[cfg(feature = "postgres")]
+mod postgres;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+
+/// Rows per statement in the batch queries, well below either backend's limit on bind parameters
+const BATCH_ROWS: usize = 1000;
+
+/// `kind`s of rows in `event_labels`
+pub(crate) const LABEL_TAG: &str = "tag";
+pub(crate) const LABEL_PLATE: &str = "plate";
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Event {
@@ -16,89 +32,975 @@ pub struct Event {
     pub start_time: i64,
     pub end_time: Option<i64>,
     pub backed_up: bool,
+    /// Set when the event was deliberately not backed up
+    pub skip_reason: Option<String>,
+    /// Comma-separated smart detection types, e.g. `person,vehicle`
+    pub smart_detect_types: String,
+    pub thumbnail_id: Option<String>,
+    pub heatmap_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Backup {
     pub event_id: String,
+    pub target: String,
+    /// 1-based part number for events exported in several parts, or 0 for a whole event
+    pub part: u32,
     pub remote_path: String,
     pub backup_time: DateTime<Utc>,
     pub size_bytes: u64,
+    /// Hex SHA-256 of the data as uploaded, if it was recorded
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// An upload which started but hadn't finished, still recorded if the process stopped part way
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InFlightUpload {
+    pub event_id: String,
+    pub target: String,
+    pub part: u32,
+    /// The spooled export being uploaded, if there's a spool
+    pub spool_path: Option<String>,
+    /// Bytes of the export passed to the target so far
+    pub bytes_sent: u64,
+    pub started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CameraPause {
+    pub id: i64,
+    pub camera_id: String,
+    pub reason: String,
+    pub start_time: i64,
+    pub end_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Camera {
+    pub id: String,
+    pub name: String,
+    pub mac: String,
+    pub model: Option<String>,
+    pub first_seen: i64,
+    pub last_updated: i64,
+    /// When the camera was removed from the NVR, if it has been
+    pub removed_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    /// The event id for backups, or `archive` for archive runs
+    pub subject: String,
+    pub target: String,
+    pub error: String,
+    /// Tail of the subprocess output, empty if the failure didn't come from a subprocess
+    pub output: String,
+    pub failure_time: DateTime<Utc>,
+}
+
+/// A doorbell ring, quick reply or person identified at the door, or a chime sounding
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DoorbellInteraction {
+    /// The doorbell camera, or the chime
+    pub device_id: String,
+    /// `ring`, `chime`, `quick_reply`, `fingerprint` or `nfc`
+    pub action: String,
+    /// Who was identified at the door, for fingerprints and NFC cards
+    pub user_id: Option<String>,
+    /// The message shown, for quick replies
+    pub detail: Option<String>,
+    /// Milliseconds since the epoch
+    pub timestamp: i64,
+}
+
+/// Events a camera recorded on one UTC day
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CameraDayCount {
+    pub camera_id: String,
+    /// `YYYY-MM-DD`
+    pub day: String,
+    pub events: i64,
+}
+
+/// Backups recorded for one target
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TargetUsage {
+    pub target: String,
+    pub backups: i64,
+    pub bytes: i64,
+}
+
+/// Failures recorded for one target
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TargetFailures {
+    pub target: String,
+    pub failures: i64,
+}
+
+/// Filters for [`Database::search_events`]; unset fields match everything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventSearch {
+    /// Case-insensitive substring of the event id, camera name, detection types, tags or plates
+    pub query: Option<String>,
+    /// Camera id or name
+    pub camera: Option<String>,
+    /// Event type or smart detection type, e.g. `motion` or `person`
+    pub detection_type: Option<String>,
+    /// Case-insensitive tag read from a detection, e.g. `red` or `suv`
+    pub tag: Option<String>,
+    /// Case-insensitive substring of a licence plate read from a detection
+    pub plate: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+impl Default for EventSearch {
+    fn default() -> Self {
+        Self {
+            query: None,
+            camera: None,
+            detection_type: None,
+            tag: None,
+            plate: None,
+            start: None,
+            end: None,
+            limit: 50,
+            offset: 0,
+        }
+    }
+}
+
+/// Finished events still waiting to be backed up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backlog {
+    pub events: u64,
+    /// Start of the oldest waiting event
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+/// SQLite journal mode. WAL lets the listener write while the poller reads without hitting
+/// `database is locked`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    #[default]
+    Wal,
+    Off,
+}
+
+impl From<JournalMode> for SqliteJournalMode {
+    fn from(mode: JournalMode) -> Self {
+        match mode {
+            JournalMode::Delete => SqliteJournalMode::Delete,
+            JournalMode::Truncate => SqliteJournalMode::Truncate,
+            JournalMode::Persist => SqliteJournalMode::Persist,
+            JournalMode::Memory => SqliteJournalMode::Memory,
+            JournalMode::Wal => SqliteJournalMode::Wal,
+            JournalMode::Off => SqliteJournalMode::Off,
+        }
+    }
+}
+
+/// Connection pool settings. The journal mode and busy timeout only apply to SQLite.
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub journal_mode: JournalMode,
+    /// How long a connection waits on a locked database before failing
+    pub busy_timeout: Duration,
+    pub max_connections: u32,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            journal_mode: JournalMode::default(),
+            busy_timeout: Duration::from_secs(5),
+            max_connections: 5,
+        }
+    }
+}
+
+/// The store behind a [`Database`]. Postgres is only available with the `postgres` feature and
+/// lets several instances share central storage instead of each keeping a local SQLite file.
+#[derive(Clone)]
+enum Backend {
+    Sqlite(SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(sqlx::PgPool),
 }
 
+#[derive(Clone)]
 pub struct Database {
-    pool: SqlitePool,
+    backend: Backend,
+    metrics: Arc<DatabaseMetrics>,
 }
 
+// each query matches on the backend, which only has the one variant without `postgres`
+#[cfg_attr(
+    not(feature = "postgres"),
+    allow(clippy::infallible_destructuring_match)
+)]
+#[metered::metered(registry = DatabaseMetrics, visibility = pub)]
 impl Database {
-    pub async fn new(db_path: &Path) -> Result<Self> {
+    pub async fn new(db_path: &Path, options: &PoolOptions) -> Result<Self> {
+        // a fresh install's data directory may not exist yet
+        if let Some(parent) = db_path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
         if !sqlx::Sqlite::database_exists(&db_path.to_string_lossy()).await? {
             sqlx::Sqlite::create_database(&db_path.to_string_lossy()).await?;
         }
 
-        let database_url = format!("sqlite:{}", db_path.display());
+        let connect_options = SqliteConnectOptions::new()
+            .filename(db_path)
+            .journal_mode(options.journal_mode.into())
+            .busy_timeout(options.busy_timeout)
+            // only takes effect for new databases; existing ones need a one-off `VACUUM`
+            .auto_vacuum(SqliteAutoVacuum::Incremental);
 
         let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(&database_url)
+            .max_connections(options.max_connections)
+            .connect_with(connect_options)
             .await?;
 
-        sqlx::migrate!("./migrations").run(&pool).await?;
+        sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
 
-        Ok(Database { pool })
+        Ok(Database {
+            backend: Backend::Sqlite(pool),
+            metrics: Arc::new(DatabaseMetrics::default()),
+        })
     }
 
-    #[tracing::instrument(skip(self))]
+    /// Open a database by URL, either `sqlite:<path>` or `postgres://...`, running the
+    /// migrations for that backend.
+    pub async fn connect(database_url: &str, options: &PoolOptions) -> Result<Self> {
+        if let Some(path) = database_url.strip_prefix("sqlite:") {
+            return Self::new(Path::new(path.trim_start_matches("//")), options).await;
+        }
+
+        #[cfg(feature = "postgres")]
+        if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            let pool = sqlx::postgres::PgPoolOptions::new()
+                .max_connections(options.max_connections)
+                .connect(database_url)
+                .await?;
+
+            sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+
+            return Ok(Database {
+                backend: Backend::Postgres(pool),
+                metrics: Arc::new(DatabaseMetrics::default()),
+            });
+        }
+
+        Err(Error::UnsupportedUrl(
+            database_url
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        ))
+    }
+
+    /// Per-query call counts, errors and timings
+    pub fn metrics(&self) -> Arc<DatabaseMetrics> {
+        self.metrics.clone()
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
     pub async fn insert_event(&self, event: &Event) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::insert_event(pool, event).await,
+        };
+
         sqlx::query!(
             r#"
-            INSERT OR REPLACE INTO events (id, event_type, camera_id, start_time, end_time, backed_up)
-            VALUES (?, ?, ?, ?, ?, ?)
+            INSERT INTO events (id, event_type, camera_id, start_time, end_time, backed_up, skip_reason, smart_detect_types, thumbnail_id, heatmap_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (id) DO UPDATE SET
+                event_type = excluded.event_type,
+                camera_id = excluded.camera_id,
+                start_time = excluded.start_time,
+                end_time = excluded.end_time,
+                backed_up = excluded.backed_up,
+                skip_reason = excluded.skip_reason,
+                smart_detect_types = excluded.smart_detect_types,
+                thumbnail_id = excluded.thumbnail_id,
+                heatmap_id = excluded.heatmap_id
             "#,
             event.id,
             event.event_type,
             event.camera_id,
             event.start_time,
             event.end_time,
-            event.backed_up
+            event.backed_up,
+            event.skip_reason,
+            event.smart_detect_types,
+            event.thumbnail_id,
+            event.heatmap_id
         )
-        .execute(&self.pool)
-        .await?;
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
 
         Ok(())
     }
 
-    #[tracing::instrument(skip(self))]
+    /// [`insert_event`](Self::insert_event) for many events in one transaction.
+    #[tracing::instrument(skip(self, events), fields(events = events.len(), rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn insert_events(&self, events: &[Event]) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::insert_events(pool, events).await,
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut rows = 0;
+        for chunk in events.chunks(BATCH_ROWS) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "INSERT INTO events (id, event_type, camera_id, start_time, end_time, \
+                 backed_up, skip_reason, smart_detect_types, thumbnail_id, heatmap_id) ",
+            );
+            query.push_values(chunk, |mut row, event| {
+                row.push_bind(event.id.as_str())
+                    .push_bind(event.event_type.as_str())
+                    .push_bind(event.camera_id.as_str())
+                    .push_bind(event.start_time)
+                    .push_bind(event.end_time)
+                    .push_bind(event.backed_up)
+                    .push_bind(event.skip_reason.as_deref())
+                    .push_bind(event.smart_detect_types.as_str())
+                    .push_bind(event.thumbnail_id.as_deref())
+                    .push_bind(event.heatmap_id.as_deref());
+            });
+            // an update rather than a replace, which would delete the event's backups with it
+            query.push(
+                r#"
+                ON CONFLICT (id) DO UPDATE SET
+                    event_type = excluded.event_type,
+                    camera_id = excluded.camera_id,
+                    start_time = excluded.start_time,
+                    end_time = excluded.end_time,
+                    backed_up = excluded.backed_up,
+                    skip_reason = excluded.skip_reason,
+                    smart_detect_types = excluded.smart_detect_types,
+                    thumbnail_id = excluded.thumbnail_id,
+                    heatmap_id = excluded.heatmap_id
+                "#,
+            );
+            rows += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        record_rows(rows);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
     pub async fn mark_event_backed_up(&self, event_id: &str) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::mark_event_backed_up(pool, event_id).await,
+        };
+
         sqlx::query!("UPDATE events SET backed_up = TRUE WHERE id = ?", event_id)
-            .execute(&self.pool)
-            .await?;
+            .execute(pool)
+            .await
+            .inspect(|result| record_rows(result.rows_affected()))?;
 
         Ok(())
     }
 
-    #[tracing::instrument(skip(self))]
+    /// [`mark_event_backed_up`](Self::mark_event_backed_up) for many events in one transaction.
+    #[tracing::instrument(skip(self, event_ids), fields(events = event_ids.len(), rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn mark_events_backed_up(&self, event_ids: &[String]) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::mark_events_backed_up(pool, event_ids).await;
+            }
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut rows = 0;
+        for chunk in event_ids.chunks(BATCH_ROWS) {
+            let mut query =
+                QueryBuilder::<Sqlite>::new("UPDATE events SET backed_up = TRUE WHERE id IN (");
+            let mut ids = query.separated(", ");
+            for event_id in chunk {
+                ids.push_bind(event_id.as_str());
+            }
+            query.push(")");
+            rows += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        record_rows(rows);
+
+        Ok(())
+    }
+
+    /// Keep the event but never back it up, recording why.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn mark_event_skipped(&self, event_id: &str, reason: &str) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::mark_event_skipped(pool, event_id, reason).await;
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE events SET skip_reason = ? WHERE id = ?",
+            reason,
+            event_id
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// Record that the event was deleted on the NVR at `deleted_at` (milliseconds since the
+    /// epoch), so it's no longer backed up if it hasn't been already.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn mark_event_deleted(&self, event_id: &str, deleted_at: i64) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::mark_event_deleted(pool, event_id, deleted_at).await;
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE events SET deleted_at = ? WHERE id = ?",
+            deleted_at,
+            event_id
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
     pub async fn insert_backup(&self, backup: &Backup) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::insert_backup(pool, backup).await,
+        };
+
         let size_bytes = backup.size_bytes as i64;
         let timestamp = backup.backup_time.timestamp();
         sqlx::query!(
             r#"
-            INSERT OR REPLACE INTO backups (event_id, remote_path, backup_time, size_bytes)
-            VALUES (?, ?, ?, ?)
+            INSERT OR REPLACE INTO backups (event_id, target, part, remote_path, backup_time, size_bytes, sha256)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
             "#,
             backup.event_id,
+            backup.target,
+            backup.part,
             backup.remote_path,
             timestamp,
-            size_bytes
+            size_bytes,
+            backup.sha256
         )
-        .execute(&self.pool)
-        .await?;
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// [`insert_backup`](Self::insert_backup) for many backups in one transaction.
+    #[tracing::instrument(skip(self, backups), fields(backups = backups.len(), rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn record_backups(&self, backups: &[Backup]) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::record_backups(pool, backups).await,
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut rows = 0;
+        for chunk in backups.chunks(BATCH_ROWS) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "INSERT OR REPLACE INTO backups \
+                 (event_id, target, part, remote_path, backup_time, size_bytes, sha256) ",
+            );
+            query.push_values(chunk, |mut row, backup| {
+                row.push_bind(backup.event_id.as_str())
+                    .push_bind(backup.target.as_str())
+                    .push_bind(backup.part)
+                    .push_bind(backup.remote_path.as_str())
+                    .push_bind(backup.backup_time.timestamp())
+                    .push_bind(backup.size_bytes as i64)
+                    .push_bind(backup.sha256.as_deref());
+            });
+            rows += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        record_rows(rows);
 
         Ok(())
     }
 
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_backups(&self) -> Result<Vec<Backup>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_backups(pool).await,
+        };
+
+        let backups = sqlx::query!(
+            r#"
+            SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+            FROM backups
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?
+        .into_iter()
+        .map(|row| Backup {
+            event_id: row.event_id,
+            target: row.target,
+            part: row.part as u32,
+            remote_path: row.remote_path,
+            backup_time: DateTime::from_timestamp(row.backup_time, 0).unwrap_or_default(),
+            size_bytes: row.size_bytes as u64,
+            sha256: row.sha256,
+        })
+        .collect();
+
+        Ok(backups)
+    }
+
+    /// When the most recent backup still recorded was made, or `None` if there are none.
     #[tracing::instrument(skip(self))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn latest_backup_time(&self) -> Result<Option<DateTime<Utc>>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::latest_backup_time(pool).await,
+        };
+
+        let latest =
+            sqlx::query_scalar!(r#"SELECT MAX(backup_time) as "latest?: i64" FROM backups"#)
+                .fetch_one(pool)
+                .await?;
+
+        Ok(latest.and_then(|latest| DateTime::from_timestamp(latest, 0)))
+    }
+
+    /// Backups of events deleted on the NVR, for `mirror-deletions` to delete in turn
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_backups_of_deleted_events(&self) -> Result<Vec<Backup>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_backups_of_deleted_events(pool).await,
+        };
+
+        let backups = sqlx::query!(
+            r#"
+            SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+            FROM backups
+            WHERE event_id IN (SELECT id FROM events WHERE deleted_at IS NOT NULL)
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?
+        .into_iter()
+        .map(|row| Backup {
+            event_id: row.event_id,
+            target: row.target,
+            part: row.part as u32,
+            remote_path: row.remote_path,
+            backup_time: DateTime::from_timestamp(row.backup_time, 0).unwrap_or_default(),
+            size_bytes: row.size_bytes as u64,
+            sha256: row.sha256,
+        })
+        .collect();
+
+        Ok(backups)
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_backups_by_event(&self, event_id: &str) -> Result<Vec<Backup>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_backups_by_event(pool, event_id).await,
+        };
+
+        let backups = sqlx::query!(
+            r#"
+            SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+            FROM backups WHERE event_id = ?
+            "#,
+            event_id
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?
+        .into_iter()
+        .map(|row| Backup {
+            event_id: row.event_id,
+            target: row.target,
+            part: row.part as u32,
+            remote_path: row.remote_path,
+            backup_time: DateTime::from_timestamp(row.backup_time, 0).unwrap_or_default(),
+            size_bytes: row.size_bytes as u64,
+            sha256: row.sha256,
+        })
+        .collect();
+
+        Ok(backups)
+    }
+
+    /// The backup stored at `remote_path` on `target`, if there is one
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_backup_by_path(
+        &self,
+        target: &str,
+        remote_path: &str,
+    ) -> Result<Option<Backup>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::get_backup_by_path(pool, target, remote_path).await;
+            }
+        };
+
+        let backup = sqlx::query!(
+            r#"
+            SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+            FROM backups WHERE target = ? AND remote_path = ?
+            "#,
+            target,
+            remote_path
+        )
+        .fetch_optional(pool)
+        .await
+        .inspect(|row| record_rows(row.is_some() as u64))?
+        .map(|row| Backup {
+            event_id: row.event_id,
+            target: row.target,
+            part: row.part as u32,
+            remote_path: row.remote_path,
+            backup_time: DateTime::from_timestamp(row.backup_time, 0).unwrap_or_default(),
+            size_bytes: row.size_bytes as u64,
+            sha256: row.sha256,
+        });
+
+        Ok(backup)
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn update_backup_remote_path(
+        &self,
+        event_id: &str,
+        target: &str,
+        part: u32,
+        remote_path: &str,
+    ) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::update_backup_remote_path(
+                    pool,
+                    event_id,
+                    target,
+                    part,
+                    remote_path,
+                )
+                .await;
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE backups SET remote_path = ? WHERE event_id = ? AND target = ? AND part = ?",
+            remote_path,
+            event_id,
+            target,
+            part
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// Backups made before `cutoff`, oldest first.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_backups_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<Backup>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_backups_before(pool, cutoff).await,
+        };
+
+        let cutoff_time = cutoff.timestamp();
+
+        let backups = sqlx::query!(
+            r#"
+            SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+            FROM backups WHERE backup_time < ?
+            ORDER BY backup_time
+            "#,
+            cutoff_time
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?
+        .into_iter()
+        .map(|row| Backup {
+            event_id: row.event_id,
+            target: row.target,
+            part: row.part as u32,
+            remote_path: row.remote_path,
+            backup_time: DateTime::from_timestamp(row.backup_time, 0).unwrap_or_default(),
+            size_bytes: row.size_bytes as u64,
+            sha256: row.sha256,
+        })
+        .collect();
+
+        Ok(backups)
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn delete_backup(&self, event_id: &str, target: &str, part: u32) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::delete_backup(pool, event_id, target, part).await;
+            }
+        };
+
+        sqlx::query!(
+            "DELETE FROM backups WHERE event_id = ? AND target = ? AND part = ?",
+            event_id,
+            target,
+            part
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// [`delete_backup`](Self::delete_backup) for many backups in one transaction, so the
+    /// records of a batch deleted from a target go together.
+    #[tracing::instrument(skip(self, backups), fields(backups = backups.len(), rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn delete_backups(&self, backups: &[Backup]) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::delete_backups(pool, backups).await,
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut rows = 0;
+        for backup in backups {
+            rows += sqlx::query!(
+                "DELETE FROM backups WHERE event_id = ? AND target = ? AND part = ?",
+                backup.event_id,
+                backup.target,
+                backup.part
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+        record_rows(rows);
+
+        Ok(())
+    }
+
+    /// Record uploads as started, replacing any earlier record of the same upload.
+    #[tracing::instrument(skip(self, uploads), fields(uploads = uploads.len(), rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn start_uploads(&self, uploads: &[InFlightUpload]) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::start_uploads(pool, uploads).await,
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut rows = 0;
+        for chunk in uploads.chunks(BATCH_ROWS) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "INSERT OR REPLACE INTO in_flight_uploads \
+                 (event_id, target, part, spool_path, bytes_sent, started_at) ",
+            );
+            query.push_values(chunk, |mut row, upload| {
+                row.push_bind(upload.event_id.as_str())
+                    .push_bind(upload.target.as_str())
+                    .push_bind(upload.part)
+                    .push_bind(upload.spool_path.as_deref())
+                    .push_bind(upload.bytes_sent as i64)
+                    .push_bind(upload.started_at.timestamp());
+            });
+            rows += query.build().execute(&mut *tx).await?.rows_affected();
+        }
+        tx.commit().await?;
+        record_rows(rows);
+
+        Ok(())
+    }
+
+    /// Record how much of an event part has been passed to every target uploading it.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn update_upload_progress(
+        &self,
+        event_id: &str,
+        part: u32,
+        bytes_sent: u64,
+    ) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::update_upload_progress(pool, event_id, part, bytes_sent).await;
+            }
+        };
+
+        // progress is recorded without waiting, so an older update can arrive late
+        let bytes_sent = bytes_sent as i64;
+        sqlx::query!(
+            r#"
+            UPDATE in_flight_uploads SET bytes_sent = max(bytes_sent, ?)
+            WHERE event_id = ? AND part = ?
+            "#,
+            bytes_sent,
+            event_id,
+            part
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// Forget the uploads of an event part, whether they succeeded or failed.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn finish_uploads(&self, event_id: &str, part: u32) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::finish_uploads(pool, event_id, part).await,
+        };
+
+        sqlx::query!(
+            "DELETE FROM in_flight_uploads WHERE event_id = ? AND part = ?",
+            event_id,
+            part
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// Every upload still recorded as in flight, removing the records. Only meaningful at
+    /// startup, before any upload has begun.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn take_in_flight_uploads(&self) -> Result<Vec<InFlightUpload>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::take_in_flight_uploads(pool).await,
+        };
+
+        let mut tx = pool.begin().await?;
+        let uploads = sqlx::query!(
+            r#"
+            SELECT event_id, target, part, spool_path, bytes_sent, started_at
+            FROM in_flight_uploads
+            ORDER BY started_at
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?
+        .into_iter()
+        .map(|row| InFlightUpload {
+            event_id: row.event_id,
+            target: row.target,
+            part: row.part as u32,
+            spool_path: row.spool_path,
+            bytes_sent: row.bytes_sent as u64,
+            started_at: DateTime::from_timestamp(row.started_at, 0).unwrap_or_default(),
+        })
+        .collect();
+        sqlx::query!("DELETE FROM in_flight_uploads")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(uploads)
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
     pub async fn get_event_by_id(&self, id: &str) -> Result<Option<Event>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_event_by_id(pool, id).await,
+        };
+
         let event = sqlx::query_as!(
             Event,
             r#"
@@ -107,19 +1009,32 @@ impl Database {
                    camera_id as "camera_id!: _",
                    start_time as "start_time!: _",
                    end_time as "end_time?: _",
-                   backed_up as "backed_up!: _"
+                   backed_up as "backed_up!: _",
+                   skip_reason as "skip_reason?: _",
+                   smart_detect_types as "smart_detect_types!: _",
+                   thumbnail_id as "thumbnail_id?: _",
+                   heatmap_id as "heatmap_id?: _"
             FROM events WHERE id = ?
             "#,
             id
         )
-        .fetch_optional(&self.pool)
-        .await?;
+        .fetch_optional(pool)
+        .await
+        .inspect(|row| record_rows(row.is_some() as u64))?;
 
         Ok(event)
     }
 
-    #[tracing::instrument(skip(self))]
+    /// Finished events still to be backed up, oldest first.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
     pub async fn get_events_not_backed_up(&self) -> Result<Vec<Event>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_events_not_backed_up(pool).await,
+        };
+
         let events = sqlx::query_as!(
             Event,
             r#"
@@ -128,18 +1043,34 @@ impl Database {
                    camera_id as "camera_id!: _",
                    start_time as "start_time!: _",
                    end_time as "end_time?: _",
-                   backed_up as "backed_up!: _"
-            FROM events WHERE backed_up = FALSE AND end_time IS NOT NULL
+                   backed_up as "backed_up!: _",
+                   skip_reason as "skip_reason?: _",
+                   smart_detect_types as "smart_detect_types!: _",
+                   thumbnail_id as "thumbnail_id?: _",
+                   heatmap_id as "heatmap_id?: _"
+            FROM events WHERE backed_up = FALSE AND skip_reason IS NULL AND end_time IS NOT NULL
+                AND deleted_at IS NULL
+            ORDER BY start_time
             "#
         )
-        .fetch_all(&self.pool)
-        .await?;
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
 
         Ok(events)
     }
 
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
     pub async fn get_events_by_camera(&self, camera_id: &str) -> Result<Vec<Event>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::get_events_by_camera(pool, camera_id).await;
+            }
+        };
+
         let events = sqlx::query_as!(
             Event,
             r#"
@@ -148,26 +1079,956 @@ impl Database {
                    camera_id as "camera_id!: _",
                    start_time as "start_time!: _",
                    end_time as "end_time?: _",
-                   backed_up as "backed_up!: _"
+                   backed_up as "backed_up!: _",
+                   skip_reason as "skip_reason?: _",
+                   smart_detect_types as "smart_detect_types!: _",
+                   thumbnail_id as "thumbnail_id?: _",
+                   heatmap_id as "heatmap_id?: _"
             FROM events WHERE camera_id = ?
             "#,
             camera_id
         )
-        .fetch_all(&self.pool)
-        .await?;
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
+
+        Ok(events)
+    }
+
+    /// Record tags and licence plates read from an event's detections, keeping those already
+    /// recorded. Labels for an event that isn't recorded are dropped.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn add_event_labels(
+        &self,
+        event_id: &str,
+        tags: &[String],
+        plates: &[String],
+    ) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::add_event_labels(pool, event_id, tags, plates).await;
+            }
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut rows = 0;
+        let labels = tags
+            .iter()
+            .map(|tag| (LABEL_TAG, tag))
+            .chain(plates.iter().map(|plate| (LABEL_PLATE, plate)));
+        for (kind, value) in labels {
+            rows += sqlx::query!(
+                r#"
+                INSERT OR IGNORE INTO event_labels (event_id, kind, value)
+                SELECT id, ?, ? FROM events WHERE id = ?
+                "#,
+                kind,
+                value,
+                event_id
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+        record_rows(rows);
+
+        Ok(())
+    }
+
+    /// Events matching `search`, newest first.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn search_events(&self, search: &EventSearch) -> Result<Vec<Event>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::search_events(pool, search).await,
+        };
+
+        let start = search.start.map(|start| start.timestamp_millis());
+        let end = search.end.map(|end| end.timestamp_millis());
+        let (limit, offset) = (search.limit as i64, search.offset as i64);
+
+        let events = sqlx::query_as!(
+            Event,
+            r#"
+            SELECT e.id as "id!: String",
+                   e.event_type as "event_type!: _",
+                   e.camera_id as "camera_id!: _",
+                   e.start_time as "start_time!: _",
+                   e.end_time as "end_time?: _",
+                   e.backed_up as "backed_up!: _",
+                   e.skip_reason as "skip_reason?: _",
+                   e.smart_detect_types as "smart_detect_types!: _",
+                   e.thumbnail_id as "thumbnail_id?: _",
+                   e.heatmap_id as "heatmap_id?: _"
+            FROM events e LEFT JOIN cameras c ON c.id = e.camera_id
+            WHERE (?1 IS NULL
+                   OR e.id LIKE '%' || ?1 || '%'
+                   OR c.name LIKE '%' || ?1 || '%'
+                   OR e.event_type LIKE '%' || ?1 || '%'
+                   OR e.smart_detect_types LIKE '%' || ?1 || '%'
+                   OR EXISTS (
+                       SELECT 1 FROM event_labels l
+                       WHERE l.event_id = e.id AND l.value LIKE '%' || ?1 || '%'))
+              AND (?2 IS NULL OR e.camera_id = ?2 OR c.name = ?2)
+              AND (?3 IS NULL
+                   OR e.event_type = ?3
+                   OR ',' || e.smart_detect_types || ',' LIKE '%,' || ?3 || ',%')
+              AND (?4 IS NULL OR e.start_time >= ?4)
+              AND (?5 IS NULL OR e.start_time < ?5)
+              AND (?8 IS NULL OR EXISTS (
+                   SELECT 1 FROM event_labels l
+                   WHERE l.event_id = e.id AND l.kind = 'tag' AND l.value = ?8 COLLATE NOCASE))
+              AND (?9 IS NULL OR EXISTS (
+                   SELECT 1 FROM event_labels l
+                   WHERE l.event_id = e.id AND l.kind = 'plate' AND l.value LIKE '%' || ?9 || '%'))
+            ORDER BY e.start_time DESC
+            LIMIT ?6 OFFSET ?7
+            "#,
+            search.query,
+            search.camera,
+            search.detection_type,
+            start,
+            end,
+            limit,
+            offset,
+            search.tag,
+            search.plate
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
 
         Ok(events)
     }
 
+    /// Every recorded event, oldest first.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_events(&self) -> Result<Vec<Event>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_events(pool).await,
+        };
+
+        let events = sqlx::query_as!(
+            Event,
+            r#"
+            SELECT id as "id!: String",
+                   event_type as "event_type!: _",
+                   camera_id as "camera_id!: _",
+                   start_time as "start_time!: _",
+                   end_time as "end_time?: _",
+                   backed_up as "backed_up!: _",
+                   skip_reason as "skip_reason?: _",
+                   smart_detect_types as "smart_detect_types!: _",
+                   thumbnail_id as "thumbnail_id?: _",
+                   heatmap_id as "heatmap_id?: _"
+            FROM events ORDER BY start_time
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
+
+        Ok(events)
+    }
+
+    /// Delete events which started before `cutoff` and have no backups left, except the events
+    /// in `holds`. Events from the cameras in `camera_cutoffs` go by the cutoff given for their
+    /// camera instead.
+    #[tracing::instrument(skip(self, camera_cutoffs, holds), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn cleanup_old_events(
+        &self,
+        cutoff: DateTime<Utc>,
+        camera_cutoffs: &[(String, DateTime<Utc>)],
+        holds: &[String],
+    ) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::cleanup_old_events(pool, cutoff, camera_cutoffs, holds).await;
+            }
+        };
+
+        let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM events WHERE ((start_time < ");
+        query.push_bind(cutoff.timestamp_millis());
+        if !camera_cutoffs.is_empty() {
+            query.push(" AND camera_id NOT IN (");
+            let mut ids = query.separated(", ");
+            for (camera_id, _) in camera_cutoffs {
+                ids.push_bind(camera_id.as_str());
+            }
+            ids.push_unseparated(")");
+        }
+        query.push(")");
+        for (camera_id, camera_cutoff) in camera_cutoffs {
+            query.push(" OR (camera_id = ");
+            query.push_bind(camera_id.as_str());
+            query.push(" AND start_time < ");
+            query.push_bind(camera_cutoff.timestamp_millis());
+            query.push(")");
+        }
+        query.push(")");
+        // a backup the pruner kept (held for min-copies, or whose delete failed) keeps its event, or
+        // the cascade would leave its file on the target with nothing left to prune it
+        query.push(" AND NOT EXISTS (SELECT 1 FROM backups WHERE backups.event_id = events.id)");
+        if !holds.is_empty() {
+            query.push(" AND id NOT IN (");
+            let mut ids = query.separated(", ");
+            for id in holds {
+                ids.push_bind(id.as_str());
+            }
+            ids.push_unseparated(")");
+        }
+
+        query
+            .build()
+            .execute(pool)
+            .await
+            .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn start_camera_pause(
+        &self,
+        camera_id: &str,
+        reason: &str,
+        start_time: i64,
+    ) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::start_camera_pause(pool, camera_id, reason, start_time).await;
+            }
+        };
+
+        let mut tx = pool.begin().await?;
+
+        // a camera can only be paused for one reason at a time; close out any open pause first
+        sqlx::query!(
+            "UPDATE camera_pauses SET end_time = ? WHERE camera_id = ? AND end_time IS NULL",
+            start_time,
+            camera_id
+        )
+        .execute(&mut *tx)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO camera_pauses (camera_id, reason, start_time)
+            VALUES (?, ?, ?)
+            "#,
+            camera_id,
+            reason,
+            start_time
+        )
+        .execute(&mut *tx)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn end_camera_pause(&self, camera_id: &str, end_time: i64) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::end_camera_pause(pool, camera_id, end_time).await;
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE camera_pauses SET end_time = ? WHERE camera_id = ? AND end_time IS NULL",
+            end_time,
+            camera_id
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_open_camera_pause(&self, camera_id: &str) -> Result<Option<CameraPause>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::get_open_camera_pause(pool, camera_id).await;
+            }
+        };
+
+        let pause = sqlx::query_as!(
+            CameraPause,
+            r#"
+            SELECT id as "id!: i64",
+                   camera_id as "camera_id!: _",
+                   reason as "reason!: _",
+                   start_time as "start_time!: _",
+                   end_time as "end_time?: _"
+            FROM camera_pauses WHERE camera_id = ? AND end_time IS NULL
+            "#,
+            camera_id
+        )
+        .fetch_optional(pool)
+        .await
+        .inspect(|row| record_rows(row.is_some() as u64))?;
+
+        Ok(pause)
+    }
+
+    #[tracing::instrument(skip(self, failure), fields(rows, subject = failure.subject, target = failure.target))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn insert_failure(&self, failure: &Failure) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::insert_failure(pool, failure).await,
+        };
+
+        let timestamp = failure.failure_time.timestamp();
+        sqlx::query!(
+            r#"
+            INSERT INTO failures (subject, target, error, output, failure_time)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            failure.subject,
+            failure.target,
+            failure.error,
+            failure.output,
+            timestamp
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// Failures for a subject on targets whose name starts with `target`, newest first.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_failures(&self, subject: &str, target: &str) -> Result<Vec<Failure>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_failures(pool, subject, target).await,
+        };
+
+        let failures = sqlx::query!(
+            r#"
+            SELECT subject, target, error, output, failure_time
+            FROM failures
+            WHERE subject = ? AND substr(target, 1, length(?)) = ?
+            ORDER BY failure_time DESC, id DESC
+            "#,
+            subject,
+            target,
+            target
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?
+        .into_iter()
+        .map(|row| Failure {
+            subject: row.subject,
+            target: row.target,
+            error: row.error,
+            output: row.output,
+            failure_time: DateTime::from_timestamp(row.failure_time, 0).unwrap_or_default(),
+        })
+        .collect();
+
+        Ok(failures)
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn cleanup_old_failures(&self, cutoff: DateTime<Utc>) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::cleanup_old_failures(pool, cutoff).await,
+        };
+
+        let cutoff_time = cutoff.timestamp();
+
+        sqlx::query!("DELETE FROM failures WHERE failure_time < ?", cutoff_time)
+            .execute(pool)
+            .await
+            .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, interaction), fields(rows, device_id = interaction.device_id))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn insert_doorbell_interaction(
+        &self,
+        interaction: &DoorbellInteraction,
+    ) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::insert_doorbell_interaction(pool, interaction).await;
+            }
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO doorbell_interactions (device_id, action, user_id, detail, timestamp)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            interaction.device_id,
+            interaction.action,
+            interaction.user_id,
+            interaction.detail,
+            interaction.timestamp
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    /// Doorbell interactions since `since`, oldest first.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_doorbell_interactions(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DoorbellInteraction>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::get_doorbell_interactions(pool, since).await;
+            }
+        };
+
+        let since = since.timestamp_millis();
+        let interactions = sqlx::query_as!(
+            DoorbellInteraction,
+            r#"
+            SELECT device_id as "device_id!: _",
+                   action as "action!: _",
+                   user_id as "user_id?: _",
+                   detail as "detail?: _",
+                   timestamp as "timestamp!: _"
+            FROM doorbell_interactions
+            WHERE timestamp >= ?
+            ORDER BY timestamp, id
+            "#,
+            since
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
+
+        Ok(interactions)
+    }
+
+    /// Record a camera as present on the NVR, updating its name and details if already known.
+    #[tracing::instrument(skip(self, camera), fields(rows, camera_id = camera.id))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn upsert_camera(&self, camera: &Camera) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::upsert_camera(pool, camera).await,
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO cameras (id, name, mac, model, first_seen, last_updated, removed_at)
+            VALUES (?, ?, ?, ?, ?, ?, NULL)
+            ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                mac = excluded.mac,
+                model = excluded.model,
+                last_updated = excluded.last_updated,
+                removed_at = NULL
+            "#,
+            camera.id,
+            camera.name,
+            camera.mac,
+            camera.model,
+            camera.first_seen,
+            camera.last_updated
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn mark_camera_removed(&self, camera_id: &str, removed_at: i64) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::mark_camera_removed(pool, camera_id, removed_at).await;
+            }
+        };
+
+        sqlx::query!(
+            "UPDATE cameras SET removed_at = ?, last_updated = ? WHERE id = ?",
+            removed_at,
+            removed_at,
+            camera_id
+        )
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn get_camera(&self, camera_id: &str) -> Result<Option<Camera>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::get_camera(pool, camera_id).await,
+        };
+
+        let camera = sqlx::query_as!(
+            Camera,
+            r#"
+            SELECT id as "id!: String",
+                   name as "name!: _",
+                   mac as "mac!: _",
+                   model as "model?: _",
+                   first_seen as "first_seen!: _",
+                   last_updated as "last_updated!: _",
+                   removed_at as "removed_at?: _"
+            FROM cameras WHERE id = ?
+            "#,
+            camera_id
+        )
+        .fetch_optional(pool)
+        .await
+        .inspect(|row| record_rows(row.is_some() as u64))?;
+
+        Ok(camera)
+    }
+
+    /// Write a consistent copy of the database to `dest`, which must not exist yet. SQLite only;
+    /// Postgres should be backed up with its own tooling.
     #[tracing::instrument(skip(self))]
-    pub async fn cleanup_old_events(&self, retention_period: u32) -> Result<()> {
-        let cutoff_time =
-            (Utc::now() - chrono::Duration::days(retention_period as i64)).timestamp();
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn snapshot(&self, dest: &Path) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => {
+                return Err(Error::Unsupported(
+                    "Snapshots of Postgres databases".to_string(),
+                ));
+            }
+        };
 
-        sqlx::query!("DELETE FROM events WHERE start_time < ?", cutoff_time)
-            .execute(&self.pool)
+        let dest = dest.to_string_lossy().into_owned();
+        sqlx::query!("VACUUM INTO ?", dest).execute(pool).await?;
+
+        Ok(())
+    }
+
+    /// Run SQLite's `PRAGMA integrity_check`, returning the problems it found (none when the
+    /// database is healthy).
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn integrity_check(&self) -> Result<Vec<String>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => {
+                return Err(Error::Unsupported(
+                    "Integrity checks of Postgres databases".to_string(),
+                ));
+            }
+        };
+
+        let results: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_all(pool)
+            .await?;
+        record_rows(results.len() as u64);
+
+        Ok(results
+            .into_iter()
+            .filter(|result| result != "ok")
+            .collect())
+    }
+
+    /// Refresh the query planner statistics and return free pages to the filesystem.
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn optimize(&self) -> Result<()> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => {
+                return Err(Error::Unsupported(
+                    "Optimizing Postgres databases".to_string(),
+                ));
+            }
+        };
+
+        sqlx::query("PRAGMA optimize").execute(pool).await?;
+        // a no-op unless the database was created with `auto_vacuum = INCREMENTAL`
+        sqlx::query("PRAGMA incremental_vacuum")
+            .execute(pool)
             .await?;
 
         Ok(())
     }
+
+    /// Event counts per camera and UTC day, for events starting from `since`.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn events_per_camera_per_day(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<CameraDayCount>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => {
+                return postgres::events_per_camera_per_day(pool, since).await;
+            }
+        };
+
+        let since = since.timestamp_millis();
+
+        let counts = sqlx::query_as!(
+            CameraDayCount,
+            r#"
+            SELECT camera_id,
+                   date(start_time / 1000, 'unixepoch') as "day!: String",
+                   COUNT(*) as "events!: i64"
+            FROM events WHERE start_time >= ?
+            GROUP BY camera_id, day
+            ORDER BY day, camera_id
+            "#,
+            since
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
+
+        Ok(counts)
+    }
+
+    /// Number and total size of the backups recorded for each target.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn bytes_per_target(&self) -> Result<Vec<TargetUsage>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::bytes_per_target(pool).await,
+        };
+
+        let usage = sqlx::query_as!(
+            TargetUsage,
+            r#"
+            SELECT target, COUNT(*) as "backups!: i64", SUM(size_bytes) as "bytes!: i64"
+            FROM backups
+            GROUP BY target
+            ORDER BY target
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
+
+        Ok(usage)
+    }
+
+    /// Number and total size of the backups made for each target from `since`.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn uploads_per_target(&self, since: DateTime<Utc>) -> Result<Vec<TargetUsage>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::uploads_per_target(pool, since).await,
+        };
+
+        let since = since.timestamp();
+
+        let usage = sqlx::query_as!(
+            TargetUsage,
+            r#"
+            SELECT target, COUNT(*) as "backups!: i64", SUM(size_bytes) as "bytes!: i64"
+            FROM backups WHERE backup_time >= ?
+            GROUP BY target
+            ORDER BY target
+            "#,
+            since
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
+
+        Ok(usage)
+    }
+
+    /// Number of failures recorded for each target from `since`.
+    #[tracing::instrument(skip(self), fields(rows))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn failures_per_target(&self, since: DateTime<Utc>) -> Result<Vec<TargetFailures>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::failures_per_target(pool, since).await,
+        };
+
+        let since = since.timestamp();
+
+        let failures = sqlx::query_as!(
+            TargetFailures,
+            r#"
+            SELECT target, COUNT(*) as "failures!: i64"
+            FROM failures WHERE failure_time >= ?
+            GROUP BY target
+            ORDER BY target
+            "#,
+            since
+        )
+        .fetch_all(pool)
+        .await
+        .inspect(|rows| record_rows(rows.len() as u64))?;
+
+        Ok(failures)
+    }
+
+    /// Mean length of finished events, or `None` if there are none.
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn average_event_length(&self) -> Result<Option<Duration>> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::average_event_length(pool).await,
+        };
+
+        let average = sqlx::query_scalar!(
+            r#"
+            SELECT CAST(AVG(end_time - start_time) AS INTEGER) as "average_ms?: i64"
+            FROM events WHERE end_time IS NOT NULL
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(average.map(|ms| Duration::from_millis(ms.max(0) as u64)))
+    }
+
+    #[tracing::instrument(skip(self))]
+    #[measure([HitCount, ErrorCount, ResponseTime])]
+    pub async fn backlog(&self) -> Result<Backlog> {
+        let pool = match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => return postgres::backlog(pool).await,
+        };
+
+        let row = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "events!: i64", MIN(start_time) as "oldest?: i64"
+            FROM events WHERE backed_up = FALSE AND skip_reason IS NULL AND end_time IS NOT NULL
+                AND deleted_at IS NULL
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Backlog {
+            events: row.events as u64,
+            oldest: row.oldest.and_then(DateTime::from_timestamp_millis),
+        })
+    }
+}
+
+/// Record how many rows a query returned or affected on the current span.
+pub(crate) fn record_rows(rows: u64) {
+    tracing::Span::current().record("rows", rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn database() -> (tempfile::TempDir, Database) {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&dir.path().join("events.db"), &PoolOptions::default())
+            .await
+            .unwrap();
+        (dir, database)
+    }
+
+    fn event(id: &str, start_time: i64) -> Event {
+        Event {
+            id: id.to_string(),
+            event_type: "motion".to_string(),
+            camera_id: "camera".to_string(),
+            start_time,
+            end_time: Some(start_time + 10_000),
+            backed_up: true,
+            skip_reason: None,
+            smart_detect_types: String::new(),
+            thumbnail_id: None,
+            heatmap_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_creates_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data").join("events.db");
+        Database::new(&path, &PoolOptions::default()).await.unwrap();
+        assert!(path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_keeps_events_with_backups() {
+        let (_dir, database) = database().await;
+        database.insert_event(&event("kept", 1_000)).await.unwrap();
+        database
+            .insert_event(&event("expired", 2_000))
+            .await
+            .unwrap();
+        let backup = Backup {
+            event_id: "kept".to_string(),
+            target: "s3".to_string(),
+            part: 0,
+            remote_path: "Front Door/1970-01-01/00-00-01.mp4".to_string(),
+            backup_time: Utc::now(),
+            size_bytes: 1024,
+            sha256: None,
+        };
+        database.insert_backup(&backup).await.unwrap();
+
+        let cutoff = DateTime::from_timestamp_millis(1_000_000).unwrap();
+        database.cleanup_old_events(cutoff, &[], &[]).await.unwrap();
+        assert!(database.get_event_by_id("expired").await.unwrap().is_none());
+        assert!(database.get_event_by_id("kept").await.unwrap().is_some());
+        assert_eq!(
+            database.get_backups_by_event("kept").await.unwrap().len(),
+            1
+        );
+
+        // once the pruner has deleted the copy, the event goes too
+        database.delete_backup("kept", "s3", 0).await.unwrap();
+        database.cleanup_old_events(cutoff, &[], &[]).await.unwrap();
+        assert!(database.get_event_by_id("kept").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_import_ledger_keeps_backups() {
+        let (_dir, database) = database().await;
+        database.insert_event(&event("event", 1_000)).await.unwrap();
+        let backup = |target: &str| Backup {
+            event_id: "event".to_string(),
+            target: target.to_string(),
+            part: 0,
+            remote_path: "Front Door/1970-01-01/00-00-01.mp4".to_string(),
+            backup_time: DateTime::from_timestamp(1_000, 0).unwrap(),
+            size_bytes: 1024,
+            sha256: None,
+        };
+        database.insert_backup(&backup("local")).await.unwrap();
+
+        // the event again, with a copy on another target only
+        let mut ledger = crate::ledger::Ledger {
+            events: vec![event("event", 1_000)],
+            backups: vec![backup("s3")],
+        };
+        ledger.events[0].smart_detect_types = "person".to_string();
+        database.import_ledger(&ledger).await.unwrap();
+
+        let imported = database.get_event_by_id("event").await.unwrap().unwrap();
+        assert_eq!(imported.smart_detect_types, "person");
+        let mut targets: Vec<_> = database
+            .get_backups_by_event("event")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.target)
+            .collect();
+        targets.sort();
+        assert_eq!(targets, vec!["local", "s3"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_events_by_label() {
+        let (_dir, database) = database().await;
+        database.insert_event(&event("car", 1_000)).await.unwrap();
+        database.insert_event(&event("van", 2_000)).await.unwrap();
+        let labels = |values: &[&str]| values.iter().map(ToString::to_string).collect::<Vec<_>>();
+        database
+            .add_event_labels("car", &labels(&["red", "sedan"]), &labels(&["ABC123"]))
+            .await
+            .unwrap();
+        database
+            .add_event_labels("van", &labels(&["white"]), &labels(&["XYZ789"]))
+            .await
+            .unwrap();
+        // labels of an event that isn't recorded are dropped
+        database
+            .add_event_labels("gone", &labels(&["red"]), &[])
+            .await
+            .unwrap();
+
+        let ids = |events: Vec<Event>| events.into_iter().map(|event| event.id).collect::<Vec<_>>();
+        let by_tag = EventSearch {
+            tag: Some("RED".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(database.search_events(&by_tag).await.unwrap()), ["car"]);
+        let by_plate = EventSearch {
+            plate: Some("xyz".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(database.search_events(&by_plate).await.unwrap()),
+            ["van"]
+        );
+        let by_query = EventSearch {
+            query: Some("abc1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(database.search_events(&by_query).await.unwrap()),
+            ["car"]
+        );
+    }
 }
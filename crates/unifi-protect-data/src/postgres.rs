@@ -0,0 +1,972 @@
+//! Postgres implementations of the [`Database`](crate::Database) queries. These use runtime
+//! checked queries since the offline query data in `.sqlx` is generated against SQLite.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::{
+    BATCH_ROWS, Backlog, Backup, Camera, CameraDayCount, CameraPause, DoorbellInteraction, Event,
+    EventSearch, Failure, InFlightUpload, LABEL_PLATE, LABEL_TAG, TargetFailures, TargetUsage,
+    error::Result, record_rows,
+};
+
+const EVENT_COLUMNS: &str = "id, event_type, camera_id, start_time, end_time, backed_up, \
+     skip_reason, smart_detect_types, thumbnail_id, heatmap_id";
+
+type BackupRow = (String, String, i32, String, i64, i64, Option<String>);
+
+fn backup_from_row(
+    (event_id, target, part, remote_path, backup_time, size_bytes, sha256): BackupRow,
+) -> Backup {
+    Backup {
+        event_id,
+        target,
+        part: part as u32,
+        remote_path,
+        backup_time: DateTime::from_timestamp(backup_time, 0).unwrap_or_default(),
+        size_bytes: size_bytes as u64,
+        sha256,
+    }
+}
+
+pub(crate) async fn insert_event(pool: &PgPool, event: &Event) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO events (id, event_type, camera_id, start_time, end_time, backed_up, skip_reason, smart_detect_types, thumbnail_id, heatmap_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (id) DO UPDATE SET
+            event_type = excluded.event_type,
+            camera_id = excluded.camera_id,
+            start_time = excluded.start_time,
+            end_time = excluded.end_time,
+            backed_up = excluded.backed_up,
+            skip_reason = excluded.skip_reason,
+            smart_detect_types = excluded.smart_detect_types,
+            thumbnail_id = excluded.thumbnail_id,
+            heatmap_id = excluded.heatmap_id
+        "#,
+    )
+    .bind(&event.id)
+    .bind(&event.event_type)
+    .bind(&event.camera_id)
+    .bind(event.start_time)
+    .bind(event.end_time)
+    .bind(event.backed_up)
+    .bind(&event.skip_reason)
+    .bind(&event.smart_detect_types)
+    .bind(&event.thumbnail_id)
+    .bind(&event.heatmap_id)
+    .execute(pool)
+    .await
+    .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn insert_events(pool: &PgPool, events: &[Event]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut rows = 0;
+    for chunk in events.chunks(BATCH_ROWS) {
+        let mut query =
+            QueryBuilder::<Postgres>::new(format!("INSERT INTO events ({EVENT_COLUMNS}) "));
+        query.push_values(chunk, |mut row, event| {
+            row.push_bind(event.id.as_str())
+                .push_bind(event.event_type.as_str())
+                .push_bind(event.camera_id.as_str())
+                .push_bind(event.start_time)
+                .push_bind(event.end_time)
+                .push_bind(event.backed_up)
+                .push_bind(event.skip_reason.as_deref())
+                .push_bind(event.smart_detect_types.as_str())
+                .push_bind(event.thumbnail_id.as_deref())
+                .push_bind(event.heatmap_id.as_deref());
+        });
+        query.push(
+            r#"
+            ON CONFLICT (id) DO UPDATE SET
+                event_type = excluded.event_type,
+                camera_id = excluded.camera_id,
+                start_time = excluded.start_time,
+                end_time = excluded.end_time,
+                backed_up = excluded.backed_up,
+                skip_reason = excluded.skip_reason,
+                smart_detect_types = excluded.smart_detect_types,
+                thumbnail_id = excluded.thumbnail_id,
+                heatmap_id = excluded.heatmap_id
+            "#,
+        );
+        rows += query.build().execute(&mut *tx).await?.rows_affected();
+    }
+    tx.commit().await?;
+    record_rows(rows);
+
+    Ok(())
+}
+
+pub(crate) async fn mark_event_backed_up(pool: &PgPool, event_id: &str) -> Result<()> {
+    sqlx::query("UPDATE events SET backed_up = TRUE WHERE id = $1")
+        .bind(event_id)
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn mark_events_backed_up(pool: &PgPool, event_ids: &[String]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut rows = 0;
+    for chunk in event_ids.chunks(BATCH_ROWS) {
+        let mut query =
+            QueryBuilder::<Postgres>::new("UPDATE events SET backed_up = TRUE WHERE id IN (");
+        let mut ids = query.separated(", ");
+        for event_id in chunk {
+            ids.push_bind(event_id.as_str());
+        }
+        query.push(")");
+        rows += query.build().execute(&mut *tx).await?.rows_affected();
+    }
+    tx.commit().await?;
+    record_rows(rows);
+
+    Ok(())
+}
+
+pub(crate) async fn mark_event_skipped(pool: &PgPool, event_id: &str, reason: &str) -> Result<()> {
+    sqlx::query("UPDATE events SET skip_reason = $1 WHERE id = $2")
+        .bind(reason)
+        .bind(event_id)
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn mark_event_deleted(
+    pool: &PgPool,
+    event_id: &str,
+    deleted_at: i64,
+) -> Result<()> {
+    sqlx::query("UPDATE events SET deleted_at = $1 WHERE id = $2")
+        .bind(deleted_at)
+        .bind(event_id)
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn insert_backup(pool: &PgPool, backup: &Backup) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO backups (event_id, target, part, remote_path, backup_time, size_bytes, sha256)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (event_id, target, part) DO UPDATE SET
+            remote_path = excluded.remote_path,
+            backup_time = excluded.backup_time,
+            size_bytes = excluded.size_bytes,
+            sha256 = excluded.sha256
+        "#,
+    )
+    .bind(&backup.event_id)
+    .bind(&backup.target)
+    .bind(backup.part as i32)
+    .bind(&backup.remote_path)
+    .bind(backup.backup_time.timestamp())
+    .bind(backup.size_bytes as i64)
+    .bind(&backup.sha256)
+    .execute(pool)
+    .await
+    .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn record_backups(pool: &PgPool, backups: &[Backup]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut rows = 0;
+    for chunk in backups.chunks(BATCH_ROWS) {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "INSERT INTO backups \
+             (event_id, target, part, remote_path, backup_time, size_bytes, sha256) ",
+        );
+        query.push_values(chunk, |mut row, backup| {
+            row.push_bind(backup.event_id.as_str())
+                .push_bind(backup.target.as_str())
+                .push_bind(backup.part as i32)
+                .push_bind(backup.remote_path.as_str())
+                .push_bind(backup.backup_time.timestamp())
+                .push_bind(backup.size_bytes as i64)
+                .push_bind(backup.sha256.as_deref());
+        });
+        query.push(
+            r#"
+            ON CONFLICT (event_id, target, part) DO UPDATE SET
+                remote_path = excluded.remote_path,
+                backup_time = excluded.backup_time,
+                size_bytes = excluded.size_bytes,
+                sha256 = excluded.sha256
+            "#,
+        );
+        rows += query.build().execute(&mut *tx).await?.rows_affected();
+    }
+    tx.commit().await?;
+    record_rows(rows);
+
+    Ok(())
+}
+
+pub(crate) async fn get_backups(pool: &PgPool) -> Result<Vec<Backup>> {
+    let backups = sqlx::query_as::<_, BackupRow>(
+        r#"
+        SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+        FROM backups
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?
+    .into_iter()
+    .map(backup_from_row)
+    .collect();
+
+    Ok(backups)
+}
+
+pub(crate) async fn latest_backup_time(pool: &PgPool) -> Result<Option<DateTime<Utc>>> {
+    let latest: Option<i64> = sqlx::query_scalar("SELECT MAX(backup_time) FROM backups")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(latest.and_then(|latest| DateTime::from_timestamp(latest, 0)))
+}
+
+pub(crate) async fn get_backups_by_event(pool: &PgPool, event_id: &str) -> Result<Vec<Backup>> {
+    let backups = sqlx::query_as::<_, BackupRow>(
+        r#"
+        SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+        FROM backups WHERE event_id = $1
+        "#,
+    )
+    .bind(event_id)
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?
+    .into_iter()
+    .map(backup_from_row)
+    .collect();
+
+    Ok(backups)
+}
+
+pub(crate) async fn get_backups_of_deleted_events(pool: &PgPool) -> Result<Vec<Backup>> {
+    let backups = sqlx::query_as::<_, BackupRow>(
+        r#"
+        SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+        FROM backups
+        WHERE event_id IN (SELECT id FROM events WHERE deleted_at IS NOT NULL)
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?
+    .into_iter()
+    .map(backup_from_row)
+    .collect();
+
+    Ok(backups)
+}
+
+pub(crate) async fn get_backup_by_path(
+    pool: &PgPool,
+    target: &str,
+    remote_path: &str,
+) -> Result<Option<Backup>> {
+    let backup = sqlx::query_as::<_, BackupRow>(
+        r#"
+        SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+        FROM backups WHERE target = $1 AND remote_path = $2
+        "#,
+    )
+    .bind(target)
+    .bind(remote_path)
+    .fetch_optional(pool)
+    .await
+    .inspect(|row| record_rows(row.is_some() as u64))?
+    .map(backup_from_row);
+
+    Ok(backup)
+}
+
+pub(crate) async fn update_backup_remote_path(
+    pool: &PgPool,
+    event_id: &str,
+    target: &str,
+    part: u32,
+    remote_path: &str,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE backups SET remote_path = $1 WHERE event_id = $2 AND target = $3 AND part = $4",
+    )
+    .bind(remote_path)
+    .bind(event_id)
+    .bind(target)
+    .bind(part as i32)
+    .execute(pool)
+    .await
+    .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_backups_before(
+    pool: &PgPool,
+    cutoff: DateTime<Utc>,
+) -> Result<Vec<Backup>> {
+    let backups = sqlx::query_as::<_, BackupRow>(
+        r#"
+        SELECT event_id, target, part, remote_path, backup_time, size_bytes, sha256
+        FROM backups WHERE backup_time < $1
+        ORDER BY backup_time
+        "#,
+    )
+    .bind(cutoff.timestamp())
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?
+    .into_iter()
+    .map(backup_from_row)
+    .collect();
+
+    Ok(backups)
+}
+
+pub(crate) async fn delete_backup(
+    pool: &PgPool,
+    event_id: &str,
+    target: &str,
+    part: u32,
+) -> Result<()> {
+    sqlx::query("DELETE FROM backups WHERE event_id = $1 AND target = $2 AND part = $3")
+        .bind(event_id)
+        .bind(target)
+        .bind(part as i32)
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_backups(pool: &PgPool, backups: &[Backup]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut rows = 0;
+    for backup in backups {
+        rows +=
+            sqlx::query("DELETE FROM backups WHERE event_id = $1 AND target = $2 AND part = $3")
+                .bind(backup.event_id.as_str())
+                .bind(backup.target.as_str())
+                .bind(backup.part as i32)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+    }
+    tx.commit().await?;
+    record_rows(rows);
+
+    Ok(())
+}
+
+pub(crate) async fn start_uploads(pool: &PgPool, uploads: &[InFlightUpload]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut rows = 0;
+    for chunk in uploads.chunks(BATCH_ROWS) {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "INSERT INTO in_flight_uploads \
+             (event_id, target, part, spool_path, bytes_sent, started_at) ",
+        );
+        query.push_values(chunk, |mut row, upload| {
+            row.push_bind(upload.event_id.as_str())
+                .push_bind(upload.target.as_str())
+                .push_bind(upload.part as i32)
+                .push_bind(upload.spool_path.as_deref())
+                .push_bind(upload.bytes_sent as i64)
+                .push_bind(upload.started_at.timestamp());
+        });
+        query.push(
+            " ON CONFLICT (event_id, target, part) DO UPDATE SET \
+             spool_path = excluded.spool_path, \
+             bytes_sent = excluded.bytes_sent, \
+             started_at = excluded.started_at",
+        );
+        rows += query.build().execute(&mut *tx).await?.rows_affected();
+    }
+    tx.commit().await?;
+    record_rows(rows);
+
+    Ok(())
+}
+
+pub(crate) async fn update_upload_progress(
+    pool: &PgPool,
+    event_id: &str,
+    part: u32,
+    bytes_sent: u64,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE in_flight_uploads SET bytes_sent = GREATEST(bytes_sent, $1) \
+         WHERE event_id = $2 AND part = $3",
+    )
+    .bind(bytes_sent as i64)
+    .bind(event_id)
+    .bind(part as i32)
+    .execute(pool)
+    .await
+    .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn finish_uploads(pool: &PgPool, event_id: &str, part: u32) -> Result<()> {
+    sqlx::query("DELETE FROM in_flight_uploads WHERE event_id = $1 AND part = $2")
+        .bind(event_id)
+        .bind(part as i32)
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn take_in_flight_uploads(pool: &PgPool) -> Result<Vec<InFlightUpload>> {
+    let mut tx = pool.begin().await?;
+    let uploads = sqlx::query_as::<_, (String, String, i32, Option<String>, i64, i64)>(
+        r#"
+        SELECT event_id, target, part, spool_path, bytes_sent, started_at
+        FROM in_flight_uploads
+        ORDER BY started_at
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?
+    .into_iter()
+    .map(
+        |(event_id, target, part, spool_path, bytes_sent, started_at)| InFlightUpload {
+            event_id,
+            target,
+            part: part as u32,
+            spool_path,
+            bytes_sent: bytes_sent as u64,
+            started_at: DateTime::from_timestamp(started_at, 0).unwrap_or_default(),
+        },
+    )
+    .collect();
+    sqlx::query("DELETE FROM in_flight_uploads")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(uploads)
+}
+
+pub(crate) async fn get_event_by_id(pool: &PgPool, id: &str) -> Result<Option<Event>> {
+    let event =
+        sqlx::query_as::<_, Event>(&format!("SELECT {EVENT_COLUMNS} FROM events WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .inspect(|row| record_rows(row.is_some() as u64))?;
+
+    Ok(event)
+}
+
+pub(crate) async fn get_events_not_backed_up(pool: &PgPool) -> Result<Vec<Event>> {
+    let events = sqlx::query_as::<_, Event>(&format!(
+        "SELECT {EVENT_COLUMNS} FROM events \
+         WHERE backed_up = FALSE AND skip_reason IS NULL AND end_time IS NOT NULL \
+         AND deleted_at IS NULL \
+         ORDER BY start_time"
+    ))
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(events)
+}
+
+pub(crate) async fn get_events_by_camera(pool: &PgPool, camera_id: &str) -> Result<Vec<Event>> {
+    let events = sqlx::query_as::<_, Event>(&format!(
+        "SELECT {EVENT_COLUMNS} FROM events WHERE camera_id = $1"
+    ))
+    .bind(camera_id)
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(events)
+}
+
+pub(crate) async fn get_events(pool: &PgPool) -> Result<Vec<Event>> {
+    let events = sqlx::query_as::<_, Event>(&format!(
+        "SELECT {EVENT_COLUMNS} FROM events ORDER BY start_time"
+    ))
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(events)
+}
+
+pub(crate) async fn add_event_labels(
+    pool: &PgPool,
+    event_id: &str,
+    tags: &[String],
+    plates: &[String],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut rows = 0;
+    let labels = tags
+        .iter()
+        .map(|tag| (LABEL_TAG, tag))
+        .chain(plates.iter().map(|plate| (LABEL_PLATE, plate)));
+    for (kind, value) in labels {
+        rows += sqlx::query(
+            r#"
+            INSERT INTO event_labels (event_id, kind, value)
+            SELECT id, $1, $2 FROM events WHERE id = $3
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(kind)
+        .bind(value)
+        .bind(event_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+    tx.commit().await?;
+    record_rows(rows);
+
+    Ok(())
+}
+
+pub(crate) async fn search_events(pool: &PgPool, search: &EventSearch) -> Result<Vec<Event>> {
+    let columns = EVENT_COLUMNS
+        .split(", ")
+        .map(|column| format!("e.{}", column.trim()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let events = sqlx::query_as::<_, Event>(&format!(
+        r#"
+        SELECT {columns}
+        FROM events e LEFT JOIN cameras c ON c.id = e.camera_id
+        WHERE ($1::TEXT IS NULL
+               OR e.id ILIKE '%' || $1 || '%'
+               OR c.name ILIKE '%' || $1 || '%'
+               OR e.event_type ILIKE '%' || $1 || '%'
+               OR e.smart_detect_types ILIKE '%' || $1 || '%'
+               OR EXISTS (
+                   SELECT 1 FROM event_labels l
+                   WHERE l.event_id = e.id AND l.value ILIKE '%' || $1 || '%'))
+          AND ($2::TEXT IS NULL OR e.camera_id = $2 OR c.name = $2)
+          AND ($3::TEXT IS NULL
+               OR e.event_type = $3
+               OR ',' || e.smart_detect_types || ',' LIKE '%,' || $3 || ',%')
+          AND ($4::BIGINT IS NULL OR e.start_time >= $4)
+          AND ($5::BIGINT IS NULL OR e.start_time < $5)
+          AND ($8::TEXT IS NULL OR EXISTS (
+               SELECT 1 FROM event_labels l
+               WHERE l.event_id = e.id AND l.kind = 'tag' AND LOWER(l.value) = LOWER($8)))
+          AND ($9::TEXT IS NULL OR EXISTS (
+               SELECT 1 FROM event_labels l
+               WHERE l.event_id = e.id AND l.kind = 'plate' AND l.value ILIKE '%' || $9 || '%'))
+        ORDER BY e.start_time DESC
+        LIMIT $6 OFFSET $7
+        "#
+    ))
+    .bind(&search.query)
+    .bind(&search.camera)
+    .bind(&search.detection_type)
+    .bind(search.start.map(|start| start.timestamp_millis()))
+    .bind(search.end.map(|end| end.timestamp_millis()))
+    .bind(search.limit as i64)
+    .bind(search.offset as i64)
+    .bind(&search.tag)
+    .bind(&search.plate)
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(events)
+}
+
+pub(crate) async fn cleanup_old_events(
+    pool: &PgPool,
+    cutoff: DateTime<Utc>,
+    camera_cutoffs: &[(String, DateTime<Utc>)],
+    holds: &[String],
+) -> Result<()> {
+    let mut query = QueryBuilder::<Postgres>::new("DELETE FROM events WHERE ((start_time < ");
+    query.push_bind(cutoff.timestamp_millis());
+    if !camera_cutoffs.is_empty() {
+        query.push(" AND camera_id NOT IN (");
+        let mut ids = query.separated(", ");
+        for (camera_id, _) in camera_cutoffs {
+            ids.push_bind(camera_id.as_str());
+        }
+        ids.push_unseparated(")");
+    }
+    query.push(")");
+    for (camera_id, camera_cutoff) in camera_cutoffs {
+        query.push(" OR (camera_id = ");
+        query.push_bind(camera_id.as_str());
+        query.push(" AND start_time < ");
+        query.push_bind(camera_cutoff.timestamp_millis());
+        query.push(")");
+    }
+    query.push(")");
+    // a backup the pruner kept (held for min-copies, or whose delete failed) keeps its event, or
+    // the cascade would leave its file on the target with nothing left to prune it
+    query.push(" AND NOT EXISTS (SELECT 1 FROM backups WHERE backups.event_id = events.id)");
+    if !holds.is_empty() {
+        query.push(" AND id NOT IN (");
+        let mut ids = query.separated(", ");
+        for id in holds {
+            ids.push_bind(id.as_str());
+        }
+        ids.push_unseparated(")");
+    }
+
+    query
+        .build()
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn start_camera_pause(
+    pool: &PgPool,
+    camera_id: &str,
+    reason: &str,
+    start_time: i64,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE camera_pauses SET end_time = $1 WHERE camera_id = $2 AND end_time IS NULL")
+        .bind(start_time)
+        .bind(camera_id)
+        .execute(&mut *tx)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    sqlx::query("INSERT INTO camera_pauses (camera_id, reason, start_time) VALUES ($1, $2, $3)")
+        .bind(camera_id)
+        .bind(reason)
+        .bind(start_time)
+        .execute(&mut *tx)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn end_camera_pause(pool: &PgPool, camera_id: &str, end_time: i64) -> Result<()> {
+    sqlx::query("UPDATE camera_pauses SET end_time = $1 WHERE camera_id = $2 AND end_time IS NULL")
+        .bind(end_time)
+        .bind(camera_id)
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_open_camera_pause(
+    pool: &PgPool,
+    camera_id: &str,
+) -> Result<Option<CameraPause>> {
+    let pause = sqlx::query_as::<_, CameraPause>(
+        r#"
+        SELECT id, camera_id, reason, start_time, end_time
+        FROM camera_pauses WHERE camera_id = $1 AND end_time IS NULL
+        "#,
+    )
+    .bind(camera_id)
+    .fetch_optional(pool)
+    .await
+    .inspect(|row| record_rows(row.is_some() as u64))?;
+
+    Ok(pause)
+}
+
+pub(crate) async fn insert_failure(pool: &PgPool, failure: &Failure) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO failures (subject, target, error, output, failure_time)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(&failure.subject)
+    .bind(&failure.target)
+    .bind(&failure.error)
+    .bind(&failure.output)
+    .bind(failure.failure_time.timestamp())
+    .execute(pool)
+    .await
+    .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_failures(
+    pool: &PgPool,
+    subject: &str,
+    target: &str,
+) -> Result<Vec<Failure>> {
+    let failures = sqlx::query_as::<_, (String, String, String, String, i64)>(
+        r#"
+        SELECT subject, target, error, output, failure_time
+        FROM failures
+        WHERE subject = $1 AND starts_with(target, $2)
+        ORDER BY failure_time DESC, id DESC
+        "#,
+    )
+    .bind(subject)
+    .bind(target)
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?
+    .into_iter()
+    .map(|(subject, target, error, output, failure_time)| Failure {
+        subject,
+        target,
+        error,
+        output,
+        failure_time: DateTime::from_timestamp(failure_time, 0).unwrap_or_default(),
+    })
+    .collect();
+
+    Ok(failures)
+}
+
+pub(crate) async fn insert_doorbell_interaction(
+    pool: &PgPool,
+    interaction: &DoorbellInteraction,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO doorbell_interactions (device_id, action, user_id, detail, timestamp)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(&interaction.device_id)
+    .bind(&interaction.action)
+    .bind(&interaction.user_id)
+    .bind(&interaction.detail)
+    .bind(interaction.timestamp)
+    .execute(pool)
+    .await
+    .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_doorbell_interactions(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<Vec<DoorbellInteraction>> {
+    let interactions = sqlx::query_as::<_, DoorbellInteraction>(
+        r#"
+        SELECT device_id, action, user_id, detail, timestamp
+        FROM doorbell_interactions
+        WHERE timestamp >= $1
+        ORDER BY timestamp, id
+        "#,
+    )
+    .bind(since.timestamp_millis())
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(interactions)
+}
+
+pub(crate) async fn cleanup_old_failures(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<()> {
+    sqlx::query("DELETE FROM failures WHERE failure_time < $1")
+        .bind(cutoff.timestamp())
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn upsert_camera(pool: &PgPool, camera: &Camera) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO cameras (id, name, mac, model, first_seen, last_updated, removed_at)
+        VALUES ($1, $2, $3, $4, $5, $6, NULL)
+        ON CONFLICT (id) DO UPDATE SET
+            name = excluded.name,
+            mac = excluded.mac,
+            model = excluded.model,
+            last_updated = excluded.last_updated,
+            removed_at = NULL
+        "#,
+    )
+    .bind(&camera.id)
+    .bind(&camera.name)
+    .bind(&camera.mac)
+    .bind(&camera.model)
+    .bind(camera.first_seen)
+    .bind(camera.last_updated)
+    .execute(pool)
+    .await
+    .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn mark_camera_removed(
+    pool: &PgPool,
+    camera_id: &str,
+    removed_at: i64,
+) -> Result<()> {
+    sqlx::query("UPDATE cameras SET removed_at = $1, last_updated = $1 WHERE id = $2")
+        .bind(removed_at)
+        .bind(camera_id)
+        .execute(pool)
+        .await
+        .inspect(|result| record_rows(result.rows_affected()))?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_camera(pool: &PgPool, camera_id: &str) -> Result<Option<Camera>> {
+    let camera = sqlx::query_as::<_, Camera>(
+        r#"
+        SELECT id, name, mac, model, first_seen, last_updated, removed_at
+        FROM cameras WHERE id = $1
+        "#,
+    )
+    .bind(camera_id)
+    .fetch_optional(pool)
+    .await
+    .inspect(|row| record_rows(row.is_some() as u64))?;
+
+    Ok(camera)
+}
+
+pub(crate) async fn events_per_camera_per_day(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<Vec<CameraDayCount>> {
+    let counts = sqlx::query_as::<_, CameraDayCount>(
+        r#"
+        SELECT camera_id,
+               to_char(to_timestamp(start_time / 1000) AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS day,
+               COUNT(*) AS events
+        FROM events WHERE start_time >= $1
+        GROUP BY camera_id, day
+        ORDER BY day, camera_id
+        "#,
+    )
+    .bind(since.timestamp_millis())
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(counts)
+}
+
+pub(crate) async fn bytes_per_target(pool: &PgPool) -> Result<Vec<TargetUsage>> {
+    let usage = sqlx::query_as::<_, TargetUsage>(
+        r#"
+        SELECT target, COUNT(*) AS backups, SUM(size_bytes)::BIGINT AS bytes
+        FROM backups
+        GROUP BY target
+        ORDER BY target
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(usage)
+}
+
+pub(crate) async fn uploads_per_target(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<Vec<TargetUsage>> {
+    let usage = sqlx::query_as::<_, TargetUsage>(
+        r#"
+        SELECT target, COUNT(*) AS backups, SUM(size_bytes)::BIGINT AS bytes
+        FROM backups WHERE backup_time >= $1
+        GROUP BY target
+        ORDER BY target
+        "#,
+    )
+    .bind(since.timestamp())
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(usage)
+}
+
+pub(crate) async fn failures_per_target(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<Vec<TargetFailures>> {
+    let failures = sqlx::query_as::<_, TargetFailures>(
+        r#"
+        SELECT target, COUNT(*) AS failures
+        FROM failures WHERE failure_time >= $1
+        GROUP BY target
+        ORDER BY target
+        "#,
+    )
+    .bind(since.timestamp())
+    .fetch_all(pool)
+    .await
+    .inspect(|rows| record_rows(rows.len() as u64))?;
+
+    Ok(failures)
+}
+
+pub(crate) async fn average_event_length(pool: &PgPool) -> Result<Option<Duration>> {
+    let average: Option<i64> = sqlx::query_scalar(
+        "SELECT AVG(end_time - start_time)::BIGINT FROM events WHERE end_time IS NOT NULL",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(average.map(|ms| Duration::from_millis(ms.max(0) as u64)))
+}
+
+pub(crate) async fn backlog(pool: &PgPool) -> Result<Backlog> {
+    let (events, oldest): (i64, Option<i64>) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), MIN(start_time)
+        FROM events WHERE backed_up = FALSE AND skip_reason IS NULL AND end_time IS NOT NULL
+            AND deleted_at IS NULL
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Backlog {
+        events: events as u64,
+        oldest: oldest.and_then(DateTime::from_timestamp_millis),
+    })
+}
@@ -0,0 +1,60 @@
+//! Print the last day's events from an NVR.
+//!
+//! ```sh
+//! UNIFI_ADDRESS=192.168.1.1 UNIFI_USERNAME=backup UNIFI_PASSWORD=secret \
+//!     cargo run -p unifi-protect --example list_events
+//! ```
+
+use std::{env, time::Duration};
+
+use chrono::Utc;
+use unifi_protect::{
+    ProtectClient, UnifiConfig,
+    client::{Result, RetryConfig},
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = UnifiConfig {
+        address: env::var("UNIFI_ADDRESS").expect("UNIFI_ADDRESS is set"),
+        port: 443,
+        username: env::var("UNIFI_USERNAME").expect("UNIFI_USERNAME is set"),
+        password: env::var("UNIFI_PASSWORD")
+            .expect("UNIFI_PASSWORD is set")
+            .into(),
+        verify_ssl: false,
+        ca_cert_path: None,
+        connect_timeout: None,
+        read_timeout: None,
+        proxy: None,
+        retry: RetryConfig::default(),
+        rate_limit: None,
+        bootstrap_refresh_interval: Duration::from_secs(5 * 60),
+        websocket_ping_interval: Duration::from_secs(30),
+        websocket_idle_timeout: Duration::from_secs(90),
+    };
+
+    let client = ProtectClient::new(config)?;
+    client.login().await?;
+    let bootstrap = client.get_bootstrap().await?;
+
+    let end = Utc::now().timestamp_millis();
+    let start = end - 24 * 60 * 60 * 1000;
+    for record in client.list_events(start, end).await? {
+        let camera_name = record
+            .camera
+            .as_ref()
+            .and_then(|camera_id| bootstrap.cameras.get(camera_id))
+            .map(|camera| camera.name.clone());
+        if let Some(event) = record.to_protect_event(camera_name) {
+            println!(
+                "{} {} on {}",
+                event.id,
+                event.event_type,
+                event.camera_name.as_deref().unwrap_or(&event.camera_id)
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! Run the backup service from a config file, as the `unifi-protect-backup` binary does but
+//! without its logging setup or metrics server.
+//!
+//! ```sh
+//! cargo run -p unifi-protect --features engine --example run_engine -- config.toml
+//! ```
+
+use std::env;
+
+use unifi_protect::{
+    BackupEngine,
+    engine::{Config, Result, toml_from_file},
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "config.toml".to_string());
+    let config: Config = toml_from_file(&path)?;
+
+    let engine = BackupEngine::new(config).await?;
+    engine.run().await
+}
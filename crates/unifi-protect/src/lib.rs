@@ -0,0 +1,76 @@
+//! Build on UniFi Protect Backup from your own tools: talk to a Protect NVR, read and write the
+//! event database, or embed the whole backup service.
+//!
+//! This crate only re-exports what's listed here from the workspace's internal crates, as they
+//! are: structs with public fields and enums that gain variants as Protect adds event types.
+//! Those change whenever the backup tool needs them to, so until 1.0 any release may break them;
+//! pin an exact version (`unifi-protect = "=0.1.0"`) and expect to update code when moving on.
+//! Anything reached any other way can change at any time.
+//!
+//! | Feature | Default | Adds |
+//! |---------|---------|------|
+//! | `native-tls` | yes | TLS through the platform's library |
+//! | `rustls` | no | TLS through rustls instead |
+//! | `postgres` | no | [`Database::connect`] to Postgres as well as SQLite |
+//! | `engine` | no | [`engine`]: running the whole backup service from a config |
+//! | `mock` | no | `client::MockProtectClient`, a [`ProtectApi`] for tests without an NVR |
+//!
+//! ```no_run
+//! use unifi_protect::{Database, PoolOptions};
+//!
+//! # async fn example() -> unifi_protect::data::Result<()> {
+//! let database = Database::new("events.db".as_ref(), &PoolOptions::default()).await?;
+//! for event in database.get_events_not_backed_up().await? {
+//!     println!("{} from {} is waiting to be backed up", event.id, event.camera_id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! See `examples/` for using the client and running the engine.
+
+/// The Protect API and WebSocket client
+pub mod client {
+    #[cfg(feature = "mock")]
+    pub use unifi_protect_client::mock::MockProtectClient;
+    pub use unifi_protect_client::{
+        ProtectClient, VideoStream,
+        api::ProtectApi,
+        config::{Secret, UnifiConfig},
+        error::{Error, Result},
+        events::{
+            AudioDetectType, DoorbellAction, DoorbellInteraction, EventRecord, EventType,
+            ProtectEvent, SensorTrigger, SmartDetectType, WebSocketMessage,
+        },
+        models::{
+            Bootstrap, Camera, ExportJob, ExportJobStatus, ExportQuality, Nvr, Sensor, SensorUpdate,
+        },
+        progress::ProgressCallback,
+        rate_limit::RateLimitConfig,
+        retry::RetryConfig,
+    };
+}
+
+/// The database of events seen and backups made
+pub mod data {
+    pub use unifi_protect_data::{
+        Backup, Camera, Database, DoorbellInteraction, Event, EventSearch, Failure, JournalMode,
+        PoolOptions,
+        error::{Error, Result},
+    };
+}
+
+/// The backup service, as run by the `unifi-protect-backup` binary
+#[cfg(feature = "engine")]
+pub mod engine {
+    pub use unifi_protect_backup::{
+        config::{Config, toml_from_file},
+        engine::BackupEngine,
+        error::{Error, Result},
+    };
+}
+
+pub use client::{ProtectApi, ProtectClient, ProtectEvent, Secret, UnifiConfig};
+pub use data::{Database, PoolOptions};
+#[cfg(feature = "engine")]
+pub use engine::BackupEngine;